@@ -0,0 +1,79 @@
+//! Generates the gRPC server and client of the `OrderService` in `proto/orders.proto`.
+//!
+//! Its messages are written by hand in `src/grpc/proto.rs`, so building doesn't need `protoc`;
+//! keep both files in step when the service changes. A test in `src/grpc/proto.rs` fails when
+//! their messages differ.
+
+use tonic_build::manual::{Builder, Method, Service};
+
+/// Methods of the service as Rust name, route name, request and response message.
+const METHODS: [(&str, &str, &str, &str); 16] = [
+    ("create_order", "CreateOrder", "CreateOrderRequest", "Order"),
+    ("get_order", "GetOrder", "OrderRequest", "Order"),
+    (
+        "list_orders",
+        "ListOrders",
+        "ListOrdersRequest",
+        "ListOrdersResponse",
+    ),
+    ("add_item", "AddItem", "AddItemRequest", "Order"),
+    ("delete_item", "DeleteItem", "ItemRequest", "Order"),
+    (
+        "update_item_quantity",
+        "UpdateItemQuantity",
+        "UpdateItemQuantityRequest",
+        "Order",
+    ),
+    (
+        "update_status",
+        "UpdateStatus",
+        "UpdateStatusRequest",
+        "Order",
+    ),
+    ("apply_coupon", "ApplyCoupon", "CouponRequest", "Order"),
+    ("remove_coupon", "RemoveCoupon", "CouponRequest", "Order"),
+    ("set_shipping", "SetShipping", "SetShippingRequest", "Order"),
+    (
+        "set_billing_address",
+        "SetBillingAddress",
+        "SetBillingAddressRequest",
+        "Order",
+    ),
+    ("checkout", "Checkout", "CheckoutRequest", "PaymentAttempt"),
+    (
+        "request_return",
+        "RequestReturn",
+        "RequestReturnRequest",
+        "Return",
+    ),
+    (
+        "approve_return",
+        "ApproveReturn",
+        "ReturnDecisionRequest",
+        "Return",
+    ),
+    (
+        "reject_return",
+        "RejectReturn",
+        "ReturnDecisionRequest",
+        "Return",
+    ),
+    ("history", "History", "OrderRequest", "HistoryResponse"),
+];
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    let mut service = Service::builder().name("OrderService").package("orders.v1");
+    for (name, route_name, input, output) in METHODS {
+        service = service.method(
+            Method::builder()
+                .name(name)
+                .route_name(route_name)
+                .input_type(format!("super::{input}"))
+                .output_type(format!("super::{output}"))
+                .codec_path("tonic::codec::ProstCodec")
+                .build(),
+        );
+    }
+    Builder::new().compile(&[service.build()]);
+}
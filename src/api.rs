@@ -1,4 +1,11 @@
+pub mod carts;
+pub mod coupons;
+pub mod graphql;
 pub mod health;
+pub mod inventory;
+pub mod metrics;
 pub mod orders;
+pub mod products;
 pub mod request;
 pub mod response;
+pub mod webhooks;
@@ -0,0 +1,125 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path,
+    },
+    http::StatusCode,
+    response::Response,
+    Extension,
+};
+use futures::{stream::BoxStream, SinkExt, StreamExt};
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::{order_store, order_updates::OrderFilter, resilient_order_store::CircuitBreaker};
+
+use super::{
+    orders::{status_code, State, Updates},
+    request::CartCommand,
+    response::{CartMessage, Order},
+};
+
+/// Opens a WebSocket to edit the cart of an order together with every other client connected to
+/// it. The client gets the order when it connects and again after every change, whoever made
+/// it; its commands that cannot be applied are answered with a `rejected` message.
+pub async fn edit(
+    ws: WebSocketUpgrade,
+    Extension(state): Extension<State>,
+    Extension(updates): Extension<Updates>,
+    Extension(breaker): Extension<Arc<CircuitBreaker>>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, StatusCode> {
+    debug!("Opening the cart of order with id: {id}");
+    // subscribing first so no change is missed between the read and the subscription
+    let changes = updates
+        .subscribe(OrderFilter::Order(id))
+        .await
+        .map_err(|err| status_code(&err))?;
+    let order = state.get_order(id).await.map_err(|err| status_code(&err))?;
+    Ok(ws.on_upgrade(move |socket| edit_cart(socket, state, breaker, order, changes)))
+}
+
+async fn edit_cart(
+    socket: WebSocket,
+    state: State,
+    breaker: Arc<CircuitBreaker>,
+    order: order_store::Order,
+    mut changes: BoxStream<'static, order_store::Order>,
+) {
+    let order_id = order.id;
+    let (mut sender, mut receiver) = socket.split();
+    if send(&mut sender, current(order)).await.is_err() {
+        return;
+    }
+    loop {
+        let message = tokio::select! {
+            Some(order) = changes.next() => current(order),
+            received = receiver.next() => match received {
+                Some(Ok(Message::Text(text))) => match apply(&state, order_id, &text).await {
+                    // the change reaches every client, this one included, through `changes`
+                    Ok(()) => continue,
+                    Err((status, error)) => {
+                        debug!("Cart command rejected: {error}");
+                        CartMessage::Rejected {
+                            command: serde_json::from_str(&text)
+                                .unwrap_or(serde_json::Value::String(text)),
+                            status: status.as_u16(),
+                            error,
+                            retry_after: (status == StatusCode::SERVICE_UNAVAILABLE)
+                                .then(|| breaker.retry_after_secs())
+                                .flatten(),
+                        }
+                    }
+                },
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // pings are answered by axum
+                Some(Ok(_)) => continue,
+            },
+        };
+        if send(&mut sender, message).await.is_err() {
+            break;
+        }
+    }
+    debug!("Closed the cart of order with id: {order_id}");
+}
+
+fn current(order: order_store::Order) -> CartMessage {
+    CartMessage::Order {
+        order: Box::new(Order::from(order)),
+    }
+}
+
+async fn send(
+    sender: &mut futures::stream::SplitSink<WebSocket, Message>,
+    message: CartMessage,
+) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(&message).expect("cart messages serialize");
+    sender.send(Message::Text(text)).await
+}
+
+/// Applies the command in `text` to the order with id `order_id`.
+async fn apply(state: &State, order_id: Uuid, text: &str) -> Result<(), (StatusCode, String)> {
+    let command: CartCommand =
+        serde_json::from_str(text).map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+    let result = match command {
+        CartCommand::AddItem {
+            product_id,
+            quantity,
+        } => state.add_item(order_id, product_id, quantity).await,
+        CartCommand::RemoveItem { index, product_id } => {
+            state.delete_item(order_id, index, Some(product_id)).await
+        }
+        CartCommand::UpdateQuantity {
+            index,
+            product_id,
+            quantity,
+        } => {
+            state
+                .update_item_quantity(order_id, index, quantity, Some(product_id))
+                .await
+        }
+    };
+    result.map_err(|err| (status_code(&err), err.to_string()))
+}
@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use axum::{extract::Path, http::StatusCode, Extension, Json};
+use tracing::debug;
+
+use crate::promotion_store::{PromotionStoreError, PromotionStoreNewType};
+
+use super::{request::CreateCoupon, response::Coupon};
+
+type State = Arc<PromotionStoreNewType>;
+
+fn status_code(err: &PromotionStoreError) -> StatusCode {
+    match err {
+        PromotionStoreError::StoreUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+        PromotionStoreError::CouponNotFound(_) => StatusCode::NOT_FOUND,
+        PromotionStoreError::DuplicateCoupon(_) | PromotionStoreError::UsageLimitReached(_) => {
+            StatusCode::CONFLICT
+        }
+        PromotionStoreError::InvalidRule(_) => StatusCode::UNPROCESSABLE_ENTITY,
+    }
+}
+
+pub async fn create(
+    Extension(state): Extension<State>,
+    Json(request): Json<CreateCoupon>,
+) -> (StatusCode, Json<Option<Coupon>>) {
+    debug!("Creating coupon with code: {}", request.code);
+    match state.create_coupon(request.into()).await {
+        Ok(coupon) => (StatusCode::CREATED, Json(Some(Coupon::from(coupon)))),
+        Err(err) => (status_code(&err), Json(None)),
+    }
+}
+
+pub async fn list(Extension(state): Extension<State>) -> (StatusCode, Json<Option<Vec<Coupon>>>) {
+    debug!("Listing all coupons");
+    match state.list_coupons().await {
+        Ok(coupons) => (
+            StatusCode::OK,
+            Json(Some(coupons.into_iter().map(Coupon::from).collect())),
+        ),
+        Err(err) => (status_code(&err), Json(None)),
+    }
+}
+
+pub async fn get(
+    Extension(state): Extension<State>,
+    Path(code): Path<String>,
+) -> (StatusCode, Json<Option<Coupon>>) {
+    debug!("Retrieving coupon with code: {code}");
+    match state.get_coupon(&code).await {
+        Ok(coupon) => (StatusCode::OK, Json(Some(Coupon::from(coupon)))),
+        Err(err) => (status_code(&err), Json(None)),
+    }
+}
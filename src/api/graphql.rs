@@ -0,0 +1,422 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_graphql::{
+    dataloader::{DataLoader, Loader},
+    http::GraphiQLSource,
+    Context, EmptySubscription, Enum, ErrorExtensions, Object, Schema, Value,
+};
+use axum::{http::StatusCode, response::Html, Extension, Json};
+use rust_decimal::Decimal;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::{
+    order_store::{self, OrderStoreError},
+    product_store::{self, ProductStoreError, ProductStoreNewType},
+    resilient_order_store::CircuitBreaker,
+};
+
+use super::orders::{status_code, State, USER_ID};
+
+pub type OrderSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+
+/// Deepest selection accepted, enough for the products of the items of orders and for the
+/// introspection queries of GraphiQL.
+const MAX_DEPTH: usize = 13;
+/// Most fields a query can select, counting every field once per time it is selected, so aliases
+/// cannot make a single request list the orders over and over.
+const MAX_COMPLEXITY: usize = 250;
+
+/// Builds the GraphQL schema over the same stores the REST routes use.
+pub fn schema(orders: State, products: Arc<ProductStoreNewType>) -> OrderSchema {
+    Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+        .data(orders)
+        .data(DataLoader::new(ProductLoader(products), tokio::spawn))
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+/// Runs a GraphQL request. Its errors answer with `200` whatever their status, so those with status
+/// `503` tell when to try again in a `retryAfter` extension, in seconds, while the circuit breaker
+/// of the order store is open.
+pub async fn execute(
+    Extension(schema): Extension<OrderSchema>,
+    Extension(breaker): Extension<Arc<CircuitBreaker>>,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    debug!("Executing GraphQL operation {:?}", request.operation_name);
+    let mut response = schema.execute(request).await;
+    if let Some(seconds) = breaker.retry_after_secs() {
+        let unavailable = Value::from(StatusCode::SERVICE_UNAVAILABLE.as_u16());
+        for extensions in response
+            .errors
+            .iter_mut()
+            .filter_map(|error| error.extensions.as_mut())
+            .filter(|extensions| extensions.get("status") == Some(&unavailable))
+        {
+            extensions.set("retryAfter", seconds);
+        }
+    }
+    Json(response)
+}
+
+/// GraphiQL playground, only served in development.
+pub async fn graphiql() -> Html<String> {
+    Html(GraphiQLSource::build().endpoint("/graphql").finish())
+}
+
+/// Turns `err` into a GraphQL error carrying the status the REST routes answer it with.
+fn error(err: OrderStoreError) -> async_graphql::Error {
+    let status = status_code(&err).as_u16();
+    err.extend_with(|_, extensions| extensions.set("status", status))
+}
+
+fn orders<'a>(ctx: &Context<'a>) -> &'a State {
+    ctx.data_unchecked::<State>()
+}
+
+/// Loads the products of every item of a response in a single store lookup.
+pub struct ProductLoader(Arc<ProductStoreNewType>);
+
+impl Loader<Uuid> for ProductLoader {
+    type Value = product_store::Product;
+    type Error = Arc<ProductStoreError>;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        let products = self.0.get_products(keys).await.map_err(Arc::new)?;
+        Ok(products
+            .into_iter()
+            .map(|product| (product.id, product))
+            .collect())
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Orders of the user.
+    async fn orders(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Order>> {
+        let orders = orders(ctx).list_orders(USER_ID).await.map_err(error)?;
+        Ok(orders.into_iter().map(Order).collect())
+    }
+
+    /// The order with id `id`, if there is one.
+    async fn order(&self, ctx: &Context<'_>, id: Uuid) -> async_graphql::Result<Option<Order>> {
+        match orders(ctx).get_order(id).await {
+            Ok(order) => Ok(Some(Order(order))),
+            Err(OrderStoreError::OrderNotFound(_)) => Ok(None),
+            Err(err) => Err(error(err)),
+        }
+    }
+}
+
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    /// Creates an empty order for the user.
+    async fn create_order(&self, ctx: &Context<'_>) -> async_graphql::Result<Order> {
+        let order = orders(ctx).create_order(USER_ID).await.map_err(error)?;
+        Ok(Order(order))
+    }
+
+    /// Adds `quantity` units of a product to an order, reserving their stock, and returns the order.
+    async fn add_item(
+        &self,
+        ctx: &Context<'_>,
+        order_id: Uuid,
+        product_id: Uuid,
+        quantity: i32,
+    ) -> async_graphql::Result<Order> {
+        let orders = orders(ctx);
+        orders
+            .add_item(order_id, product_id, quantity)
+            .await
+            .map_err(error)?;
+        Ok(Order(orders.get_order(order_id).await.map_err(error)?))
+    }
+
+    /// Deletes the item at position `index` of an order, releasing its stock, and returns the order.
+    async fn delete_item(
+        &self,
+        ctx: &Context<'_>,
+        order_id: Uuid,
+        index: usize,
+    ) -> async_graphql::Result<Order> {
+        let orders = orders(ctx);
+        orders
+            .delete_item(order_id, index, None)
+            .await
+            .map_err(error)?;
+        Ok(Order(orders.get_order(order_id).await.map_err(error)?))
+    }
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+#[graphql(remote = "order_store::OrderStatus")]
+pub enum OrderStatus {
+    Draft,
+    Placed,
+    Paid,
+    Shipped,
+    Delivered,
+    Cancelled,
+}
+
+pub struct Order(order_store::Order);
+
+#[Object]
+impl Order {
+    async fn id(&self) -> Uuid {
+        self.0.id
+    }
+
+    async fn user_id(&self) -> Uuid {
+        self.0.user_id
+    }
+
+    async fn status(&self) -> OrderStatus {
+        self.0.status.into()
+    }
+
+    async fn items(&self) -> Vec<Item> {
+        self.0.items.iter().cloned().map(Item).collect()
+    }
+
+    async fn currency(&self) -> Option<&str> {
+        self.0.currency.as_deref()
+    }
+
+    async fn subtotal(&self) -> Decimal {
+        self.0.subtotal
+    }
+
+    async fn discount(&self) -> Decimal {
+        self.0.discount
+    }
+
+    /// Taxes of the items, whether or not they are part of the prices.
+    async fn tax(&self) -> Decimal {
+        self.0.tax.total
+    }
+
+    async fn shipping_cost(&self) -> Decimal {
+        self.0.shipping_cost
+    }
+
+    async fn refunded(&self) -> Decimal {
+        self.0.refunded
+    }
+
+    async fn total(&self) -> Decimal {
+        self.0.total
+    }
+}
+
+pub struct Item(order_store::Item);
+
+#[Object]
+impl Item {
+    async fn product_id(&self) -> Uuid {
+        self.0.product_id
+    }
+
+    /// The product as it is now in the catalog, `null` if it was removed from it.
+    async fn product(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Product>> {
+        let loader = ctx.data_unchecked::<DataLoader<ProductLoader>>();
+        let product = loader.load_one(self.0.product_id).await?;
+        Ok(product.map(Product))
+    }
+
+    async fn quantity(&self) -> i32 {
+        self.0.quantity
+    }
+
+    /// Price of one unit when the item was added to the order.
+    async fn unit_price(&self) -> Decimal {
+        self.0.unit_price
+    }
+
+    async fn currency(&self) -> &str {
+        &self.0.currency
+    }
+
+    async fn line_total(&self) -> Decimal {
+        self.0.line_total
+    }
+}
+
+pub struct Product(product_store::Product);
+
+#[Object]
+impl Product {
+    async fn id(&self) -> Uuid {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn sku(&self) -> &str {
+        &self.0.sku
+    }
+
+    async fn price(&self) -> Decimal {
+        self.0.price
+    }
+
+    async fn currency(&self) -> &str {
+        &self.0.currency
+    }
+
+    async fn active(&self) -> bool {
+        self.0.active
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::{
+        fake_payment_provider::FakePaymentProvider,
+        in_mem_inventory_store::InMemInventoryStore,
+        in_mem_order_store::InMemOrderStore,
+        in_mem_product_store::InMemProductStore,
+        in_mem_promotion_store::InMemPromotionStore,
+        inventory_store::InventoryStoreNewType,
+        order_store::OrderStoreNewType,
+        product_store::{ProductDetails, ProductStore},
+        promotion_store::PromotionStoreNewType,
+        rules_table_tax_calculator::RulesTableTaxCalculator,
+    };
+
+    /// Product store counting the lookups of several products.
+    struct CountingProductStore {
+        products: InMemProductStore,
+        lookups: Arc<AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl ProductStore for CountingProductStore {
+        async fn create_product(
+            &self,
+            details: ProductDetails,
+        ) -> Result<product_store::Product, ProductStoreError> {
+            self.products.create_product(details).await
+        }
+
+        async fn get_product(
+            &self,
+            product_id: Uuid,
+        ) -> Result<product_store::Product, ProductStoreError> {
+            self.products.get_product(product_id).await
+        }
+
+        async fn get_products(
+            &self,
+            product_ids: &[Uuid],
+        ) -> Result<Vec<product_store::Product>, ProductStoreError> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            self.products.get_products(product_ids).await
+        }
+
+        async fn list_products(&self) -> Result<Vec<product_store::Product>, ProductStoreError> {
+            self.products.list_products().await
+        }
+
+        async fn update_product(
+            &self,
+            product_id: Uuid,
+            details: ProductDetails,
+        ) -> Result<product_store::Product, ProductStoreError> {
+            self.products.update_product(product_id, details).await
+        }
+
+        async fn delete_product(&self, product_id: Uuid) -> Result<(), ProductStoreError> {
+            self.products.delete_product(product_id).await
+        }
+    }
+
+    #[tokio::test]
+    async fn products_of_nested_items_are_loaded_in_one_lookup() {
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let products = Arc::new(ProductStoreNewType::new(CountingProductStore {
+            products: InMemProductStore::new(),
+            lookups: lookups.clone(),
+        }));
+        let inventory = Arc::new(InventoryStoreNewType::new(InMemInventoryStore::new()));
+        let orders = Arc::new(OrderStoreNewType::new(InMemOrderStore::new(
+            products.clone(),
+            inventory.clone(),
+            Arc::new(PromotionStoreNewType::new(InMemPromotionStore::new())),
+            Arc::new(RulesTableTaxCalculator::tax_free()),
+            Arc::new(FakePaymentProvider::new()),
+        )));
+        for sku in ["TEA-1", "TEA-2"] {
+            let product = products
+                .create_product(ProductDetails {
+                    name: sku.to_string(),
+                    sku: sku.to_string(),
+                    price: dec!(2.50),
+                    currency: "USD".to_string(),
+                    tax_category: "standard".to_string(),
+                    active: true,
+                })
+                .await
+                .unwrap();
+            inventory.set_available(product.id, 10).await.unwrap();
+            for _ in 0..2 {
+                let order = orders.create_order(USER_ID).await.unwrap();
+                orders.add_item(order.id, product.id, 2).await.unwrap();
+            }
+        }
+
+        let response = schema(orders, products)
+            .execute("{ orders { total items { quantity product { sku } } } }")
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        let data = response.data.into_json().unwrap();
+        let orders = data["orders"].as_array().unwrap();
+        assert_eq!(orders.len(), 4);
+        assert!(orders.iter().all(|order| order["total"] == "5.00"));
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn queries_too_deep_or_too_complex_are_rejected() {
+        let products = Arc::new(ProductStoreNewType::new(InMemProductStore::new()));
+        let orders = Arc::new(OrderStoreNewType::new(InMemOrderStore::new(
+            products.clone(),
+            Arc::new(InventoryStoreNewType::new(InMemInventoryStore::new())),
+            Arc::new(PromotionStoreNewType::new(InMemPromotionStore::new())),
+            Arc::new(RulesTableTaxCalculator::tax_free()),
+            Arc::new(FakePaymentProvider::new()),
+        )));
+        let schema = schema(orders, products);
+
+        let nested = (0..MAX_DEPTH).fold("name".to_string(), |selection, _| {
+            format!("ofType {{ {selection} }}")
+        });
+        let deep = format!("{{ __schema {{ types {{ fields {{ type {{ {nested} }} }} }} }} }}");
+        let response = schema.execute(deep).await;
+        assert!(response.errors[0].message.contains("nested too deep"));
+
+        let aliases: String = (0..MAX_COMPLEXITY / 4)
+            .map(|n| format!("o{n}: orders {{ id total items {{ quantity }} }} "))
+            .collect();
+        let response = schema.execute(format!("{{ {aliases} }}")).await;
+        assert!(response.errors[0].message.contains("too complex"));
+
+        let response = schema
+            .execute("{ orders { id total items { quantity product { name sku } } } }")
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+    }
+}
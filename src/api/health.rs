@@ -1,9 +1,32 @@
-use axum::http::StatusCode;
+use std::sync::Arc;
+
+use axum::{http::StatusCode, Extension, Json};
+use serde::Serialize;
 use tracing::info;
 
+use crate::resilient_order_store::{BreakerStatus, CircuitBreaker};
+
 /// health check
 #[tracing::instrument]
 pub async fn get() -> StatusCode {
     info!("new incoming health check status request");
     StatusCode::OK
 }
+
+#[derive(Serialize)]
+pub struct Readiness {
+    order_store: BreakerStatus,
+}
+
+/// readiness check, failing while the circuit breaker of the order store is open
+pub async fn ready(
+    Extension(breaker): Extension<Arc<CircuitBreaker>>,
+) -> (StatusCode, Json<Readiness>) {
+    let readiness = Readiness {
+        order_store: breaker.status(),
+    };
+    match readiness.order_store {
+        BreakerStatus::Open { .. } => (StatusCode::SERVICE_UNAVAILABLE, Json(readiness)),
+        BreakerStatus::Closed { .. } | BreakerStatus::HalfOpen => (StatusCode::OK, Json(readiness)),
+    }
+}
@@ -0,0 +1,47 @@
+use std::sync::Arc;
+
+use axum::{extract::Path, http::StatusCode, Extension, Json};
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::inventory_store::{InventoryStoreError, InventoryStoreNewType};
+
+use super::{request::SetStock, response::StockLevel};
+
+type State = Arc<InventoryStoreNewType>;
+
+fn status_code(err: &InventoryStoreError) -> StatusCode {
+    match err {
+        InventoryStoreError::StoreUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+        InventoryStoreError::InsufficientStock(_) => StatusCode::CONFLICT,
+    }
+}
+
+pub async fn get(
+    Extension(state): Extension<State>,
+    Path(product_id): Path<Uuid>,
+) -> (StatusCode, Json<Option<StockLevel>>) {
+    debug!("Retrieving stock of product with id: {product_id}");
+    match state.get_stock(product_id).await {
+        Ok(level) => (StatusCode::OK, Json(Some(StockLevel::from(level)))),
+        Err(err) => (status_code(&err), Json(None)),
+    }
+}
+
+pub async fn set(
+    Extension(state): Extension<State>,
+    Path(product_id): Path<Uuid>,
+    Json(request): Json<SetStock>,
+) -> (StatusCode, Json<Option<StockLevel>>) {
+    debug!(
+        "Setting available stock of product with id: {} to {}",
+        product_id, request.available
+    );
+    if request.available < 0 {
+        return (StatusCode::UNPROCESSABLE_ENTITY, Json(None));
+    }
+    match state.set_available(product_id, request.available).await {
+        Ok(level) => (StatusCode::OK, Json(Some(StockLevel::from(level)))),
+        Err(err) => (status_code(&err), Json(None)),
+    }
+}
@@ -0,0 +1,10 @@
+use std::sync::Arc;
+
+use axum::{Extension, Json};
+
+use crate::cached_order_store::{CacheMetrics, CacheStats};
+
+/// Hits and misses of the order cache since the service started.
+pub async fn order_cache(Extension(metrics): Extension<Arc<CacheMetrics>>) -> Json<CacheStats> {
+    Json(metrics.stats())
+}
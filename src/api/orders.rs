@@ -1,48 +1,420 @@
 use std::sync::Arc;
 
-use axum::{extract::Path, http::StatusCode, Extension, Json};
+use axum::{
+    extract::Path,
+    http::{header, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    Extension, Json,
+};
+use futures::{Stream, StreamExt};
 use tracing::debug;
 use uuid::Uuid;
 
-use crate::order_store::OrderStoreNewType;
+use crate::{
+    audit_log::as_actor,
+    internal_caller::InternalToken,
+    order_store::{self, OrderStatus, OrderStoreError, OrderStoreNewType},
+    order_updates::{OrderFilter, OrderUpdates},
+    payment_provider::PaymentOutcome,
+    resilient_order_store::CircuitBreaker,
+};
 
-use super::{request::AddItem, response::Order};
+use super::{
+    request::{
+        AddItem, Address, ApplyCoupon, Checkout, RequestReturn, SetShipping, UpdateQuantity,
+    },
+    response::{HistoryEntry, Order, Payment, Return},
+};
 
-const USER_ID: Uuid = Uuid::from_u128(0xa1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8u128);
+pub(super) const USER_ID: Uuid = Uuid::from_u128(0xa1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8u128);
 
-type State = Arc<OrderStoreNewType>;
+pub(super) type State = Arc<OrderStoreNewType>;
+pub(super) type Updates = Arc<dyn OrderUpdates>;
+
+pub(super) fn status_code(err: &OrderStoreError) -> StatusCode {
+    match err {
+        OrderStoreError::StoreUnavailable
+        | OrderStoreError::StoreConnection(_)
+        | OrderStoreError::StoreTimeout(_)
+        | OrderStoreError::StoreFailure(_)
+        | OrderStoreError::TaxUnavailable
+        | OrderStoreError::PaymentUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+        OrderStoreError::StoreMisconfigured(_) | OrderStoreError::StoreSerialization(_) => {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+        OrderStoreError::OrderNotFound(_)
+        | OrderStoreError::ItemIndexOutOfBounds(_)
+        | OrderStoreError::CouponNotFound(_)
+        | OrderStoreError::ReturnNotFound(_) => StatusCode::NOT_FOUND,
+        OrderStoreError::ProductNotFound(_)
+        | OrderStoreError::ProductInactive(_)
+        | OrderStoreError::CurrencyMismatch(_)
+        | OrderStoreError::InvalidQuantity(_)
+        | OrderStoreError::InvalidPrice(_)
+        | OrderStoreError::CouponExpired(_)
+        | OrderStoreError::NoTaxRate(_, _)
+        | OrderStoreError::InvalidAddress(_)
+        | OrderStoreError::NotReadyForCheckout(_)
+        | OrderStoreError::InvalidReturn(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        OrderStoreError::InsufficientStock(_)
+        | OrderStoreError::OrderNotEditable(_)
+        | OrderStoreError::InvalidStatusTransition(_, _)
+        | OrderStoreError::CouponUsageLimitReached(_)
+        | OrderStoreError::CouponAlreadyApplied(_)
+        | OrderStoreError::OrderNotDelivered(_)
+        | OrderStoreError::ReturnAlreadyDecided(_)
+        | OrderStoreError::ConcurrentModification(_)
+        | OrderStoreError::ItemChanged(_, _)
+        | OrderStoreError::DuplicateKey(_)
+        | OrderStoreError::WriteConflict(_) => StatusCode::CONFLICT,
+    }
+}
+
+/// Middleware telling the clients of any request that failed with `503` while the circuit breaker
+/// of the order store is open when to try again, WebSocket handshakes included.
+pub async fn with_retry_after<B>(request: Request<B>, next: Next<B>) -> Response {
+    let breaker = request.extensions().get::<Arc<CircuitBreaker>>().cloned();
+    let mut response = next.run(request).await;
+    if response.status() == StatusCode::SERVICE_UNAVAILABLE {
+        if let Some(seconds) = breaker.and_then(|breaker| breaker.retry_after_secs()) {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+        }
+    }
+    response
+}
+
+/// Header naming who makes a request, e.g. a support agent, recorded in the order history.
+/// Requests without it are made on behalf of the user; only authenticated internal callers can
+/// send it.
+const ACTOR_HEADER: &str = "x-actor";
+
+/// Middleware running the rest of the request on behalf of its actor. Requests naming an actor
+/// without the token of internal callers are rejected with `401`.
+pub async fn with_actor<B>(
+    Extension(internal): Extension<InternalToken>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let actor = request
+        .headers()
+        .get(ACTOR_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let actor = match actor {
+        None => USER_ID.to_string(),
+        Some(actor) if internal.authenticates(authorization(&request)) => actor,
+        Some(_) => return StatusCode::UNAUTHORIZED.into_response(),
+    };
+    as_actor(actor, next.run(request)).await
+}
+
+/// Middleware letting only internal callers through, for the routes managing the catalog, stock,
+/// coupons and webhooks of the shop. Other requests are rejected with `401`.
+pub async fn require_internal_caller<B>(
+    Extension(internal): Extension<InternalToken>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if !internal.authenticates(authorization(&request)) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    next.run(request).await
+}
+
+/// Value of the `Authorization` header of `request`.
+fn authorization<B>(request: &Request<B>) -> Option<&str> {
+    request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+}
 
 #[axum_macros::debug_handler] // adding this debugger just to exemplify debugging
 pub async fn create(Extension(state): Extension<State>) -> (StatusCode, Json<Option<Order>>) {
     debug!("Creating a new order");
-    if let Ok(order) = state.create_order(USER_ID).await {
-        // ommiting '.0' since we 'Deref' trait has been
-        // implementd for OrderStore
-        (StatusCode::OK, Json(Some(Order::from(order))))
-    } else {
-        (StatusCode::FORBIDDEN, Json(None))
+    // ommiting '.0' since we 'Deref' trait has been
+    // implementd for OrderStore
+    match state.create_order(USER_ID).await {
+        Ok(order) => (StatusCode::OK, Json(Some(Order::from(order)))),
+        Err(err) => (status_code(&err), Json(None)),
     }
 }
 
-pub async fn list() -> (StatusCode, Json<Option<Vec<Order>>>) {
+pub async fn list(Extension(state): Extension<State>) -> (StatusCode, Json<Option<Vec<Order>>>) {
     debug!("Listing all orders");
-    (StatusCode::OK, Json(None))
+    match state.list_orders(USER_ID).await {
+        Ok(orders) => (
+            StatusCode::OK,
+            Json(Some(orders.into_iter().map(Order::from).collect())),
+        ),
+        Err(err) => (status_code(&err), Json(None)),
+    }
 }
 
-pub async fn get(Path(id): Path<Uuid>) -> (StatusCode, Json<Option<Order>>) {
+pub async fn get(
+    Extension(state): Extension<State>,
+    Path(id): Path<Uuid>,
+) -> (StatusCode, Json<Option<Order>>) {
     debug!("Retrieving order with id: {id}");
-    (StatusCode::OK, Json(None))
+    match state.get_order(id).await {
+        Ok(order) => (StatusCode::OK, Json(Some(Order::from(order)))),
+        Err(err) => (status_code(&err), Json(None)),
+    }
 }
 
-pub async fn add_item(Path(id): Path<Uuid>, Json(request): Json<AddItem>) -> StatusCode {
+pub async fn add_item(
+    Extension(state): Extension<State>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<AddItem>,
+) -> StatusCode {
     debug!(
         "Adding item to order with id: {}, product Id: {} and quantity: {}",
         id, request.product_id, request.quantity
     );
-    StatusCode::OK
+    match state
+        .add_item(id, request.product_id, request.quantity)
+        .await
+    {
+        Ok(()) => StatusCode::OK,
+        Err(err) => {
+            debug!("Item rejected: {err}");
+            status_code(&err)
+        }
+    }
 }
 
-pub async fn delete_item(Path((id, index)): Path<(Uuid, usize)>) -> StatusCode {
+pub async fn delete_item(
+    Extension(state): Extension<State>,
+    Path((id, index)): Path<(Uuid, usize)>,
+) -> StatusCode {
     debug!("Deleting item from order with id: {id}, index: {index}");
-    StatusCode::OK
+    match state.delete_item(id, index, None).await {
+        Ok(()) => StatusCode::OK,
+        Err(err) => status_code(&err),
+    }
+}
+
+pub async fn update_quantity(
+    Extension(state): Extension<State>,
+    Path((id, index)): Path<(Uuid, usize)>,
+    Json(request): Json<UpdateQuantity>,
+) -> StatusCode {
+    debug!(
+        "Updating item of order with id: {id}, index: {index} to quantity: {}",
+        request.quantity
+    );
+    match state
+        .update_item_quantity(id, index, request.quantity, None)
+        .await
+    {
+        Ok(()) => StatusCode::OK,
+        Err(err) => status_code(&err),
+    }
+}
+
+pub async fn place(Extension(state): Extension<State>, Path(id): Path<Uuid>) -> StatusCode {
+    debug!("Placing order with id: {id}");
+    match state.update_status(id, OrderStatus::Placed).await {
+        Ok(()) => StatusCode::OK,
+        Err(err) => status_code(&err),
+    }
+}
+
+pub async fn cancel(Extension(state): Extension<State>, Path(id): Path<Uuid>) -> StatusCode {
+    debug!("Cancelling order with id: {id}");
+    match state.update_status(id, OrderStatus::Cancelled).await {
+        Ok(()) => StatusCode::OK,
+        Err(err) => status_code(&err),
+    }
+}
+
+pub async fn ship(Extension(state): Extension<State>, Path(id): Path<Uuid>) -> StatusCode {
+    debug!("Shipping order with id: {id}");
+    match state.update_status(id, OrderStatus::Shipped).await {
+        Ok(()) => StatusCode::OK,
+        Err(err) => status_code(&err),
+    }
+}
+
+pub async fn deliver(Extension(state): Extension<State>, Path(id): Path<Uuid>) -> StatusCode {
+    debug!("Delivering order with id: {id}");
+    match state.update_status(id, OrderStatus::Delivered).await {
+        Ok(()) => StatusCode::OK,
+        Err(err) => status_code(&err),
+    }
+}
+
+pub async fn apply_coupon(
+    Extension(state): Extension<State>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<ApplyCoupon>,
+) -> StatusCode {
+    debug!("Applying coupon {} to order with id: {}", request.code, id);
+    match state.apply_coupon(id, &request.code).await {
+        Ok(()) => StatusCode::OK,
+        Err(err) => {
+            debug!("Coupon rejected: {err}");
+            status_code(&err)
+        }
+    }
+}
+
+pub async fn remove_coupon(
+    Extension(state): Extension<State>,
+    Path((id, code)): Path<(Uuid, String)>,
+) -> StatusCode {
+    debug!("Removing coupon {code} from order with id: {id}");
+    match state.remove_coupon(id, &code).await {
+        Ok(()) => StatusCode::OK,
+        Err(err) => status_code(&err),
+    }
+}
+
+pub async fn set_shipping(
+    Extension(state): Extension<State>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<SetShipping>,
+) -> StatusCode {
+    debug!("Setting shipping of order with id: {id}");
+    match state
+        .set_shipping(id, request.address.into(), request.method)
+        .await
+    {
+        Ok(()) => StatusCode::OK,
+        Err(err) => {
+            debug!("Shipping rejected: {err}");
+            status_code(&err)
+        }
+    }
+}
+
+pub async fn set_billing_address(
+    Extension(state): Extension<State>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<Address>,
+) -> StatusCode {
+    debug!("Setting billing address of order with id: {id}");
+    match state.set_billing_address(id, request.into()).await {
+        Ok(()) => StatusCode::OK,
+        Err(err) => {
+            debug!("Billing address rejected: {err}");
+            status_code(&err)
+        }
+    }
+}
+
+/// Pays the order. Responds with the payment attempt, `402` when it was declined.
+pub async fn checkout(
+    Extension(state): Extension<State>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<Checkout>,
+) -> (StatusCode, Json<Option<Payment>>) {
+    debug!("Checking out order with id: {id}");
+    match state.checkout(id, &request.payment_token).await {
+        Ok(attempt) => {
+            let status = match attempt.outcome {
+                PaymentOutcome::Approved { .. } => StatusCode::OK,
+                _ => StatusCode::PAYMENT_REQUIRED,
+            };
+            (status, Json(Some(Payment::from(attempt))))
+        }
+        Err(err) => {
+            debug!("Checkout rejected: {err}");
+            (status_code(&err), Json(None))
+        }
+    }
+}
+
+pub async fn request_return(
+    Extension(state): Extension<State>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<RequestReturn>,
+) -> (StatusCode, Json<Option<Return>>) {
+    debug!("Requesting return for order with id: {id}");
+    let items = request.items.into_iter().map(Into::into).collect();
+    match state.request_return(id, items, request.reason).await {
+        Ok(request) => (StatusCode::OK, Json(Some(Return::from(request)))),
+        Err(err) => {
+            debug!("Return rejected: {err}");
+            (status_code(&err), Json(None))
+        }
+    }
+}
+
+pub async fn approve_return(
+    Extension(state): Extension<State>,
+    Path((id, return_id)): Path<(Uuid, Uuid)>,
+) -> (StatusCode, Json<Option<Return>>) {
+    debug!("Approving return {return_id} of order with id: {id}");
+    match state.approve_return(id, return_id).await {
+        Ok(request) => (StatusCode::OK, Json(Some(Return::from(request)))),
+        Err(err) => (status_code(&err), Json(None)),
+    }
+}
+
+pub async fn reject_return(
+    Extension(state): Extension<State>,
+    Path((id, return_id)): Path<(Uuid, Uuid)>,
+) -> (StatusCode, Json<Option<Return>>) {
+    debug!("Rejecting return {return_id} of order with id: {id}");
+    match state.reject_return(id, return_id).await {
+        Ok(request) => (StatusCode::OK, Json(Some(Return::from(request)))),
+        Err(err) => (status_code(&err), Json(None)),
+    }
+}
+
+pub async fn history(
+    Extension(state): Extension<State>,
+    Path(id): Path<Uuid>,
+) -> (StatusCode, Json<Option<Vec<HistoryEntry>>>) {
+    debug!("Retrieving history of order with id: {id}");
+    match state.history(id).await {
+        Ok(entries) => (
+            StatusCode::OK,
+            Json(Some(entries.into_iter().map(HistoryEntry::from).collect())),
+        ),
+        Err(err) => (status_code(&err), Json(None)),
+    }
+}
+
+/// Server-sent event carrying the new state of an order.
+fn order_event(order: order_store::Order) -> Result<Event, serde_json::Error> {
+    Event::default()
+        .event("order")
+        .json_data(Order::from(order))
+}
+
+/// Streams the orders of the user as they change.
+pub async fn user_events(
+    Extension(updates): Extension<Updates>,
+) -> Result<Sse<impl Stream<Item = Result<Event, serde_json::Error>>>, StatusCode> {
+    debug!("Streaming the changes of the orders of the user");
+    let orders = updates
+        .subscribe(OrderFilter::User(USER_ID))
+        .await
+        .map_err(|err| status_code(&err))?;
+    Ok(Sse::new(orders.map(order_event)).keep_alive(KeepAlive::default()))
+}
+
+/// Streams an order as it changes, starting with its current state.
+pub async fn events(
+    Extension(state): Extension<State>,
+    Extension(updates): Extension<Updates>,
+    Path(id): Path<Uuid>,
+) -> Result<Sse<impl Stream<Item = Result<Event, serde_json::Error>>>, StatusCode> {
+    debug!("Streaming the changes of order with id: {id}");
+    // subscribing first so no change is missed between the read and the subscription
+    let changes = updates
+        .subscribe(OrderFilter::Order(id))
+        .await
+        .map_err(|err| status_code(&err))?;
+    let current = state.get_order(id).await.map_err(|err| status_code(&err))?;
+    let orders = futures::stream::once(futures::future::ready(current)).chain(changes);
+    Ok(Sse::new(orders.map(order_event)).keep_alive(KeepAlive::default()))
 }
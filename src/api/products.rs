@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use axum::{extract::Path, http::StatusCode, Extension, Json};
+use tracing::{debug, error};
+use uuid::Uuid;
+
+use crate::product_store::{ProductStoreError, ProductStoreNewType};
+
+use super::{request::SaveProduct, response::Product};
+
+type State = Arc<ProductStoreNewType>;
+
+fn status_code(err: &ProductStoreError) -> StatusCode {
+    match err {
+        ProductStoreError::StoreUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+        ProductStoreError::ProductNotFound(_) => StatusCode::NOT_FOUND,
+        ProductStoreError::DuplicateSku(_) => StatusCode::CONFLICT,
+        ProductStoreError::InvalidPrice(_) => StatusCode::UNPROCESSABLE_ENTITY,
+    }
+}
+
+pub async fn create(
+    Extension(state): Extension<State>,
+    Json(request): Json<SaveProduct>,
+) -> (StatusCode, Json<Option<Product>>) {
+    debug!("Creating product with SKU: {}", request.sku);
+    match state.create_product(request.into()).await {
+        Ok(product) => (StatusCode::CREATED, Json(Some(Product::from(product)))),
+        Err(err) => {
+            error!("Failed to create product: {err}");
+            (status_code(&err), Json(None))
+        }
+    }
+}
+
+pub async fn list(Extension(state): Extension<State>) -> (StatusCode, Json<Option<Vec<Product>>>) {
+    debug!("Listing all products");
+    match state.list_products().await {
+        Ok(products) => (
+            StatusCode::OK,
+            Json(Some(products.into_iter().map(Product::from).collect())),
+        ),
+        Err(err) => (status_code(&err), Json(None)),
+    }
+}
+
+pub async fn get(
+    Extension(state): Extension<State>,
+    Path(id): Path<Uuid>,
+) -> (StatusCode, Json<Option<Product>>) {
+    debug!("Retrieving product with id: {id}");
+    match state.get_product(id).await {
+        Ok(product) => (StatusCode::OK, Json(Some(Product::from(product)))),
+        Err(err) => (status_code(&err), Json(None)),
+    }
+}
+
+pub async fn update(
+    Extension(state): Extension<State>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<SaveProduct>,
+) -> (StatusCode, Json<Option<Product>>) {
+    debug!("Updating product with id: {id}");
+    match state.update_product(id, request.into()).await {
+        Ok(product) => (StatusCode::OK, Json(Some(Product::from(product)))),
+        Err(err) => (status_code(&err), Json(None)),
+    }
+}
+
+pub async fn delete(Extension(state): Extension<State>, Path(id): Path<Uuid>) -> StatusCode {
+    debug!("Deleting product with id: {id}");
+    match state.delete_product(id).await {
+        Ok(()) => StatusCode::NO_CONTENT,
+        Err(err) => status_code(&err),
+    }
+}
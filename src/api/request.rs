@@ -1,8 +1,227 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
 use serde::Deserialize;
 use uuid::Uuid;
 
+use crate::{fulfilment, product_store, promotions, returns};
+
 #[derive(Deserialize)]
 pub struct AddItem {
     pub product_id: Uuid,
     pub quantity: i32,
 }
+
+#[derive(Deserialize)]
+pub struct UpdateQuantity {
+    pub quantity: i32,
+}
+
+/// Command sent over the cart WebSocket of an order. Commands on an existing item carry the
+/// product the client expects at `index`, so a command aimed at an item another client removed
+/// or moved in the meantime is rejected instead of changing the wrong item.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CartCommand {
+    AddItem {
+        product_id: Uuid,
+        quantity: i32,
+    },
+    RemoveItem {
+        index: usize,
+        product_id: Uuid,
+    },
+    UpdateQuantity {
+        index: usize,
+        product_id: Uuid,
+        quantity: i32,
+    },
+}
+
+fn active_by_default() -> bool {
+    true
+}
+
+fn default_currency() -> String {
+    "USD".to_string()
+}
+
+fn default_tax_category() -> String {
+    "standard".to_string()
+}
+
+#[derive(Deserialize)]
+pub struct SaveProduct {
+    pub name: String,
+    pub sku: String,
+    pub price: Decimal,
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    #[serde(default = "default_tax_category")]
+    pub tax_category: String,
+    #[serde(default = "active_by_default")]
+    pub active: bool,
+}
+
+impl From<SaveProduct> for product_store::ProductDetails {
+    fn from(request: SaveProduct) -> Self {
+        product_store::ProductDetails {
+            name: request.name,
+            sku: request.sku,
+            price: request.price,
+            currency: request.currency,
+            tax_category: request.tax_category,
+            active: request.active,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Address {
+    pub name: String,
+    pub line1: String,
+    pub line2: Option<String>,
+    pub city: String,
+    pub region: Option<String>,
+    pub postal_code: String,
+    pub country: String,
+}
+
+impl From<Address> for fulfilment::Address {
+    fn from(request: Address) -> Self {
+        fulfilment::Address {
+            name: request.name,
+            line1: request.line1,
+            line2: request.line2,
+            city: request.city,
+            region: request.region,
+            postal_code: request.postal_code,
+            country: request.country,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SetShipping {
+    pub address: Address,
+    pub method: fulfilment::ShippingMethod,
+}
+
+#[derive(Deserialize)]
+pub struct Checkout {
+    pub payment_token: String,
+}
+
+#[derive(Deserialize)]
+pub struct ReturnItem {
+    pub index: usize,
+    pub quantity: i32,
+}
+
+impl From<ReturnItem> for returns::ReturnItem {
+    fn from(request: ReturnItem) -> Self {
+        returns::ReturnItem {
+            index: request.index,
+            quantity: request.quantity,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RequestReturn {
+    pub items: Vec<ReturnItem>,
+    pub reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct SetStock {
+    pub available: i32,
+}
+
+#[derive(Deserialize)]
+pub struct ApplyCoupon {
+    pub code: String,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PromotionRule {
+    Percentage {
+        percent: Decimal,
+    },
+    FixedAmount {
+        amount: Decimal,
+        #[serde(default = "default_currency")]
+        currency: String,
+    },
+    BuyXGetY {
+        product_id: Uuid,
+        buy: i32,
+        get: i32,
+    },
+    MinimumSpend {
+        minimum: Decimal,
+        amount: Decimal,
+        #[serde(default = "default_currency")]
+        currency: String,
+    },
+}
+
+#[derive(Deserialize)]
+pub struct CreateCoupon {
+    pub code: String,
+    pub rule: PromotionRule,
+    pub valid_from: Option<DateTime<Utc>>,
+    pub valid_until: Option<DateTime<Utc>>,
+    pub max_uses_per_user: Option<u32>,
+}
+
+impl From<PromotionRule> for promotions::PromotionRule {
+    fn from(rule: PromotionRule) -> Self {
+        match rule {
+            PromotionRule::Percentage { percent } => {
+                promotions::PromotionRule::Percentage { percent }
+            }
+            PromotionRule::FixedAmount { amount, currency } => {
+                promotions::PromotionRule::FixedAmount { amount, currency }
+            }
+            PromotionRule::BuyXGetY {
+                product_id,
+                buy,
+                get,
+            } => promotions::PromotionRule::BuyXGetY {
+                product_id,
+                buy,
+                get,
+            },
+            PromotionRule::MinimumSpend {
+                minimum,
+                amount,
+                currency,
+            } => promotions::PromotionRule::MinimumSpend {
+                minimum,
+                amount,
+                currency,
+            },
+        }
+    }
+}
+
+impl From<CreateCoupon> for promotions::Coupon {
+    fn from(request: CreateCoupon) -> Self {
+        promotions::Coupon {
+            code: request.code,
+            rule: request.rule.into(),
+            valid_from: request.valid_from,
+            valid_until: request.valid_until,
+            max_uses_per_user: request.max_uses_per_user,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateWebhook {
+    pub url: String,
+    pub event_types: Vec<String>,
+    /// Key to sign the deliveries with, a random one is generated when missing.
+    pub secret: Option<String>,
+}
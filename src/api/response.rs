@@ -1,12 +1,23 @@
+use chrono::{DateTime, Utc};
+use mongodb::bson::{spec::BinarySubtype, Bson};
+use rust_decimal::Decimal;
 use serde::Serialize;
+use serde_json::Value;
 use uuid::Uuid;
 
-use crate::order_store;
+use crate::{
+    audit_log, fulfilment, inventory_store, order_store, payment_provider, product_store,
+    promotions, returns, tax_calculator, webhook_store,
+};
 
 #[derive(Serialize)]
 pub struct Item {
     pub product_id: Uuid,
     pub quantity: i32,
+    pub unit_price: Decimal,
+    pub currency: String,
+    pub tax_category: String,
+    pub line_total: Decimal,
 }
 
 #[derive(Serialize)]
@@ -14,6 +25,173 @@ pub struct Order {
     pub id: Uuid,
     pub user_id: Uuid,
     pub items: Vec<Item>,
+    pub status: order_store::OrderStatus,
+    pub currency: Option<String>,
+    pub coupons: Vec<String>,
+    pub subtotal: Decimal,
+    pub discount: Decimal,
+    pub tax: Tax,
+    pub shipping_address: Option<Address>,
+    pub shipping_method: Option<fulfilment::ShippingMethod>,
+    pub shipping_cost: Decimal,
+    pub billing_address: Option<Address>,
+    pub payments: Vec<Payment>,
+    pub returns: Vec<Return>,
+    pub refunded: Decimal,
+    pub total: Decimal,
+}
+
+#[derive(Serialize)]
+pub struct ReturnLine {
+    pub item_index: usize,
+    pub product_id: Uuid,
+    pub quantity: i32,
+    pub refund_amount: Decimal,
+}
+
+#[derive(Serialize)]
+pub struct Return {
+    pub id: Uuid,
+    pub lines: Vec<ReturnLine>,
+    pub reason: String,
+    pub status: returns::ReturnStatus,
+    pub requested_at: DateTime<Utc>,
+    pub decided_at: Option<DateTime<Utc>>,
+    pub refund_amount: Decimal,
+    pub refund_reference: Option<String>,
+}
+
+impl From<returns::ReturnLine> for ReturnLine {
+    fn from(line: returns::ReturnLine) -> Self {
+        ReturnLine {
+            item_index: line.item_index,
+            product_id: line.product_id,
+            quantity: line.quantity,
+            refund_amount: line.refund_amount,
+        }
+    }
+}
+
+impl From<returns::ReturnRequest> for Return {
+    fn from(request: returns::ReturnRequest) -> Self {
+        Return {
+            id: request.id,
+            lines: request.lines.into_iter().map(ReturnLine::from).collect(),
+            reason: request.reason,
+            status: request.status,
+            requested_at: request.requested_at,
+            decided_at: request.decided_at,
+            refund_amount: request.refund_amount,
+            refund_reference: request.refund_reference,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentStatus {
+    Approved,
+    Declined,
+    Failed,
+    Pending,
+}
+
+#[derive(Serialize)]
+pub struct Payment {
+    pub id: Uuid,
+    pub amount: Decimal,
+    pub currency: String,
+    pub attempted_at: DateTime<Utc>,
+    pub status: PaymentStatus,
+    pub reference: Option<String>,
+    pub reason: Option<String>,
+}
+
+impl From<payment_provider::PaymentAttempt> for Payment {
+    fn from(attempt: payment_provider::PaymentAttempt) -> Self {
+        let (status, reference, reason) = match attempt.outcome {
+            payment_provider::PaymentOutcome::Approved { reference } => {
+                (PaymentStatus::Approved, Some(reference), None)
+            }
+            payment_provider::PaymentOutcome::Declined { reason } => {
+                (PaymentStatus::Declined, None, Some(reason))
+            }
+            payment_provider::PaymentOutcome::Failed => (PaymentStatus::Failed, None, None),
+            payment_provider::PaymentOutcome::Pending => (PaymentStatus::Pending, None, None),
+        };
+        Payment {
+            id: attempt.id,
+            amount: attempt.amount,
+            currency: attempt.currency,
+            attempted_at: attempt.attempted_at,
+            status,
+            reference,
+            reason,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Address {
+    pub name: String,
+    pub line1: String,
+    pub line2: Option<String>,
+    pub city: String,
+    pub region: Option<String>,
+    pub postal_code: String,
+    pub country: String,
+}
+
+impl From<fulfilment::Address> for Address {
+    fn from(address: fulfilment::Address) -> Self {
+        Address {
+            name: address.name,
+            line1: address.line1,
+            line2: address.line2,
+            city: address.city,
+            region: address.region,
+            postal_code: address.postal_code,
+            country: address.country,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct LineTax {
+    pub product_id: Uuid,
+    pub category: String,
+    pub rate: Decimal,
+    pub amount: Decimal,
+}
+
+#[derive(Serialize)]
+pub struct Tax {
+    pub region: Option<String>,
+    pub inclusive: bool,
+    pub lines: Vec<LineTax>,
+    pub total: Decimal,
+}
+
+impl From<tax_calculator::LineTax> for LineTax {
+    fn from(line: tax_calculator::LineTax) -> Self {
+        LineTax {
+            product_id: line.product_id,
+            category: line.category,
+            rate: line.rate,
+            amount: line.amount,
+        }
+    }
+}
+
+impl From<tax_calculator::TaxBreakdown> for Tax {
+    fn from(tax: tax_calculator::TaxBreakdown) -> Self {
+        Tax {
+            region: tax.region,
+            inclusive: tax.inclusive,
+            lines: tax.lines.into_iter().map(LineTax::from).collect(),
+            total: tax.total,
+        }
+    }
 }
 
 impl From<order_store::Item> for Item {
@@ -21,6 +199,10 @@ impl From<order_store::Item> for Item {
         Item {
             product_id: item.product_id,
             quantity: item.quantity,
+            unit_price: item.unit_price,
+            currency: item.currency,
+            tax_category: item.tax_category,
+            line_total: item.line_total,
         }
     }
 }
@@ -31,6 +213,293 @@ impl From<order_store::Order> for Order {
             id: order.id,
             user_id: order.user_id,
             items: order.items.iter().map(|i| Item::from(i.clone())).collect(),
+            status: order.status,
+            currency: order.currency,
+            coupons: order.coupons.into_iter().map(|c| c.code).collect(),
+            subtotal: order.subtotal,
+            discount: order.discount,
+            tax: Tax::from(order.tax),
+            shipping_address: order.shipping_address.map(Address::from),
+            shipping_method: order.shipping_method,
+            shipping_cost: order.shipping_cost,
+            billing_address: order.billing_address.map(Address::from),
+            payments: order.payments.into_iter().map(Payment::from).collect(),
+            returns: order.returns.into_iter().map(Return::from).collect(),
+            refunded: order.refunded,
+            total: order.total,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Product {
+    pub id: Uuid,
+    pub name: String,
+    pub sku: String,
+    pub price: Decimal,
+    pub currency: String,
+    pub tax_category: String,
+    pub active: bool,
+}
+
+impl From<product_store::Product> for Product {
+    fn from(product: product_store::Product) -> Self {
+        Product {
+            id: product.id,
+            name: product.name,
+            sku: product.sku,
+            price: product.price,
+            currency: product.currency,
+            tax_category: product.tax_category,
+            active: product.active,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct StockLevel {
+    pub product_id: Uuid,
+    pub available: i32,
+    pub reserved: i32,
+    pub committed: i32,
+}
+
+impl From<inventory_store::StockLevel> for StockLevel {
+    fn from(level: inventory_store::StockLevel) -> Self {
+        StockLevel {
+            product_id: level.product_id,
+            available: level.available,
+            reserved: level.reserved,
+            committed: level.committed,
+        }
+    }
+}
+
+/// Message sent over the cart WebSocket of an order.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CartMessage {
+    /// The order as it is now, sent when the client connects and after every change of any client.
+    Order { order: Box<Order> },
+    /// A command of the client that was not applied, with the status the same change gets over HTTP,
+    /// e.g. `409` when it conflicts with a change of another client, and for `503` while the
+    /// circuit breaker of the order store is open the seconds to wait before trying again.
+    Rejected {
+        command: serde_json::Value,
+        status: u16,
+        error: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        retry_after: Option<u64>,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PromotionRule {
+    Percentage {
+        percent: Decimal,
+    },
+    FixedAmount {
+        amount: Decimal,
+        currency: String,
+    },
+    BuyXGetY {
+        product_id: Uuid,
+        buy: i32,
+        get: i32,
+    },
+    MinimumSpend {
+        minimum: Decimal,
+        amount: Decimal,
+        currency: String,
+    },
+}
+
+#[derive(Serialize)]
+pub struct Coupon {
+    pub code: String,
+    pub rule: PromotionRule,
+    pub valid_from: Option<DateTime<Utc>>,
+    pub valid_until: Option<DateTime<Utc>>,
+    pub max_uses_per_user: Option<u32>,
+}
+
+impl From<promotions::PromotionRule> for PromotionRule {
+    fn from(rule: promotions::PromotionRule) -> Self {
+        match rule {
+            promotions::PromotionRule::Percentage { percent } => {
+                PromotionRule::Percentage { percent }
+            }
+            promotions::PromotionRule::FixedAmount { amount, currency } => {
+                PromotionRule::FixedAmount { amount, currency }
+            }
+            promotions::PromotionRule::BuyXGetY {
+                product_id,
+                buy,
+                get,
+            } => PromotionRule::BuyXGetY {
+                product_id,
+                buy,
+                get,
+            },
+            promotions::PromotionRule::MinimumSpend {
+                minimum,
+                amount,
+                currency,
+            } => PromotionRule::MinimumSpend {
+                minimum,
+                amount,
+                currency,
+            },
+        }
+    }
+}
+
+impl From<promotions::Coupon> for Coupon {
+    fn from(coupon: promotions::Coupon) -> Self {
+        Coupon {
+            code: coupon.code,
+            rule: coupon.rule.into(),
+            valid_from: coupon.valid_from,
+            valid_until: coupon.valid_until,
+            max_uses_per_user: coupon.max_uses_per_user,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+#[derive(Serialize)]
+pub struct HistoryEntry {
+    pub actor: String,
+    pub timestamp: DateTime<Utc>,
+    pub operation: String,
+    pub changes: Vec<FieldChange>,
+}
+
+/// Renders a stored order field the way the order responses show it: UUIDs and decimals as
+/// strings instead of their extended JSON wrappers.
+pub(crate) fn readable(value: Bson) -> Value {
+    match value {
+        Bson::Binary(binary) if binary.subtype == BinarySubtype::Generic => {
+            match Uuid::from_slice(&binary.bytes) {
+                Ok(id) => Value::String(id.to_string()),
+                Err(_) => Bson::Binary(binary).into_relaxed_extjson(),
+            }
+        }
+        Bson::Decimal128(decimal) => Value::String(decimal.to_string()),
+        Bson::Array(values) => Value::Array(values.into_iter().map(readable).collect()),
+        Bson::Document(document) => Value::Object(
+            document
+                .into_iter()
+                .map(|(key, value)| (key, readable(value)))
+                .collect(),
+        ),
+        other => other.into_relaxed_extjson(),
+    }
+}
+
+impl From<audit_log::FieldChange> for FieldChange {
+    fn from(change: audit_log::FieldChange) -> Self {
+        FieldChange {
+            field: change.field,
+            before: change.before.map(readable).unwrap_or(Value::Null),
+            after: change.after.map(readable).unwrap_or(Value::Null),
+        }
+    }
+}
+
+impl From<audit_log::AuditEntry> for HistoryEntry {
+    fn from(entry: audit_log::AuditEntry) -> Self {
+        HistoryEntry {
+            actor: entry.actor,
+            timestamp: entry.timestamp,
+            operation: entry.operation,
+            changes: entry.changes.into_iter().map(FieldChange::from).collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub url: String,
+    pub event_types: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<webhook_store::Subscription> for Webhook {
+    fn from(subscription: webhook_store::Subscription) -> Self {
+        Webhook {
+            id: subscription.id,
+            url: subscription.url,
+            event_types: subscription.event_types,
+            created_at: subscription.created_at,
+        }
+    }
+}
+
+/// A webhook as returned once, right after it was created, with the secret its deliveries are
+/// signed with.
+#[derive(Serialize)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+    pub secret: String,
+}
+
+impl From<webhook_store::Subscription> for CreatedWebhook {
+    fn from(subscription: webhook_store::Subscription) -> Self {
+        CreatedWebhook {
+            secret: subscription.secret.clone(),
+            webhook: Webhook::from(subscription),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct DeliveryAttempt {
+    pub attempted_at: DateTime<Utc>,
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub event_id: Uuid,
+    pub event_type: String,
+    pub status: webhook_store::DeliveryStatus,
+    pub attempts: Vec<DeliveryAttempt>,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+}
+
+impl From<webhook_store::Delivery> for WebhookDelivery {
+    fn from(delivery: webhook_store::Delivery) -> Self {
+        let next_attempt_at = match delivery.status {
+            webhook_store::DeliveryStatus::Pending => Some(delivery.next_attempt_at),
+            _ => None,
+        };
+        WebhookDelivery {
+            id: delivery.id,
+            event_id: delivery.event_id,
+            event_type: delivery.event_type,
+            status: delivery.status,
+            attempts: delivery
+                .attempts
+                .into_iter()
+                .map(|attempt| DeliveryAttempt {
+                    attempted_at: attempt.attempted_at,
+                    status_code: attempt.status_code,
+                    error: attempt.error,
+                })
+                .collect(),
+            next_attempt_at,
         }
     }
 }
@@ -0,0 +1,86 @@
+use std::sync::Arc;
+
+use axum::{extract::Path, http::StatusCode, Extension, Json};
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::webhook_store::{Subscription, WebhookStoreError, WebhookStoreNewType};
+
+use super::{
+    request::CreateWebhook,
+    response::{CreatedWebhook, Webhook, WebhookDelivery},
+};
+
+type State = Arc<WebhookStoreNewType>;
+
+fn status_code(err: &WebhookStoreError) -> StatusCode {
+    match err {
+        WebhookStoreError::StoreUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+        WebhookStoreError::SubscriptionNotFound(_) => StatusCode::NOT_FOUND,
+        WebhookStoreError::InvalidSubscription(_) => StatusCode::UNPROCESSABLE_ENTITY,
+    }
+}
+
+pub async fn create(
+    Extension(state): Extension<State>,
+    Json(request): Json<CreateWebhook>,
+) -> (StatusCode, Json<Option<CreatedWebhook>>) {
+    debug!("Creating webhook for url: {}", request.url);
+    let subscription = match Subscription::new(request.url, request.event_types, request.secret) {
+        Ok(subscription) => subscription,
+        Err(err) => return (status_code(&err), Json(None)),
+    };
+    match state.create_subscription(subscription).await {
+        Ok(subscription) => (
+            StatusCode::CREATED,
+            Json(Some(CreatedWebhook::from(subscription))),
+        ),
+        Err(err) => (status_code(&err), Json(None)),
+    }
+}
+
+pub async fn list(Extension(state): Extension<State>) -> (StatusCode, Json<Option<Vec<Webhook>>>) {
+    debug!("Listing all webhooks");
+    match state.list_subscriptions().await {
+        Ok(subscriptions) => (
+            StatusCode::OK,
+            Json(Some(subscriptions.into_iter().map(Webhook::from).collect())),
+        ),
+        Err(err) => (status_code(&err), Json(None)),
+    }
+}
+
+pub async fn get(
+    Extension(state): Extension<State>,
+    Path(id): Path<Uuid>,
+) -> (StatusCode, Json<Option<Webhook>>) {
+    debug!("Retrieving webhook with id: {id}");
+    match state.get_subscription(id).await {
+        Ok(subscription) => (StatusCode::OK, Json(Some(Webhook::from(subscription)))),
+        Err(err) => (status_code(&err), Json(None)),
+    }
+}
+
+pub async fn delete(Extension(state): Extension<State>, Path(id): Path<Uuid>) -> StatusCode {
+    debug!("Deleting webhook with id: {id}");
+    match state.delete_subscription(id).await {
+        Ok(()) => StatusCode::NO_CONTENT,
+        Err(err) => status_code(&err),
+    }
+}
+
+pub async fn deliveries(
+    Extension(state): Extension<State>,
+    Path(id): Path<Uuid>,
+) -> (StatusCode, Json<Option<Vec<WebhookDelivery>>>) {
+    debug!("Retrieving deliveries of webhook with id: {id}");
+    match state.deliveries(id).await {
+        Ok(deliveries) => (
+            StatusCode::OK,
+            Json(Some(
+                deliveries.into_iter().map(WebhookDelivery::from).collect(),
+            )),
+        ),
+        Err(err) => (status_code(&err), Json(None)),
+    }
+}
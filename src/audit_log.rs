@@ -0,0 +1,123 @@
+use std::future::Future;
+
+use chrono::{DateTime, Utc};
+use mongodb::bson::{self, Bson, Document};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::order_store::Order;
+
+tokio::task_local! {
+    static ACTOR: String;
+}
+
+/// Actor recorded for changes made outside of [`as_actor`](as_actor).
+pub const SYSTEM_ACTOR: &str = "system";
+
+/// Runs `task` on behalf of `actor`, who gets recorded as the author of every order change the
+/// task makes.
+pub async fn as_actor<F: Future>(actor: String, task: F) -> F::Output {
+    ACTOR.scope(actor, task).await
+}
+
+/// Returns the actor the current task runs on behalf of.
+pub fn current_actor() -> String {
+    ACTOR
+        .try_with(Clone::clone)
+        .unwrap_or_else(|_| SYSTEM_ACTOR.to_string())
+}
+
+/// Value of one field of an order before and after a change, as stored in MongoDB.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub field: String,
+    /// `None` when the order didn't exist yet.
+    pub before: Option<Bson>,
+    pub after: Option<Bson>,
+}
+
+/// An append-only record of one change of an order.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: Uuid,
+    pub order_id: Uuid,
+    /// Who made the change, see [`as_actor`](as_actor).
+    pub actor: String,
+    pub timestamp: DateTime<Utc>,
+    /// Name of the [`OrderStore`](crate::order_store::OrderStore) method that made the change.
+    pub operation: String,
+    /// Fields of the order that changed, in the order they are declared.
+    pub changes: Vec<FieldChange>,
+}
+
+impl AuditEntry {
+    /// Records that `operation` turned `before` into `after`, by the current actor.
+    pub fn new(operation: &str, before: Option<&Order>, after: &Order) -> AuditEntry {
+        AuditEntry {
+            id: Uuid::new_v4(),
+            order_id: after.id,
+            actor: current_actor(),
+            timestamp: Utc::now(),
+            operation: operation.to_string(),
+            changes: diff(
+                &before.map(to_document).unwrap_or_default(),
+                &to_document(after),
+            ),
+        }
+    }
+}
+
+fn to_document(order: &Order) -> Document {
+    bson::to_document(order).expect("orders always serialize to a document")
+}
+
+/// Lists the top-level fields whose value differs between `before` and `after`.
+fn diff(before: &Document, after: &Document) -> Vec<FieldChange> {
+    let removed = before.keys().filter(|field| !after.contains_key(field));
+    after
+        .keys()
+        .chain(removed)
+        .filter(|field| before.get(field) != after.get(field))
+        .map(|field| FieldChange {
+            field: field.clone(),
+            before: before.get(field).cloned(),
+            after: after.get(field).cloned(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order_store::OrderStatus;
+
+    #[tokio::test]
+    async fn only_changed_fields_are_recorded_for_the_current_actor() {
+        let before = Order::new(Uuid::new_v4());
+        let mut after = before.clone();
+        after.set_status(OrderStatus::Cancelled).unwrap();
+
+        let entry = as_actor("support".to_string(), async {
+            AuditEntry::new("update_status", Some(&before), &after)
+        })
+        .await;
+        assert_eq!(entry.actor, "support");
+        assert_eq!(
+            entry.changes,
+            vec![FieldChange {
+                field: "status".to_string(),
+                before: Some(Bson::String("Draft".to_string())),
+                after: Some(Bson::String("Cancelled".to_string())),
+            }]
+        );
+    }
+
+    #[test]
+    fn created_order_records_every_field_outside_of_any_actor() {
+        let order = Order::new(Uuid::new_v4());
+        let entry = AuditEntry::new("create_order", None, &order);
+        assert_eq!(entry.actor, SYSTEM_ACTOR);
+        assert_eq!(entry.changes.len(), to_document(&order).len());
+        assert!(entry.changes.iter().all(|change| change.before.is_none()));
+    }
+}
@@ -0,0 +1,434 @@
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use lru::LruCache;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    audit_log::AuditEntry,
+    fulfilment::{Address, ShippingMethod},
+    order_store::{Order, OrderStatus, OrderStore, OrderStoreError},
+    payment_provider::PaymentAttempt,
+    returns::{ReturnItem, ReturnRequest},
+};
+
+/// Size and freshness of the cache of a [`CachedOrderStore`](CachedOrderStore).
+#[derive(Clone, Copy, Debug)]
+pub struct CacheConfig {
+    /// Orders, and separately lists of orders of a user, kept before evicting the least recently used.
+    pub capacity: NonZeroUsize,
+    /// How long a cached value is served, which bounds how stale changes made elsewhere can be.
+    pub ttl: Duration,
+}
+
+/// Lookups served by a [`CachedOrderStore`](CachedOrderStore), shared with whoever reports them.
+#[derive(Debug, Default)]
+pub struct CacheMetrics {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Counts of [`CacheMetrics`](CacheMetrics) at some point.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheMetrics {
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record(&self, hit: bool) {
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+struct Cached<T> {
+    value: T,
+    expires_at: Instant,
+}
+
+struct Entries {
+    orders: LruCache<Uuid, Cached<Order>>,
+    lists: LruCache<Uuid, Cached<Vec<Order>>>, // by user id
+    generation: u64, // bumped on every invalidation, lookups that overlap one are not cached
+}
+
+impl Entries {
+    fn invalidate_order(&mut self, order_id: Uuid) {
+        self.generation += 1;
+        if let Some(cached) = self.orders.pop(&order_id) {
+            self.lists.pop(&cached.value.user_id);
+        }
+        // the order may have been evicted while the list of its user is still cached
+        let stale: Vec<Uuid> = self
+            .lists
+            .iter()
+            .filter(|(_, cached)| cached.value.iter().any(|order| order.id == order_id))
+            .map(|(user_id, _)| *user_id)
+            .collect();
+        for user_id in stale {
+            self.lists.pop(&user_id);
+        }
+    }
+}
+
+/// Caches the orders read from another [`OrderStore`](OrderStore), invalidating them on every
+/// change made through it.
+///
+/// Changes made by other instances of the service are only seen once the cached values expire.
+pub struct CachedOrderStore<S: OrderStore> {
+    store: S,
+    ttl: Duration,
+    entries: Mutex<Entries>,
+    metrics: Arc<CacheMetrics>,
+}
+
+impl<S: OrderStore> CachedOrderStore<S> {
+    /// Creates a new cache in front of `store`, counting its hits and misses in `metrics`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let store = CachedOrderStore::new(store, config, Arc::new(CacheMetrics::default()));
+    /// ```
+    pub fn new(store: S, config: CacheConfig, metrics: Arc<CacheMetrics>) -> CachedOrderStore<S> {
+        CachedOrderStore {
+            store,
+            ttl: config.ttl,
+            entries: Mutex::new(Entries {
+                orders: LruCache::new(config.capacity),
+                lists: LruCache::new(config.capacity),
+                generation: 0,
+            }),
+            metrics,
+        }
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, Entries> {
+        // the entries are only swapped in and out, a panic cannot leave them half updated
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn expiry(&self) -> Instant {
+        Instant::now() + self.ttl
+    }
+
+    fn invalidate(&self, order_id: Uuid) {
+        self.entries().invalidate_order(order_id);
+    }
+}
+
+/// Looks `key` up in `cache`, dropping the value if it expired.
+fn lookup<T: Clone>(cache: &mut LruCache<Uuid, Cached<T>>, key: Uuid) -> Option<T> {
+    match cache.get(&key) {
+        Some(cached) if cached.expires_at > Instant::now() => Some(cached.value.clone()),
+        Some(_) => {
+            cache.pop(&key);
+            None
+        }
+        None => None,
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: OrderStore> OrderStore for CachedOrderStore<S> {
+    async fn create_order(&self, user_id: Uuid) -> Result<Order, OrderStoreError> {
+        let order = self.store.create_order(user_id).await?;
+        let mut entries = self.entries();
+        entries.generation += 1;
+        entries.lists.pop(&user_id);
+        Ok(order)
+    }
+
+    async fn get_order(&self, order_id: Uuid) -> Result<Order, OrderStoreError> {
+        let generation = {
+            let mut entries = self.entries();
+            let cached = lookup(&mut entries.orders, order_id);
+            self.metrics.record(cached.is_some());
+            if let Some(order) = cached {
+                return Ok(order);
+            }
+            entries.generation
+        };
+        let order = self.store.get_order(order_id).await?;
+        let mut entries = self.entries();
+        if entries.generation == generation {
+            let cached = Cached {
+                value: order.clone(),
+                expires_at: self.expiry(),
+            };
+            entries.orders.put(order_id, cached);
+        }
+        Ok(order)
+    }
+
+    async fn list_orders(&self, user_id: Uuid) -> Result<Vec<Order>, OrderStoreError> {
+        let generation = {
+            let mut entries = self.entries();
+            let cached = lookup(&mut entries.lists, user_id);
+            self.metrics.record(cached.is_some());
+            if let Some(orders) = cached {
+                return Ok(orders);
+            }
+            entries.generation
+        };
+        let orders = self.store.list_orders(user_id).await?;
+        let mut entries = self.entries();
+        if entries.generation == generation {
+            let cached = Cached {
+                value: orders.clone(),
+                expires_at: self.expiry(),
+            };
+            entries.lists.put(user_id, cached);
+        }
+        Ok(orders)
+    }
+
+    async fn add_item(
+        &self,
+        order_id: Uuid,
+        product_id: Uuid,
+        quantity: i32,
+    ) -> Result<(), OrderStoreError> {
+        let result = self.store.add_item(order_id, product_id, quantity).await;
+        self.invalidate(order_id);
+        result
+    }
+
+    async fn delete_item(
+        &self,
+        order_id: Uuid,
+        index: usize,
+        expected: Option<Uuid>,
+    ) -> Result<(), OrderStoreError> {
+        let result = self.store.delete_item(order_id, index, expected).await;
+        self.invalidate(order_id);
+        result
+    }
+
+    async fn update_item_quantity(
+        &self,
+        order_id: Uuid,
+        index: usize,
+        quantity: i32,
+        expected: Option<Uuid>,
+    ) -> Result<(), OrderStoreError> {
+        let result = self
+            .store
+            .update_item_quantity(order_id, index, quantity, expected)
+            .await;
+        self.invalidate(order_id);
+        result
+    }
+
+    async fn update_status(
+        &self,
+        order_id: Uuid,
+        status: OrderStatus,
+    ) -> Result<(), OrderStoreError> {
+        let result = self.store.update_status(order_id, status).await;
+        self.invalidate(order_id);
+        result
+    }
+
+    async fn apply_coupon(&self, order_id: Uuid, code: &str) -> Result<(), OrderStoreError> {
+        let result = self.store.apply_coupon(order_id, code).await;
+        self.invalidate(order_id);
+        result
+    }
+
+    async fn remove_coupon(&self, order_id: Uuid, code: &str) -> Result<(), OrderStoreError> {
+        let result = self.store.remove_coupon(order_id, code).await;
+        self.invalidate(order_id);
+        result
+    }
+
+    async fn set_shipping(
+        &self,
+        order_id: Uuid,
+        address: Address,
+        method: ShippingMethod,
+    ) -> Result<(), OrderStoreError> {
+        let result = self.store.set_shipping(order_id, address, method).await;
+        self.invalidate(order_id);
+        result
+    }
+
+    async fn set_billing_address(
+        &self,
+        order_id: Uuid,
+        address: Address,
+    ) -> Result<(), OrderStoreError> {
+        let result = self.store.set_billing_address(order_id, address).await;
+        self.invalidate(order_id);
+        result
+    }
+
+    async fn checkout(
+        &self,
+        order_id: Uuid,
+        payment_token: &str,
+    ) -> Result<PaymentAttempt, OrderStoreError> {
+        // declined payments are recorded on the order too
+        let result = self.store.checkout(order_id, payment_token).await;
+        self.invalidate(order_id);
+        result
+    }
+
+    async fn request_return(
+        &self,
+        order_id: Uuid,
+        items: Vec<ReturnItem>,
+        reason: String,
+    ) -> Result<ReturnRequest, OrderStoreError> {
+        let result = self.store.request_return(order_id, items, reason).await;
+        self.invalidate(order_id);
+        result
+    }
+
+    async fn approve_return(
+        &self,
+        order_id: Uuid,
+        return_id: Uuid,
+    ) -> Result<ReturnRequest, OrderStoreError> {
+        let result = self.store.approve_return(order_id, return_id).await;
+        self.invalidate(order_id);
+        result
+    }
+
+    async fn reject_return(
+        &self,
+        order_id: Uuid,
+        return_id: Uuid,
+    ) -> Result<ReturnRequest, OrderStoreError> {
+        let result = self.store.reject_return(order_id, return_id).await;
+        self.invalidate(order_id);
+        result
+    }
+
+    async fn history(&self, order_id: Uuid) -> Result<Vec<AuditEntry>, OrderStoreError> {
+        self.store.history(order_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        in_mem_order_store::InMemOrderStore, payment_provider::PaymentProvider,
+        tax_calculator::TaxCalculator,
+    };
+
+    fn new_store(
+        products: Arc<ProductStoreNewType>,
+        inventory: Arc<InventoryStoreNewType>,
+        promotions: Arc<PromotionStoreNewType>,
+        tax: Arc<dyn TaxCalculator>,
+        payments: Arc<dyn PaymentProvider>,
+    ) -> CachedOrderStore<InMemOrderStore> {
+        CachedOrderStore::new(
+            InMemOrderStore::new(products, inventory, promotions, tax, payments),
+            CacheConfig {
+                capacity: NonZeroUsize::new(2).unwrap(),
+                ttl: Duration::from_secs(60),
+            },
+            Arc::new(CacheMetrics::default()),
+        )
+    }
+
+    crate::order_store_tests::order_store_tests!(new_store);
+
+    fn cached_store(ttl: Duration) -> (CachedOrderStore<InMemOrderStore>, Arc<CacheMetrics>) {
+        let metrics = Arc::new(CacheMetrics::default());
+        let store = CachedOrderStore::new(
+            InMemOrderStore::new(
+                Arc::new(ProductStoreNewType::new(InMemProductStore::new())),
+                Arc::new(InventoryStoreNewType::new(InMemInventoryStore::new())),
+                Arc::new(PromotionStoreNewType::new(InMemPromotionStore::new())),
+                Arc::new(RulesTableTaxCalculator::tax_free()),
+                Arc::new(FakePaymentProvider::new()),
+            ),
+            CacheConfig {
+                capacity: NonZeroUsize::new(2).unwrap(),
+                ttl,
+            },
+            metrics.clone(),
+        );
+        (store, metrics)
+    }
+
+    #[tokio::test]
+    async fn repeated_lookups_are_served_from_the_cache() {
+        let (store, metrics) = cached_store(Duration::from_secs(60));
+        let user_id = Uuid::new_v4();
+        let order = store.create_order(user_id).await.unwrap();
+
+        for _ in 0..3 {
+            assert_eq!(store.get_order(order.id).await.unwrap(), order);
+            assert_eq!(store.list_orders(user_id).await.unwrap()[0], order);
+        }
+        assert_eq!(metrics.stats(), CacheStats { hits: 4, misses: 2 });
+    }
+
+    #[tokio::test]
+    async fn changes_invalidate_the_order_and_the_list_of_its_user() {
+        let (store, metrics) = cached_store(Duration::from_secs(60));
+        let user_id = Uuid::new_v4();
+        let order = store.create_order(user_id).await.unwrap();
+        store.get_order(order.id).await.unwrap();
+        store.list_orders(user_id).await.unwrap();
+
+        store
+            .update_status(order.id, OrderStatus::Cancelled)
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_order(order.id).await.unwrap().status,
+            OrderStatus::Cancelled
+        );
+        assert_eq!(
+            store.list_orders(user_id).await.unwrap()[0].status,
+            OrderStatus::Cancelled
+        );
+        store.create_order(user_id).await.unwrap();
+        assert_eq!(store.list_orders(user_id).await.unwrap().len(), 2);
+        assert_eq!(metrics.stats(), CacheStats { hits: 0, misses: 5 });
+    }
+
+    #[tokio::test]
+    async fn expired_and_evicted_orders_are_read_again() {
+        let (store, metrics) = cached_store(Duration::ZERO);
+        let order = store.create_order(Uuid::new_v4()).await.unwrap();
+        store.get_order(order.id).await.unwrap();
+        store.get_order(order.id).await.unwrap();
+        assert_eq!(metrics.stats(), CacheStats { hits: 0, misses: 2 });
+
+        let (store, metrics) = cached_store(Duration::from_secs(60));
+        let mut orders = Vec::new();
+        for _ in 0..3 {
+            let order = store.create_order(Uuid::new_v4()).await.unwrap();
+            store.get_order(order.id).await.unwrap();
+            orders.push(order);
+        }
+        // the capacity is 2, so the first order was evicted
+        store.get_order(orders[2].id).await.unwrap();
+        store.get_order(orders[0].id).await.unwrap();
+        assert_eq!(metrics.stats(), CacheStats { hits: 1, misses: 4 });
+    }
+}
@@ -0,0 +1,418 @@
+use std::{collections::HashMap, fs, path::Path, sync::Mutex, time::Duration};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Deserialize;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::{
+    audit_log::AuditEntry,
+    fulfilment::{Address, ShippingMethod},
+    order_store::{Order, OrderStatus, OrderStore, OrderStoreError},
+    payment_provider::PaymentAttempt,
+    returns::{ReturnItem, ReturnRequest},
+};
+
+/// Methods of [`OrderStore`](OrderStore) faults can be configured for.
+const METHODS: [&str; 16] = [
+    "create_order",
+    "get_order",
+    "list_orders",
+    "add_item",
+    "delete_item",
+    "update_item_quantity",
+    "update_status",
+    "apply_coupon",
+    "remove_coupon",
+    "set_shipping",
+    "set_billing_address",
+    "checkout",
+    "request_return",
+    "approve_return",
+    "reject_return",
+    "history",
+];
+
+/// Faults injected in the calls of a method, each with its own probability between 0 and 1.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Faults {
+    /// Probability of waiting `latency_ms` before making the call.
+    pub latency_rate: f64,
+    pub latency_ms: u64,
+    /// Probability of failing with [`StoreUnavailable`](OrderStoreError::StoreUnavailable)
+    /// instead of making the call.
+    pub error_rate: f64,
+    /// Probability of hanging for the `timeout_ms` of the configuration and then failing with
+    /// [`StoreUnavailable`](OrderStoreError::StoreUnavailable), as a call the driver gave up on.
+    pub timeout_rate: f64,
+}
+
+/// Faults a [`ChaosOrderStore`](ChaosOrderStore) injects, read from a JSON file like:
+///
+/// ```json
+/// { "seed": 42, "timeout_ms": 10000,
+///   "default": { "latency_rate": 0.2, "latency_ms": 300 },
+///   "methods": { "add_item": { "error_rate": 0.1 } } }
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+pub struct ChaosConfig {
+    /// Seed of the random draws, the same seed injects the same faults in the same sequence of calls.
+    pub seed: u64,
+    pub timeout_ms: u64,
+    /// Faults of the methods not in `methods`.
+    #[serde(default)]
+    pub default: Faults,
+    /// Faults by name of the method, e.g. `get_order`.
+    #[serde(default)]
+    pub methods: HashMap<String, Faults>,
+}
+
+impl ChaosConfig {
+    /// Loads the configuration from the JSON file at `path`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<ChaosConfig, Box<dyn std::error::Error>> {
+        let contents = fs::read_to_string(path)?;
+        let config: ChaosConfig = serde_json::from_str(&contents)?;
+        if let Some(unknown) = config
+            .methods
+            .keys()
+            .find(|method| !METHODS.contains(&method.as_str()))
+        {
+            return Err(format!("Unknown order store method {unknown}").into());
+        }
+        let mut rates = std::iter::once(&config.default)
+            .chain(config.methods.values())
+            .flat_map(|faults| [faults.latency_rate, faults.error_rate, faults.timeout_rate]);
+        if let Some(rate) = rates.find(|rate| !(0.0..=1.0).contains(rate)) {
+            return Err(format!("Fault rate {rate} is not between 0 and 1").into());
+        }
+        Ok(config)
+    }
+
+    fn faults(&self, method: &str) -> Faults {
+        self.methods.get(method).copied().unwrap_or(self.default)
+    }
+}
+
+/// Wraps another [`OrderStore`](OrderStore), injecting latency, errors and timeouts in its calls
+/// to check how the service copes with a misbehaving storage. Never to be used in production.
+pub struct ChaosOrderStore<S: OrderStore> {
+    store: S,
+    config: ChaosConfig,
+    rng: Mutex<StdRng>,
+}
+
+impl<S: OrderStore> ChaosOrderStore<S> {
+    /// Creates a new wrapper of `store` injecting the faults of `config`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let store = ChaosOrderStore::new(store, ChaosConfig::from_file("chaos.json")?);
+    /// ```
+    pub fn new(store: S, config: ChaosConfig) -> ChaosOrderStore<S> {
+        let rng = StdRng::seed_from_u64(config.seed);
+        ChaosOrderStore {
+            store,
+            config,
+            rng: Mutex::new(rng),
+        }
+    }
+
+    /// Injects the faults drawn for a call of `method`, failing it if it should not be made.
+    async fn inject(&self, method: &str) -> Result<(), OrderStoreError> {
+        let faults = self.config.faults(method);
+        // every call draws the same numbers, so a seed gives the same faults whatever the rates
+        let (latency, error, timeout): (f64, f64, f64) = {
+            let mut rng = self
+                .rng
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            (rng.gen(), rng.gen(), rng.gen())
+        };
+        if timeout < faults.timeout_rate {
+            debug!("injecting a timeout in {method}");
+            tokio::time::sleep(Duration::from_millis(self.config.timeout_ms)).await;
+            return Err(OrderStoreError::StoreUnavailable);
+        }
+        if latency < faults.latency_rate {
+            debug!("injecting {}ms of latency in {method}", faults.latency_ms);
+            tokio::time::sleep(Duration::from_millis(faults.latency_ms)).await;
+        }
+        if error < faults.error_rate {
+            debug!("injecting an error in {method}");
+            return Err(OrderStoreError::StoreUnavailable);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: OrderStore> OrderStore for ChaosOrderStore<S> {
+    async fn create_order(&self, user_id: Uuid) -> Result<Order, OrderStoreError> {
+        self.inject("create_order").await?;
+        self.store.create_order(user_id).await
+    }
+
+    async fn get_order(&self, order_id: Uuid) -> Result<Order, OrderStoreError> {
+        self.inject("get_order").await?;
+        self.store.get_order(order_id).await
+    }
+
+    async fn list_orders(&self, user_id: Uuid) -> Result<Vec<Order>, OrderStoreError> {
+        self.inject("list_orders").await?;
+        self.store.list_orders(user_id).await
+    }
+
+    async fn add_item(
+        &self,
+        order_id: Uuid,
+        product_id: Uuid,
+        quantity: i32,
+    ) -> Result<(), OrderStoreError> {
+        self.inject("add_item").await?;
+        self.store.add_item(order_id, product_id, quantity).await
+    }
+
+    async fn delete_item(
+        &self,
+        order_id: Uuid,
+        index: usize,
+        expected: Option<Uuid>,
+    ) -> Result<(), OrderStoreError> {
+        self.inject("delete_item").await?;
+        self.store.delete_item(order_id, index, expected).await
+    }
+
+    async fn update_item_quantity(
+        &self,
+        order_id: Uuid,
+        index: usize,
+        quantity: i32,
+        expected: Option<Uuid>,
+    ) -> Result<(), OrderStoreError> {
+        self.inject("update_item_quantity").await?;
+        self.store
+            .update_item_quantity(order_id, index, quantity, expected)
+            .await
+    }
+
+    async fn update_status(
+        &self,
+        order_id: Uuid,
+        status: OrderStatus,
+    ) -> Result<(), OrderStoreError> {
+        self.inject("update_status").await?;
+        self.store.update_status(order_id, status).await
+    }
+
+    async fn apply_coupon(&self, order_id: Uuid, code: &str) -> Result<(), OrderStoreError> {
+        self.inject("apply_coupon").await?;
+        self.store.apply_coupon(order_id, code).await
+    }
+
+    async fn remove_coupon(&self, order_id: Uuid, code: &str) -> Result<(), OrderStoreError> {
+        self.inject("remove_coupon").await?;
+        self.store.remove_coupon(order_id, code).await
+    }
+
+    async fn set_shipping(
+        &self,
+        order_id: Uuid,
+        address: Address,
+        method: ShippingMethod,
+    ) -> Result<(), OrderStoreError> {
+        self.inject("set_shipping").await?;
+        self.store.set_shipping(order_id, address, method).await
+    }
+
+    async fn set_billing_address(
+        &self,
+        order_id: Uuid,
+        address: Address,
+    ) -> Result<(), OrderStoreError> {
+        self.inject("set_billing_address").await?;
+        self.store.set_billing_address(order_id, address).await
+    }
+
+    async fn checkout(
+        &self,
+        order_id: Uuid,
+        payment_token: &str,
+    ) -> Result<PaymentAttempt, OrderStoreError> {
+        self.inject("checkout").await?;
+        self.store.checkout(order_id, payment_token).await
+    }
+
+    async fn request_return(
+        &self,
+        order_id: Uuid,
+        items: Vec<ReturnItem>,
+        reason: String,
+    ) -> Result<ReturnRequest, OrderStoreError> {
+        self.inject("request_return").await?;
+        self.store.request_return(order_id, items, reason).await
+    }
+
+    async fn approve_return(
+        &self,
+        order_id: Uuid,
+        return_id: Uuid,
+    ) -> Result<ReturnRequest, OrderStoreError> {
+        self.inject("approve_return").await?;
+        self.store.approve_return(order_id, return_id).await
+    }
+
+    async fn reject_return(
+        &self,
+        order_id: Uuid,
+        return_id: Uuid,
+    ) -> Result<ReturnRequest, OrderStoreError> {
+        self.inject("reject_return").await?;
+        self.store.reject_return(order_id, return_id).await
+    }
+
+    async fn history(&self, order_id: Uuid) -> Result<Vec<AuditEntry>, OrderStoreError> {
+        self.inject("history").await?;
+        self.store.history(order_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::{
+        in_mem_order_store::InMemOrderStore, payment_provider::PaymentProvider,
+        tax_calculator::TaxCalculator,
+    };
+
+    fn chaos_store(config: ChaosConfig) -> ChaosOrderStore<InMemOrderStore> {
+        ChaosOrderStore::new(
+            InMemOrderStore::new(
+                Arc::new(ProductStoreNewType::new(InMemProductStore::new())),
+                Arc::new(InventoryStoreNewType::new(InMemInventoryStore::new())),
+                Arc::new(PromotionStoreNewType::new(InMemPromotionStore::new())),
+                Arc::new(RulesTableTaxCalculator::tax_free()),
+                Arc::new(FakePaymentProvider::new()),
+            ),
+            config,
+        )
+    }
+
+    fn config(seed: u64, default: Faults, methods: &[(&str, Faults)]) -> ChaosConfig {
+        ChaosConfig {
+            seed,
+            timeout_ms: 50,
+            default,
+            methods: methods
+                .iter()
+                .map(|(method, faults)| (method.to_string(), *faults))
+                .collect(),
+        }
+    }
+
+    fn new_store(
+        products: Arc<ProductStoreNewType>,
+        inventory: Arc<InventoryStoreNewType>,
+        promotions: Arc<PromotionStoreNewType>,
+        tax: Arc<dyn TaxCalculator>,
+        payments: Arc<dyn PaymentProvider>,
+    ) -> ChaosOrderStore<InMemOrderStore> {
+        ChaosOrderStore::new(
+            InMemOrderStore::new(products, inventory, promotions, tax, payments),
+            config(7, Faults::default(), &[]),
+        )
+    }
+
+    crate::order_store_tests::order_store_tests!(new_store);
+
+    /// Whether each of `calls` reads of a missing order failed with an injected error.
+    async fn injected_errors(store: &ChaosOrderStore<InMemOrderStore>, calls: usize) -> Vec<bool> {
+        let mut errors = Vec::new();
+        for _ in 0..calls {
+            let result = store.get_order(Uuid::new_v4()).await;
+            errors.push(matches!(result, Err(OrderStoreError::StoreUnavailable)));
+        }
+        errors
+    }
+
+    #[tokio::test]
+    async fn the_same_seed_injects_the_same_faults() {
+        let faults = Faults {
+            error_rate: 0.5,
+            ..Faults::default()
+        };
+        let first = injected_errors(&chaos_store(config(42, faults, &[])), 50).await;
+        let second = injected_errors(&chaos_store(config(42, faults, &[])), 50).await;
+        let other_seed = injected_errors(&chaos_store(config(43, faults, &[])), 50).await;
+
+        assert_eq!(first, second);
+        assert_ne!(first, other_seed);
+        let failed = first.iter().filter(|failed| **failed).count();
+        assert!((10..40).contains(&failed), "{failed} of 50 failed");
+    }
+
+    #[tokio::test]
+    async fn faults_of_a_method_override_the_default_ones() {
+        let always = Faults {
+            error_rate: 1.0,
+            ..Faults::default()
+        };
+        let store = chaos_store(config(1, always, &[("create_order", Faults::default())]));
+
+        let order = store.create_order(Uuid::new_v4()).await.unwrap();
+        assert!(matches!(
+            store.get_order(order.id).await,
+            Err(OrderStoreError::StoreUnavailable)
+        ));
+    }
+
+    #[tokio::test]
+    async fn latency_and_timeouts_delay_the_calls() {
+        let slow = Faults {
+            latency_rate: 1.0,
+            latency_ms: 20,
+            ..Faults::default()
+        };
+        let hanging = Faults {
+            timeout_rate: 1.0,
+            ..Faults::default()
+        };
+        let store = chaos_store(config(1, slow, &[("get_order", hanging)]));
+
+        let started = Instant::now();
+        let order = store.create_order(Uuid::new_v4()).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        let started = Instant::now();
+        assert!(matches!(
+            store.get_order(order.id).await,
+            Err(OrderStoreError::StoreUnavailable)
+        ));
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn configuration_is_checked_when_loaded() {
+        let path = std::env::temp_dir().join(format!("chaos-{}.json", Uuid::new_v4()));
+        let load = |contents: &str| {
+            fs::write(&path, contents).unwrap();
+            ChaosConfig::from_file(&path)
+        };
+
+        let config = load(
+            r#"{ "seed": 42, "timeout_ms": 1000, "methods": { "add_item": { "error_rate": 0.1 } } }"#,
+        )
+        .unwrap();
+        assert_eq!(config.faults("add_item").error_rate, 0.1);
+        assert_eq!(config.faults("get_order"), Faults::default());
+        assert!(
+            load(r#"{ "seed": 42, "timeout_ms": 1000, "methods": { "add_items": {} } }"#).is_err()
+        );
+        assert!(
+            load(r#"{ "seed": 42, "timeout_ms": 1000, "default": { "error_rate": 2 } }"#).is_err()
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,60 @@
+//! Serde helpers that persist [`Decimal`] values as BSON `Decimal128`, so money amounts keep
+//! their exact value in MongoDB instead of being stored as strings or doubles.
+//!
+//! Use them with `#[serde(with = "crate::decimal128")]`.
+
+use std::str::FromStr;
+
+use mongodb::bson::Decimal128;
+use rust_decimal::Decimal;
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+pub fn serialize<S>(value: &Decimal, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    Decimal128::from_str(&value.to_string())
+        .map_err(serde::ser::Error::custom)?
+        .serialize(serializer)
+}
+
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Decimal, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Decimal128::deserialize(deserializer)?.to_string();
+    Decimal::from_str(&value)
+        .or_else(|_| Decimal::from_scientific(&value))
+        .map_err(D::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::{self, Bson};
+    use rust_decimal_macros::dec;
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Amount {
+        #[serde(with = "crate::decimal128")]
+        value: Decimal,
+    }
+
+    #[test]
+    fn amount_is_stored_as_decimal128() {
+        let document = bson::to_document(&Amount { value: dec!(12.50) }).unwrap();
+        assert!(matches!(document.get("value"), Some(Bson::Decimal128(_))));
+    }
+
+    #[test]
+    fn amount_round_trips_without_losing_scale() {
+        for value in [dec!(0), dec!(12.50), dec!(-3.333), dec!(0.0000001)] {
+            let bytes = bson::to_vec(&Amount { value }).unwrap();
+            let amount: Amount = bson::from_slice(&bytes).unwrap();
+            assert_eq!(amount.value, value);
+            assert_eq!(amount.value.scale(), value.scale());
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use uuid::Uuid;
+
+use crate::{
+    order_events::{Snapshot, StoredEvent},
+    order_store::OrderStoreError,
+};
+
+/// A trait that defines the behavior of a type used to persist the event streams of orders.
+#[async_trait::async_trait]
+pub trait EventLog: Send + Sync + 'static {
+    /// Appends `event` to the stream of its order.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](OrderStoreError::StoreUnavailable) if the log cannot be written.
+    ///
+    /// Returns [`ConcurrentModification`](OrderStoreError::ConcurrentModification) if the stream
+    /// already has an event with the same version.
+    async fn append(&self, event: &StoredEvent) -> Result<(), OrderStoreError>;
+
+    /// Returns the events of the order with id `order_id` whose version is greater than `after`,
+    /// oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](OrderStoreError::StoreUnavailable) if the log cannot be read.
+    async fn events(&self, order_id: Uuid, after: i64)
+        -> Result<Vec<StoredEvent>, OrderStoreError>;
+
+    /// Returns the ids of the orders of the user with id `user_id`, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](OrderStoreError::StoreUnavailable) if the log cannot be read.
+    async fn order_ids(&self, user_id: Uuid) -> Result<Vec<Uuid>, OrderStoreError>;
+
+    /// Returns the latest snapshot of the order with id `order_id`, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](OrderStoreError::StoreUnavailable) if the log cannot be read.
+    async fn snapshot(&self, order_id: Uuid) -> Result<Option<Snapshot>, OrderStoreError>;
+
+    /// Stores `snapshot`, replacing the previous snapshot of the order.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](OrderStoreError::StoreUnavailable) if the log cannot be written.
+    async fn save_snapshot(&self, snapshot: &Snapshot) -> Result<(), OrderStoreError>;
+}
@@ -0,0 +1,51 @@
+use std::{error::Error, fmt::Display, sync::Arc};
+
+use crate::outbox::DomainEvent;
+
+/// Type for describing errors that result from trying to publish to an [`EventSink`](EventSink).
+#[derive(Debug)]
+pub enum EventSinkError {
+    /// The event could not be published, for the provided reason; it will be retried.
+    Unavailable(String),
+}
+
+impl Display for EventSinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EventSinkError::Unavailable(reason) => {
+                write!(f, "Event sink unavailable: {}", reason)
+            }
+        }
+    }
+}
+
+impl Error for EventSinkError {}
+
+/// A trait that defines the behavior of a type used to hand domain events to other services.
+///
+/// Events are delivered at least once, so the same event can be published more than once.
+#[async_trait::async_trait]
+pub trait EventSink: Send + Sync + 'static {
+    /// Publishes `event`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Unavailable`](EventSinkError::Unavailable) if the event was not taken.
+    async fn publish(&self, event: &DomainEvent) -> Result<(), EventSinkError>;
+}
+
+/// Publishes every event to several sinks in turn. An event one of them fails to take is
+/// published again to all of them, so each sink must take an event it already took without
+/// delivering it twice, as the [`WebhookEventSink`](crate::webhook_dispatcher::WebhookEventSink)
+/// does, or leave it to consumers to discard it by its id.
+pub struct EventSinks(pub Vec<Arc<dyn EventSink>>);
+
+#[async_trait::async_trait]
+impl EventSink for EventSinks {
+    async fn publish(&self, event: &DomainEvent) -> Result<(), EventSinkError> {
+        for sink in &self.0 {
+            sink.publish(event).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,609 @@
+use std::{fmt::Display, future::Future, sync::Arc};
+use tokio::sync::Mutex;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{
+    audit_log::{current_actor, AuditEntry},
+    event_log::EventLog,
+    fulfilment::{Address, ShippingMethod},
+    in_mem_order_updates::InMemOrderUpdates,
+    inventory_store::InventoryStoreNewType,
+    order_events::{OrderEvent, Snapshot, StoredEvent},
+    order_store::{
+        find_applicable_coupon, find_orderable_product, move_order_stock, pay, payment_result,
+        refund, restock_return, unmove_order_stock, unrestock_return, update_taxes, void_charge,
+        Order, OrderStatus, OrderStore, OrderStoreError,
+    },
+    payment_provider::{PaymentAttempt, PaymentOutcome, PaymentProvider},
+    product_store::ProductStoreNewType,
+    promotion_store::PromotionStoreNewType,
+    returns::{ReturnItem, ReturnRequest},
+    tax_calculator::TaxCalculator,
+};
+
+/// A snapshot is taken every time a stream reaches a multiple of this many events.
+const SNAPSHOT_EVERY: i64 = 20;
+
+/// Persists orders as streams of [`OrderEvent`](OrderEvent)s and rebuilds them by replaying
+/// their latest snapshot and the events that followed it.
+pub struct EventSourcedOrderStore {
+    events: Box<dyn EventLog>,
+    products: Arc<ProductStoreNewType>,
+    inventory: Arc<InventoryStoreNewType>,
+    promotions: Arc<PromotionStoreNewType>,
+    tax: Arc<dyn TaxCalculator>,
+    payments: Arc<dyn PaymentProvider>,
+    updates: Arc<InMemOrderUpdates>,
+    mutations: Mutex<()>, // serializes read-modify-append cycles, the log rejects the ones of other processes
+}
+
+impl EventSourcedOrderStore {
+    /// Creates a new event-sourced order store keeping its events in `events`, with the same
+    /// collaborators as [`InMemOrderStore`](crate::in_mem_order_store::InMemOrderStore).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let store = EventSourcedOrderStore::new(InMemEventLog::new(), products, inventory, promotions, tax, payments);
+    /// ```
+    pub fn new(
+        events: impl EventLog,
+        products: Arc<ProductStoreNewType>,
+        inventory: Arc<InventoryStoreNewType>,
+        promotions: Arc<PromotionStoreNewType>,
+        tax: Arc<dyn TaxCalculator>,
+        payments: Arc<dyn PaymentProvider>,
+    ) -> EventSourcedOrderStore {
+        EventSourcedOrderStore {
+            events: Box::new(events),
+            products,
+            inventory,
+            promotions,
+            tax,
+            payments,
+            updates: Arc::new(InMemOrderUpdates::new()),
+            mutations: Mutex::new(()),
+        }
+    }
+
+    /// Returns the broadcast of the orders changed through this store; changes appended by
+    /// other processes are not seen.
+    pub fn updates(&self) -> Arc<InMemOrderUpdates> {
+        self.updates.clone()
+    }
+
+    /// Rebuilds the order with id `order_id` and returns it with the version of its last event.
+    async fn load(&self, order_id: Uuid) -> Result<(Order, i64), OrderStoreError> {
+        let (order, version) = match self.events.snapshot(order_id).await? {
+            Some(snapshot) => (Some(snapshot.order), snapshot.version),
+            None => (None, 0),
+        };
+        let events = self.events.events(order_id, version).await?;
+        let version = events.last().map_or(version, |stored| stored.version);
+        let order = events
+            .into_iter()
+            .try_fold(order, |order, stored| stored.event.apply(order).map(Some))?;
+        order
+            .map(|order| (order, version))
+            .ok_or(OrderStoreError::OrderNotFound(order_id))
+    }
+
+    /// Appends `event`, emitted by `operation` on the order at `version`, whose resulting state is
+    /// `order`. A snapshot that cannot be saved is only logged, as the event is already appended.
+    async fn append(
+        &self,
+        operation: &str,
+        order: &Order,
+        version: i64,
+        event: OrderEvent,
+    ) -> Result<(), OrderStoreError> {
+        let stored = StoredEvent {
+            order_id: order.id,
+            user_id: order.user_id,
+            version: version + 1,
+            operation: operation.to_string(),
+            actor: current_actor(),
+            recorded_at: chrono::Utc::now(),
+            event,
+        };
+        self.events.append(&stored).await?;
+        self.updates.publish(order);
+        if stored.version % SNAPSHOT_EVERY == 0 {
+            let snapshot = Snapshot {
+                order: order.clone(),
+                version: stored.version,
+            };
+            if let Err(err) = self.events.save_snapshot(&snapshot).await {
+                error!(
+                    "cannot save the snapshot of order {} at version {}: {}",
+                    order.id, stored.version, err
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends `event` like [`append`](Self::append) once its side effects, e.g. reserving stock,
+    /// are made, and awaits `undo` to reverse them when the event cannot be appended, so they
+    /// don't outlive a change that didn't happen.
+    async fn append_or_undo<E: Display>(
+        &self,
+        operation: &str,
+        order: &Order,
+        version: i64,
+        event: OrderEvent,
+        undo: impl Future<Output = Result<(), E>>,
+    ) -> Result<(), OrderStoreError> {
+        let result = self.append(operation, order, version, event).await;
+        if result.is_err() {
+            log_undo_failure(operation, order, undo.await);
+        }
+        result
+    }
+
+    /// Reverses moving `order` to `status`: puts back its stock and redeems again the first
+    /// `released` of its coupons.
+    async fn undo_status(
+        &self,
+        order: &Order,
+        status: OrderStatus,
+        released: usize,
+    ) -> Result<(), OrderStoreError> {
+        for coupon in &order.coupons[..released] {
+            self.promotions.redeem(&coupon.code, order.user_id).await?;
+        }
+        unmove_order_stock(&self.inventory, order, status).await?;
+        Ok(())
+    }
+}
+
+/// Logs the failure to reverse the side effects of `operation` on `order`.
+fn log_undo_failure(operation: &str, order: &Order, undo: Result<(), impl Display>) {
+    if let Err(err) = undo {
+        error!(
+            "cannot undo {} on order {} after it failed: {}",
+            operation, order.id, err
+        );
+    }
+}
+
+#[async_trait::async_trait]
+impl OrderStore for EventSourcedOrderStore {
+    async fn create_order(&self, user_id: Uuid) -> Result<Order, OrderStoreError> {
+        let order = Order::new(user_id);
+        let event = OrderEvent::OrderCreated {
+            order_id: order.id,
+            user_id,
+            created_at: order.created_at,
+        };
+        self.append("create_order", &order, 0, event).await?;
+        Ok(order)
+    }
+
+    async fn get_order(&self, order_id: Uuid) -> Result<Order, OrderStoreError> {
+        self.load(order_id).await.map(|(order, _)| order)
+    }
+
+    async fn list_orders(&self, user_id: Uuid) -> Result<Vec<Order>, OrderStoreError> {
+        let mut orders = vec![];
+        for order_id in self.events.order_ids(user_id).await? {
+            orders.push(self.get_order(order_id).await?);
+        }
+        Ok(orders)
+    }
+
+    async fn add_item(
+        &self,
+        order_id: Uuid,
+        product_id: Uuid,
+        quantity: i32,
+    ) -> Result<(), OrderStoreError> {
+        let product = find_orderable_product(&self.products, product_id).await?;
+        let _guard = self.mutations.lock().await;
+        let (mut order, version) = self.load(order_id).await?;
+        let item = order.add_item(&product, quantity)?;
+        update_taxes(&mut order, self.tax.as_ref()).await?;
+        self.inventory.reserve(product_id, quantity).await?;
+        let event = OrderEvent::ItemAdded {
+            item,
+            tax: order.tax.clone(),
+        };
+        let undo = self.inventory.release(product_id, quantity);
+        self.append_or_undo("add_item", &order, version, event, undo)
+            .await
+    }
+
+    async fn delete_item(
+        &self,
+        order_id: Uuid,
+        index: usize,
+        expected: Option<Uuid>,
+    ) -> Result<(), OrderStoreError> {
+        let _guard = self.mutations.lock().await;
+        let (mut order, version) = self.load(order_id).await?;
+        let item = order.delete_item(index, expected)?;
+        update_taxes(&mut order, self.tax.as_ref()).await?;
+        self.inventory
+            .release(item.product_id, item.quantity)
+            .await?;
+        let event = OrderEvent::ItemRemoved {
+            index,
+            tax: order.tax.clone(),
+        };
+        let undo = self.inventory.reserve(item.product_id, item.quantity);
+        self.append_or_undo("delete_item", &order, version, event, undo)
+            .await
+    }
+
+    async fn update_item_quantity(
+        &self,
+        order_id: Uuid,
+        index: usize,
+        quantity: i32,
+        expected: Option<Uuid>,
+    ) -> Result<(), OrderStoreError> {
+        let _guard = self.mutations.lock().await;
+        let (mut order, version) = self.load(order_id).await?;
+        let previous = order.set_item_quantity(index, quantity, expected)?;
+        update_taxes(&mut order, self.tax.as_ref()).await?;
+        let product_id = order.items[index].product_id;
+        if quantity > previous {
+            self.inventory
+                .reserve(product_id, quantity - previous)
+                .await?;
+        } else {
+            self.inventory
+                .release(product_id, previous - quantity)
+                .await?;
+        }
+        let event = OrderEvent::ItemQuantityChanged {
+            index,
+            quantity,
+            tax: order.tax.clone(),
+        };
+        let undo = async {
+            if quantity > previous {
+                self.inventory
+                    .release(product_id, quantity - previous)
+                    .await
+            } else {
+                self.inventory
+                    .reserve(product_id, previous - quantity)
+                    .await
+            }
+        };
+        self.append_or_undo("update_item_quantity", &order, version, event, undo)
+            .await
+    }
+
+    async fn update_status(
+        &self,
+        order_id: Uuid,
+        status: OrderStatus,
+    ) -> Result<(), OrderStoreError> {
+        let _guard = self.mutations.lock().await;
+        let (mut order, version) = self.load(order_id).await?;
+        order.set_status(status)?;
+        move_order_stock(&self.inventory, &order, status).await?;
+        let mut released = 0;
+        if status == OrderStatus::Cancelled {
+            for coupon in &order.coupons {
+                if let Err(err) = self.promotions.release(&coupon.code, order.user_id).await {
+                    let undo = self.undo_status(&order, status, released).await;
+                    log_undo_failure("update_status", &order, undo);
+                    return Err(err.into());
+                }
+                released += 1;
+            }
+        }
+        let event = OrderEvent::StatusChanged { status };
+        let undo = self.undo_status(&order, status, released);
+        self.append_or_undo("update_status", &order, version, event, undo)
+            .await
+    }
+
+    async fn apply_coupon(&self, order_id: Uuid, code: &str) -> Result<(), OrderStoreError> {
+        let coupon = find_applicable_coupon(&self.promotions, code).await?;
+        let _guard = self.mutations.lock().await;
+        let (mut order, version) = self.load(order_id).await?;
+        order.apply_coupon(coupon.clone())?;
+        update_taxes(&mut order, self.tax.as_ref()).await?;
+        self.promotions.redeem(code, order.user_id).await?;
+        let event = OrderEvent::CouponApplied {
+            coupon,
+            tax: Some(order.tax.clone()),
+        };
+        let undo = self.promotions.release(code, order.user_id);
+        self.append_or_undo("apply_coupon", &order, version, event, undo)
+            .await
+    }
+
+    async fn remove_coupon(&self, order_id: Uuid, code: &str) -> Result<(), OrderStoreError> {
+        let _guard = self.mutations.lock().await;
+        let (mut order, version) = self.load(order_id).await?;
+        order.remove_coupon(code)?;
+        update_taxes(&mut order, self.tax.as_ref()).await?;
+        self.promotions.release(code, order.user_id).await?;
+        let event = OrderEvent::CouponRemoved {
+            code: code.to_string(),
+            tax: Some(order.tax.clone()),
+        };
+        let undo = self.promotions.redeem(code, order.user_id);
+        self.append_or_undo("remove_coupon", &order, version, event, undo)
+            .await
+    }
+
+    async fn set_shipping(
+        &self,
+        order_id: Uuid,
+        address: Address,
+        method: ShippingMethod,
+    ) -> Result<(), OrderStoreError> {
+        let _guard = self.mutations.lock().await;
+        let (mut order, version) = self.load(order_id).await?;
+        order.set_shipping(address.clone(), method)?;
+        update_taxes(&mut order, self.tax.as_ref()).await?;
+        let event = OrderEvent::ShippingSet {
+            address,
+            method,
+            cost: order.shipping_cost,
+            tax: order.tax.clone(),
+        };
+        self.append("set_shipping", &order, version, event).await
+    }
+
+    async fn set_billing_address(
+        &self,
+        order_id: Uuid,
+        address: Address,
+    ) -> Result<(), OrderStoreError> {
+        let _guard = self.mutations.lock().await;
+        let (mut order, version) = self.load(order_id).await?;
+        order.set_billing_address(address.clone())?;
+        let event = OrderEvent::BillingAddressSet { address };
+        self.append("set_billing_address", &order, version, event)
+            .await
+    }
+
+    async fn checkout(
+        &self,
+        order_id: Uuid,
+        payment_token: &str,
+    ) -> Result<PaymentAttempt, OrderStoreError> {
+        let _guard = self.mutations.lock().await;
+        let (mut order, version) = self.load(order_id).await?;
+        let charge = order.checkout_charge(payment_token)?;
+        if let Some(attempt) = order.start_payment(&charge) {
+            let event = OrderEvent::PaymentAttempted { attempt };
+            self.append("checkout", &order, version, event).await?;
+        }
+        let outcome = pay(self.payments.as_ref(), &charge).await;
+        let (mut order, version) = self.load(order_id).await?;
+        let (attempt, recorded) = order.record_payment(&charge, outcome);
+        if recorded {
+            if order.status == OrderStatus::Paid {
+                let placed = move_order_stock(&self.inventory, &order, OrderStatus::Placed).await;
+                if let Err(err) = placed {
+                    if void_charge(self.payments.as_ref(), &charge, &attempt).await {
+                        let voided = async {
+                            let (mut order, version) = self.load(order_id).await?;
+                            let (attempt, _) =
+                                order.record_payment(&charge, PaymentOutcome::Failed);
+                            let event = OrderEvent::PaymentAttempted { attempt };
+                            self.append("checkout", &order, version, event).await
+                        };
+                        if let Err(voided_err) = voided.await {
+                            error!(
+                                "cannot record the charge of order {} as given back: {}",
+                                order_id,
+                                voided_err.detailed()
+                            );
+                        }
+                    }
+                    return Err(err);
+                }
+            }
+            let event = OrderEvent::PaymentAttempted {
+                attempt: attempt.clone(),
+            };
+            // the payment itself is not undone: checking out again records the same attempt
+            let undo = async {
+                match order.status {
+                    OrderStatus::Paid => {
+                        unmove_order_stock(&self.inventory, &order, OrderStatus::Placed).await
+                    }
+                    _ => Ok(()),
+                }
+            };
+            self.append_or_undo("checkout", &order, version, event, undo)
+                .await?;
+        }
+        payment_result(attempt)
+    }
+
+    async fn request_return(
+        &self,
+        order_id: Uuid,
+        items: Vec<ReturnItem>,
+        reason: String,
+    ) -> Result<ReturnRequest, OrderStoreError> {
+        let _guard = self.mutations.lock().await;
+        let (mut order, version) = self.load(order_id).await?;
+        let request = order.request_return(&items, reason)?;
+        let event = OrderEvent::ReturnRequested {
+            request: request.clone(),
+        };
+        self.append("request_return", &order, version, event)
+            .await?;
+        Ok(request)
+    }
+
+    async fn approve_return(
+        &self,
+        order_id: Uuid,
+        return_id: Uuid,
+    ) -> Result<ReturnRequest, OrderStoreError> {
+        let _guard = self.mutations.lock().await;
+        let (mut order, version) = self.load(order_id).await?;
+        let request = refund(&mut order, self.payments.as_ref(), return_id).await?;
+        restock_return(&self.inventory, &request).await?;
+        let event = OrderEvent::ReturnDecided {
+            request: request.clone(),
+        };
+        // the refund itself is not undone: approving the return again retries the same refund
+        let undo = unrestock_return(&self.inventory, &request);
+        self.append_or_undo("approve_return", &order, version, event, undo)
+            .await?;
+        Ok(request)
+    }
+
+    async fn reject_return(
+        &self,
+        order_id: Uuid,
+        return_id: Uuid,
+    ) -> Result<ReturnRequest, OrderStoreError> {
+        let _guard = self.mutations.lock().await;
+        let (mut order, version) = self.load(order_id).await?;
+        let request = order.reject_return(return_id)?;
+        let event = OrderEvent::ReturnDecided {
+            request: request.clone(),
+        };
+        self.append("reject_return", &order, version, event).await?;
+        Ok(request)
+    }
+
+    /// The history is derived from the events themselves, every event turning into the entry
+    /// that diffs the order before and after it.
+    async fn history(&self, order_id: Uuid) -> Result<Vec<AuditEntry>, OrderStoreError> {
+        let events = self.events.events(order_id, 0).await?;
+        if events.is_empty() {
+            return Err(OrderStoreError::OrderNotFound(order_id));
+        }
+        let mut order: Option<Order> = None;
+        let mut history = vec![];
+        for stored in events {
+            let after = stored.event.apply(order.clone())?;
+            history.push(AuditEntry {
+                actor: stored.actor,
+                timestamp: stored.recorded_at,
+                ..AuditEntry::new(&stored.operation, order.as_ref(), &after)
+            });
+            order = Some(after);
+        }
+        Ok(history)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+    use crate::in_mem_event_log::InMemEventLog;
+
+    fn new_store(
+        products: Arc<ProductStoreNewType>,
+        inventory: Arc<InventoryStoreNewType>,
+        promotions: Arc<PromotionStoreNewType>,
+        tax: Arc<dyn TaxCalculator>,
+        payments: Arc<dyn PaymentProvider>,
+    ) -> EventSourcedOrderStore {
+        EventSourcedOrderStore::new(
+            InMemEventLog::new(),
+            products,
+            inventory,
+            promotions,
+            tax,
+            payments,
+        )
+    }
+
+    crate::order_store_tests::order_store_tests!(new_store);
+
+    #[test_context(Context)]
+    #[tokio::test]
+    async fn long_streams_are_rebuilt_from_their_snapshot(ctx: &mut Context) {
+        let order_id = ctx.order_1_user_1.id;
+        for _ in 0..SNAPSHOT_EVERY {
+            ctx.store
+                .add_item(order_id, ctx.product_id_1, 1)
+                .await
+                .unwrap();
+        }
+        ctx.store.delete_item(order_id, 0, None).await.unwrap();
+
+        let order = ctx.store.get_order(order_id).await.unwrap();
+        assert_eq!(order.items.len() as i64, SNAPSHOT_EVERY - 1);
+        assert_eq!(
+            ctx.store.history(order_id).await.unwrap().len() as i64,
+            SNAPSHOT_EVERY + 2
+        );
+    }
+
+    /// An in-memory log whose appends fail while `unavailable` is set.
+    struct FlakyEventLog {
+        log: InMemEventLog,
+        unavailable: Arc<AtomicBool>,
+    }
+
+    #[async_trait::async_trait]
+    impl EventLog for FlakyEventLog {
+        async fn append(&self, event: &StoredEvent) -> Result<(), OrderStoreError> {
+            if self.unavailable.load(Ordering::SeqCst) {
+                return Err(OrderStoreError::StoreUnavailable);
+            }
+            self.log.append(event).await
+        }
+
+        async fn events(
+            &self,
+            order_id: Uuid,
+            after: i64,
+        ) -> Result<Vec<StoredEvent>, OrderStoreError> {
+            self.log.events(order_id, after).await
+        }
+
+        async fn order_ids(&self, user_id: Uuid) -> Result<Vec<Uuid>, OrderStoreError> {
+            self.log.order_ids(user_id).await
+        }
+
+        async fn snapshot(&self, order_id: Uuid) -> Result<Option<Snapshot>, OrderStoreError> {
+            self.log.snapshot(order_id).await
+        }
+
+        async fn save_snapshot(&self, snapshot: &Snapshot) -> Result<(), OrderStoreError> {
+            self.log.save_snapshot(snapshot).await
+        }
+    }
+
+    #[test_context(Context)]
+    #[tokio::test]
+    async fn stock_is_released_when_the_event_cannot_be_appended(ctx: &mut Context) {
+        let unavailable = Arc::new(AtomicBool::new(false));
+        let store = EventSourcedOrderStore::new(
+            FlakyEventLog {
+                log: InMemEventLog::new(),
+                unavailable: unavailable.clone(),
+            },
+            ctx.products.clone(),
+            ctx.inventory.clone(),
+            ctx.promotions.clone(),
+            Arc::new(RulesTableTaxCalculator::tax_free()),
+            Arc::new(FakePaymentProvider::new()),
+        );
+        let order = store.create_order(ctx.user_id_1).await.unwrap();
+        let before = ctx.inventory.get_stock(ctx.product_id_1).await.unwrap();
+
+        unavailable.store(true, Ordering::SeqCst);
+        assert!(matches!(
+            store.add_item(order.id, ctx.product_id_1, 3).await,
+            Err(OrderStoreError::StoreUnavailable)
+        ));
+        assert_eq!(
+            ctx.inventory.get_stock(ctx.product_id_1).await.unwrap(),
+            before
+        );
+        assert!(store.get_order(order.id).await.unwrap().items.is_empty());
+    }
+}
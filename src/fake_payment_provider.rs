@@ -0,0 +1,42 @@
+use crate::payment_provider::{Charge, PaymentError, PaymentOutcome, PaymentProvider, Refund};
+
+/// Token the fake provider declines, as if the card had no funds.
+pub const DECLINED_TOKEN: &str = "tok_declined";
+/// Token the fake provider fails with, as if it could not be reached.
+pub const UNAVAILABLE_TOKEN: &str = "tok_unavailable";
+
+/// In-process payment provider for tests and local development. It approves every charge except
+/// the ones made with [`DECLINED_TOKEN`] or [`UNAVAILABLE_TOKEN`], and every refund.
+pub struct FakePaymentProvider;
+
+impl FakePaymentProvider {
+    /// Creates a new fake payment provider.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let payments = FakePaymentProvider::new();
+    /// ```
+    pub fn new() -> FakePaymentProvider {
+        FakePaymentProvider
+    }
+}
+
+#[async_trait::async_trait]
+impl PaymentProvider for FakePaymentProvider {
+    async fn charge(&self, charge: &Charge) -> Result<PaymentOutcome, PaymentError> {
+        match charge.payment_token.as_str() {
+            DECLINED_TOKEN => Ok(PaymentOutcome::Declined {
+                reason: "insufficient funds".to_string(),
+            }),
+            UNAVAILABLE_TOKEN => Err(PaymentError::ProviderUnavailable),
+            _ => Ok(PaymentOutcome::Approved {
+                reference: format!("fake_{}", charge.attempt_id.simple()),
+            }),
+        }
+    }
+
+    async fn refund(&self, refund: &Refund) -> Result<String, PaymentError> {
+        Ok(format!("fake_refund_{}", refund.refund_id.simple()))
+    }
+}
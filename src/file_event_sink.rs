@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+
+use crate::{
+    event_sink::{EventSink, EventSinkError},
+    outbox::DomainEvent,
+};
+
+/// Appends every event as a line of JSON to a file.
+pub struct FileEventSink {
+    path: PathBuf,
+}
+
+impl FileEventSink {
+    /// Creates a new sink appending to the file at `path`, which is created if missing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let sink = FileEventSink::new("order_events.jsonl");
+    /// ```
+    pub fn new(path: impl Into<PathBuf>) -> FileEventSink {
+        FileEventSink { path: path.into() }
+    }
+}
+
+#[async_trait::async_trait]
+impl EventSink for FileEventSink {
+    async fn publish(&self, event: &DomainEvent) -> Result<(), EventSinkError> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(|err| EventSinkError::Unavailable(err.to_string()))?;
+        file.write_all(format!("{}\n", event.to_json()).as_bytes())
+            .await
+            .map_err(|err| EventSinkError::Unavailable(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{order_events::OrderEvent, order_store::OrderStatus};
+
+    #[tokio::test]
+    async fn events_are_appended_as_json_lines() {
+        let path = std::env::temp_dir().join(format!("events-{}.jsonl", Uuid::new_v4()));
+        let sink = FileEventSink::new(&path);
+        for status in [OrderStatus::Placed, OrderStatus::Shipped] {
+            let event = DomainEvent::new(
+                Uuid::new_v4(),
+                Uuid::new_v4(),
+                OrderEvent::StatusChanged { status },
+            );
+            sink.publish(&event).await.unwrap();
+        }
+
+        let written = tokio::fs::read_to_string(&path).await.unwrap();
+        _ = tokio::fs::remove_file(&path).await;
+        let lines: Vec<serde_json::Value> = written
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["event"]["StatusChanged"]["status"], "Shipped");
+    }
+}
@@ -0,0 +1,114 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// A postal address orders are shipped or billed to.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Address {
+    /// Person or company receiving the order.
+    pub name: String,
+    pub line1: String,
+    pub line2: Option<String>,
+    pub city: String,
+    /// State or province, when the country has them.
+    pub region: Option<String>,
+    pub postal_code: String,
+    /// ISO 3166-1 alpha-2 code of the country.
+    pub country: String,
+}
+
+impl Address {
+    /// Returns the name of the first required field that is blank or malformed, `None` if the
+    /// address is complete.
+    pub fn invalid_field(&self) -> Option<&'static str> {
+        let required = [
+            ("name", &self.name),
+            ("line1", &self.line1),
+            ("city", &self.city),
+            ("postal_code", &self.postal_code),
+        ];
+        if let Some((field, _)) = required.iter().find(|(_, value)| value.trim().is_empty()) {
+            return Some(field);
+        }
+        if self.country.len() != 2 || !self.country.chars().all(|c| c.is_ascii_uppercase()) {
+            return Some("country");
+        }
+        None
+    }
+
+    /// Region whose tax rates apply to the address, `US-CA` or `DE` style.
+    pub fn tax_region(&self) -> String {
+        match &self.region {
+            Some(region) if !region.trim().is_empty() => format!("{}-{}", self.country, region),
+            _ => self.country.clone(),
+        }
+    }
+}
+
+/// How an order is delivered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShippingMethod {
+    /// Delivered within a week.
+    Standard,
+    /// Delivered within two days.
+    Express,
+    /// Delivered the next working day.
+    Overnight,
+}
+
+impl ShippingMethod {
+    /// Flat cost of the method, in the currency of the order.
+    pub fn cost(self) -> Decimal {
+        match self {
+            ShippingMethod::Standard => Decimal::new(499, 2),
+            ShippingMethod::Express => Decimal::new(1499, 2),
+            ShippingMethod::Overnight => Decimal::new(2499, 2),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address() -> Address {
+        Address {
+            name: "Ada Lovelace".to_string(),
+            line1: "1 Main St".to_string(),
+            line2: None,
+            city: "Sacramento".to_string(),
+            region: Some("CA".to_string()),
+            postal_code: "95814".to_string(),
+            country: "US".to_string(),
+        }
+    }
+
+    #[test]
+    fn complete_address_is_valid() {
+        assert_eq!(address().invalid_field(), None);
+    }
+
+    #[test]
+    fn blank_or_malformed_fields_are_reported() {
+        let blank_city = Address {
+            city: " ".to_string(),
+            ..address()
+        };
+        assert_eq!(blank_city.invalid_field(), Some("city"));
+        let bad_country = Address {
+            country: "usa".to_string(),
+            ..address()
+        };
+        assert_eq!(bad_country.invalid_field(), Some("country"));
+    }
+
+    #[test]
+    fn tax_region_includes_the_region_when_present() {
+        assert_eq!(address().tax_region(), "US-CA");
+        let german = Address {
+            region: None,
+            country: "DE".to_string(),
+            ..address()
+        };
+        assert_eq!(german.tax_region(), "DE");
+    }
+}
@@ -0,0 +1,598 @@
+//! gRPC `OrderService` for internal callers, see `proto/orders.proto`.
+
+// every call fails with a `tonic::Status`, large or not
+#![allow(clippy::result_large_err)]
+
+pub mod proto;
+
+use std::{future::Future, net::SocketAddr, sync::Arc};
+
+use axum::http;
+use tonic::{transport::Server, Code, Request, Response, Status};
+use tower::util::MapResponseLayer;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::{
+    audit_log::as_actor,
+    internal_caller::InternalToken,
+    order_store::{OrderStoreError, OrderStoreNewType},
+    resilient_order_store::CircuitBreaker,
+    returns,
+};
+
+use proto::order_service_server::{OrderService, OrderServiceServer};
+
+/// Metadata naming who makes a call, recorded in the order history like the `X-Actor` header of
+/// the REST routes. Calls without it are made by the system; only callers authenticated with the
+/// token of internal callers can send it.
+const ACTOR_METADATA: &str = "x-actor";
+
+/// Maps `err` to the gRPC status code callers get for it.
+fn status(err: OrderStoreError) -> Status {
+    let code = match err {
+        OrderStoreError::StoreUnavailable
+        | OrderStoreError::TaxUnavailable
+        | OrderStoreError::PaymentUnavailable
+        | OrderStoreError::StoreConnection(_)
+        | OrderStoreError::StoreFailure(_) => Code::Unavailable,
+        OrderStoreError::StoreTimeout(_) => Code::DeadlineExceeded,
+        OrderStoreError::StoreMisconfigured(_) | OrderStoreError::StoreSerialization(_) => {
+            Code::Internal
+        }
+        OrderStoreError::OrderNotFound(_)
+        | OrderStoreError::ProductNotFound(_)
+        | OrderStoreError::CouponNotFound(_)
+        | OrderStoreError::ReturnNotFound(_) => Code::NotFound,
+        OrderStoreError::ItemIndexOutOfBounds(_) => Code::OutOfRange,
+        OrderStoreError::CurrencyMismatch(_)
+        | OrderStoreError::InvalidQuantity(_)
+        | OrderStoreError::InvalidPrice(_)
+        | OrderStoreError::InvalidAddress(_)
+        | OrderStoreError::InvalidReturn(_) => Code::InvalidArgument,
+        OrderStoreError::ProductInactive(_)
+        | OrderStoreError::CouponExpired(_)
+        | OrderStoreError::NoTaxRate(_, _)
+        | OrderStoreError::NotReadyForCheckout(_)
+        | OrderStoreError::OrderNotEditable(_)
+        | OrderStoreError::ItemChanged(_, _)
+        | OrderStoreError::InvalidStatusTransition(_, _)
+        | OrderStoreError::OrderNotDelivered(_)
+        | OrderStoreError::ReturnAlreadyDecided(_) => Code::FailedPrecondition,
+        OrderStoreError::InsufficientStock(_) | OrderStoreError::CouponUsageLimitReached(_) => {
+            Code::ResourceExhausted
+        }
+        OrderStoreError::CouponAlreadyApplied(_) | OrderStoreError::DuplicateKey(_) => {
+            Code::AlreadyExists
+        }
+        // the caller can retry on the new state of the order
+        OrderStoreError::ConcurrentModification(_) | OrderStoreError::WriteConflict(_) => {
+            Code::Aborted
+        }
+    };
+    Status::new(code, err.to_string())
+}
+
+fn parse_id(id: &str, field: &str) -> Result<Uuid, Status> {
+    Uuid::parse_str(id).map_err(|_| Status::invalid_argument(format!("Invalid {field}: {id}")))
+}
+
+fn required<T>(value: Option<T>, field: &str) -> Result<T, Status> {
+    value.ok_or_else(|| Status::invalid_argument(format!("Missing or invalid {field}")))
+}
+
+/// Metadata telling callers failing with `UNAVAILABLE` while the circuit breaker of the order store
+/// is open how many seconds to wait before trying again, like the `Retry-After` header of the REST
+/// routes.
+const RETRY_AFTER_METADATA: &str = "retry-after";
+
+/// Layer adding the [`RETRY_AFTER_METADATA`] to the failed calls whose status is `UNAVAILABLE`,
+/// which are answered with the status in the headers and no body.
+fn with_retry_after<B>(
+    breaker: Arc<CircuitBreaker>,
+) -> MapResponseLayer<impl Fn(http::Response<B>) -> http::Response<B> + Clone> {
+    MapResponseLayer::new(move |mut response: http::Response<B>| {
+        let unavailable = response
+            .headers()
+            .get("grpc-status")
+            .is_some_and(|code| Code::from_bytes(code.as_bytes()) == Code::Unavailable);
+        if let Some(seconds) = breaker.retry_after_secs().filter(|_| unavailable) {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER_METADATA, http::HeaderValue::from(seconds));
+        }
+        response
+    })
+}
+
+/// Serves the order store over gRPC.
+pub struct GrpcOrderService {
+    orders: Arc<OrderStoreNewType>,
+    internal: InternalToken,
+}
+
+impl GrpcOrderService {
+    pub fn new(orders: Arc<OrderStoreNewType>, internal: InternalToken) -> GrpcOrderService {
+        GrpcOrderService { orders, internal }
+    }
+
+    /// Runs `call` with the message of `request`, on behalf of the actor named in its metadata.
+    ///
+    /// # Errors
+    ///
+    /// Returns `UNAUTHENTICATED` if `request` names an actor without the token of internal callers.
+    async fn on_behalf<T, R, F>(
+        &self,
+        request: Request<T>,
+        call: impl FnOnce(T) -> F,
+    ) -> Result<Response<R>, Status>
+    where
+        F: Future<Output = Result<R, Status>>,
+    {
+        let metadata = request.metadata();
+        let actor = metadata
+            .get(ACTOR_METADATA)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let authorization = metadata
+            .get("authorization")
+            .and_then(|value| value.to_str().ok());
+        if actor.is_some() && !self.internal.authenticates(authorization) {
+            return Err(Status::unauthenticated(
+                "x-actor is only accepted from internal callers",
+            ));
+        }
+        let message = request.into_inner();
+        let result = match actor {
+            Some(actor) => as_actor(actor, call(message)).await,
+            None => call(message).await,
+        };
+        result.map(Response::new)
+    }
+
+    /// Serves the service on `address` until `shutdown` completes, telling callers failing while
+    /// `breaker` is open when to try again.
+    pub async fn serve(
+        self,
+        address: SocketAddr,
+        breaker: Arc<CircuitBreaker>,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<(), tonic::transport::Error> {
+        Server::builder()
+            .layer(with_retry_after(breaker))
+            .add_service(OrderServiceServer::new(self))
+            .serve_with_shutdown(address, shutdown)
+            .await
+    }
+
+    async fn order(&self, order_id: Uuid) -> Result<proto::Order, Status> {
+        let order = self.orders.get_order(order_id).await.map_err(status)?;
+        Ok(proto::Order::from(order))
+    }
+}
+
+#[tonic::async_trait]
+impl OrderService for GrpcOrderService {
+    async fn create_order(
+        &self,
+        request: Request<proto::CreateOrderRequest>,
+    ) -> Result<Response<proto::Order>, Status> {
+        self.on_behalf(request, |request| async move {
+            let user_id = parse_id(&request.user_id, "user_id")?;
+            debug!("Creating a new order for user with id: {user_id}");
+            let order = self.orders.create_order(user_id).await.map_err(status)?;
+            Ok(proto::Order::from(order))
+        })
+        .await
+    }
+
+    async fn get_order(
+        &self,
+        request: Request<proto::OrderRequest>,
+    ) -> Result<Response<proto::Order>, Status> {
+        self.on_behalf(request, |request| async move {
+            self.order(parse_id(&request.order_id, "order_id")?).await
+        })
+        .await
+    }
+
+    async fn list_orders(
+        &self,
+        request: Request<proto::ListOrdersRequest>,
+    ) -> Result<Response<proto::ListOrdersResponse>, Status> {
+        self.on_behalf(request, |request| async move {
+            let user_id = parse_id(&request.user_id, "user_id")?;
+            let orders = self.orders.list_orders(user_id).await.map_err(status)?;
+            Ok(proto::ListOrdersResponse {
+                orders: orders.into_iter().map(proto::Order::from).collect(),
+            })
+        })
+        .await
+    }
+
+    async fn add_item(
+        &self,
+        request: Request<proto::AddItemRequest>,
+    ) -> Result<Response<proto::Order>, Status> {
+        self.on_behalf(request, |request| async move {
+            let order_id = parse_id(&request.order_id, "order_id")?;
+            let product_id = parse_id(&request.product_id, "product_id")?;
+            self.orders
+                .add_item(order_id, product_id, request.quantity)
+                .await
+                .map_err(status)?;
+            self.order(order_id).await
+        })
+        .await
+    }
+
+    async fn delete_item(
+        &self,
+        request: Request<proto::ItemRequest>,
+    ) -> Result<Response<proto::Order>, Status> {
+        self.on_behalf(request, |request| async move {
+            let order_id = parse_id(&request.order_id, "order_id")?;
+            self.orders
+                .delete_item(order_id, request.index as usize, None)
+                .await
+                .map_err(status)?;
+            self.order(order_id).await
+        })
+        .await
+    }
+
+    async fn update_item_quantity(
+        &self,
+        request: Request<proto::UpdateItemQuantityRequest>,
+    ) -> Result<Response<proto::Order>, Status> {
+        self.on_behalf(request, |request| async move {
+            let order_id = parse_id(&request.order_id, "order_id")?;
+            self.orders
+                .update_item_quantity(order_id, request.index as usize, request.quantity, None)
+                .await
+                .map_err(status)?;
+            self.order(order_id).await
+        })
+        .await
+    }
+
+    async fn update_status(
+        &self,
+        request: Request<proto::UpdateStatusRequest>,
+    ) -> Result<Response<proto::Order>, Status> {
+        self.on_behalf(request, |request| async move {
+            let order_id = parse_id(&request.order_id, "order_id")?;
+            let order_status = required(
+                proto::OrderStatus::from_i32(request.status)
+                    .and_then(|status| status.try_into().ok()),
+                "status",
+            )?;
+            self.orders
+                .update_status(order_id, order_status)
+                .await
+                .map_err(status)?;
+            self.order(order_id).await
+        })
+        .await
+    }
+
+    async fn apply_coupon(
+        &self,
+        request: Request<proto::CouponRequest>,
+    ) -> Result<Response<proto::Order>, Status> {
+        self.on_behalf(request, |request| async move {
+            let order_id = parse_id(&request.order_id, "order_id")?;
+            self.orders
+                .apply_coupon(order_id, &request.code)
+                .await
+                .map_err(status)?;
+            self.order(order_id).await
+        })
+        .await
+    }
+
+    async fn remove_coupon(
+        &self,
+        request: Request<proto::CouponRequest>,
+    ) -> Result<Response<proto::Order>, Status> {
+        self.on_behalf(request, |request| async move {
+            let order_id = parse_id(&request.order_id, "order_id")?;
+            self.orders
+                .remove_coupon(order_id, &request.code)
+                .await
+                .map_err(status)?;
+            self.order(order_id).await
+        })
+        .await
+    }
+
+    async fn set_shipping(
+        &self,
+        request: Request<proto::SetShippingRequest>,
+    ) -> Result<Response<proto::Order>, Status> {
+        self.on_behalf(request, |request| async move {
+            let order_id = parse_id(&request.order_id, "order_id")?;
+            let address = required(request.address, "address")?;
+            let method = required(
+                proto::ShippingMethod::from_i32(request.method)
+                    .and_then(|method| method.try_into().ok()),
+                "method",
+            )?;
+            self.orders
+                .set_shipping(order_id, address.into(), method)
+                .await
+                .map_err(status)?;
+            self.order(order_id).await
+        })
+        .await
+    }
+
+    async fn set_billing_address(
+        &self,
+        request: Request<proto::SetBillingAddressRequest>,
+    ) -> Result<Response<proto::Order>, Status> {
+        self.on_behalf(request, |request| async move {
+            let order_id = parse_id(&request.order_id, "order_id")?;
+            let address = required(request.address, "address")?;
+            self.orders
+                .set_billing_address(order_id, address.into())
+                .await
+                .map_err(status)?;
+            self.order(order_id).await
+        })
+        .await
+    }
+
+    async fn checkout(
+        &self,
+        request: Request<proto::CheckoutRequest>,
+    ) -> Result<Response<proto::PaymentAttempt>, Status> {
+        self.on_behalf(request, |request| async move {
+            let order_id = parse_id(&request.order_id, "order_id")?;
+            let attempt = self
+                .orders
+                .checkout(order_id, &request.payment_token)
+                .await
+                .map_err(status)?;
+            Ok(proto::PaymentAttempt::from(attempt))
+        })
+        .await
+    }
+
+    async fn request_return(
+        &self,
+        request: Request<proto::RequestReturnRequest>,
+    ) -> Result<Response<proto::Return>, Status> {
+        self.on_behalf(request, |request| async move {
+            let order_id = parse_id(&request.order_id, "order_id")?;
+            let items = request
+                .items
+                .into_iter()
+                .map(|item| returns::ReturnItem {
+                    index: item.index as usize,
+                    quantity: item.quantity,
+                })
+                .collect();
+            let requested = self
+                .orders
+                .request_return(order_id, items, request.reason)
+                .await
+                .map_err(status)?;
+            Ok(proto::Return::from(requested))
+        })
+        .await
+    }
+
+    async fn approve_return(
+        &self,
+        request: Request<proto::ReturnDecisionRequest>,
+    ) -> Result<Response<proto::Return>, Status> {
+        self.on_behalf(request, |request| async move {
+            let order_id = parse_id(&request.order_id, "order_id")?;
+            let return_id = parse_id(&request.return_id, "return_id")?;
+            let decided = self
+                .orders
+                .approve_return(order_id, return_id)
+                .await
+                .map_err(status)?;
+            Ok(proto::Return::from(decided))
+        })
+        .await
+    }
+
+    async fn reject_return(
+        &self,
+        request: Request<proto::ReturnDecisionRequest>,
+    ) -> Result<Response<proto::Return>, Status> {
+        self.on_behalf(request, |request| async move {
+            let order_id = parse_id(&request.order_id, "order_id")?;
+            let return_id = parse_id(&request.return_id, "return_id")?;
+            let decided = self
+                .orders
+                .reject_return(order_id, return_id)
+                .await
+                .map_err(status)?;
+            Ok(proto::Return::from(decided))
+        })
+        .await
+    }
+
+    async fn history(
+        &self,
+        request: Request<proto::OrderRequest>,
+    ) -> Result<Response<proto::HistoryResponse>, Status> {
+        self.on_behalf(request, |request| async move {
+            let order_id = parse_id(&request.order_id, "order_id")?;
+            let entries = self.orders.history(order_id).await.map_err(status)?;
+            Ok(proto::HistoryResponse {
+                entries: entries.into_iter().map(proto::HistoryEntry::from).collect(),
+            })
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+    use tonic::transport::server::TcpIncoming;
+
+    use super::*;
+    use crate::{
+        fake_payment_provider::FakePaymentProvider,
+        in_mem_inventory_store::InMemInventoryStore,
+        in_mem_order_store::InMemOrderStore,
+        in_mem_product_store::InMemProductStore,
+        in_mem_promotion_store::InMemPromotionStore,
+        inventory_store::InventoryStoreNewType,
+        product_store::{ProductDetails, ProductStoreNewType},
+        promotion_store::PromotionStoreNewType,
+        rules_table_tax_calculator::RulesTableTaxCalculator,
+    };
+    use proto::order_service_client::OrderServiceClient;
+
+    const TOKEN: &str = "internal-token";
+
+    /// Serves an in-memory order store with one product in stock, returning a client and the product id.
+    async fn serve() -> (OrderServiceClient<tonic::transport::Channel>, Uuid) {
+        let products = Arc::new(ProductStoreNewType::new(InMemProductStore::new()));
+        let inventory = Arc::new(InventoryStoreNewType::new(InMemInventoryStore::new()));
+        let product = products
+            .create_product(ProductDetails {
+                name: "Coffee".to_string(),
+                sku: "COF-001".to_string(),
+                price: dec!(4.50),
+                currency: "USD".to_string(),
+                tax_category: "standard".to_string(),
+                active: true,
+            })
+            .await
+            .unwrap();
+        inventory.set_available(product.id, 10).await.unwrap();
+        let orders = Arc::new(OrderStoreNewType::new(InMemOrderStore::new(
+            products,
+            inventory,
+            Arc::new(PromotionStoreNewType::new(InMemPromotionStore::new())),
+            Arc::new(RulesTableTaxCalculator::tax_free()),
+            Arc::new(FakePaymentProvider::new()),
+        )));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(OrderServiceServer::new(GrpcOrderService::new(
+                    orders,
+                    InternalToken::new(Some(TOKEN.to_string())),
+                )))
+                .serve_with_incoming(incoming),
+        );
+        let client = OrderServiceClient::connect(format!("http://{address}"))
+            .await
+            .unwrap();
+        (client, product.id)
+    }
+
+    #[tokio::test]
+    async fn orders_are_changed_on_behalf_of_the_caller() {
+        let (mut client, product_id) = serve().await;
+        let order = client
+            .create_order(proto::CreateOrderRequest {
+                user_id: Uuid::new_v4().to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        let mut request = Request::new(proto::AddItemRequest {
+            order_id: order.id.clone(),
+            product_id: product_id.to_string(),
+            quantity: 2,
+        });
+        request
+            .metadata_mut()
+            .insert(ACTOR_METADATA, "fulfilment-service".parse().unwrap());
+        let mut unauthenticated = Request::new(request.get_ref().clone());
+        *unauthenticated.metadata_mut() = request.metadata().clone();
+        assert_eq!(
+            client.add_item(unauthenticated).await.unwrap_err().code(),
+            Code::Unauthenticated
+        );
+        request
+            .metadata_mut()
+            .insert("authorization", format!("Bearer {TOKEN}").parse().unwrap());
+        let order = client.add_item(request).await.unwrap().into_inner();
+        assert_eq!(order.items.len(), 1);
+        assert_eq!(order.total, "9.00");
+        assert_eq!(order.status, proto::OrderStatus::Draft as i32);
+
+        let history = client
+            .history(proto::OrderRequest { order_id: order.id })
+            .await
+            .unwrap()
+            .into_inner();
+        let actors: Vec<&str> = history
+            .entries
+            .iter()
+            .map(|entry| entry.actor.as_str())
+            .collect();
+        assert_eq!(actors, ["system", "fulfilment-service"]);
+    }
+
+    #[tokio::test]
+    async fn store_errors_map_to_status_codes() {
+        let (mut client, product_id) = serve().await;
+        let order = client
+            .create_order(proto::CreateOrderRequest {
+                user_id: Uuid::new_v4().to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        let add_item = |order_id: &str, quantity| proto::AddItemRequest {
+            order_id: order_id.to_string(),
+            product_id: product_id.to_string(),
+            quantity,
+        };
+
+        let code = |result: Result<Response<proto::Order>, Status>| result.unwrap_err().code();
+        assert_eq!(
+            code(client.add_item(add_item("not-a-uuid", 1)).await),
+            Code::InvalidArgument
+        );
+        assert_eq!(
+            code(
+                client
+                    .add_item(add_item(&Uuid::new_v4().to_string(), 1))
+                    .await
+            ),
+            Code::NotFound
+        );
+        assert_eq!(
+            code(client.add_item(add_item(&order.id, 11)).await),
+            Code::ResourceExhausted
+        );
+        assert_eq!(
+            code(
+                client
+                    .delete_item(proto::ItemRequest {
+                        order_id: order.id.clone(),
+                        index: 3,
+                    })
+                    .await
+            ),
+            Code::OutOfRange
+        );
+        assert_eq!(
+            code(
+                client
+                    .update_status(proto::UpdateStatusRequest {
+                        order_id: order.id,
+                        status: proto::OrderStatus::Unspecified as i32,
+                    })
+                    .await
+            ),
+            Code::InvalidArgument
+        );
+    }
+}
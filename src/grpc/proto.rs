@@ -0,0 +1,712 @@
+//! Messages of `proto/orders.proto`, with the server and client generated from them by `build.rs`.
+
+use crate::{
+    api::response::readable, audit_log, fulfilment, order_store, payment_provider, returns,
+};
+
+include!(concat!(env!("OUT_DIR"), "/orders.v1.OrderService.rs"));
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum OrderStatus {
+    Unspecified = 0,
+    Draft = 1,
+    Placed = 2,
+    Paid = 3,
+    Shipped = 4,
+    Delivered = 5,
+    Cancelled = 6,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ShippingMethod {
+    Unspecified = 0,
+    Standard = 1,
+    Express = 2,
+    Overnight = 3,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ReturnStatus {
+    Unspecified = 0,
+    Requested = 1,
+    Approved = 2,
+    Rejected = 3,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CreateOrderRequest {
+    #[prost(string, tag = "1")]
+    pub user_id: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct OrderRequest {
+    #[prost(string, tag = "1")]
+    pub order_id: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListOrdersRequest {
+    #[prost(string, tag = "1")]
+    pub user_id: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListOrdersResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub orders: Vec<Order>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AddItemRequest {
+    #[prost(string, tag = "1")]
+    pub order_id: String,
+    #[prost(string, tag = "2")]
+    pub product_id: String,
+    #[prost(int32, tag = "3")]
+    pub quantity: i32,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ItemRequest {
+    #[prost(string, tag = "1")]
+    pub order_id: String,
+    #[prost(uint32, tag = "2")]
+    pub index: u32,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct UpdateItemQuantityRequest {
+    #[prost(string, tag = "1")]
+    pub order_id: String,
+    #[prost(uint32, tag = "2")]
+    pub index: u32,
+    #[prost(int32, tag = "3")]
+    pub quantity: i32,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct UpdateStatusRequest {
+    #[prost(string, tag = "1")]
+    pub order_id: String,
+    #[prost(enumeration = "OrderStatus", tag = "2")]
+    pub status: i32,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CouponRequest {
+    #[prost(string, tag = "1")]
+    pub order_id: String,
+    #[prost(string, tag = "2")]
+    pub code: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SetShippingRequest {
+    #[prost(string, tag = "1")]
+    pub order_id: String,
+    #[prost(message, optional, tag = "2")]
+    pub address: Option<Address>,
+    #[prost(enumeration = "ShippingMethod", tag = "3")]
+    pub method: i32,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SetBillingAddressRequest {
+    #[prost(string, tag = "1")]
+    pub order_id: String,
+    #[prost(message, optional, tag = "2")]
+    pub address: Option<Address>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CheckoutRequest {
+    #[prost(string, tag = "1")]
+    pub order_id: String,
+    #[prost(string, tag = "2")]
+    pub payment_token: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RequestReturnRequest {
+    #[prost(string, tag = "1")]
+    pub order_id: String,
+    #[prost(message, repeated, tag = "2")]
+    pub items: Vec<ReturnItem>,
+    #[prost(string, tag = "3")]
+    pub reason: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ReturnItem {
+    #[prost(uint32, tag = "1")]
+    pub index: u32,
+    #[prost(int32, tag = "2")]
+    pub quantity: i32,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ReturnDecisionRequest {
+    #[prost(string, tag = "1")]
+    pub order_id: String,
+    #[prost(string, tag = "2")]
+    pub return_id: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HistoryResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub entries: Vec<HistoryEntry>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Order {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub user_id: String,
+    #[prost(message, repeated, tag = "3")]
+    pub items: Vec<Item>,
+    #[prost(enumeration = "OrderStatus", tag = "4")]
+    pub status: i32,
+    #[prost(string, optional, tag = "5")]
+    pub currency: Option<String>,
+    #[prost(string, repeated, tag = "6")]
+    pub coupons: Vec<String>,
+    #[prost(string, tag = "7")]
+    pub subtotal: String,
+    #[prost(string, tag = "8")]
+    pub discount: String,
+    #[prost(string, tag = "9")]
+    pub tax: String,
+    #[prost(message, optional, tag = "10")]
+    pub shipping_address: Option<Address>,
+    #[prost(enumeration = "ShippingMethod", optional, tag = "11")]
+    pub shipping_method: Option<i32>,
+    #[prost(string, tag = "12")]
+    pub shipping_cost: String,
+    #[prost(message, optional, tag = "13")]
+    pub billing_address: Option<Address>,
+    #[prost(message, repeated, tag = "14")]
+    pub payments: Vec<PaymentAttempt>,
+    #[prost(message, repeated, tag = "15")]
+    pub returns: Vec<Return>,
+    #[prost(string, tag = "16")]
+    pub refunded: String,
+    #[prost(string, tag = "17")]
+    pub total: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Item {
+    #[prost(string, tag = "1")]
+    pub product_id: String,
+    #[prost(int32, tag = "2")]
+    pub quantity: i32,
+    #[prost(string, tag = "3")]
+    pub unit_price: String,
+    #[prost(string, tag = "4")]
+    pub currency: String,
+    #[prost(string, tag = "5")]
+    pub tax_category: String,
+    #[prost(string, tag = "6")]
+    pub line_total: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Address {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub line1: String,
+    #[prost(string, optional, tag = "3")]
+    pub line2: Option<String>,
+    #[prost(string, tag = "4")]
+    pub city: String,
+    #[prost(string, optional, tag = "5")]
+    pub region: Option<String>,
+    #[prost(string, tag = "6")]
+    pub postal_code: String,
+    #[prost(string, tag = "7")]
+    pub country: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PaymentAttempt {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub amount: String,
+    #[prost(string, tag = "3")]
+    pub currency: String,
+    #[prost(string, tag = "4")]
+    pub attempted_at: String,
+    #[prost(oneof = "payment_attempt::Outcome", tags = "5, 6, 7, 8")]
+    pub outcome: Option<payment_attempt::Outcome>,
+}
+
+pub mod payment_attempt {
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Outcome {
+        #[prost(string, tag = "5")]
+        Approved(String),
+        #[prost(string, tag = "6")]
+        Declined(String),
+        #[prost(bool, tag = "7")]
+        Failed(bool),
+        #[prost(bool, tag = "8")]
+        Pending(bool),
+    }
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Return {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(message, repeated, tag = "2")]
+    pub lines: Vec<ReturnLine>,
+    #[prost(string, tag = "3")]
+    pub reason: String,
+    #[prost(enumeration = "ReturnStatus", tag = "4")]
+    pub status: i32,
+    #[prost(string, tag = "5")]
+    pub requested_at: String,
+    #[prost(string, optional, tag = "6")]
+    pub decided_at: Option<String>,
+    #[prost(string, tag = "7")]
+    pub refund_amount: String,
+    #[prost(string, optional, tag = "8")]
+    pub refund_reference: Option<String>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ReturnLine {
+    #[prost(uint32, tag = "1")]
+    pub item_index: u32,
+    #[prost(string, tag = "2")]
+    pub product_id: String,
+    #[prost(int32, tag = "3")]
+    pub quantity: i32,
+    #[prost(string, tag = "4")]
+    pub refund_amount: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HistoryEntry {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub actor: String,
+    #[prost(string, tag = "3")]
+    pub timestamp: String,
+    #[prost(string, tag = "4")]
+    pub operation: String,
+    #[prost(message, repeated, tag = "5")]
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct FieldChange {
+    #[prost(string, tag = "1")]
+    pub field: String,
+    #[prost(string, optional, tag = "2")]
+    pub before: Option<String>,
+    #[prost(string, optional, tag = "3")]
+    pub after: Option<String>,
+}
+
+impl From<order_store::OrderStatus> for OrderStatus {
+    fn from(status: order_store::OrderStatus) -> Self {
+        match status {
+            order_store::OrderStatus::Draft => OrderStatus::Draft,
+            order_store::OrderStatus::Placed => OrderStatus::Placed,
+            order_store::OrderStatus::Paid => OrderStatus::Paid,
+            order_store::OrderStatus::Shipped => OrderStatus::Shipped,
+            order_store::OrderStatus::Delivered => OrderStatus::Delivered,
+            order_store::OrderStatus::Cancelled => OrderStatus::Cancelled,
+        }
+    }
+}
+
+/// Fails for [`Unspecified`](OrderStatus::Unspecified).
+impl TryFrom<OrderStatus> for order_store::OrderStatus {
+    type Error = ();
+
+    fn try_from(status: OrderStatus) -> Result<Self, Self::Error> {
+        match status {
+            OrderStatus::Unspecified => Err(()),
+            OrderStatus::Draft => Ok(order_store::OrderStatus::Draft),
+            OrderStatus::Placed => Ok(order_store::OrderStatus::Placed),
+            OrderStatus::Paid => Ok(order_store::OrderStatus::Paid),
+            OrderStatus::Shipped => Ok(order_store::OrderStatus::Shipped),
+            OrderStatus::Delivered => Ok(order_store::OrderStatus::Delivered),
+            OrderStatus::Cancelled => Ok(order_store::OrderStatus::Cancelled),
+        }
+    }
+}
+
+impl From<fulfilment::ShippingMethod> for ShippingMethod {
+    fn from(method: fulfilment::ShippingMethod) -> Self {
+        match method {
+            fulfilment::ShippingMethod::Standard => ShippingMethod::Standard,
+            fulfilment::ShippingMethod::Express => ShippingMethod::Express,
+            fulfilment::ShippingMethod::Overnight => ShippingMethod::Overnight,
+        }
+    }
+}
+
+/// Fails for [`Unspecified`](ShippingMethod::Unspecified).
+impl TryFrom<ShippingMethod> for fulfilment::ShippingMethod {
+    type Error = ();
+
+    fn try_from(method: ShippingMethod) -> Result<Self, Self::Error> {
+        match method {
+            ShippingMethod::Unspecified => Err(()),
+            ShippingMethod::Standard => Ok(fulfilment::ShippingMethod::Standard),
+            ShippingMethod::Express => Ok(fulfilment::ShippingMethod::Express),
+            ShippingMethod::Overnight => Ok(fulfilment::ShippingMethod::Overnight),
+        }
+    }
+}
+
+impl From<returns::ReturnStatus> for ReturnStatus {
+    fn from(status: returns::ReturnStatus) -> Self {
+        match status {
+            returns::ReturnStatus::Requested => ReturnStatus::Requested,
+            returns::ReturnStatus::Approved => ReturnStatus::Approved,
+            returns::ReturnStatus::Rejected => ReturnStatus::Rejected,
+        }
+    }
+}
+
+impl From<fulfilment::Address> for Address {
+    fn from(address: fulfilment::Address) -> Self {
+        Address {
+            name: address.name,
+            line1: address.line1,
+            line2: address.line2,
+            city: address.city,
+            region: address.region,
+            postal_code: address.postal_code,
+            country: address.country,
+        }
+    }
+}
+
+impl From<Address> for fulfilment::Address {
+    fn from(address: Address) -> Self {
+        fulfilment::Address {
+            name: address.name,
+            line1: address.line1,
+            line2: address.line2,
+            city: address.city,
+            region: address.region,
+            postal_code: address.postal_code,
+            country: address.country,
+        }
+    }
+}
+
+impl From<order_store::Item> for Item {
+    fn from(item: order_store::Item) -> Self {
+        Item {
+            product_id: item.product_id.to_string(),
+            quantity: item.quantity,
+            unit_price: item.unit_price.to_string(),
+            currency: item.currency,
+            tax_category: item.tax_category,
+            line_total: item.line_total.to_string(),
+        }
+    }
+}
+
+impl From<payment_provider::PaymentAttempt> for PaymentAttempt {
+    fn from(attempt: payment_provider::PaymentAttempt) -> Self {
+        let outcome = match attempt.outcome {
+            payment_provider::PaymentOutcome::Approved { reference } => {
+                payment_attempt::Outcome::Approved(reference)
+            }
+            payment_provider::PaymentOutcome::Declined { reason } => {
+                payment_attempt::Outcome::Declined(reason)
+            }
+            payment_provider::PaymentOutcome::Failed => payment_attempt::Outcome::Failed(true),
+            payment_provider::PaymentOutcome::Pending => payment_attempt::Outcome::Pending(true),
+        };
+        PaymentAttempt {
+            id: attempt.id.to_string(),
+            amount: attempt.amount.to_string(),
+            currency: attempt.currency,
+            attempted_at: attempt.attempted_at.to_rfc3339(),
+            outcome: Some(outcome),
+        }
+    }
+}
+
+impl From<returns::ReturnRequest> for Return {
+    fn from(request: returns::ReturnRequest) -> Self {
+        Return {
+            id: request.id.to_string(),
+            lines: request
+                .lines
+                .into_iter()
+                .map(|line| ReturnLine {
+                    item_index: line.item_index as u32,
+                    product_id: line.product_id.to_string(),
+                    quantity: line.quantity,
+                    refund_amount: line.refund_amount.to_string(),
+                })
+                .collect(),
+            reason: request.reason,
+            status: ReturnStatus::from(request.status) as i32,
+            requested_at: request.requested_at.to_rfc3339(),
+            decided_at: request.decided_at.map(|decided_at| decided_at.to_rfc3339()),
+            refund_amount: request.refund_amount.to_string(),
+            refund_reference: request.refund_reference,
+        }
+    }
+}
+
+impl From<order_store::Order> for Order {
+    fn from(order: order_store::Order) -> Self {
+        Order {
+            id: order.id.to_string(),
+            user_id: order.user_id.to_string(),
+            items: order.items.into_iter().map(Item::from).collect(),
+            status: OrderStatus::from(order.status) as i32,
+            currency: order.currency,
+            coupons: order
+                .coupons
+                .into_iter()
+                .map(|coupon| coupon.code)
+                .collect(),
+            subtotal: order.subtotal.to_string(),
+            discount: order.discount.to_string(),
+            tax: order.tax.total.to_string(),
+            shipping_address: order.shipping_address.map(Address::from),
+            shipping_method: order
+                .shipping_method
+                .map(|method| ShippingMethod::from(method) as i32),
+            shipping_cost: order.shipping_cost.to_string(),
+            billing_address: order.billing_address.map(Address::from),
+            payments: order
+                .payments
+                .into_iter()
+                .map(PaymentAttempt::from)
+                .collect(),
+            returns: order.returns.into_iter().map(Return::from).collect(),
+            refunded: order.refunded.to_string(),
+            total: order.total.to_string(),
+        }
+    }
+}
+
+impl From<audit_log::AuditEntry> for HistoryEntry {
+    fn from(entry: audit_log::AuditEntry) -> Self {
+        HistoryEntry {
+            id: entry.id.to_string(),
+            actor: entry.actor,
+            timestamp: entry.timestamp.to_rfc3339(),
+            operation: entry.operation,
+            changes: entry
+                .changes
+                .into_iter()
+                .map(|change| FieldChange {
+                    field: change.field,
+                    before: change.before.map(|value| readable(value).to_string()),
+                    after: change.after.map(|value| readable(value).to_string()),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    /// Describes the enum values and message fields declared in `proto/orders.proto`, one line
+    /// each, as `scope name label type number`.
+    fn declared_in_proto(proto: &str) -> BTreeSet<String> {
+        let lines: Vec<&str> = proto
+            .lines()
+            .map(|line| line.split("//").next().unwrap().trim())
+            .filter(|line| !line.is_empty())
+            .collect();
+        let enums: BTreeSet<&str> = lines
+            .iter()
+            .filter_map(|line| line.strip_prefix("enum "))
+            .map(|line| line.trim_end_matches(" {"))
+            .collect();
+        let mut scopes: Vec<(&str, &str)> = Vec::new();
+        let mut declared = BTreeSet::new();
+        for line in lines {
+            let words: Vec<&str> = line
+                .trim_end_matches([';', '{'])
+                .split_whitespace()
+                .collect();
+            match words.as_slice() {
+                ["}"] => {
+                    scopes.pop();
+                }
+                [kind, name] if ["service", "message", "enum", "oneof"].contains(kind) => {
+                    scopes.push((kind, name))
+                }
+                [value, "=", number] => {
+                    if let Some(("enum", name)) = scopes.last() {
+                        declared.insert(format!("{name} {value} - - {number}"));
+                    }
+                }
+                [field @ .., name, "=", number] => {
+                    let (label, kind) = match field {
+                        [label, kind] => (*label, *kind),
+                        [kind] => ("-", *kind),
+                        _ => panic!("unexpected field {line}"),
+                    };
+                    let message_kind =
+                        kind.starts_with(char::is_uppercase) && !enums.contains(kind);
+                    let label = match scopes.last().unwrap() {
+                        ("oneof", oneof) => format!("oneof:{oneof}"),
+                        _ if message_kind && label == "optional" => "-".to_string(),
+                        _ => label.to_string(),
+                    };
+                    let message = scopes
+                        .iter()
+                        .rev()
+                        .find(|(kind, _)| *kind == "message")
+                        .unwrap()
+                        .1;
+                    declared.insert(format!("{message} {name} {label} {kind} {number}"));
+                }
+                _ => {}
+            }
+        }
+        declared
+    }
+
+    /// Describes the enum values and message fields declared by the hand-written messages in
+    /// `source`, in the same form as [`declared_in_proto`].
+    fn declared_in_rust(source: &str) -> BTreeSet<String> {
+        let source = source.split("\nimpl ").next().unwrap();
+        let mut scopes: Vec<(&str, String)> = Vec::new();
+        let mut attribute: Option<&str> = None;
+        let mut declared = BTreeSet::new();
+        for line in source.lines().map(str::trim) {
+            if let Some(prost) = line
+                .strip_prefix("#[prost(")
+                .and_then(|line| line.strip_suffix(")]"))
+            {
+                attribute = Some(prost);
+                continue;
+            }
+            let words: Vec<&str> = line
+                .trim_end_matches([',', '{'])
+                .split_whitespace()
+                .collect();
+            match words.as_slice() {
+                ["}"] => {
+                    scopes.pop();
+                }
+                ["pub", kind, name] if ["mod", "struct", "enum"].contains(kind) => {
+                    scopes.push((kind, name.to_string()))
+                }
+                [variant, "=", number] => {
+                    let (_, name) = scopes.last().unwrap();
+                    let value = format!("{}_{}", screaming_snake(name), screaming_snake(variant));
+                    declared.insert(format!("{name} {value} - - {number}"));
+                }
+                _ => {
+                    let Some(prost) = attribute.take() else {
+                        continue;
+                    };
+                    if prost.starts_with("oneof") {
+                        continue;
+                    }
+                    let options: Vec<&str> = prost.split(", ").collect();
+                    let number = options
+                        .iter()
+                        .find_map(|option| option.strip_prefix("tag = "))
+                        .unwrap()
+                        .trim_matches('"');
+                    let (message, name, label, rust_type) = match scopes.as_slice() {
+                        [.., ("mod", module), ("enum", oneof)] => {
+                            let (variant, rust_type) = line.split_once('(').unwrap();
+                            (
+                                upper_camel(module),
+                                snake(variant),
+                                format!("oneof:{}", snake(oneof)),
+                                rust_type,
+                            )
+                        }
+                        [.., ("struct", message)] => {
+                            let (name, rust_type) =
+                                line.strip_prefix("pub ").unwrap().split_once(": ").unwrap();
+                            let label = match options.get(1) {
+                                Some(&"repeated") => "repeated",
+                                Some(&"optional") if options[0] != "message" => "optional",
+                                _ => "-",
+                            };
+                            (
+                                message.clone(),
+                                name.to_string(),
+                                label.to_string(),
+                                rust_type,
+                            )
+                        }
+                        _ => panic!("unexpected field {line}"),
+                    };
+                    let kind = match options[0] {
+                        "message" => rust_type
+                            .trim_start_matches("Option<")
+                            .trim_start_matches("Vec<")
+                            .split(['>', ')', ','])
+                            .next()
+                            .unwrap(),
+                        kind => kind
+                            .strip_prefix("enumeration = ")
+                            .map_or(kind, |kind| kind.trim_matches('"')),
+                    };
+                    declared.insert(format!("{message} {name} {label} {kind} {number}"));
+                }
+            }
+        }
+        declared
+    }
+
+    fn snake(name: &str) -> String {
+        let mut snake = String::new();
+        for (i, c) in name.chars().enumerate() {
+            if c.is_uppercase() && i > 0 {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        }
+        snake
+    }
+
+    fn screaming_snake(name: &str) -> String {
+        snake(name).to_uppercase()
+    }
+
+    fn upper_camel(name: &str) -> String {
+        name.split('_')
+            .map(|word| word[..1].to_uppercase() + &word[1..])
+            .collect()
+    }
+
+    #[test]
+    fn messages_match_the_proto_file() {
+        let proto = declared_in_proto(include_str!("../../proto/orders.proto"));
+        let rust = declared_in_rust(include_str!("proto.rs"));
+        assert!(proto.contains("PaymentAttempt approved oneof:outcome string 5"));
+        assert!(proto.contains("OrderStatus ORDER_STATUS_UNSPECIFIED - - 0"));
+        let missing: Vec<&String> = proto.difference(&rust).collect();
+        let extra: Vec<&String> = rust.difference(&proto).collect();
+        assert!(
+            missing.is_empty() && extra.is_empty(),
+            "declared only in orders.proto: {missing:#?}\ndeclared only in proto.rs: {extra:#?}"
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use crate::{
+    event_sink::{EventSink, EventSinkError},
+    outbox::DomainEvent,
+};
+
+/// Posts every event as JSON to a webhook URL. Any response but a 2xx is a failed delivery.
+pub struct HttpEventSink {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl HttpEventSink {
+    /// Creates a new sink posting to `url`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let sink = HttpEventSink::new("https://example.com/order-events");
+    /// ```
+    pub fn new(url: &str) -> HttpEventSink {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("the HTTP client is built from static settings");
+        HttpEventSink {
+            client,
+            url: url.to_string(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EventSink for HttpEventSink {
+    async fn publish(&self, event: &DomainEvent) -> Result<(), EventSinkError> {
+        self.client
+            .post(&self.url)
+            .json(&event.to_json())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map(|_| ())
+            .map_err(|err| EventSinkError::Unavailable(err.to_string()))
+    }
+}
@@ -0,0 +1,94 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+use uuid::Uuid;
+
+use crate::{
+    event_log::EventLog,
+    in_mem_outbox::InMemOutbox,
+    order_events::{Snapshot, StoredEvent},
+    order_store::OrderStoreError,
+    outbox::{DomainEvent, OutboxMessage},
+};
+
+pub struct InMemEventLog {
+    events: RwLock<Vec<StoredEvent>>,
+    snapshots: RwLock<HashMap<Uuid, Snapshot>>,
+    outbox: Arc<InMemOutbox>,
+}
+
+impl InMemEventLog {
+    /// Creates a new in-memory event log without events.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let in_mem_log = InMemEventLog::new();
+    /// ```
+    pub fn new() -> InMemEventLog {
+        InMemEventLog {
+            events: RwLock::new(vec![]),
+            snapshots: RwLock::new(HashMap::new()),
+            outbox: Arc::new(InMemOutbox::new()),
+        }
+    }
+
+    /// Returns the outbox every appended event is also written to.
+    pub fn outbox(&self) -> Arc<InMemOutbox> {
+        self.outbox.clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl EventLog for InMemEventLog {
+    async fn append(&self, event: &StoredEvent) -> Result<(), OrderStoreError> {
+        let mut data = self.events.write().unwrap();
+        if data
+            .iter()
+            .any(|stored| stored.order_id == event.order_id && stored.version == event.version)
+        {
+            return Err(OrderStoreError::ConcurrentModification(event.order_id));
+        }
+        data.push(event.clone());
+        self.outbox.add(OutboxMessage::new(DomainEvent::new(
+            event.order_id,
+            event.user_id,
+            event.event.clone(),
+        )));
+        Ok(())
+    }
+
+    async fn events(
+        &self,
+        order_id: Uuid,
+        after: i64,
+    ) -> Result<Vec<StoredEvent>, OrderStoreError> {
+        let data = self.events.read().unwrap();
+        Ok(data
+            .iter()
+            .filter(|event| event.order_id == order_id && event.version > after)
+            .cloned()
+            .collect())
+    }
+
+    async fn order_ids(&self, user_id: Uuid) -> Result<Vec<Uuid>, OrderStoreError> {
+        let data = self.events.read().unwrap();
+        Ok(data
+            .iter()
+            .filter(|event| event.user_id == user_id && event.version == 1)
+            .map(|event| event.order_id)
+            .collect())
+    }
+
+    async fn snapshot(&self, order_id: Uuid) -> Result<Option<Snapshot>, OrderStoreError> {
+        let snapshots = self.snapshots.read().unwrap();
+        Ok(snapshots.get(&order_id).cloned())
+    }
+
+    async fn save_snapshot(&self, snapshot: &Snapshot) -> Result<(), OrderStoreError> {
+        let mut snapshots = self.snapshots.write().unwrap();
+        snapshots.insert(snapshot.order.id, snapshot.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+use std::{collections::HashMap, sync::RwLock};
+use uuid::Uuid;
+
+use crate::inventory_store::{InventoryStore, InventoryStoreError, StockLevel};
+
+pub struct InMemInventoryStore {
+    stock: RwLock<HashMap<Uuid, StockLevel>>,
+}
+
+impl InMemInventoryStore {
+    /// Creates a new in-memory inventory store where no product has stock.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let in_mem_store = InMemInventoryStore::new();
+    /// ```
+    pub fn new() -> InMemInventoryStore {
+        InMemInventoryStore {
+            stock: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Applies `change` to the stock level of `product_id` if `allowed` holds for it.
+    fn update(
+        &self,
+        product_id: Uuid,
+        allowed: impl Fn(&StockLevel) -> bool,
+        change: impl Fn(&mut StockLevel),
+    ) -> Result<(), InventoryStoreError> {
+        let mut data = self.stock.write().unwrap();
+        let level = data
+            .entry(product_id)
+            .or_insert_with(|| StockLevel::empty(product_id));
+        if allowed(level) {
+            change(level);
+            Ok(())
+        } else {
+            Err(InventoryStoreError::InsufficientStock(product_id))
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl InventoryStore for InMemInventoryStore {
+    async fn get_stock(&self, product_id: Uuid) -> Result<StockLevel, InventoryStoreError> {
+        let data = self.stock.read().unwrap();
+        Ok(data
+            .get(&product_id)
+            .cloned()
+            .unwrap_or_else(|| StockLevel::empty(product_id)))
+    }
+
+    async fn set_available(
+        &self,
+        product_id: Uuid,
+        available: i32,
+    ) -> Result<StockLevel, InventoryStoreError> {
+        let mut data = self.stock.write().unwrap();
+        let level = data
+            .entry(product_id)
+            .or_insert_with(|| StockLevel::empty(product_id));
+        level.available = available;
+        Ok(level.clone())
+    }
+
+    async fn reserve(&self, product_id: Uuid, quantity: i32) -> Result<(), InventoryStoreError> {
+        self.update(
+            product_id,
+            |level| level.available >= quantity,
+            |level| {
+                level.available -= quantity;
+                level.reserved += quantity;
+            },
+        )
+    }
+
+    async fn release(&self, product_id: Uuid, quantity: i32) -> Result<(), InventoryStoreError> {
+        self.update(
+            product_id,
+            |level| level.reserved >= quantity,
+            |level| {
+                level.reserved -= quantity;
+                level.available += quantity;
+            },
+        )
+    }
+
+    async fn commit(&self, product_id: Uuid, quantity: i32) -> Result<(), InventoryStoreError> {
+        self.update(
+            product_id,
+            |level| level.reserved >= quantity,
+            |level| {
+                level.reserved -= quantity;
+                level.committed += quantity;
+            },
+        )
+    }
+
+    async fn restock(&self, product_id: Uuid, quantity: i32) -> Result<(), InventoryStoreError> {
+        self.update(
+            product_id,
+            |level| level.committed >= quantity,
+            |level| {
+                level.committed -= quantity;
+                level.available += quantity;
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn unknown_product_has_no_stock() {
+        let in_mem_store = InMemInventoryStore::new();
+        let product_id = Uuid::new_v4();
+        assert_eq!(
+            in_mem_store.get_stock(product_id).await.unwrap(),
+            StockLevel::empty(product_id)
+        );
+    }
+
+    #[tokio::test]
+    async fn reserved_units_can_be_released_or_committed() {
+        let in_mem_store = InMemInventoryStore::new();
+        let product_id = Uuid::new_v4();
+        in_mem_store.set_available(product_id, 10).await.unwrap();
+        in_mem_store.reserve(product_id, 6).await.unwrap();
+        in_mem_store.release(product_id, 2).await.unwrap();
+        in_mem_store.commit(product_id, 4).await.unwrap();
+        let level = in_mem_store.get_stock(product_id).await.unwrap();
+        assert_eq!(
+            (level.available, level.reserved, level.committed),
+            (6, 0, 4)
+        );
+    }
+
+    #[tokio::test]
+    async fn reservation_beyond_available_stock_is_rejected() {
+        let in_mem_store = InMemInventoryStore::new();
+        let product_id = Uuid::new_v4();
+        in_mem_store.set_available(product_id, 3).await.unwrap();
+        assert!(matches!(
+            in_mem_store.reserve(product_id, 4).await,
+            Err(InventoryStoreError::InsufficientStock(id)) if id == product_id
+        ));
+        assert_eq!(
+            in_mem_store.get_stock(product_id).await.unwrap().available,
+            3
+        );
+    }
+}
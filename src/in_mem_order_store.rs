@@ -1,23 +1,109 @@
-use std::sync::RwLock;
+use std::sync::{Arc, RwLock};
+use tokio::sync::Mutex;
+use tracing::error;
 use uuid::Uuid;
 
-use crate::order_store::{Item, Order, OrderStore, OrderStoreError};
+use crate::{
+    audit_log::AuditEntry,
+    fulfilment::{Address, ShippingMethod},
+    in_mem_order_updates::InMemOrderUpdates,
+    in_mem_outbox::InMemOutbox,
+    inventory_store::InventoryStoreNewType,
+    order_events::OrderEvent,
+    order_store::{
+        find_applicable_coupon, find_orderable_product, move_order_stock, pay, payment_result,
+        refund, restock_return, update_taxes, void_charge, Order, OrderStatus, OrderStore,
+        OrderStoreError,
+    },
+    outbox::{DomainEvent, OutboxMessage},
+    payment_provider::{PaymentAttempt, PaymentOutcome, PaymentProvider},
+    product_store::ProductStoreNewType,
+    promotion_store::PromotionStoreNewType,
+    returns::{ReturnItem, ReturnRequest},
+    tax_calculator::TaxCalculator,
+};
 
 pub struct InMemOrderStore {
     orders: RwLock<Vec<Order>>,
+    audit: RwLock<Vec<AuditEntry>>,
+    products: Arc<ProductStoreNewType>,
+    inventory: Arc<InventoryStoreNewType>,
+    promotions: Arc<PromotionStoreNewType>,
+    tax: Arc<dyn TaxCalculator>,
+    payments: Arc<dyn PaymentProvider>,
+    outbox: Arc<InMemOutbox>,
+    updates: Arc<InMemOrderUpdates>,
+    mutations: Mutex<()>, // serializes read-modify-write cycles, which await on the inventory in between
 }
 
 impl InMemOrderStore {
-    /// Creates a new in-memory order store.
+    /// Creates a new in-memory order store that validates items against the `products` catalog,
+    /// reserves their stock in `inventory`, looks coupons up in `promotions`, calculates taxes
+    /// with `tax` and charges checkouts with `payments`.
     ///
     /// # Examples
     ///
     /// ```
-    /// let in_mem_store = InMemOrderStore::new();
+    /// let products = Arc::new(ProductStoreNewType::new(InMemProductStore::new()));
+    /// let inventory = Arc::new(InventoryStoreNewType::new(InMemInventoryStore::new()));
+    /// let promotions = Arc::new(PromotionStoreNewType::new(InMemPromotionStore::new()));
+    /// let tax = Arc::new(RulesTableTaxCalculator::tax_free());
+    /// let payments = Arc::new(FakePaymentProvider::new());
+    /// let in_mem_store = InMemOrderStore::new(products, inventory, promotions, tax, payments);
     /// ```
-    pub fn new() -> InMemOrderStore {
+    pub fn new(
+        products: Arc<ProductStoreNewType>,
+        inventory: Arc<InventoryStoreNewType>,
+        promotions: Arc<PromotionStoreNewType>,
+        tax: Arc<dyn TaxCalculator>,
+        payments: Arc<dyn PaymentProvider>,
+    ) -> InMemOrderStore {
         InMemOrderStore {
             orders: RwLock::new(vec![]),
+            audit: RwLock::new(vec![]),
+            products,
+            inventory,
+            promotions,
+            tax,
+            payments,
+            outbox: Arc::new(InMemOutbox::new()),
+            updates: Arc::new(InMemOrderUpdates::new()),
+            mutations: Mutex::new(()),
+        }
+    }
+
+    /// Returns the outbox the domain events of the store are written to.
+    pub fn outbox(&self) -> Arc<InMemOutbox> {
+        self.outbox.clone()
+    }
+
+    /// Returns the broadcast of the orders changed by the store.
+    pub fn updates(&self) -> Arc<InMemOrderUpdates> {
+        self.updates.clone()
+    }
+
+    /// Replaces the stored version of `order`, recording the change made by `operation` and
+    /// the `event` it emitted.
+    /// Gives the usage of coupon `code` back to `user_id` once the change to order `order_id`
+    /// dropping it is saved. A failure is logged instead of failing a change that already happened.
+    async fn release_coupon(&self, code: &str, order_id: Uuid, user_id: Uuid) {
+        if let Err(err) = self.promotions.release(code, user_id).await {
+            error!(
+                "cannot release coupon {} of order {}: {}",
+                code, order_id, err
+            );
+        }
+    }
+
+    fn save_order(&self, operation: &str, order: Order, event: OrderEvent) {
+        let mut data = self.orders.write().unwrap();
+        if let Some(stored) = data.iter_mut().find(|stored| stored.id == order.id) {
+            let entry = AuditEntry::new(operation, Some(stored), &order);
+            self.audit.write().unwrap().push(entry);
+            let message = OutboxMessage::new(DomainEvent::new(order.id, order.user_id, event));
+            self.outbox.add(message);
+            self.updates.publish(&order);
+            *stored = order;
         }
     }
 }
@@ -28,6 +114,17 @@ impl OrderStore for InMemOrderStore {
         let order = Order::new(user_id);
         let mut data = self.orders.write().unwrap();
         data.push(order.clone());
+        let entry = AuditEntry::new("create_order", None, &order);
+        self.audit.write().unwrap().push(entry);
+        let event = OrderEvent::OrderCreated {
+            order_id: order.id,
+            user_id,
+            created_at: order.created_at,
+        };
+        self.outbox.add(OutboxMessage::new(DomainEvent::new(
+            order.id, user_id, event,
+        )));
+        self.updates.publish(&order);
         Ok(order)
     }
 
@@ -55,263 +152,365 @@ impl OrderStore for InMemOrderStore {
         product_id: Uuid,
         quantity: i32,
     ) -> Result<(), OrderStoreError> {
-        let mut data = self.orders.write().unwrap();
-        for order in data.iter_mut() {
-            if order.id == order_id {
-                order.items.push(Item {
-                    product_id,
-                    quantity,
-                });
-                return Ok(());
+        let product = find_orderable_product(&self.products, product_id).await?;
+        let _guard = self.mutations.lock().await;
+        let mut order = self.get_order(order_id).await?;
+        let item = order.add_item(&product, quantity)?;
+        update_taxes(&mut order, self.tax.as_ref()).await?;
+        self.inventory.reserve(product_id, quantity).await?;
+        let event = OrderEvent::ItemAdded {
+            item,
+            tax: order.tax.clone(),
+        };
+        self.save_order("add_item", order, event);
+        Ok(())
+    }
+
+    async fn delete_item(
+        &self,
+        order_id: Uuid,
+        index: usize,
+        expected: Option<Uuid>,
+    ) -> Result<(), OrderStoreError> {
+        let _guard = self.mutations.lock().await;
+        let mut order = self.get_order(order_id).await?;
+        let item = order.delete_item(index, expected)?;
+        update_taxes(&mut order, self.tax.as_ref()).await?;
+        self.inventory
+            .release(item.product_id, item.quantity)
+            .await?;
+        let event = OrderEvent::ItemRemoved {
+            index,
+            tax: order.tax.clone(),
+        };
+        self.save_order("delete_item", order, event);
+        Ok(())
+    }
+
+    async fn update_item_quantity(
+        &self,
+        order_id: Uuid,
+        index: usize,
+        quantity: i32,
+        expected: Option<Uuid>,
+    ) -> Result<(), OrderStoreError> {
+        let _guard = self.mutations.lock().await;
+        let mut order = self.get_order(order_id).await?;
+        let previous = order.set_item_quantity(index, quantity, expected)?;
+        update_taxes(&mut order, self.tax.as_ref()).await?;
+        let product_id = order.items[index].product_id;
+        if quantity > previous {
+            self.inventory
+                .reserve(product_id, quantity - previous)
+                .await?;
+        } else {
+            self.inventory
+                .release(product_id, previous - quantity)
+                .await?;
+        }
+        let event = OrderEvent::ItemQuantityChanged {
+            index,
+            quantity,
+            tax: order.tax.clone(),
+        };
+        self.save_order("update_item_quantity", order, event);
+        Ok(())
+    }
+
+    async fn update_status(
+        &self,
+        order_id: Uuid,
+        status: OrderStatus,
+    ) -> Result<(), OrderStoreError> {
+        let _guard = self.mutations.lock().await;
+        let mut order = self.get_order(order_id).await?;
+        order.set_status(status)?;
+        move_order_stock(&self.inventory, &order, status).await?;
+        let (user_id, coupons) = (order.user_id, order.coupons.clone());
+        self.save_order("update_status", order, OrderEvent::StatusChanged { status });
+        if status == OrderStatus::Cancelled {
+            for coupon in &coupons {
+                self.release_coupon(&coupon.code, order_id, user_id).await;
             }
         }
-        Err(OrderStoreError::OrderNotFound(order_id))
+        Ok(())
     }
 
-    async fn delete_item(&self, order_id: Uuid, index: usize) -> Result<(), OrderStoreError> {
-        let mut data = self.orders.write().unwrap();
-        for order in data.iter_mut() {
-            if order.id == order_id {
-                if index < order.items.len() {
-                    order.items.remove(index);
-                    return Ok(());
-                } else {
-                    return Err(OrderStoreError::ItemIndexOutOfBounds(index));
+    async fn apply_coupon(&self, order_id: Uuid, code: &str) -> Result<(), OrderStoreError> {
+        let coupon = find_applicable_coupon(&self.promotions, code).await?;
+        let _guard = self.mutations.lock().await;
+        let mut order = self.get_order(order_id).await?;
+        order.apply_coupon(coupon.clone())?;
+        update_taxes(&mut order, self.tax.as_ref()).await?;
+        self.promotions.redeem(code, order.user_id).await?;
+        let event = OrderEvent::CouponApplied {
+            coupon,
+            tax: Some(order.tax.clone()),
+        };
+        self.save_order("apply_coupon", order, event);
+        Ok(())
+    }
+
+    async fn remove_coupon(&self, order_id: Uuid, code: &str) -> Result<(), OrderStoreError> {
+        let _guard = self.mutations.lock().await;
+        let mut order = self.get_order(order_id).await?;
+        order.remove_coupon(code)?;
+        update_taxes(&mut order, self.tax.as_ref()).await?;
+        let user_id = order.user_id;
+        let event = OrderEvent::CouponRemoved {
+            code: code.to_string(),
+            tax: Some(order.tax.clone()),
+        };
+        self.save_order("remove_coupon", order, event);
+        self.release_coupon(code, order_id, user_id).await;
+        Ok(())
+    }
+
+    async fn set_shipping(
+        &self,
+        order_id: Uuid,
+        address: Address,
+        method: ShippingMethod,
+    ) -> Result<(), OrderStoreError> {
+        let _guard = self.mutations.lock().await;
+        let mut order = self.get_order(order_id).await?;
+        order.set_shipping(address.clone(), method)?;
+        update_taxes(&mut order, self.tax.as_ref()).await?;
+        let event = OrderEvent::ShippingSet {
+            address,
+            method,
+            cost: order.shipping_cost,
+            tax: order.tax.clone(),
+        };
+        self.save_order("set_shipping", order, event);
+        Ok(())
+    }
+
+    async fn set_billing_address(
+        &self,
+        order_id: Uuid,
+        address: Address,
+    ) -> Result<(), OrderStoreError> {
+        let _guard = self.mutations.lock().await;
+        let mut order = self.get_order(order_id).await?;
+        order.set_billing_address(address.clone())?;
+        let event = OrderEvent::BillingAddressSet { address };
+        self.save_order("set_billing_address", order, event);
+        Ok(())
+    }
+
+    async fn checkout(
+        &self,
+        order_id: Uuid,
+        payment_token: &str,
+    ) -> Result<PaymentAttempt, OrderStoreError> {
+        let _guard = self.mutations.lock().await;
+        let mut order = self.get_order(order_id).await?;
+        let charge = order.checkout_charge(payment_token)?;
+        if let Some(attempt) = order.start_payment(&charge) {
+            self.save_order("checkout", order, OrderEvent::PaymentAttempted { attempt });
+        }
+        let outcome = pay(self.payments.as_ref(), &charge).await;
+        let mut order = self.get_order(order_id).await?;
+        let (attempt, recorded) = order.record_payment(&charge, outcome);
+        if recorded {
+            if order.status == OrderStatus::Paid {
+                let placed = move_order_stock(&self.inventory, &order, OrderStatus::Placed).await;
+                if let Err(err) = placed {
+                    if void_charge(self.payments.as_ref(), &charge, &attempt).await {
+                        let mut order = self.get_order(order_id).await?;
+                        let (attempt, _) = order.record_payment(&charge, PaymentOutcome::Failed);
+                        let event = OrderEvent::PaymentAttempted { attempt };
+                        self.save_order("checkout", order, event);
+                    }
+                    return Err(err);
                 }
             }
+            let event = OrderEvent::PaymentAttempted {
+                attempt: attempt.clone(),
+            };
+            self.save_order("checkout", order, event);
         }
-        Err(OrderStoreError::OrderNotFound(order_id))
+        payment_result(attempt)
+    }
+
+    async fn request_return(
+        &self,
+        order_id: Uuid,
+        items: Vec<ReturnItem>,
+        reason: String,
+    ) -> Result<ReturnRequest, OrderStoreError> {
+        let _guard = self.mutations.lock().await;
+        let mut order = self.get_order(order_id).await?;
+        let request = order.request_return(&items, reason)?;
+        let event = OrderEvent::ReturnRequested {
+            request: request.clone(),
+        };
+        self.save_order("request_return", order, event);
+        Ok(request)
+    }
+
+    async fn approve_return(
+        &self,
+        order_id: Uuid,
+        return_id: Uuid,
+    ) -> Result<ReturnRequest, OrderStoreError> {
+        let _guard = self.mutations.lock().await;
+        let mut order = self.get_order(order_id).await?;
+        let request = refund(&mut order, self.payments.as_ref(), return_id).await?;
+        restock_return(&self.inventory, &request).await?;
+        let event = OrderEvent::ReturnDecided {
+            request: request.clone(),
+        };
+        self.save_order("approve_return", order, event);
+        Ok(request)
+    }
+
+    async fn reject_return(
+        &self,
+        order_id: Uuid,
+        return_id: Uuid,
+    ) -> Result<ReturnRequest, OrderStoreError> {
+        let _guard = self.mutations.lock().await;
+        let mut order = self.get_order(order_id).await?;
+        let request = order.reject_return(return_id)?;
+        let event = OrderEvent::ReturnDecided {
+            request: request.clone(),
+        };
+        self.save_order("reject_return", order, event);
+        Ok(request)
+    }
+
+    async fn history(&self, order_id: Uuid) -> Result<Vec<AuditEntry>, OrderStoreError> {
+        self.get_order(order_id).await?;
+        let audit = self.audit.read().unwrap();
+        Ok(audit
+            .iter()
+            .filter(|entry| entry.order_id == order_id)
+            .cloned()
+            .collect())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use futures::StreamExt;
+
     use super::*;
-    use test_context::{test_context, AsyncTestContext};
+    use crate::order_updates::{OrderFilter, OrderUpdates};
+    use crate::promotion_store::{PromotionStore, PromotionStoreError};
+
+    crate::order_store_tests::order_store_tests!(InMemOrderStore::new);
 
     #[test_context(Context)]
     #[tokio::test]
-    async fn create_order_adds_order_to_store(ctx: &mut Context) {
-        assert_eq!(
-            ctx.in_mem_store
-                .list_orders(ctx.user_id_1)
-                .await
-                .unwrap()
-                .len(),
-            2
+    async fn changes_write_their_events_to_the_outbox(ctx: &mut Context) {
+        let store = InMemOrderStore::new(
+            ctx.products.clone(),
+            ctx.inventory.clone(),
+            ctx.promotions.clone(),
+            Arc::new(RulesTableTaxCalculator::tax_free()),
+            Arc::new(FakePaymentProvider::new()),
         );
-        assert_eq!(
-            ctx.in_mem_store
-                .list_orders(ctx.user_id_2)
-                .await
-                .unwrap()
-                .len(),
-            1
-        );
-    }
+        let order = store.create_order(ctx.user_id_1).await.unwrap();
+        store.add_item(order.id, ctx.product_id_0, 1).await.unwrap();
+        store
+            .update_status(order.id, OrderStatus::Placed)
+            .await
+            .unwrap();
 
-    #[test_context(Context)]
-    #[tokio::test]
-    async fn get_order_retrieves_existing_order(ctx: &mut Context) {
-        if let Ok(stored_order) = ctx.in_mem_store.get_order(ctx.order_1_user_1.id).await {
-            assert_eq!(stored_order, ctx.order_1_user_1);
-        } else {
-            panic!("Order not found after being created");
-        }
+        let messages = store.outbox().messages();
+        let event_types: Vec<&str> = messages
+            .iter()
+            .map(|message| message.event.event_type.as_str())
+            .collect();
+        assert_eq!(event_types, ["OrderCreated", "ItemAdded", "StatusChanged"]);
+        assert!(messages
+            .iter()
+            .all(|message| message.event.order_id == order.id && !message.delivered));
     }
 
     #[test_context(Context)]
     #[tokio::test]
-    async fn get_order_returns_error_for_non_existing_order(ctx: &mut Context) {
-        let order_id = Uuid::new_v4();
-        if let Err(OrderStoreError::OrderNotFound(not_found_id)) =
-            ctx.in_mem_store.get_order(order_id).await
-        {
-            assert_eq!(order_id, not_found_id);
-        } else {
-            panic!("Unexpected order found");
-        }
-    }
-
-    #[tokio::test]
-    async fn item_cannot_be_added_to_non_existing_order() {
-        let in_mem_store = InMemOrderStore::new();
-        assert!(in_mem_store
-            .add_item(Uuid::new_v4(), Uuid::new_v4(), 1)
+    async fn subscribers_get_every_change(ctx: &mut Context) {
+        let store = InMemOrderStore::new(
+            ctx.products.clone(),
+            ctx.inventory.clone(),
+            ctx.promotions.clone(),
+            Arc::new(RulesTableTaxCalculator::tax_free()),
+            Arc::new(FakePaymentProvider::new()),
+        );
+        let mut updates = store
+            .updates()
+            .subscribe(OrderFilter::User(ctx.user_id_1))
             .await
-            .is_err());
+            .unwrap();
+        let order = store.create_order(ctx.user_id_1).await.unwrap();
+        store.add_item(order.id, ctx.product_id_0, 2).await.unwrap();
+
+        assert_eq!(updates.next().await.unwrap(), order);
+        let changed = updates.next().await.unwrap();
+        assert_eq!(changed.id, order.id);
+        assert_eq!(changed.items.len(), 1);
+        assert_eq!(changed.items[0].quantity, 2);
     }
 
-    #[test_context(Context)]
-    #[tokio::test]
-    async fn order_contains_added_item(ctx: &mut Context) {
-        let product_id = Uuid::new_v4();
-        let quantity = 42;
-        if let Ok(()) = ctx
-            .in_mem_store
-            .add_item(ctx.order_1_user_1.id, product_id, quantity)
-            .await
-        {
-            if let Ok(stored_order) = ctx.in_mem_store.get_order(ctx.order_1_user_1.id).await {
-                assert_eq!(stored_order.items.len(), 1);
-                assert_eq!(stored_order.items[0].product_id, product_id);
-                assert_eq!(stored_order.items[0].quantity, quantity);
-            } else {
-                panic!("Order not found after being created");
-            }
-        } else {
-            panic!("Failed to add item to order");
+    /// Promotions giving usages back to nobody.
+    struct UnreleasablePromotions(InMemPromotionStore);
+
+    #[async_trait::async_trait]
+    impl PromotionStore for UnreleasablePromotions {
+        async fn create_coupon(&self, coupon: Coupon) -> Result<Coupon, PromotionStoreError> {
+            self.0.create_coupon(coupon).await
         }
-    }
 
-    #[test_context(Context)]
-    #[tokio::test]
-    async fn order_contains_added_items(ctx: &mut Context) {
-        let product_id_0 = Uuid::new_v4();
-        let quantity_0 = 42;
-        let product_id_1 = Uuid::new_v4();
-        let quantity_1 = 7;
-        if let (Ok(()), Ok(())) = (
-            ctx.in_mem_store
-                .add_item(ctx.order_1_user_1.id, product_id_0, quantity_0)
-                .await,
-            ctx.in_mem_store
-                .add_item(ctx.order_1_user_1.id, product_id_1, quantity_1)
-                .await,
-        ) {
-            if let Ok(stored_order) = ctx.in_mem_store.get_order(ctx.order_1_user_1.id).await {
-                assert_eq!(stored_order.items.len(), 2);
-                assert_eq!(stored_order.items[0].product_id, product_id_0);
-                assert_eq!(stored_order.items[0].quantity, quantity_0);
-                assert_eq!(stored_order.items[1].product_id, product_id_1);
-                assert_eq!(stored_order.items[1].quantity, quantity_1);
-            } else {
-                panic!("Order not found after being created");
-            }
-        } else {
-            panic!("Failed to add items to order");
+        async fn get_coupon(&self, code: &str) -> Result<Coupon, PromotionStoreError> {
+            self.0.get_coupon(code).await
         }
-    }
 
-    #[tokio::test]
-    async fn item_cannot_be_deleted_from_non_existing_order() {
-        let in_mem_store = InMemOrderStore::new();
-        assert!(in_mem_store.delete_item(Uuid::new_v4(), 1).await.is_err());
-    }
+        async fn list_coupons(&self) -> Result<Vec<Coupon>, PromotionStoreError> {
+            self.0.list_coupons().await
+        }
 
-    #[test_context(Context)]
-    #[tokio::test]
-    async fn attempt_to_delete_non_existent_item_from_order_returns_error(ctx: &mut Context) {
-        let product_id_0 = Uuid::new_v4();
-        let quantity_0 = 42;
-        let product_id_1 = Uuid::new_v4();
-        let quantity_1 = 7;
-        if let (Ok(()), Ok(())) = (
-            ctx.in_mem_store
-                .add_item(ctx.order_1_user_1.id, product_id_0, quantity_0)
-                .await,
-            ctx.in_mem_store
-                .add_item(ctx.order_1_user_1.id, product_id_1, quantity_1)
-                .await,
-        ) {
-            if let Err(OrderStoreError::ItemIndexOutOfBounds(index)) =
-                ctx.in_mem_store.delete_item(ctx.order_1_user_1.id, 2).await
-            {
-                assert_eq!(index, 2);
-            } else {
-                panic!("Deleting non-existent item must produce error");
-            }
-        } else {
-            panic!("Failed to add items to order");
+        async fn redeem(&self, code: &str, user_id: Uuid) -> Result<(), PromotionStoreError> {
+            self.0.redeem(code, user_id).await
         }
-    }
 
-    #[test_context(Context)]
-    #[tokio::test]
-    async fn last_item_can_be_deleted_from_order(ctx: &mut Context) {
-        let product_id_0 = Uuid::new_v4();
-        let quantity_0 = 42;
-        let product_id_1 = Uuid::new_v4();
-        let quantity_1 = 7;
-        if let (Ok(()), Ok(())) = (
-            ctx.in_mem_store
-                .add_item(ctx.order_1_user_1.id, product_id_0, quantity_0)
-                .await,
-            ctx.in_mem_store
-                .add_item(ctx.order_1_user_1.id, product_id_1, quantity_1)
-                .await,
-        ) {
-            if let Ok(()) = ctx.in_mem_store.delete_item(ctx.order_1_user_1.id, 1).await {
-                if let Ok(stored_order) = ctx.in_mem_store.get_order(ctx.order_1_user_1.id).await {
-                    assert_eq!(stored_order.items.len(), 1);
-                    assert_eq!(stored_order.items[0].product_id, product_id_0);
-                    assert_eq!(stored_order.items[0].quantity, quantity_0);
-                } else {
-                    panic!("Order not found after being created");
-                }
-            } else {
-                panic!("Failed to delete item from order");
-            }
-        } else {
-            panic!("Failed to add items to order");
+        async fn release(&self, _code: &str, _user_id: Uuid) -> Result<(), PromotionStoreError> {
+            Err(PromotionStoreError::StoreUnavailable)
         }
     }
 
     #[test_context(Context)]
     #[tokio::test]
-    async fn first_item_can_be_deleted_from_order(ctx: &mut Context) {
-        let product_id_0 = Uuid::new_v4();
-        let quantity_0 = 42;
-        let product_id_1 = Uuid::new_v4();
-        let quantity_1 = 7;
-        if let (Ok(()), Ok(())) = (
-            ctx.in_mem_store
-                .add_item(ctx.order_1_user_1.id, product_id_0, quantity_0)
-                .await,
-            ctx.in_mem_store
-                .add_item(ctx.order_1_user_1.id, product_id_1, quantity_1)
-                .await,
-        ) {
-            if let Ok(()) = ctx.in_mem_store.delete_item(ctx.order_1_user_1.id, 0).await {
-                if let Ok(stored_order) = ctx.in_mem_store.get_order(ctx.order_1_user_1.id).await {
-                    assert_eq!(stored_order.items.len(), 1);
-                    assert_eq!(stored_order.items[0].product_id, product_id_1);
-                    assert_eq!(stored_order.items[0].quantity, quantity_1);
-                } else {
-                    panic!("Order not found after being created");
-                }
-            } else {
-                panic!("Failed to delete item from order");
-            }
-        } else {
-            panic!("Failed to add items to order");
-        }
-    }
-
-    struct Context {
-        user_id_1: Uuid,
-        user_id_2: Uuid,
-        in_mem_store: InMemOrderStore,
-        order_1_user_1: Order,
-    }
+    async fn orders_are_cancelled_even_if_their_coupons_cannot_be_released(ctx: &mut Context) {
+        let promotions = Arc::new(PromotionStoreNewType::new(UnreleasablePromotions(
+            InMemPromotionStore::new(),
+        )));
+        let store = InMemOrderStore::new(
+            ctx.products.clone(),
+            ctx.inventory.clone(),
+            promotions.clone(),
+            Arc::new(RulesTableTaxCalculator::tax_free()),
+            Arc::new(FakePaymentProvider::new()),
+        );
+        promotions
+            .create_coupon(coupon(
+                "TENOFF",
+                PromotionRule::Percentage { percent: dec!(10) },
+            ))
+            .await
+            .unwrap();
+        let order = store.create_order(ctx.user_id_1).await.unwrap();
+        store.add_item(order.id, ctx.product_id_0, 1).await.unwrap();
+        store.apply_coupon(order.id, "TENOFF").await.unwrap();
 
-    #[async_trait::async_trait]
-    impl AsyncTestContext for Context {
-        async fn setup() -> Context {
-            let user_id_1 = Uuid::new_v4();
-            let in_mem_store = InMemOrderStore::new();
-            let order = in_mem_store.create_order(user_id_1).await;
-            let ctx = Context {
-                user_id_1,
-                user_id_2: Uuid::new_v4(),
-                in_mem_store,
-                order_1_user_1: order.unwrap(),
-            };
-            _ = ctx.in_mem_store.create_order(ctx.user_id_2).await;
-            _ = ctx.in_mem_store.create_order(ctx.user_id_1).await;
+        store
+            .update_status(order.id, OrderStatus::Cancelled)
+            .await
+            .unwrap();
 
-            ctx
-        }
-        async fn teardown(self) {}
+        let stored = store.get_order(order.id).await.unwrap();
+        assert_eq!(stored.status, OrderStatus::Cancelled);
     }
 }
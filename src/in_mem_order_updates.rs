@@ -0,0 +1,138 @@
+use std::{
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+};
+
+use futures::{stream::BoxStream, StreamExt};
+use lru::LruCache;
+use tokio::sync::broadcast::{self, error::RecvError, Receiver};
+use uuid::Uuid;
+
+use crate::{
+    order_store::{Order, OrderStoreError},
+    order_updates::{OrderFilter, OrderUpdates},
+};
+
+/// Changes a subscriber can fall behind by before missing some.
+const CAPACITY: usize = 256;
+
+/// Orders whose latest state is kept, so subscribers that fell behind get the orders they missed.
+const LATEST_CAPACITY: usize = 4096;
+
+/// Broadcasts the orders saved by the stores of this process to every subscriber.
+pub struct InMemOrderUpdates {
+    sender: broadcast::Sender<Order>,
+    latest: Arc<Mutex<LruCache<Uuid, Order>>>, // locked while sending, so it matches the broadcast
+}
+
+impl InMemOrderUpdates {
+    /// Creates a new broadcast of order changes without subscribers.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let updates = InMemOrderUpdates::new();
+    /// ```
+    pub fn new() -> InMemOrderUpdates {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        let capacity = NonZeroUsize::new(LATEST_CAPACITY).expect("the capacity is not zero");
+        InMemOrderUpdates {
+            sender,
+            latest: Arc::new(Mutex::new(LruCache::new(capacity))),
+        }
+    }
+
+    /// Sends the new state of `order` to the current subscribers.
+    pub fn publish(&self, order: &Order) {
+        let mut latest = self.latest.lock().unwrap();
+        latest.put(order.id, order.clone());
+        // no subscribers is fine
+        _ = self.sender.send(order.clone());
+    }
+}
+
+/// A subscriber: its receiver, the orders it follows and the orders to send before receiving
+/// again.
+struct Subscriber {
+    receiver: Receiver<Order>,
+    filter: OrderFilter,
+    latest: Arc<Mutex<LruCache<Uuid, Order>>>,
+    missed: Vec<Order>,
+}
+
+impl Subscriber {
+    async fn next(mut self) -> Option<(Order, Subscriber)> {
+        loop {
+            if let Some(order) = self.missed.pop() {
+                return Some((order, self));
+            }
+            match self.receiver.recv().await {
+                Ok(order) if self.filter.matches(&order) => return Some((order, self)),
+                Ok(_) => continue,
+                Err(RecvError::Lagged(_)) => self.catch_up(),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Skips the changes still queued and sends the latest state of the followed orders instead,
+    /// as some of their changes were dropped.
+    fn catch_up(&mut self) {
+        let latest = self.latest.lock().unwrap();
+        self.receiver = self.receiver.resubscribe();
+        // popped from the end, so the least recently changed order is sent first
+        self.missed = latest
+            .iter()
+            .map(|(_, order)| order)
+            .filter(|order| self.filter.matches(order))
+            .cloned()
+            .collect();
+    }
+}
+
+#[async_trait::async_trait]
+impl OrderUpdates for InMemOrderUpdates {
+    async fn subscribe(
+        &self,
+        filter: OrderFilter,
+    ) -> Result<BoxStream<'static, Order>, OrderStoreError> {
+        let subscriber = Subscriber {
+            receiver: self.sender.subscribe(),
+            filter,
+            latest: self.latest.clone(),
+            missed: vec![],
+        };
+        Ok(futures::stream::unfold(subscriber, Subscriber::next).boxed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn subscribers_that_fall_behind_get_the_latest_state_of_their_orders() {
+        let updates = InMemOrderUpdates::new();
+        let followed = Order::new(Uuid::new_v4());
+        let other = Order::new(Uuid::new_v4());
+        let mut orders = updates
+            .subscribe(OrderFilter::Order(followed.id))
+            .await
+            .unwrap();
+
+        let mut last = followed.clone();
+        for round in 0..CAPACITY * 2 {
+            last.shipping_cost = round.into();
+            updates.publish(&last);
+            updates.publish(&other);
+        }
+
+        assert_eq!(orders.next().await.unwrap(), last);
+        last.shipping_cost = 1.into();
+        updates.publish(&other);
+        updates.publish(&last);
+        assert_eq!(orders.next().await.unwrap(), last);
+    }
+}
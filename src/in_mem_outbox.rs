@@ -0,0 +1,80 @@
+use std::sync::RwLock;
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::{
+    order_store::OrderStoreError,
+    outbox::{Outbox, OutboxMessage},
+};
+
+pub struct InMemOutbox {
+    messages: RwLock<Vec<OutboxMessage>>,
+}
+
+impl InMemOutbox {
+    /// Creates a new empty in-memory outbox.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let in_mem_outbox = InMemOutbox::new();
+    /// ```
+    pub fn new() -> InMemOutbox {
+        InMemOutbox {
+            messages: RwLock::new(vec![]),
+        }
+    }
+
+    /// Adds `message` to the outbox; the in-memory stores call it while holding their own lock,
+    /// so the message is written together with the change.
+    pub fn add(&self, message: OutboxMessage) {
+        self.messages.write().unwrap().push(message);
+    }
+
+    /// Returns every message, delivered or not, oldest first.
+    #[cfg(test)]
+    pub fn messages(&self) -> Vec<OutboxMessage> {
+        self.messages.read().unwrap().clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl Outbox for InMemOutbox {
+    async fn pending(
+        &self,
+        now: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<OutboxMessage>, OrderStoreError> {
+        let messages = self.messages.read().unwrap();
+        Ok(messages
+            .iter()
+            .filter(|message| !message.delivered && message.next_attempt_at <= now)
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    async fn mark_delivered(&self, id: Uuid) -> Result<(), OrderStoreError> {
+        let mut messages = self.messages.write().unwrap();
+        if let Some(message) = messages.iter_mut().find(|message| message.id == id) {
+            message.delivered = true;
+        }
+        Ok(())
+    }
+
+    async fn mark_failed(
+        &self,
+        id: Uuid,
+        error: String,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<(), OrderStoreError> {
+        let mut messages = self.messages.write().unwrap();
+        if let Some(message) = messages.iter_mut().find(|message| message.id == id) {
+            message.attempts += 1;
+            message.last_error = Some(error);
+            message.next_attempt_at = next_attempt_at;
+        }
+        Ok(())
+    }
+}
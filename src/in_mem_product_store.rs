@@ -0,0 +1,192 @@
+use std::sync::RwLock;
+use uuid::Uuid;
+
+use crate::product_store::{Product, ProductDetails, ProductStore, ProductStoreError};
+
+pub struct InMemProductStore {
+    products: RwLock<Vec<Product>>,
+}
+
+impl InMemProductStore {
+    /// Creates a new in-memory product store.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let in_mem_store = InMemProductStore::new();
+    /// ```
+    pub fn new() -> InMemProductStore {
+        InMemProductStore {
+            products: RwLock::new(vec![]),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ProductStore for InMemProductStore {
+    async fn create_product(&self, details: ProductDetails) -> Result<Product, ProductStoreError> {
+        details.validate()?;
+        let mut data = self.products.write().unwrap();
+        if data.iter().any(|p| p.sku == details.sku) {
+            return Err(ProductStoreError::DuplicateSku(details.sku));
+        }
+        let product = Product::new(details);
+        data.push(product.clone());
+        Ok(product)
+    }
+
+    async fn get_product(&self, product_id: Uuid) -> Result<Product, ProductStoreError> {
+        let data = self.products.read().unwrap();
+        data.iter()
+            .find(|&product| product.id == product_id)
+            .cloned()
+            .ok_or(ProductStoreError::ProductNotFound(product_id))
+    }
+
+    async fn get_products(&self, product_ids: &[Uuid]) -> Result<Vec<Product>, ProductStoreError> {
+        let data = self.products.read().unwrap();
+        Ok(data
+            .iter()
+            .filter(|product| product_ids.contains(&product.id))
+            .cloned()
+            .collect())
+    }
+
+    async fn list_products(&self) -> Result<Vec<Product>, ProductStoreError> {
+        let data = self.products.read().unwrap();
+        Ok(data.clone())
+    }
+
+    async fn update_product(
+        &self,
+        product_id: Uuid,
+        details: ProductDetails,
+    ) -> Result<Product, ProductStoreError> {
+        details.validate()?;
+        let mut data = self.products.write().unwrap();
+        if data
+            .iter()
+            .any(|p| p.sku == details.sku && p.id != product_id)
+        {
+            return Err(ProductStoreError::DuplicateSku(details.sku));
+        }
+        for product in data.iter_mut() {
+            if product.id == product_id {
+                *product = Product::with_id(product_id, details);
+                return Ok(product.clone());
+            }
+        }
+        Err(ProductStoreError::ProductNotFound(product_id))
+    }
+
+    async fn delete_product(&self, product_id: Uuid) -> Result<(), ProductStoreError> {
+        let mut data = self.products.write().unwrap();
+        if let Some(index) = data.iter().position(|p| p.id == product_id) {
+            data.remove(index);
+            Ok(())
+        } else {
+            Err(ProductStoreError::ProductNotFound(product_id))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn details(sku: &str) -> ProductDetails {
+        ProductDetails {
+            name: format!("Product {sku}"),
+            sku: sku.to_string(),
+            price: dec!(9.99),
+            currency: "USD".to_string(),
+            tax_category: "standard".to_string(),
+            active: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn created_product_can_be_retrieved() {
+        let in_mem_store = InMemProductStore::new();
+        let product = in_mem_store.create_product(details("SKU-1")).await.unwrap();
+        assert_eq!(in_mem_store.get_product(product.id).await.unwrap(), product);
+    }
+
+    #[tokio::test]
+    async fn products_are_retrieved_together() {
+        let in_mem_store = InMemProductStore::new();
+        let first = in_mem_store.create_product(details("SKU-1")).await.unwrap();
+        _ = in_mem_store.create_product(details("SKU-2")).await.unwrap();
+        let third = in_mem_store.create_product(details("SKU-3")).await.unwrap();
+        let products = in_mem_store
+            .get_products(&[third.id, first.id, Uuid::new_v4()])
+            .await
+            .unwrap();
+        assert_eq!(products, vec![first, third]);
+    }
+
+    #[tokio::test]
+    async fn product_with_duplicate_sku_is_rejected() {
+        let in_mem_store = InMemProductStore::new();
+        _ = in_mem_store.create_product(details("SKU-1")).await;
+        if let Err(ProductStoreError::DuplicateSku(sku)) =
+            in_mem_store.create_product(details("SKU-1")).await
+        {
+            assert_eq!(sku, "SKU-1");
+        } else {
+            panic!("Duplicate SKU must produce error");
+        }
+    }
+
+    #[tokio::test]
+    async fn product_without_a_positive_price_is_rejected() {
+        let in_mem_store = InMemProductStore::new();
+        for price in [dec!(0), dec!(-1.50)] {
+            let result = in_mem_store
+                .create_product(ProductDetails {
+                    price,
+                    ..details("SKU-1")
+                })
+                .await;
+            assert!(matches!(result, Err(ProductStoreError::InvalidPrice(p)) if p == price));
+        }
+        let product = in_mem_store.create_product(details("SKU-1")).await.unwrap();
+        let result = in_mem_store
+            .update_product(
+                product.id,
+                ProductDetails {
+                    price: dec!(-1),
+                    ..details("SKU-1")
+                },
+            )
+            .await;
+        assert!(matches!(result, Err(ProductStoreError::InvalidPrice(_))));
+    }
+
+    #[tokio::test]
+    async fn updated_product_keeps_its_id() {
+        let in_mem_store = InMemProductStore::new();
+        let product = in_mem_store.create_product(details("SKU-1")).await.unwrap();
+        let mut new_details = details("SKU-2");
+        new_details.active = false;
+        let updated = in_mem_store
+            .update_product(product.id, new_details)
+            .await
+            .unwrap();
+        assert_eq!(updated.id, product.id);
+        assert_eq!(updated.sku, "SKU-2");
+        assert!(!updated.active);
+    }
+
+    #[tokio::test]
+    async fn deleted_product_is_not_found() {
+        let in_mem_store = InMemProductStore::new();
+        let product = in_mem_store.create_product(details("SKU-1")).await.unwrap();
+        in_mem_store.delete_product(product.id).await.unwrap();
+        assert!(matches!(
+            in_mem_store.get_product(product.id).await,
+            Err(ProductStoreError::ProductNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,141 @@
+use std::{collections::HashMap, sync::RwLock};
+use uuid::Uuid;
+
+use crate::{
+    promotion_store::{validate, PromotionStore, PromotionStoreError},
+    promotions::Coupon,
+};
+
+pub struct InMemPromotionStore {
+    coupons: RwLock<Vec<Coupon>>,
+    usages: RwLock<HashMap<(String, Uuid), u32>>,
+}
+
+impl InMemPromotionStore {
+    /// Creates a new in-memory promotion store without coupons.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let in_mem_store = InMemPromotionStore::new();
+    /// ```
+    pub fn new() -> InMemPromotionStore {
+        InMemPromotionStore {
+            coupons: RwLock::new(vec![]),
+            usages: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl PromotionStore for InMemPromotionStore {
+    async fn create_coupon(&self, coupon: Coupon) -> Result<Coupon, PromotionStoreError> {
+        validate(&coupon)?;
+        let mut data = self.coupons.write().unwrap();
+        if data.iter().any(|c| c.code == coupon.code) {
+            return Err(PromotionStoreError::DuplicateCoupon(coupon.code));
+        }
+        data.push(coupon.clone());
+        Ok(coupon)
+    }
+
+    async fn get_coupon(&self, code: &str) -> Result<Coupon, PromotionStoreError> {
+        let data = self.coupons.read().unwrap();
+        data.iter()
+            .find(|coupon| coupon.code == code)
+            .cloned()
+            .ok_or_else(|| PromotionStoreError::CouponNotFound(code.to_string()))
+    }
+
+    async fn list_coupons(&self) -> Result<Vec<Coupon>, PromotionStoreError> {
+        let data = self.coupons.read().unwrap();
+        Ok(data.clone())
+    }
+
+    async fn redeem(&self, code: &str, user_id: Uuid) -> Result<(), PromotionStoreError> {
+        let coupon = self.get_coupon(code).await?;
+        let mut usages = self.usages.write().unwrap();
+        let used = usages.entry((code.to_string(), user_id)).or_insert(0);
+        match coupon.max_uses_per_user {
+            Some(max_uses) if *used >= max_uses => {
+                Err(PromotionStoreError::UsageLimitReached(code.to_string()))
+            }
+            _ => {
+                *used += 1;
+                Ok(())
+            }
+        }
+    }
+
+    async fn release(&self, code: &str, user_id: Uuid) -> Result<(), PromotionStoreError> {
+        let mut usages = self.usages.write().unwrap();
+        if let Some(used) = usages.get_mut(&(code.to_string(), user_id)) {
+            *used = used.saturating_sub(1);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::promotions::PromotionRule;
+    use rust_decimal_macros::dec;
+
+    fn coupon(max_uses_per_user: Option<u32>) -> Coupon {
+        Coupon {
+            code: "WELCOME".to_string(),
+            rule: PromotionRule::FixedAmount {
+                amount: dec!(5),
+                currency: "USD".to_string(),
+            },
+            valid_from: None,
+            valid_until: None,
+            max_uses_per_user,
+        }
+    }
+
+    #[tokio::test]
+    async fn coupon_with_duplicate_code_is_rejected() {
+        let in_mem_store = InMemPromotionStore::new();
+        in_mem_store.create_coupon(coupon(None)).await.unwrap();
+        assert!(matches!(
+            in_mem_store.create_coupon(coupon(None)).await,
+            Err(PromotionStoreError::DuplicateCoupon(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn coupon_with_a_rule_out_of_range_is_rejected() {
+        let in_mem_store = InMemPromotionStore::new();
+        let result = in_mem_store
+            .create_coupon(Coupon {
+                rule: PromotionRule::Percentage { percent: dec!(150) },
+                ..coupon(None)
+            })
+            .await;
+        assert!(
+            matches!(result, Err(PromotionStoreError::InvalidRule(field)) if field == "percent")
+        );
+    }
+
+    #[tokio::test]
+    async fn coupon_cannot_be_redeemed_beyond_its_usage_limit() {
+        let in_mem_store = InMemPromotionStore::new();
+        in_mem_store.create_coupon(coupon(Some(1))).await.unwrap();
+        let user_id = Uuid::new_v4();
+        in_mem_store.redeem("WELCOME", user_id).await.unwrap();
+        assert!(matches!(
+            in_mem_store.redeem("WELCOME", user_id).await,
+            Err(PromotionStoreError::UsageLimitReached(_))
+        ));
+        // limits are per user
+        in_mem_store
+            .redeem("WELCOME", Uuid::new_v4())
+            .await
+            .unwrap();
+        // released usages can be redeemed again
+        in_mem_store.release("WELCOME", user_id).await.unwrap();
+        in_mem_store.redeem("WELCOME", user_id).await.unwrap();
+    }
+}
@@ -0,0 +1,116 @@
+use std::sync::RwLock;
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::webhook_store::{
+    Delivery, DeliveryStatus, Subscription, WebhookStore, WebhookStoreError,
+};
+
+pub struct InMemWebhookStore {
+    subscriptions: RwLock<Vec<Subscription>>,
+    deliveries: RwLock<Vec<Delivery>>,
+}
+
+impl InMemWebhookStore {
+    /// Creates a new in-memory webhook store without subscriptions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let in_mem_store = InMemWebhookStore::new();
+    /// ```
+    pub fn new() -> InMemWebhookStore {
+        InMemWebhookStore {
+            subscriptions: RwLock::new(vec![]),
+            deliveries: RwLock::new(vec![]),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl WebhookStore for InMemWebhookStore {
+    async fn create_subscription(
+        &self,
+        subscription: Subscription,
+    ) -> Result<Subscription, WebhookStoreError> {
+        let mut data = self.subscriptions.write().unwrap();
+        data.push(subscription.clone());
+        Ok(subscription)
+    }
+
+    async fn get_subscription(&self, id: Uuid) -> Result<Subscription, WebhookStoreError> {
+        let data = self.subscriptions.read().unwrap();
+        data.iter()
+            .find(|subscription| subscription.id == id)
+            .cloned()
+            .ok_or(WebhookStoreError::SubscriptionNotFound(id))
+    }
+
+    async fn list_subscriptions(&self) -> Result<Vec<Subscription>, WebhookStoreError> {
+        let data = self.subscriptions.read().unwrap();
+        Ok(data.clone())
+    }
+
+    async fn delete_subscription(&self, id: Uuid) -> Result<(), WebhookStoreError> {
+        let mut data = self.subscriptions.write().unwrap();
+        let count = data.len();
+        data.retain(|subscription| subscription.id != id);
+        if data.len() == count {
+            return Err(WebhookStoreError::SubscriptionNotFound(id));
+        }
+        Ok(())
+    }
+
+    async fn queue_delivery(&self, delivery: &Delivery) -> Result<(), WebhookStoreError> {
+        let mut data = self.deliveries.write().unwrap();
+        if !data.iter().any(|stored| {
+            stored.subscription_id == delivery.subscription_id
+                && stored.event_id == delivery.event_id
+        }) {
+            data.push(delivery.clone());
+        }
+        Ok(())
+    }
+
+    async fn save_delivery(&self, delivery: &Delivery) -> Result<(), WebhookStoreError> {
+        let mut data = self.deliveries.write().unwrap();
+        match data.iter_mut().find(|stored| stored.id == delivery.id) {
+            Some(stored) => *stored = delivery.clone(),
+            None => data.push(delivery.clone()),
+        }
+        Ok(())
+    }
+
+    async fn due_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<Delivery>, WebhookStoreError> {
+        let subscriptions = self.subscriptions.read().unwrap();
+        let data = self.deliveries.read().unwrap();
+        Ok(data
+            .iter()
+            .filter(|delivery| {
+                delivery.status == DeliveryStatus::Pending
+                    && delivery.next_attempt_at <= now
+                    && subscriptions
+                        .iter()
+                        .any(|subscription| subscription.id == delivery.subscription_id)
+            })
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    async fn deliveries(&self, subscription_id: Uuid) -> Result<Vec<Delivery>, WebhookStoreError> {
+        self.get_subscription(subscription_id).await?;
+        let data = self.deliveries.read().unwrap();
+        Ok(data
+            .iter()
+            .rev()
+            .filter(|delivery| delivery.subscription_id == subscription_id)
+            .cloned()
+            .collect())
+    }
+}
@@ -0,0 +1,58 @@
+//! Authentication of the other services of the shop, the only callers trusted to act on behalf of
+//! someone else or to manage webhooks.
+
+use std::sync::Arc;
+
+use sha2::{Digest, Sha256};
+
+/// Secret internal callers present as `Authorization: Bearer <token>`, over HTTP or as gRPC
+/// metadata.
+#[derive(Clone)]
+pub struct InternalToken(Option<Arc<str>>);
+
+impl InternalToken {
+    /// Creates the token internal callers authenticate with, none at all when `token` is `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let internal = InternalToken::new(env::var("INTERNAL_API_TOKEN").ok());
+    /// ```
+    pub fn new(token: Option<String>) -> InternalToken {
+        InternalToken(token.filter(|token| !token.is_empty()).map(Arc::from))
+    }
+
+    /// Tells whether `authorization`, the value of an `Authorization` header, carries the token.
+    /// Nobody is authenticated when there is no token.
+    pub fn authenticates(&self, authorization: Option<&str>) -> bool {
+        let (Some(token), Some(presented)) = (
+            &self.0,
+            authorization.and_then(|value| value.strip_prefix("Bearer ")),
+        ) else {
+            return false;
+        };
+        // comparing digests takes the same time wherever the first difference is
+        Sha256::digest(presented.as_bytes()) == Sha256::digest(token.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_bearer_token_authenticates() {
+        let internal = InternalToken::new(Some("s3cret".to_string()));
+        assert!(internal.authenticates(Some("Bearer s3cret")));
+        assert!(!internal.authenticates(Some("Bearer s3cre")));
+        assert!(!internal.authenticates(Some("s3cret")));
+        assert!(!internal.authenticates(None));
+    }
+
+    #[test]
+    fn nobody_is_authenticated_without_a_token() {
+        let internal = InternalToken::new(Some(String::new()));
+        assert!(!internal.authenticates(Some("Bearer ")));
+        assert!(!InternalToken::new(None).authenticates(Some("Bearer s3cret")));
+    }
+}
@@ -0,0 +1,132 @@
+use std::{error::Error, fmt::Display, ops::Deref};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub struct InventoryStoreNewType(pub Box<dyn InventoryStore>); // same idea as `OrderStoreNewType`
+
+impl InventoryStoreNewType {
+    pub fn new(repo: impl InventoryStore) -> InventoryStoreNewType {
+        InventoryStoreNewType(Box::new(repo))
+    }
+}
+
+impl Deref for InventoryStoreNewType {
+    type Target = dyn InventoryStore;
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+/// Stock of a single product.
+///
+/// Units move from `available` to `reserved` when they are added to a draft order, back to
+/// `available` when they are released, and from `reserved` to `committed` when the order is placed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StockLevel {
+    /// Id of the product this stock belongs to.
+    pub product_id: Uuid,
+    /// Units that can still be added to orders.
+    pub available: i32,
+    /// Units held by draft orders.
+    pub reserved: i32,
+    /// Units sold through placed orders.
+    pub committed: i32,
+}
+
+impl StockLevel {
+    /// Creates the stock level of a product that has no units at all.
+    pub fn empty(product_id: Uuid) -> StockLevel {
+        StockLevel {
+            product_id,
+            available: 0,
+            reserved: 0,
+            committed: 0,
+        }
+    }
+}
+
+/// Type for describing errors that result from trying to interact with an [`InventoryStore`](InventoryStore).
+#[derive(Debug)]
+pub enum InventoryStoreError {
+    /// The store is unavailable.
+    StoreUnavailable,
+    /// There are not enough units of the provided product to fulfil the operation.
+    InsufficientStock(Uuid),
+}
+
+impl Display for InventoryStoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InventoryStoreError::StoreUnavailable => {
+                write!(f, "Store unavailable")
+            }
+            InventoryStoreError::InsufficientStock(id) => {
+                write!(f, "Insufficient stock for product {}", id)
+            }
+        }
+    }
+}
+
+impl Error for InventoryStoreError {}
+
+/// A trait that defines the behavior of a type used to keep track of stock levels.
+#[async_trait::async_trait]
+pub trait InventoryStore: Send + Sync + 'static {
+    /// Returns the stock level of the product with id `product_id`.
+    ///
+    /// Products that were never stocked have an [`empty`](StockLevel::empty) stock level.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](InventoryStoreError::StoreUnavailable) if the Store cannot be used to read stock.
+    async fn get_stock(&self, product_id: Uuid) -> Result<StockLevel, InventoryStoreError>;
+
+    /// Sets the units of `product_id` that can be added to orders, leaving reserved and committed
+    /// units untouched.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](InventoryStoreError::StoreUnavailable) if the Store cannot be used to update stock.
+    async fn set_available(
+        &self,
+        product_id: Uuid,
+        available: i32,
+    ) -> Result<StockLevel, InventoryStoreError>;
+
+    /// Moves `quantity` units of `product_id` from available to reserved.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](InventoryStoreError::StoreUnavailable) if the Store cannot be used to update stock.
+    ///
+    /// Returns [`InsufficientStock`](InventoryStoreError::InsufficientStock) if fewer than `quantity` units are available.
+    async fn reserve(&self, product_id: Uuid, quantity: i32) -> Result<(), InventoryStoreError>;
+
+    /// Moves `quantity` units of `product_id` from reserved back to available.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](InventoryStoreError::StoreUnavailable) if the Store cannot be used to update stock.
+    ///
+    /// Returns [`InsufficientStock`](InventoryStoreError::InsufficientStock) if fewer than `quantity` units are reserved.
+    async fn release(&self, product_id: Uuid, quantity: i32) -> Result<(), InventoryStoreError>;
+
+    /// Moves `quantity` units of `product_id` from reserved to committed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](InventoryStoreError::StoreUnavailable) if the Store cannot be used to update stock.
+    ///
+    /// Returns [`InsufficientStock`](InventoryStoreError::InsufficientStock) if fewer than `quantity` units are reserved.
+    async fn commit(&self, product_id: Uuid, quantity: i32) -> Result<(), InventoryStoreError>;
+
+    /// Moves `quantity` units of `product_id` from committed back to available, when they are returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](InventoryStoreError::StoreUnavailable) if the Store cannot be used to update stock.
+    ///
+    /// Returns [`InsufficientStock`](InventoryStoreError::InsufficientStock) if fewer than `quantity` units are committed.
+    async fn restock(&self, product_id: Uuid, quantity: i32) -> Result<(), InventoryStoreError>;
+}
@@ -1,53 +1,316 @@
 mod api;
+mod audit_log;
+mod cached_order_store;
+mod chaos_order_store;
+mod decimal128;
+mod event_log;
+mod event_sink;
+mod event_sourced_order_store;
+mod fake_payment_provider;
+mod file_event_sink;
+mod fulfilment;
+mod grpc;
+mod http_event_sink;
+mod in_mem_event_log;
+mod in_mem_inventory_store;
 mod in_mem_order_store;
+mod in_mem_order_updates;
+mod in_mem_outbox;
+mod in_mem_product_store;
+mod in_mem_promotion_store;
+mod in_mem_webhook_store;
+mod internal_caller;
+mod inventory_store;
+mod mongodb_event_log;
+mod mongodb_inventory_store;
+mod mongodb_migrations;
+mod mongodb_order_document;
 mod mongodb_order_store;
+mod mongodb_order_updates;
+mod mongodb_outbox;
+mod mongodb_product_store;
+mod mongodb_promotion_store;
+mod mongodb_schema;
+mod mongodb_settings;
+mod mongodb_webhook_store;
+mod order_events;
 mod order_store;
+#[cfg(test)]
+mod order_store_tests;
+mod order_updates;
+mod outbox;
+mod outbox_relay;
+mod payment_provider;
+mod product_store;
+mod promotion_store;
+mod promotions;
+mod resilient_order_store;
+mod returns;
+mod rules_table_tax_calculator;
+mod stdout_event_sink;
+mod tax_calculator;
+mod unconfigured_payment_provider;
+mod webhook_dispatcher;
+mod webhook_store;
 use api::health;
 use dotenv::dotenv;
-use std::{env, error::Error, sync::Arc, time::Duration};
+use std::{env, error::Error, num::NonZeroUsize, str::FromStr, sync::Arc, time::Duration};
 use tower::{timeout::TimeoutLayer, ServiceBuilder};
 use tower_http::trace::TraceLayer;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use axum::{
     error_handling::HandleErrorLayer,
     http::{StatusCode, Uri},
+    middleware,
     response::IntoResponse,
-    routing::{delete, get, post},
+    routing::{delete, get, post, put},
     BoxError, Extension, Router, Server,
 };
+use mongodb::{options::WriteConcern, Client};
 
-use crate::{api::orders, mongodb_order_store::MongodbOrderStore, order_store::OrderStoreNewType};
+use crate::{
+    api::{carts, coupons, graphql, inventory, metrics, orders, products, webhooks},
+    cached_order_store::{CacheConfig, CacheMetrics, CachedOrderStore},
+    chaos_order_store::{ChaosConfig, ChaosOrderStore},
+    event_sink::{EventSink, EventSinks},
+    event_sourced_order_store::EventSourcedOrderStore,
+    fake_payment_provider::FakePaymentProvider,
+    file_event_sink::FileEventSink,
+    grpc::GrpcOrderService,
+    http_event_sink::HttpEventSink,
+    in_mem_event_log::InMemEventLog,
+    in_mem_inventory_store::InMemInventoryStore,
+    in_mem_order_store::InMemOrderStore,
+    in_mem_product_store::InMemProductStore,
+    in_mem_promotion_store::InMemPromotionStore,
+    in_mem_webhook_store::InMemWebhookStore,
+    internal_caller::InternalToken,
+    inventory_store::InventoryStoreNewType,
+    mongodb_event_log::MongodbEventLog,
+    mongodb_inventory_store::MongodbInventoryStore,
+    mongodb_migrations::Migrator,
+    mongodb_order_store::MongodbOrderStore,
+    mongodb_order_updates::MongodbOrderUpdates,
+    mongodb_outbox::MongodbOutbox,
+    mongodb_product_store::MongodbProductStore,
+    mongodb_promotion_store::MongodbPromotionStore,
+    mongodb_schema::bootstrap,
+    mongodb_settings::{acknowledgment, read_concern, MongodbNames, MongodbSettings, TlsSettings},
+    mongodb_webhook_store::MongodbWebhookStore,
+    order_store::{OrderStore, OrderStoreNewType},
+    order_updates::OrderUpdates,
+    outbox::Outbox,
+    outbox_relay::OutboxRelay,
+    payment_provider::PaymentProvider,
+    product_store::ProductStoreNewType,
+    promotion_store::PromotionStoreNewType,
+    resilient_order_store::{CircuitBreaker, ResilientOrderStore, RetryPolicy},
+    rules_table_tax_calculator::RulesTableTaxCalculator,
+    stdout_event_sink::StdoutEventSink,
+    tax_calculator::TaxCalculator,
+    unconfigured_payment_provider::UnconfiguredPaymentProvider,
+    webhook_dispatcher::{WebhookDispatcher, WebhookEventSink},
+    webhook_store::WebhookStoreNewType,
+};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     tracing_subscriber::fmt::init();
     dotenv().expect("Set your configuration in an .env file");
 
-    let mongodb_uri = env::var("MONGODB_URI").expect("Define MONGODB_URI environment variable");
-    // repository
-    let repo = MongodbOrderStore::new(&mongodb_uri).await?;
+    // one-off commands run against MongoDB instead of serving
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        return run_command(&args).await;
+    }
+
+    let tax: Arc<dyn TaxCalculator> = match env::var("TAX_RULES_FILE") {
+        Ok(path) => Arc::new(RulesTableTaxCalculator::from_file(path)?),
+        Err(_) => {
+            warn!("TAX_RULES_FILE not defined, orders will not be taxed");
+            Arc::new(RulesTableTaxCalculator::tax_free())
+        }
+    };
+
+    // the only provider so far, it approves every payment token but a few test ones
+    let payments: Arc<dyn PaymentProvider> = if non_production() {
+        Arc::new(FakePaymentProvider::new())
+    } else {
+        warn!("no payment provider is available for production, checkouts and refunds will fail as payment unavailable");
+        Arc::new(UnconfiguredPaymentProvider)
+    };
+
+    // orders are kept as event streams instead of documents
+    let event_sourced = env::var("EVENT_SOURCING").as_deref() == Ok("true");
+    if event_sourced {
+        info!("using event-sourced orders");
+    }
+
+    let layers = OrderStoreLayers::from_env()?;
 
-    let state = Arc::new(OrderStoreNewType::new(repo)); // allowing repo to be avalable in muliple threads
-                                                        // 'Arc' to allow many copies
-                                                        // OrderNewType -> just the type we defined
-                                                        // All this stuff just to use this in an abstract way rather that in a specific one.
+    // repositories
+    let stores = if env::var("STORAGE").as_deref() == Ok("memory") {
+        if !non_production() {
+            return Err("STORAGE=memory loses every change on restart, it is only allowed when APP_ENV is defined and not production".into());
+        }
+        info!("using in-memory storage");
+        Stores::in_mem(tax, payments, event_sourced, &layers)
+    } else {
+        let client = connect_mongodb().await?;
+        let names = mongodb_names();
+        // the validator and indexes of the new version apply before its migrations, and instances
+        // of an older version may not cope with either: see the upgrade notes of the README
+        if env_or("MONGODB_BOOTSTRAP", true) {
+            bootstrap(&client, &names).await?;
+        }
+        if env_or("MONGODB_MIGRATE", true) {
+            Migrator::new(client.clone(), &names).up().await?;
+        }
+        Stores::mongodb(client, &names, tax, payments, event_sourced, &layers).await?
+    };
+
+    // domain events of the orders, delivered to other services by a background relay
+    let sink: Arc<dyn EventSink> = match env::var("EVENT_SINK").as_deref() {
+        Ok("file") => {
+            let path =
+                env::var("EVENT_SINK_PATH").expect("Define EVENT_SINK_PATH environment variable");
+            Arc::new(FileEventSink::new(path))
+        }
+        Ok("http") => {
+            let url =
+                env::var("EVENT_SINK_URL").expect("Define EVENT_SINK_URL environment variable");
+            Arc::new(HttpEventSink::new(&url))
+        }
+        Ok("stdout") => Arc::new(StdoutEventSink::new()),
+        // events printed to stdout reach no other service, which is only fine while developing
+        Err(_) if non_production() => {
+            warn!("EVENT_SINK not defined, events will be printed to stdout");
+            Arc::new(StdoutEventSink::new())
+        }
+        Ok(other) => return Err(format!("unknown EVENT_SINK {}, use stdout, file or http", other).into()),
+        Err(_) => return Err("Define EVENT_SINK environment variable as stdout, file or http, it is only optional when APP_ENV is defined and not production".into()),
+    };
+    let webhook_sink = Arc::new(WebhookEventSink::new(stores.webhooks.clone()));
+    let sinks = Arc::new(EventSinks(vec![sink, webhook_sink]));
+    OutboxRelay::new(stores.outbox.clone(), sinks, Duration::from_secs(1)).spawn();
+    WebhookDispatcher::new(stores.webhooks.clone(), Duration::from_secs(1)).spawn();
+    // the other services of the shop authenticate with this token to act on behalf of someone
+    let internal_token = InternalToken::new(env::var("INTERNAL_API_TOKEN").ok());
+    // gRPC for internal callers, on its own port
+    if let Ok(grpc_address) = env::var("GRPC_SERVER") {
+        let grpc_address = grpc_address
+            .parse()
+            .expect("Define GRPC_SERVER as a host:port pair");
+        info!("grpc_address: {:?}", grpc_address);
+        let service = GrpcOrderService::new(stores.orders.clone(), internal_token.clone());
+        let breaker = layers.breaker.clone();
+        tokio::spawn(async move {
+            if let Err(err) = service
+                .serve(grpc_address, breaker, signal_shutdown())
+                .await
+            {
+                error!("gRPC server failed: {}", err);
+            }
+        });
+    }
+
+    let schema = graphql::schema(stores.orders.clone(), stores.products.clone());
+    let state = stores.orders; // allowing repo to be avalable in muliple threads
+                               // 'Arc' to allow many copies
+                               // OrderNewType -> just the type we defined
+                               // All this stuff just to use this in an abstract way rather that in a specific one.
 
     let message = "Define a SERVER=host:port pair in your .env file";
-    let server_address = env::var("SERVER").expect(&message);
-    let server_address = server_address.parse().expect(&message);
+    let server_address = env::var("SERVER").expect(message);
+    let server_address = server_address.parse().expect(message);
     info!("server_address: http://{:?}/", server_address);
     Server::bind(&server_address);
 
     let order_routes = Router::new()
         .route("/", get(orders::list).post(orders::create)) // handles gets and posts depenging of the method reaching the server
+        .route("/events", get(orders::user_events))
         .route("/:id", get(orders::get))
         .route("/:id/items", post(orders::add_item))
-        .route("/:id/items/:index", delete(orders::delete_item))
+        .route(
+            "/:id/items/:index",
+            put(orders::update_quantity).delete(orders::delete_item),
+        )
+        .route("/:id/cart", get(carts::edit))
+        .route("/:id/place", post(orders::place))
+        .route("/:id/cancel", post(orders::cancel))
+        .route("/:id/coupons", post(orders::apply_coupon))
+        .route("/:id/coupons/:code", delete(orders::remove_coupon))
+        .route("/:id/shipping", put(orders::set_shipping))
+        .route("/:id/billing", put(orders::set_billing_address))
+        .route("/:id/checkout", post(orders::checkout))
+        .route("/:id/ship", post(orders::ship))
+        .route("/:id/deliver", post(orders::deliver))
+        .route("/:id/returns", post(orders::request_return))
+        .route(
+            "/:id/returns/:return_id/approve",
+            post(orders::approve_return),
+        )
+        .route(
+            "/:id/returns/:return_id/reject",
+            post(orders::reject_return),
+        )
+        .route("/:id/history", get(orders::history))
+        .route("/:id/events", get(orders::events))
+        .route_layer(middleware::from_fn(orders::with_actor))
+        .layer(Extension(internal_token.clone()))
+        .layer(Extension(stores.updates))
         .layer(Extension(state)); // Axum stores this in a dictionary key value where the key is the "type" of what is being stored in it.
-    let app = Router::new()
+    let product_routes = Router::new()
+        .route("/", get(products::list).post(products::create))
+        .route(
+            "/:id",
+            get(products::get)
+                .put(products::update)
+                .delete(products::delete),
+        )
+        .route_layer(middleware::from_fn(orders::require_internal_caller))
+        .layer(Extension(internal_token.clone()))
+        .layer(Extension(stores.products));
+    let inventory_routes = Router::new()
+        .route("/:product_id", get(inventory::get).put(inventory::set))
+        .route_layer(middleware::from_fn(orders::require_internal_caller))
+        .layer(Extension(internal_token.clone()))
+        .layer(Extension(stores.inventory));
+    let coupon_routes = Router::new()
+        .route("/", get(coupons::list).post(coupons::create))
+        .route("/:code", get(coupons::get))
+        .route_layer(middleware::from_fn(orders::require_internal_caller))
+        .layer(Extension(internal_token.clone()))
+        .layer(Extension(stores.promotions));
+    let webhook_routes = Router::new()
+        .route("/", get(webhooks::list).post(webhooks::create))
+        .route("/:id", get(webhooks::get).delete(webhooks::delete))
+        .route("/:id/deliveries", get(webhooks::deliveries))
+        .route_layer(middleware::from_fn(orders::require_internal_caller))
+        .layer(Extension(internal_token.clone()))
+        .layer(Extension(stores.webhooks));
+    // the playground is only for trying queries out while developing
+    let graphql_routes = if env::var("APP_ENV").as_deref() == Ok("development") {
+        Router::new().route("/", get(graphql::graphiql).post(graphql::execute))
+    } else {
+        Router::new().route("/", post(graphql::execute))
+    }
+    .route_layer(middleware::from_fn(orders::with_actor))
+    .layer(Extension(internal_token.clone()))
+    .layer(Extension(schema));
+    let mut app = Router::new()
         .route("/health", get(health::get))
+        .route("/ready", get(health::ready))
+        .nest("/graphql", graphql_routes)
         .nest("/orders", order_routes)
+        .nest("/products", product_routes)
+        .nest("/inventory", inventory_routes)
+        .nest("/coupons", coupon_routes)
+        .nest("/webhooks", webhook_routes)
+        // every route can fail while the order store is unavailable, the readiness check included
+        .layer(middleware::from_fn(orders::with_retry_after))
+        .layer(Extension(layers.breaker.clone()))
         .layer(
             ServiceBuilder::new()
                 .layer(TraceLayer::new_for_http())
@@ -57,6 +320,12 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 .layer(TimeoutLayer::new(Duration::from_secs(5))),
         )
         .fallback(fallback_handler);
+    if let Some((_, cache_metrics)) = layers.cache {
+        app = app.route(
+            "/metrics/order-cache",
+            get(metrics::order_cache).layer(Extension(cache_metrics)),
+        );
+    }
 
     Server::bind(&server_address)
         .serve(app.into_make_service())
@@ -66,6 +335,300 @@ async fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+/// Stores shared by the request handlers, all backed by the same kind of storage.
+struct Stores {
+    products: Arc<ProductStoreNewType>,
+    inventory: Arc<InventoryStoreNewType>,
+    promotions: Arc<PromotionStoreNewType>,
+    orders: Arc<OrderStoreNewType>,
+    outbox: Arc<dyn Outbox>,
+    updates: Arc<dyn OrderUpdates>,
+    webhooks: Arc<WebhookStoreNewType>,
+}
+
+impl Stores {
+    fn in_mem(
+        tax: Arc<dyn TaxCalculator>,
+        payments: Arc<dyn PaymentProvider>,
+        event_sourced: bool,
+        layers: &OrderStoreLayers,
+    ) -> Stores {
+        let products = Arc::new(ProductStoreNewType::new(InMemProductStore::new()));
+        let inventory = Arc::new(InventoryStoreNewType::new(InMemInventoryStore::new()));
+        let promotions = Arc::new(PromotionStoreNewType::new(InMemPromotionStore::new()));
+        let (orders, outbox, updates): (_, Arc<dyn Outbox>, Arc<dyn OrderUpdates>) =
+            if event_sourced {
+                let events = InMemEventLog::new();
+                let outbox = events.outbox();
+                let orders = EventSourcedOrderStore::new(
+                    events,
+                    products.clone(),
+                    inventory.clone(),
+                    promotions.clone(),
+                    tax,
+                    payments,
+                );
+                let updates = orders.updates();
+                (layers.wrap(orders), outbox, updates)
+            } else {
+                let orders = InMemOrderStore::new(
+                    products.clone(),
+                    inventory.clone(),
+                    promotions.clone(),
+                    tax,
+                    payments,
+                );
+                let (outbox, updates) = (orders.outbox(), orders.updates());
+                (layers.wrap(orders), outbox, updates)
+            };
+        Stores {
+            products,
+            inventory,
+            promotions,
+            orders: Arc::new(orders),
+            outbox,
+            updates,
+            webhooks: Arc::new(WebhookStoreNewType::new(InMemWebhookStore::new())),
+        }
+    }
+
+    async fn mongodb(
+        client: Client,
+        names: &MongodbNames,
+        tax: Arc<dyn TaxCalculator>,
+        payments: Arc<dyn PaymentProvider>,
+        event_sourced: bool,
+        layers: &OrderStoreLayers,
+    ) -> Result<Stores, Box<dyn Error>> {
+        let products = Arc::new(ProductStoreNewType::new(MongodbProductStore::new(
+            client.clone(),
+            names,
+        )));
+        let inventory = Arc::new(InventoryStoreNewType::new(MongodbInventoryStore::new(
+            client.clone(),
+            names,
+        )));
+        let promotions = Arc::new(PromotionStoreNewType::new(MongodbPromotionStore::new(
+            client.clone(),
+            names,
+        )));
+        let (orders, updates): (_, Arc<dyn OrderUpdates>) = if event_sourced {
+            let orders = EventSourcedOrderStore::new(
+                MongodbEventLog::new(client.clone(), names).await?,
+                products.clone(),
+                inventory.clone(),
+                promotions.clone(),
+                tax,
+                payments,
+            );
+            let updates = orders.updates();
+            (layers.wrap(orders), updates)
+        } else {
+            // stock is moved by the order store itself, inside the same transaction as the order update
+            let orders = MongodbOrderStore::new(
+                client.clone(),
+                names,
+                products.clone(),
+                promotions.clone(),
+                tax,
+                payments,
+            );
+            let updates = Arc::new(MongodbOrderUpdates::new(client.clone(), names));
+            (layers.wrap(orders), updates)
+        };
+        Ok(Stores {
+            products,
+            inventory,
+            promotions,
+            orders: Arc::new(orders),
+            outbox: Arc::new(MongodbOutbox::new(client.clone(), names)),
+            updates,
+            webhooks: Arc::new(WebhookStoreNewType::new(MongodbWebhookStore::new(
+                client, names,
+            ))),
+        })
+    }
+}
+
+/// Wrappers put in front of the order store, whatever its storage.
+struct OrderStoreLayers {
+    /// Cache of the orders read recently with the metrics it reports, when enabled.
+    cache: Option<(CacheConfig, Arc<CacheMetrics>)>,
+    retry: RetryPolicy,
+    breaker: Arc<CircuitBreaker>,
+    /// Faults injected in the calls to the storage, never in production.
+    chaos: Option<ChaosConfig>,
+}
+
+impl OrderStoreLayers {
+    fn from_env() -> Result<OrderStoreLayers, Box<dyn Error>> {
+        let cache = (env::var("ORDER_CACHE").as_deref() == Ok("true")).then(|| {
+            let capacity: usize = env_or("ORDER_CACHE_CAPACITY", 1000);
+            let config = CacheConfig {
+                capacity: NonZeroUsize::new(capacity)
+                    .expect("Define ORDER_CACHE_CAPACITY as a positive number"),
+                ttl: Duration::from_secs(env_or("ORDER_CACHE_TTL_SECS", 30)),
+            };
+            info!("caching orders: {:?}", config);
+            (config, Arc::new(CacheMetrics::default()))
+        });
+        let retry = RetryPolicy {
+            attempts: env_or("ORDER_STORE_READ_ATTEMPTS", 3),
+            base_delay: Duration::from_millis(env_or("ORDER_STORE_RETRY_DELAY_MS", 50)),
+            max_delay: Duration::from_secs(1),
+        };
+        let breaker = CircuitBreaker::new(
+            env_or("ORDER_STORE_BREAKER_FAILURES", 5),
+            Duration::from_secs(env_or("ORDER_STORE_BREAKER_OPEN_SECS", 30)),
+        );
+        let chaos = match env::var("CHAOS_FILE") {
+            Ok(_) if !non_production() => {
+                warn!("CHAOS_FILE ignored, faults are only injected when APP_ENV is defined and not production");
+                None
+            }
+            Ok(path) => {
+                let config = ChaosConfig::from_file(path)?;
+                warn!("injecting faults in the order store: {:?}", config);
+                Some(config)
+            }
+            Err(_) => None,
+        };
+        Ok(OrderStoreLayers {
+            cache,
+            retry,
+            breaker: Arc::new(breaker),
+            chaos,
+        })
+    }
+
+    /// Puts the wrappers in front of `orders`, the cache first so that its hits don't count
+    /// against the breaker, and the faults right in front of the storage.
+    fn wrap(&self, orders: impl OrderStore) -> OrderStoreNewType {
+        match &self.chaos {
+            Some(config) => self.wrap_reliable(ChaosOrderStore::new(orders, config.clone())),
+            None => self.wrap_reliable(orders),
+        }
+    }
+
+    fn wrap_reliable(&self, orders: impl OrderStore) -> OrderStoreNewType {
+        let orders = ResilientOrderStore::new(orders, self.retry, self.breaker.clone());
+        match &self.cache {
+            Some((config, metrics)) => {
+                OrderStoreNewType::new(CachedOrderStore::new(orders, *config, metrics.clone()))
+            }
+            None => OrderStoreNewType::new(orders),
+        }
+    }
+}
+
+/// Runs the command given on the command line as `args`.
+async fn run_command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args[..] {
+        ["bootstrap"] => {
+            let client = connect_mongodb().await?;
+            bootstrap(&client, &mongodb_names()).await?;
+        }
+        ["migrate", "up"] => {
+            let client = connect_mongodb().await?;
+            let applied = Migrator::new(client, &mongodb_names()).up().await?;
+            println!("{} migrations applied", applied.len());
+        }
+        ["migrate", "status"] => {
+            let client = connect_mongodb().await?;
+            for status in Migrator::new(client, &mongodb_names()).status().await? {
+                let applied_at = status
+                    .applied_at
+                    .map_or("pending".to_string(), |applied_at| applied_at.to_rfc3339());
+                println!("{:<40} {}", status.name, applied_at);
+            }
+        }
+        _ => {
+            let usage = "expected bootstrap, migrate up or migrate status";
+            return Err(format!("Unknown command {}, {usage}", args.join(" ")).into());
+        }
+    }
+    Ok(())
+}
+
+/// Connects to MongoDB, stopping the service with the cause when it cannot be used.
+async fn connect_mongodb() -> Result<Client, Box<dyn Error>> {
+    match mongodb_settings()?.connect().await {
+        Ok(client) => Ok(client),
+        Err(err) => {
+            error!("MongoDB cannot be used: {}", err.detailed());
+            Err(err.into())
+        }
+    }
+}
+
+/// Reads how to connect to MongoDB, the settings not defined are left to the connection string.
+fn mongodb_settings() -> Result<MongodbSettings, Box<dyn Error>> {
+    let uri = env::var("MONGODB_URI").expect("Define MONGODB_URI environment variable");
+    let millis = |name| env_opt(name).map(Duration::from_millis);
+    let write_concern = env::var("MONGODB_WRITE_CONCERN").ok().map(|w| {
+        WriteConcern::builder()
+            .w(acknowledgment(&w))
+            .journal(env_opt::<bool>("MONGODB_JOURNAL"))
+            .w_timeout(millis("MONGODB_WRITE_TIMEOUT_MS"))
+            .build()
+    });
+    let tls = (env_opt("MONGODB_TLS") == Some(true)).then(|| TlsSettings {
+        ca_file: env_opt("MONGODB_TLS_CA_FILE"),
+        cert_key_file: env_opt("MONGODB_TLS_CERT_KEY_FILE"),
+        allow_invalid_certificates: env_or("MONGODB_TLS_ALLOW_INVALID_CERTIFICATES", false),
+    });
+    Ok(MongodbSettings {
+        uri,
+        resolver: env::var("MONGODB_RESOLVER")
+            .map_or(Ok(Default::default()), |name| name.parse())?,
+        app_name: Some(env_or("MONGODB_APP_NAME", "rust-mongo".to_string())),
+        min_pool_size: env_opt("MONGODB_MIN_POOL_SIZE"),
+        max_pool_size: env_opt("MONGODB_MAX_POOL_SIZE"),
+        connect_timeout: millis("MONGODB_CONNECT_TIMEOUT_MS"),
+        server_selection_timeout: millis("MONGODB_SERVER_SELECTION_TIMEOUT_MS"),
+        read_concern: env::var("MONGODB_READ_CONCERN")
+            .ok()
+            .map(|level| read_concern(&level))
+            .transpose()?,
+        write_concern,
+        tls,
+    })
+}
+
+/// Reads the database and collections used, so that several environments can share a cluster.
+fn mongodb_names() -> MongodbNames {
+    let names = MongodbNames::new(
+        &env_or("MONGODB_DATABASE", "examplemongo-ms".to_string()),
+        &env::var("MONGODB_COLLECTION_PREFIX").unwrap_or_default(),
+    );
+    info!(
+        "using MongoDB database {} with collections such as {}",
+        names.database, names.orders
+    );
+    names
+}
+
+/// Tells whether `APP_ENV` is defined and not `production`, which allows what must never run in
+/// production.
+fn non_production() -> bool {
+    env::var("APP_ENV").is_ok_and(|app_env| app_env != "production")
+}
+
+/// Reads the environment variable `name`, `None` when it is not defined.
+fn env_opt<T: FromStr>(name: &str) -> Option<T> {
+    env::var(name).ok().map(|value| {
+        value
+            .parse()
+            .unwrap_or_else(|_| panic!("Define {name} as a valid value"))
+    })
+}
+
+/// Reads the environment variable `name`, `default` when it is not defined.
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    env_opt(name).unwrap_or(default)
+}
+
 /// shutdown handler
 async fn signal_shutdown() {
     tokio::signal::ctrl_c()
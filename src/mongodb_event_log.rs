@@ -0,0 +1,152 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::doc,
+    error::{ErrorKind, WriteFailure},
+    options::{FindOptions, IndexOptions, ReplaceOptions},
+    Client, Collection, IndexModel,
+};
+use uuid::Uuid;
+
+use crate::{
+    event_log::EventLog,
+    mongodb_order_store::{store_error, uuid_as_bson},
+    mongodb_settings::MongodbNames,
+    order_events::{Snapshot, StoredEvent},
+    order_store::OrderStoreError,
+    outbox::{DomainEvent, OutboxMessage},
+};
+
+const DUPLICATE_KEY: i32 = 11000;
+
+/// Keeps events in `order_events`, where a unique index on order id and version rejects
+/// concurrent appends to the same stream, and the latest snapshot of every order in `order_snapshots`.
+/// Every event is written to the `order_outbox` in the same transaction.
+pub struct MongodbEventLog {
+    client: Client,
+    events: Collection<StoredEvent>,
+    outbox: Collection<OutboxMessage>,
+    snapshots: Collection<Snapshot>,
+}
+
+impl MongodbEventLog {
+    /// Creates a new event log, creating the unique index of `order_events` if it is missing.
+    pub async fn new(
+        client: Client,
+        names: &MongodbNames,
+    ) -> Result<MongodbEventLog, OrderStoreError> {
+        let event_log = MongodbEventLog {
+            events: names.collection(&client, &names.order_events),
+            outbox: names.collection(&client, &names.outbox),
+            snapshots: names.collection(&client, &names.order_snapshots),
+            client,
+        };
+        let index = IndexModel::builder()
+            .keys(doc! { "order_id": 1, "version": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        event_log
+            .events
+            .create_index(index, None)
+            .await
+            .map_err(store_error("creating the order_events index"))?;
+        Ok(event_log)
+    }
+}
+
+#[async_trait::async_trait]
+impl EventLog for MongodbEventLog {
+    async fn append(&self, event: &StoredEvent) -> Result<(), OrderStoreError> {
+        let mut session = self
+            .client
+            .start_session(None)
+            .await
+            .map_err(store_error("starting a session"))?;
+        session
+            .start_transaction(None)
+            .await
+            .map_err(store_error("starting a transaction"))?;
+        if let Err(err) = self
+            .events
+            .insert_one_with_session(event, None, &mut session)
+            .await
+        {
+            return match err.kind.as_ref() {
+                ErrorKind::Write(WriteFailure::WriteError(write_error))
+                    if write_error.code == DUPLICATE_KEY =>
+                {
+                    Err(OrderStoreError::ConcurrentModification(event.order_id))
+                }
+                _ => Err(store_error("appending an event")(err)),
+            };
+        }
+        let message = OutboxMessage::new(DomainEvent::new(
+            event.order_id,
+            event.user_id,
+            event.event.clone(),
+        ));
+        self.outbox
+            .insert_one_with_session(message, None, &mut session)
+            .await
+            .map_err(store_error("writing to the outbox"))?;
+        session
+            .commit_transaction()
+            .await
+            .map_err(store_error("committing a transaction"))
+    }
+
+    async fn events(
+        &self,
+        order_id: Uuid,
+        after: i64,
+    ) -> Result<Vec<StoredEvent>, OrderStoreError> {
+        let options = FindOptions::builder().sort(doc! { "version": 1 }).build();
+        self.events
+            .find(
+                doc! { "order_id": uuid_as_bson(order_id), "version": { "$gt": after } },
+                options,
+            )
+            .await
+            .map_err(store_error("reading the events of an order"))?
+            .try_collect()
+            .await
+            .map_err(store_error("reading the events of an order"))
+    }
+
+    async fn order_ids(&self, user_id: Uuid) -> Result<Vec<Uuid>, OrderStoreError> {
+        let options = FindOptions::builder()
+            .sort(doc! { "recorded_at": 1 })
+            .build();
+        let created: Vec<StoredEvent> = self
+            .events
+            .find(
+                doc! { "user_id": uuid_as_bson(user_id), "version": 1 },
+                options,
+            )
+            .await
+            .map_err(store_error("reading the orders of a user"))?
+            .try_collect()
+            .await
+            .map_err(store_error("reading the orders of a user"))?;
+        Ok(created.into_iter().map(|event| event.order_id).collect())
+    }
+
+    async fn snapshot(&self, order_id: Uuid) -> Result<Option<Snapshot>, OrderStoreError> {
+        self.snapshots
+            .find_one(doc! { "order.id": uuid_as_bson(order_id) }, None)
+            .await
+            .map_err(store_error("reading snapshot"))
+    }
+
+    async fn save_snapshot(&self, snapshot: &Snapshot) -> Result<(), OrderStoreError> {
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.snapshots
+            .replace_one(
+                doc! { "order.id": uuid_as_bson(snapshot.order.id) },
+                snapshot,
+                options,
+            )
+            .await
+            .map(|_| ())
+            .map_err(store_error("writing snapshot"))
+    }
+}
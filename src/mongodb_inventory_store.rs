@@ -0,0 +1,139 @@
+use mongodb::{
+    bson::{self, doc, Document},
+    options::{FindOneAndUpdateOptions, ReturnDocument},
+    Client, Collection,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    inventory_store::{InventoryStore, InventoryStoreError, StockLevel},
+    mongodb_order_document::{bson_uuid, from_document_uuid},
+    mongodb_settings::MongodbNames,
+};
+
+/// A stock level as stored in the `inventory` collection, the id of its product a BSON UUID.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct StockLevelDocument {
+    product_id: bson::Uuid,
+    available: i32,
+    reserved: i32,
+    committed: i32,
+}
+
+impl From<StockLevelDocument> for StockLevel {
+    fn from(document: StockLevelDocument) -> Self {
+        StockLevel {
+            product_id: from_document_uuid(document.product_id),
+            available: document.available,
+            reserved: document.reserved,
+            committed: document.committed,
+        }
+    }
+}
+
+/// Returns the filter and update that move `quantity` units of `product_id` from the `from`
+/// counter to the `to` counter of its stock level.
+///
+/// The filter only matches when `from` holds at least `quantity` units, so a write that matches
+/// nothing means there was not enough stock. [`MongodbOrderStore`](crate::mongodb_order_store::MongodbOrderStore)
+/// uses it to move stock inside its own transactions.
+pub(crate) fn transfer(
+    product_id: Uuid,
+    quantity: i32,
+    from: &str,
+    to: &str,
+) -> (Document, Document) {
+    (
+        doc! { "product_id": bson_uuid(product_id), from: { "$gte": quantity } },
+        doc! { "$inc": { from: -quantity, to: quantity } },
+    )
+}
+
+pub struct MongodbInventoryStore {
+    stock: Collection<StockLevelDocument>,
+}
+
+impl MongodbInventoryStore {
+    pub fn new(client: Client, names: &MongodbNames) -> MongodbInventoryStore {
+        MongodbInventoryStore {
+            stock: names.collection(&client, &names.inventory),
+        }
+    }
+
+    async fn move_units(
+        &self,
+        product_id: Uuid,
+        quantity: i32,
+        from: &str,
+        to: &str,
+    ) -> Result<(), InventoryStoreError> {
+        let (filter, update) = transfer(product_id, quantity, from, to);
+        let result = self
+            .stock
+            .update_one(filter, update, None)
+            .await
+            .map_err(|_| InventoryStoreError::StoreUnavailable)?;
+        if result.matched_count == 0 {
+            Err(InventoryStoreError::InsufficientStock(product_id))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl InventoryStore for MongodbInventoryStore {
+    async fn get_stock(&self, product_id: Uuid) -> Result<StockLevel, InventoryStoreError> {
+        Ok(self
+            .stock
+            .find_one(doc! { "product_id": bson_uuid(product_id) }, None)
+            .await
+            .map_err(|_| InventoryStoreError::StoreUnavailable)?
+            .map_or_else(|| StockLevel::empty(product_id), StockLevel::from))
+    }
+
+    async fn set_available(
+        &self,
+        product_id: Uuid,
+        available: i32,
+    ) -> Result<StockLevel, InventoryStoreError> {
+        let options = FindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .build();
+        self.stock
+            .find_one_and_update(
+                doc! { "product_id": bson_uuid(product_id) },
+                doc! {
+                    "$set": { "available": available },
+                    "$setOnInsert": { "reserved": 0, "committed": 0 },
+                },
+                options,
+            )
+            .await
+            .map_err(|_| InventoryStoreError::StoreUnavailable)?
+            .map(StockLevel::from)
+            .ok_or(InventoryStoreError::StoreUnavailable)
+    }
+
+    async fn reserve(&self, product_id: Uuid, quantity: i32) -> Result<(), InventoryStoreError> {
+        self.move_units(product_id, quantity, "available", "reserved")
+            .await
+    }
+
+    async fn release(&self, product_id: Uuid, quantity: i32) -> Result<(), InventoryStoreError> {
+        self.move_units(product_id, quantity, "reserved", "available")
+            .await
+    }
+
+    async fn commit(&self, product_id: Uuid, quantity: i32) -> Result<(), InventoryStoreError> {
+        self.move_units(product_id, quantity, "reserved", "committed")
+            .await
+    }
+
+    async fn restock(&self, product_id: Uuid, quantity: i32) -> Result<(), InventoryStoreError> {
+        self.move_units(product_id, quantity, "committed", "available")
+            .await
+    }
+}
@@ -0,0 +1,1055 @@
+use std::{
+    future::Future,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, spec::BinarySubtype, Bson, Decimal128, Document},
+    options::UpdateOptions,
+    Client, Collection,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    mongodb_order_document::bson_uuid,
+    mongodb_order_store::{classify, store_error},
+    mongodb_settings::MongodbNames,
+    order_store::OrderStoreError,
+};
+
+/// A change to the documents of a collection, e.g. backfilling a field `Order` gained.
+pub struct Migration {
+    /// Unique name, recorded in `_migrations` once the migration is applied.
+    pub name: &'static str,
+    /// Collection whose documents are migrated.
+    pub collection: fn(&MongodbNames) -> &String,
+    /// Documents that may need the migration; every one of them is passed to `up`.
+    pub filter: fn() -> Document,
+    /// Migrates a document, `None` when it is already migrated, so that running it twice is harmless.
+    /// Fails with the reason a document cannot be migrated, which fails the migration once the
+    /// other documents are migrated.
+    pub up: fn(&Document) -> Result<Option<Change>, String>,
+}
+
+/// How a [`Migration`] changes a document.
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    /// Sets the provided fields, as long as the document still matches the filter of the
+    /// migration, so that changes made to its other fields meanwhile are kept.
+    Set(Document),
+    /// Replaces the document by the provided one, which has another `_id`. Both happen in a
+    /// transaction that reads the document again and fails if it changes before committing.
+    Replace(Document),
+}
+
+/// Every migration, in the order they are applied, sorted by name.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        name: "0001_backfill_order_created_at",
+        collection: |names| &names.orders,
+        filter: || doc! { "created_at": { "$exists": false } },
+        up: backfill_order_created_at,
+    },
+    Migration {
+        name: "0002_backfill_order_fields",
+        collection: |names| &names.orders,
+        filter: missing_order_fields,
+        up: backfill_order_fields,
+    },
+    Migration {
+        name: "0003_order_id_as_uuid_id",
+        collection: |names| &names.orders,
+        filter: || doc! { "id": { "$exists": true } },
+        up: order_id_as_uuid_id,
+    },
+    Migration {
+        name: "0004_order_nested_ids_as_uuids",
+        collection: |names| &names.orders,
+        filter: Document::new,
+        up: |order| ids_as_uuids(order, &ORDER_NESTED_IDS),
+    },
+    Migration {
+        name: "0005_audit_ids_as_uuids",
+        collection: |names| &names.order_audit,
+        filter: Document::new,
+        up: |entry| ids_as_uuids(entry, &["id", "order_id"]),
+    },
+    Migration {
+        name: "0006_stock_product_ids_as_uuids",
+        collection: |names| &names.inventory,
+        filter: Document::new,
+        up: |stock| ids_as_uuids(stock, &["product_id"]),
+    },
+    Migration {
+        name: "0007_product_ids_as_uuids",
+        collection: |names| &names.products,
+        filter: Document::new,
+        up: |product| ids_as_uuids(product, &["id"]),
+    },
+    Migration {
+        name: "0008_coupon_product_ids_as_uuids",
+        collection: |names| &names.coupons,
+        filter: Document::new,
+        up: |coupon| ids_as_uuids(coupon, &["rule.BuyXGetY.product_id"]),
+    },
+];
+
+/// Orders stored before they had a creation time get the one of their `ObjectId`.
+fn backfill_order_created_at(order: &Document) -> Result<Option<Change>, String> {
+    if order.contains_key("created_at") {
+        return Ok(None);
+    }
+    let created_at = order
+        .get_object_id("_id")
+        .map_or(0, |id| id.timestamp().timestamp_millis());
+    Ok(Some(Change::Set(doc! { "created_at": created_at })))
+}
+
+/// Fields of orders added after the first version, except `created_at`, and the fields of their
+/// items added with prices.
+const ORDER_FIELDS: [&str; 10] = [
+    "status",
+    "coupons",
+    "subtotal",
+    "discount",
+    "tax",
+    "shipping_cost",
+    "payments",
+    "returns",
+    "refunded",
+    "total",
+];
+const ITEM_FIELDS: [&str; 4] = ["unit_price", "currency", "tax_category", "line_total"];
+
+/// Tax category of the items stored before they had one, the category rules tables usually have.
+const BACKFILLED_TAX_CATEGORY: &str = "standard";
+
+/// Orders missing one of the [`ORDER_FIELDS`] or with an item missing one of the [`ITEM_FIELDS`].
+fn missing_order_fields() -> Document {
+    let missing = ORDER_FIELDS
+        .iter()
+        .map(|field| doc! { *field: { "$exists": false } })
+        .chain(
+            ITEM_FIELDS
+                .iter()
+                .map(|field| doc! { "items": { "$elemMatch": { *field: { "$exists": false } } } }),
+        )
+        .collect::<Vec<_>>();
+    doc! { "$or": missing }
+}
+
+fn zero() -> Bson {
+    Bson::Decimal128(Decimal128::from_str("0").unwrap())
+}
+
+/// Orders stored before `Order` had a status, prices, coupons, taxes, shipping, payments and
+/// returns get the values of a draft with none of them. Their items were not priced when they
+/// were added, and there was no catalog yet to price them from, so they are backfilled at a price
+/// of zero in no currency and taxed at a rate of zero: checking out such an order only charges
+/// the items added since. The fields a document already has are left alone.
+fn backfill_order_fields(order: &Document) -> Result<Option<Change>, String> {
+    let items = match order.get("items") {
+        Some(Bson::Array(items)) => items.clone(),
+        None => vec![],
+        Some(other) => return Err(format!("items {} is not an array", other)),
+    };
+    let mut fields = Document::new();
+    let mut backfilled = vec![];
+    let mut items_changed = !order.contains_key("items");
+    for item in &items {
+        let Bson::Document(item) = item else {
+            return Err(format!("item {} is not a document", item));
+        };
+        let mut item = item.clone();
+        for field in ITEM_FIELDS {
+            if !item.contains_key(field) {
+                items_changed = true;
+                let value = match field {
+                    "currency" => Bson::String(String::new()),
+                    "tax_category" => Bson::String(BACKFILLED_TAX_CATEGORY.to_string()),
+                    _ => zero(),
+                };
+                item.insert(field, value);
+            }
+        }
+        backfilled.push(item);
+    }
+    if items_changed {
+        fields.insert("items", backfilled.clone());
+    }
+
+    for field in ORDER_FIELDS {
+        if order.contains_key(field) {
+            continue;
+        }
+        let value = match field {
+            "status" => Bson::String("Draft".to_string()),
+            "coupons" | "payments" | "returns" => Bson::Array(vec![]),
+            "tax" => {
+                // one line per item, in the same order
+                let lines = backfilled
+                    .iter()
+                    .map(|item| {
+                        doc! {
+                            "product_id": item.get("product_id").cloned().unwrap_or(Bson::Null),
+                            "category": item.get("tax_category").cloned().unwrap_or(Bson::Null),
+                            "rate": zero(),
+                            "amount": zero(),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                Bson::Document(doc! {
+                    "region": Bson::Null,
+                    "inclusive": false,
+                    "lines": lines,
+                    "total": zero(),
+                })
+            }
+            _ => zero(),
+        };
+        fields.insert(field, value);
+    }
+    Ok((!fields.is_empty()).then_some(Change::Set(fields)))
+}
+
+/// Orders stored with their id in `id` and an `ObjectId` as `_id` get their id as `_id`, and
+/// their ids as BSON UUIDs instead of strings or generic binaries, the shape of
+/// [`OrderDocument`](crate::mongodb_order_document::OrderDocument). Orders whose ids are not UUIDs
+/// cannot be read by the order store, so they fail the migration.
+///
+/// Instances older than this migration look orders up by `id`, and cannot insert orders once the
+/// validator requires a UUID `_id`, so they must all be stopped before it is applied.
+fn order_id_as_uuid_id(order: &Document) -> Result<Option<Change>, String> {
+    let mut order = order.clone();
+    let Some(id) = order.remove("id") else {
+        return Ok(None);
+    };
+    let id = as_bson_uuid(&id).ok_or_else(|| format!("id {} is not a UUID", id))?;
+    let user_id = order
+        .get("user_id")
+        .and_then(as_bson_uuid)
+        .ok_or_else(|| format!("user_id of order {} is not a UUID", id))?;
+    order.insert("_id", id);
+    order.insert("user_id", user_id);
+    Ok(Some(Change::Replace(order)))
+}
+
+/// Paths of the ids of the items, coupons, taxes, payments and returns of orders.
+const ORDER_NESTED_IDS: [&str; 6] = [
+    "items.product_id",
+    "coupons.rule.BuyXGetY.product_id",
+    "tax.lines.product_id",
+    "payments.id",
+    "returns.id",
+    "returns.lines.product_id",
+];
+
+/// Documents whose ids at `paths` were written as the driver serializes [`Uuid`], a generic
+/// binary, get them as BSON UUIDs, the shape of
+/// [`OrderDocument`](crate::mongodb_order_document::OrderDocument) and of the stock levels and
+/// products. A path goes through every element of the arrays it meets; the fields it leads to
+/// are set whole. Ids that are not UUIDs cannot be read anymore, so they fail the migration.
+fn ids_as_uuids(document: &Document, paths: &[&str]) -> Result<Option<Change>, String> {
+    let mut fields = Document::new();
+    for path in paths {
+        let (field, rest) = path.split_once('.').unwrap_or((path, ""));
+        let Some(value) = fields.get(field).or_else(|| document.get(field)) else {
+            continue;
+        };
+        let mut value = value.clone();
+        if ids_at_path_as_uuids(&mut value, rest)? {
+            fields.insert(field, value);
+        }
+    }
+    Ok((!fields.is_empty()).then_some(Change::Set(fields)))
+}
+
+/// Turns the ids at `path` of `value` into BSON UUIDs, returning whether one changed.
+fn ids_at_path_as_uuids(value: &mut Bson, path: &str) -> Result<bool, String> {
+    if let Bson::Array(values) = value {
+        let mut changed = false;
+        for value in values {
+            changed |= ids_at_path_as_uuids(value, path)?;
+        }
+        return Ok(changed);
+    }
+    if path.is_empty() {
+        return match value {
+            Bson::Binary(binary) if binary.subtype == BinarySubtype::Uuid => Ok(false),
+            Bson::Null => Ok(false),
+            id => {
+                *id = as_bson_uuid(id).ok_or_else(|| format!("id {} is not a UUID", id))?;
+                Ok(true)
+            }
+        };
+    }
+    let (field, rest) = path.split_once('.').unwrap_or((path, ""));
+    match value {
+        Bson::Document(document) => match document.get_mut(field) {
+            Some(value) => ids_at_path_as_uuids(value, rest),
+            None => Ok(false),
+        },
+        _ => Ok(false),
+    }
+}
+
+/// Reads a UUID written as a string or a binary, `None` if `value` is neither.
+fn as_bson_uuid(value: &Bson) -> Option<Bson> {
+    let id = match value {
+        Bson::Binary(binary) => Uuid::from_slice(&binary.bytes).ok()?,
+        Bson::String(id) => Uuid::parse_str(id).ok()?,
+        _ => return None,
+    };
+    Some(bson_uuid(id))
+}
+
+/// Id of the document of `_migrations` held by the instance applying migrations.
+const LOCK: &str = "lock";
+/// How long the lock is held at most without being renewed, so that an instance dying while
+/// migrating doesn't block the others forever. The instance migrating renews it every third of it.
+const LOCK_LEASE: Duration = Duration::from_secs(600);
+const LOCK_RETRY: Duration = Duration::from_secs(1);
+/// How many times replacing a document changed by someone else meanwhile is tried before the
+/// migration fails.
+const REPLACE_ATTEMPTS: u32 = 3;
+
+/// Lock letting a single instance apply migrations at a time, for a lease it renews while
+/// migrating.
+#[async_trait::async_trait]
+trait MigrationLock: Send + Sync {
+    /// Takes the lock for `lease`, returning `false` while another instance holds it.
+    async fn take(&self, lease: Duration) -> Result<bool, OrderStoreError>;
+
+    /// Extends the lease to `lease` from now, returning `false` when the lock is no longer held,
+    /// i.e. it expired and another instance took it.
+    async fn renew(&self, lease: Duration) -> Result<bool, OrderStoreError>;
+
+    async fn release(&self) -> Result<(), OrderStoreError>;
+}
+
+/// Error for a lock whose lease ended before the migrations were applied.
+fn lock_lost() -> OrderStoreError {
+    OrderStoreError::StoreFailure(
+        "the lock of migrations expired while migrating, another instance may be migrating".into(),
+    )
+}
+
+/// Runs `work` while holding `lock`, waiting `retry` between attempts to take it while another
+/// instance holds it, and renewing its `lease` until `work` completes.
+///
+/// # Errors
+///
+/// Returns the error of `work`, or fails without waiting for it to complete when the lease cannot
+/// be renewed before it ends. The lock is released either way; an error releasing it is only
+/// returned when `work` succeeded.
+async fn while_locked<T>(
+    lock: &impl MigrationLock,
+    lease: Duration,
+    retry: Duration,
+    work: impl Future<Output = Result<T, OrderStoreError>>,
+) -> Result<T, OrderStoreError> {
+    while !lock.take(lease).await? {
+        info!("waiting for another instance to finish migrating");
+        tokio::time::sleep(retry).await;
+    }
+    let result = tokio::select! {
+        result = work => result,
+        err = keep(lock, lease) => Err(err),
+    };
+    let released = lock.release().await;
+    let value = result?;
+    released?;
+    Ok(value)
+}
+
+/// Renews the lease of `lock` every third of it until it cannot be renewed anymore, returning why.
+/// Failing renewals are retried as long as the lease lasts.
+async fn keep(lock: &impl MigrationLock, lease: Duration) -> OrderStoreError {
+    let every = lease / 3;
+    let mut expires_at = Instant::now() + lease;
+    loop {
+        tokio::time::sleep(every).await;
+        let renewed_at = Instant::now();
+        match lock.renew(lease).await {
+            Ok(true) => expires_at = renewed_at + lease,
+            Ok(false) => return lock_lost(),
+            Err(err) if Instant::now() + every < expires_at => {
+                warn!("renewing the lock of migrations failed, retrying: {}", err)
+            }
+            Err(_) => return lock_lost(),
+        }
+    }
+}
+
+/// The lock document of `_migrations`, taken by an instance until it expires.
+struct MongodbLock {
+    locks: Collection<Document>,
+    /// Tells this instance's lock apart from the ones of other instances.
+    owner: String,
+}
+
+#[async_trait::async_trait]
+impl MigrationLock for MongodbLock {
+    async fn take(&self, lease: Duration) -> Result<bool, OrderStoreError> {
+        let now = Utc::now().timestamp_millis();
+        // matches nothing while the lock is held, and the upsert then fails on the duplicate id
+        let taken = self
+            .locks
+            .update_one(
+                doc! { "_id": LOCK, "expires_at": { "$lt": now } },
+                doc! { "$set": { "owner": &self.owner, "expires_at": expires_at(lease) } },
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await;
+        match taken.map_err(classify) {
+            Ok(_) => Ok(true),
+            Err(OrderStoreError::DuplicateKey(_)) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn renew(&self, lease: Duration) -> Result<bool, OrderStoreError> {
+        let renewed = self
+            .locks
+            .update_one(
+                doc! { "_id": LOCK, "owner": &self.owner },
+                doc! { "$set": { "expires_at": expires_at(lease) } },
+                None,
+            )
+            .await
+            .map_err(store_error("renewing the lock of migrations"))?;
+        Ok(renewed.matched_count == 1)
+    }
+
+    async fn release(&self) -> Result<(), OrderStoreError> {
+        self.locks
+            .delete_one(doc! { "_id": LOCK, "owner": &self.owner }, None)
+            .await
+            .map(|_| ())
+            .map_err(store_error("unlocking migrations"))
+    }
+}
+
+/// When a lease of `lease` taken now ends, in milliseconds since the epoch.
+fn expires_at(lease: Duration) -> i64 {
+    Utc::now().timestamp_millis() + lease.as_millis() as i64
+}
+
+/// A migration recorded in `_migrations`.
+#[derive(Debug, Serialize, Deserialize)]
+struct AppliedMigration {
+    #[serde(rename = "_id")]
+    name: String,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    applied_at: DateTime<Utc>,
+    /// Number of documents changed.
+    migrated: i64,
+}
+
+/// Whether a migration was applied.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MigrationStatus {
+    pub name: &'static str,
+    pub applied_at: Option<DateTime<Utc>>,
+}
+
+/// Returns the migrations of `migrations` whose name is not in `applied`, in order.
+fn pending<'a>(migrations: &'a [Migration], applied: &[String]) -> Vec<&'a Migration> {
+    migrations
+        .iter()
+        .filter(|migration| !applied.iter().any(|name| name == migration.name))
+        .collect()
+}
+
+/// Applies the [`MIGRATIONS`] to a MongoDB database, recording them in `_migrations`.
+pub struct Migrator {
+    client: Client,
+    names: MongodbNames,
+    records: Collection<AppliedMigration>,
+    lock: MongodbLock,
+}
+
+impl Migrator {
+    pub fn new(client: Client, names: &MongodbNames) -> Migrator {
+        let records: Collection<AppliedMigration> = names.collection(&client, &names.migrations);
+        Migrator {
+            lock: MongodbLock {
+                locks: records.clone_with_type(),
+                owner: Uuid::new_v4().to_string(),
+            },
+            records,
+            names: names.clone(),
+            client,
+        }
+    }
+
+    /// Returns every migration with when it was applied, `None` for the pending ones.
+    pub async fn status(&self) -> Result<Vec<MigrationStatus>, OrderStoreError> {
+        let applied: Vec<AppliedMigration> = self
+            .records
+            .find(doc! { "_id": { "$ne": LOCK } }, None)
+            .await
+            .map_err(store_error("reading applied migrations"))?
+            .try_collect()
+            .await
+            .map_err(store_error("reading applied migrations"))?;
+        Ok(MIGRATIONS
+            .iter()
+            .map(|migration| MigrationStatus {
+                name: migration.name,
+                applied_at: applied
+                    .iter()
+                    .find(|record| record.name == migration.name)
+                    .map(|record| record.applied_at),
+            })
+            .collect())
+    }
+
+    /// Applies the pending migrations in order and returns their names. Only one instance migrates
+    /// at a time: the others wait for it to finish, and then find nothing left to apply.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the first command MongoDB rejects, or
+    /// [`StoreFailure`](OrderStoreError::StoreFailure) when the lock expired before the migrations
+    /// were applied. The migrations applied before stay recorded, and the failed one is applied
+    /// again by the next run.
+    pub async fn up(&self) -> Result<Vec<&'static str>, OrderStoreError> {
+        while_locked(&self.lock, LOCK_LEASE, LOCK_RETRY, self.apply_pending()).await
+    }
+
+    async fn apply_pending(&self) -> Result<Vec<&'static str>, OrderStoreError> {
+        let applied: Vec<String> = self
+            .status()
+            .await?
+            .into_iter()
+            .filter(|status| status.applied_at.is_some())
+            .map(|status| status.name.to_string())
+            .collect();
+        let mut names = vec![];
+        for migration in pending(MIGRATIONS, &applied) {
+            let migrated = self.apply(migration).await?;
+            self.records
+                .insert_one(
+                    AppliedMigration {
+                        name: migration.name.to_string(),
+                        applied_at: Utc::now(),
+                        migrated,
+                    },
+                    None,
+                )
+                .await
+                .map_err(store_error("recording a migration"))?;
+            info!(
+                "applied migration {} to {} documents",
+                migration.name, migrated
+            );
+            names.push(migration.name);
+        }
+        Ok(names)
+    }
+
+    /// Migrates the documents of the collection of `migration` and returns how many changed.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the first command MongoDB rejects, or
+    /// [`StoreSerialization`](OrderStoreError::StoreSerialization) once the other documents are
+    /// migrated when some cannot be, each logged with the reason.
+    async fn apply(&self, migration: &Migration) -> Result<i64, OrderStoreError> {
+        let collection: Collection<Document> = self
+            .names
+            .collection(&self.client, (migration.collection)(&self.names));
+        let mut documents = collection
+            .find((migration.filter)(), None)
+            .await
+            .map_err(store_error("reading documents to migrate"))?;
+        let mut migrated = 0;
+        let mut failed = 0;
+        while let Some(document) = documents
+            .try_next()
+            .await
+            .map_err(store_error("reading documents to migrate"))?
+        {
+            let id = document.get("_id").cloned().unwrap_or(Bson::Null);
+            let change = match (migration.up)(&document) {
+                Ok(Some(change)) => change,
+                Ok(None) => continue,
+                Err(reason) => {
+                    error!(
+                        "cannot apply migration {} to document {}: {}",
+                        migration.name, id, reason
+                    );
+                    failed += 1;
+                    continue;
+                }
+            };
+            match change {
+                Change::Set(fields) => {
+                    let mut filter = (migration.filter)();
+                    filter.insert("_id", id);
+                    let updated = collection
+                        .update_one(filter, doc! { "$set": fields }, None)
+                        .await
+                        .map_err(store_error("migrating a document"))?;
+                    migrated += updated.modified_count as i64;
+                }
+                Change::Replace(_) => {
+                    let mut attempts = 1;
+                    let replaced = loop {
+                        match self.replace(&collection, migration, &id).await {
+                            Err(OrderStoreError::WriteConflict(_))
+                                if attempts < REPLACE_ATTEMPTS =>
+                            {
+                                attempts += 1
+                            }
+                            result => break result?,
+                        }
+                    };
+                    migrated += i64::from(replaced);
+                }
+            }
+        }
+        if failed > 0 {
+            let reason = format!(
+                "{} documents cannot be migrated by {}",
+                failed, migration.name
+            );
+            return Err(OrderStoreError::StoreSerialization(reason.into()));
+        }
+        Ok(migrated)
+    }
+
+    /// Replaces document `id` by its migrated version in a transaction, reading it again inside it
+    /// so that the changes made since it was first read are kept. A change made before the
+    /// transaction commits fails it with [`WriteConflict`](OrderStoreError::WriteConflict).
+    /// Returns whether the document was replaced, `false` when it no longer needs the migration.
+    async fn replace(
+        &self,
+        collection: &Collection<Document>,
+        migration: &Migration,
+        id: &Bson,
+    ) -> Result<bool, OrderStoreError> {
+        let mut session = self
+            .client
+            .start_session(None)
+            .await
+            .map_err(store_error("starting a session"))?;
+        session
+            .start_transaction(None)
+            .await
+            .map_err(store_error("starting a transaction"))?;
+        let document = collection
+            .find_one_with_session(doc! { "_id": id }, None, &mut session)
+            .await
+            .map_err(store_error("reading a document to migrate"))?;
+        let Some(Ok(Some(Change::Replace(replacement)))) = document.as_ref().map(migration.up)
+        else {
+            return Ok(false);
+        };
+        // the _id of a document cannot change, the migrated one takes its place; it may already
+        // exist when a run before the migration was transactional stopped before deleting
+        let replaced = collection
+            .find_one_with_session(doc! { "_id": replacement.get("_id") }, None, &mut session)
+            .await
+            .map_err(store_error("migrating a document"))?;
+        if replaced.is_none() {
+            collection
+                .insert_one_with_session(replacement, None, &mut session)
+                .await
+                .map_err(store_error("migrating a document"))?;
+        }
+        collection
+            .delete_one_with_session(doc! { "_id": id }, None, &mut session)
+            .await
+            .map_err(store_error("migrating a document"))?;
+        session
+            .commit_transaction()
+            .await
+            .map_err(store_error("migrating a document"))?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use mongodb::bson::{self, oid::ObjectId, spec::BinarySubtype, Binary};
+    use rust_decimal::Decimal;
+
+    use super::*;
+    use crate::{
+        fake_payment_provider::FakePaymentProvider,
+        in_mem_inventory_store::InMemInventoryStore,
+        in_mem_order_store::InMemOrderStore,
+        in_mem_product_store::InMemProductStore,
+        in_mem_promotion_store::InMemPromotionStore,
+        inventory_store::InventoryStoreNewType,
+        mongodb_order_document::OrderDocument,
+        mongodb_schema::tests::check_order,
+        order_store::{Item, Order, OrderStore},
+        product_store::ProductStoreNewType,
+        promotion_store::PromotionStoreNewType,
+        rules_table_tax_calculator::RulesTableTaxCalculator,
+        tax_calculator::{LineTax, TaxBreakdown},
+    };
+
+    /// Orders created with the in-memory store.
+    async fn orders() -> Vec<Order> {
+        let store = InMemOrderStore::new(
+            Arc::new(ProductStoreNewType::new(InMemProductStore::new())),
+            Arc::new(InventoryStoreNewType::new(InMemInventoryStore::new())),
+            Arc::new(PromotionStoreNewType::new(InMemPromotionStore::new())),
+            Arc::new(RulesTableTaxCalculator::tax_free()),
+            Arc::new(FakePaymentProvider::new()),
+        );
+        let user_id = Uuid::new_v4();
+        for _ in 0..3 {
+            store.create_order(user_id).await.unwrap();
+        }
+        store.list_orders(user_id).await.unwrap()
+    }
+
+    /// Serializes `value` the way the driver does when it writes it.
+    fn stored<T: Serialize>(value: &T) -> Document {
+        bson::to_raw_document_buf(value)
+            .unwrap()
+            .to_document()
+            .unwrap()
+    }
+
+    /// `id` as the first version of the order store wrote it, a generic binary.
+    fn first_version_uuid(id: Uuid) -> Bson {
+        Bson::Binary(Binary {
+            subtype: BinarySubtype::Generic,
+            bytes: id.as_bytes().to_vec(),
+        })
+    }
+
+    /// An order as the first version of the order store wrote it: its id in `id` with the
+    /// `ObjectId` generated by the driver as `_id`, and items with only a product and a quantity.
+    fn first_version(
+        id: ObjectId,
+        order_id: Uuid,
+        user_id: Uuid,
+        items: &[(Uuid, i32)],
+    ) -> Document {
+        let items: Vec<Document> = items
+            .iter()
+            .map(|(product_id, quantity)| {
+                doc! { "product_id": first_version_uuid(*product_id), "quantity": *quantity }
+            })
+            .collect();
+        doc! {
+            "_id": id,
+            "id": first_version_uuid(order_id),
+            "user_id": first_version_uuid(user_id),
+            "items": items,
+        }
+    }
+
+    /// Applies every migration of `orders` to `document`, as `Migrator::up` does.
+    fn migrate(mut document: Document) -> Document {
+        let names = MongodbNames::default();
+        for migration in MIGRATIONS {
+            if (migration.collection)(&names) == &names.orders {
+                match (migration.up)(&document).unwrap() {
+                    Some(Change::Set(fields)) => document.extend(fields),
+                    Some(Change::Replace(replacement)) => document = replacement,
+                    None => {}
+                }
+            }
+        }
+        document
+    }
+
+    fn migrate_order(document: Document) -> Order {
+        bson::from_document::<OrderDocument>(migrate(document))
+            .unwrap()
+            .into()
+    }
+
+    #[test]
+    fn migrations_have_unique_ordered_names() {
+        let names: Vec<_> = MIGRATIONS.iter().map(|migration| migration.name).collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(names, sorted);
+    }
+
+    #[test]
+    fn only_migrations_not_applied_are_pending() {
+        let names: Vec<_> = MIGRATIONS.iter().map(|m| m.name).collect();
+        let all: Vec<_> = pending(MIGRATIONS, &[]).iter().map(|m| m.name).collect();
+        assert_eq!(all, names);
+
+        let applied = ["0002_backfill_order_fields".to_string()];
+        let rest: Vec<_> = pending(MIGRATIONS, &applied)
+            .iter()
+            .map(|m| m.name)
+            .collect();
+        assert_eq!(
+            rest[..2],
+            ["0001_backfill_order_created_at", "0003_order_id_as_uuid_id"]
+        );
+        assert_eq!(rest.len(), names.len() - 1);
+    }
+
+    #[test]
+    fn orders_of_the_first_version_are_migrated() {
+        let (order_id, user_id, product_id) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        for items in [vec![], vec![(product_id, 2)]] {
+            let id = ObjectId::new();
+            let document = migrate(first_version(id, order_id, user_id, &items));
+            check_order(document.clone()).unwrap();
+
+            let migrated = Order::from(bson::from_document::<OrderDocument>(document).unwrap());
+            assert_eq!(
+                migrated.created_at.timestamp_millis(),
+                id.timestamp().timestamp_millis()
+            );
+            let items: Vec<Item> = items
+                .iter()
+                .map(|(product_id, quantity)| Item {
+                    product_id: *product_id,
+                    quantity: *quantity,
+                    unit_price: Decimal::ZERO,
+                    currency: String::new(),
+                    tax_category: BACKFILLED_TAX_CATEGORY.to_string(),
+                    line_total: Decimal::ZERO,
+                })
+                .collect();
+            let tax = TaxBreakdown {
+                lines: items
+                    .iter()
+                    .map(|item| LineTax {
+                        product_id: item.product_id,
+                        category: item.tax_category.clone(),
+                        rate: Decimal::ZERO,
+                        amount: Decimal::ZERO,
+                    })
+                    .collect(),
+                ..TaxBreakdown::default()
+            };
+            assert_eq!(
+                migrated,
+                Order {
+                    id: order_id,
+                    created_at: migrated.created_at,
+                    items,
+                    tax,
+                    ..Order::new(user_id)
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn nested_ids_of_the_first_version_are_migrated_to_bson_uuids() {
+        let product_id = Uuid::new_v4();
+        let document = first_version(
+            ObjectId::new(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            &[(product_id, 1)],
+        );
+        let migrated = migrate(document);
+        let items = migrated.get_array("items").unwrap();
+        let item = items[0].as_document().unwrap();
+        assert_eq!(item.get("product_id"), Some(&bson_uuid(product_id)));
+        let lines = migrated
+            .get_document("tax")
+            .unwrap()
+            .get_array("lines")
+            .unwrap();
+        let line = lines[0].as_document().unwrap();
+        assert_eq!(line.get("product_id"), Some(&bson_uuid(product_id)));
+    }
+
+    #[test]
+    fn ids_of_other_collections_are_migrated_to_bson_uuids() {
+        let (id, order_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let entry = doc! {
+            "_id": ObjectId::new(),
+            "id": first_version_uuid(id),
+            "order_id": order_id.to_string(),
+        };
+        assert_eq!(
+            ids_as_uuids(&entry, &["id", "order_id"]),
+            Ok(Some(Change::Set(
+                doc! { "id": bson_uuid(id), "order_id": bson_uuid(order_id) }
+            )))
+        );
+
+        let coupon = doc! {
+            "code": "TWOFORONE",
+            "rule": { "BuyXGetY": { "product_id": first_version_uuid(id), "buy": 1, "get": 1 } },
+        };
+        assert_eq!(
+            ids_as_uuids(&coupon, &["rule.BuyXGetY.product_id"]),
+            Ok(Some(Change::Set(doc! {
+                "rule": { "BuyXGetY": { "product_id": bson_uuid(id), "buy": 1, "get": 1 } },
+            })))
+        );
+        let coupon = doc! { "code": "TENOFF", "rule": { "Percentage": { "percent": 10 } } };
+        assert_eq!(
+            ids_as_uuids(&coupon, &["rule.BuyXGetY.product_id"]),
+            Ok(None)
+        );
+
+        let stock = doc! { "product_id": "SKU-1", "available": 1 };
+        assert_eq!(
+            ids_as_uuids(&stock, &["product_id"]),
+            Err("id \"SKU-1\" is not a UUID".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn only_the_missing_fields_are_backfilled() {
+        let order = &orders().await[0];
+        let mut document = stored(&OrderDocument::from(order));
+        document.remove("returns");
+        document.remove("refunded");
+        assert_eq!(
+            backfill_order_fields(&document),
+            Ok(Some(Change::Set(
+                doc! { "returns": [], "refunded": zero() }
+            )))
+        );
+    }
+
+    #[test]
+    fn ids_stored_as_strings_are_migrated() {
+        let (order_id, user_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut document = first_version(ObjectId::new(), order_id, user_id, &[]);
+        document.insert("id", order_id.to_string());
+        document.insert("user_id", user_id.to_string());
+        let migrated = migrate_order(document);
+        assert_eq!((migrated.id, migrated.user_id), (order_id, user_id));
+    }
+
+    #[test]
+    fn orders_whose_ids_are_not_uuids_cannot_be_migrated() {
+        let (order_id, user_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut document = first_version(ObjectId::new(), order_id, user_id, &[]);
+        document.insert("id", "order-1");
+        assert_eq!(
+            order_id_as_uuid_id(&document),
+            Err("id \"order-1\" is not a UUID".to_string())
+        );
+        document.insert("id", order_id.to_string());
+        document.insert("user_id", 42);
+        assert!(order_id_as_uuid_id(&document).is_err());
+    }
+
+    #[tokio::test]
+    async fn migrated_orders_are_left_alone() {
+        for order in orders().await {
+            let document = stored(&OrderDocument::from(&order));
+            for migration in MIGRATIONS {
+                assert_eq!((migration.up)(&document), Ok(None), "{}", migration.name);
+            }
+            assert_eq!(migrate_order(document), order);
+        }
+    }
+
+    /// Lock held by another instance for the first `busy` attempts to take it.
+    #[derive(Default)]
+    struct FakeLock {
+        busy: AtomicUsize,
+        renewals: AtomicUsize,
+        /// Whether the lease expired and another instance took the lock.
+        lost: AtomicBool,
+        released: AtomicBool,
+        release_fails: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl MigrationLock for FakeLock {
+        async fn take(&self, _: Duration) -> Result<bool, OrderStoreError> {
+            let busy = self.busy.load(Ordering::SeqCst);
+            self.busy.store(busy.saturating_sub(1), Ordering::SeqCst);
+            Ok(busy == 0)
+        }
+
+        async fn renew(&self, _: Duration) -> Result<bool, OrderStoreError> {
+            self.renewals.fetch_add(1, Ordering::SeqCst);
+            Ok(!self.lost.load(Ordering::SeqCst))
+        }
+
+        async fn release(&self) -> Result<(), OrderStoreError> {
+            self.released.store(true, Ordering::SeqCst);
+            match self.release_fails {
+                true => Err(OrderStoreError::StoreUnavailable),
+                false => Ok(()),
+            }
+        }
+    }
+
+    const LEASE: Duration = Duration::from_millis(30);
+    const RETRY: Duration = Duration::from_millis(1);
+
+    #[tokio::test]
+    async fn the_lock_is_waited_for_and_renewed_while_migrating() {
+        let lock = FakeLock {
+            busy: AtomicUsize::new(2),
+            ..FakeLock::default()
+        };
+        let migrated = while_locked(&lock, LEASE, RETRY, async {
+            tokio::time::sleep(LEASE * 2).await;
+            Ok("migrated")
+        })
+        .await;
+        assert_eq!(migrated.unwrap(), "migrated");
+        assert_eq!(lock.busy.load(Ordering::SeqCst), 0);
+        assert!(lock.renewals.load(Ordering::SeqCst) >= 2);
+        assert!(lock.released.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn migrating_stops_when_the_lock_expired() {
+        let lock = FakeLock::default();
+        lock.lost.store(true, Ordering::SeqCst);
+        let result = while_locked(
+            &lock,
+            LEASE,
+            RETRY,
+            futures::future::pending::<Result<(), _>>(),
+        );
+        assert!(matches!(
+            result.await,
+            Err(OrderStoreError::StoreFailure(_))
+        ));
+        assert!(lock.released.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn failing_to_release_the_lock_does_not_hide_the_migration_error() {
+        let lock = FakeLock {
+            release_fails: true,
+            ..FakeLock::default()
+        };
+        let result = while_locked(&lock, LEASE, RETRY, async {
+            Err::<(), _>(OrderStoreError::StoreMisconfigured("not allowed".into()))
+        });
+        assert!(matches!(
+            result.await,
+            Err(OrderStoreError::StoreMisconfigured(_))
+        ));
+        let result = while_locked(&lock, LEASE, RETRY, async { Ok(()) });
+        assert!(matches!(
+            result.await,
+            Err(OrderStoreError::StoreUnavailable)
+        ));
+    }
+}
@@ -0,0 +1,805 @@
+//! How [`Order`] is persisted by [`MongodbOrderStore`](crate::mongodb_order_store::MongodbOrderStore).
+//!
+//! The document shape is spelled out here, down to the items, coupons, payments, returns, taxes
+//! and addresses of the order, rather than derived from the domain types, so that changing them
+//! doesn't silently change what is stored. The id of the order is the `_id` of its document and
+//! every id, its own, the one of its user and the ones of the products and payments it refers
+//! to, is a native BSON UUID (binary subtype 4), whatever the settings of the driver.
+
+use chrono::{DateTime, Utc};
+use mongodb::bson::{self, Bson};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    audit_log::{AuditEntry, FieldChange},
+    fulfilment::{Address, ShippingMethod},
+    order_store::{Item, Order, OrderStatus},
+    payment_provider::{PaymentAttempt, PaymentOutcome},
+    promotions::{Coupon, PromotionRule},
+    returns::{ReturnLine, ReturnRequest, ReturnStatus},
+    tax_calculator::{LineTax, TaxBreakdown},
+};
+
+/// Converts `id` into a BSON UUID, the type of every id stored in order documents.
+pub fn bson_uuid(id: Uuid) -> Bson {
+    to_document_uuid(id).into()
+}
+
+pub(crate) fn to_document_uuid(id: Uuid) -> bson::Uuid {
+    bson::Uuid::from_bytes(id.into_bytes())
+}
+
+pub(crate) fn from_document_uuid(id: bson::Uuid) -> Uuid {
+    Uuid::from_bytes(id.bytes())
+}
+
+/// An order as stored in the `orders` collection.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OrderDocument {
+    #[serde(rename = "_id")]
+    pub id: bson::Uuid,
+    pub user_id: bson::Uuid,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    pub created_at: DateTime<Utc>,
+    pub items: Vec<ItemDocument>,
+    pub status: OrderStatusDocument,
+    pub currency: Option<String>,
+    pub coupons: Vec<CouponDocument>,
+    #[serde(with = "crate::decimal128")]
+    pub subtotal: Decimal,
+    #[serde(with = "crate::decimal128")]
+    pub discount: Decimal,
+    pub tax: TaxBreakdownDocument,
+    pub shipping_address: Option<AddressDocument>,
+    pub shipping_method: Option<ShippingMethodDocument>,
+    #[serde(with = "crate::decimal128")]
+    pub shipping_cost: Decimal,
+    pub billing_address: Option<AddressDocument>,
+    pub payments: Vec<PaymentAttemptDocument>,
+    pub returns: Vec<ReturnRequestDocument>,
+    #[serde(with = "crate::decimal128")]
+    pub refunded: Decimal,
+    #[serde(with = "crate::decimal128")]
+    pub total: Decimal,
+}
+
+/// An item of an order document.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ItemDocument {
+    pub product_id: bson::Uuid,
+    pub quantity: i32,
+    #[serde(with = "crate::decimal128")]
+    pub unit_price: Decimal,
+    pub currency: String,
+    pub tax_category: String,
+    #[serde(with = "crate::decimal128")]
+    pub line_total: Decimal,
+}
+
+/// The status of an order document, stored as its name.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum OrderStatusDocument {
+    Draft,
+    Placed,
+    Paid,
+    Shipped,
+    Delivered,
+    Cancelled,
+}
+
+/// A coupon applied to an order, also the shape of the documents of the `coupons` collection.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CouponDocument {
+    pub code: String,
+    pub rule: PromotionRuleDocument,
+    pub valid_from: Option<DateTime<Utc>>,
+    pub valid_until: Option<DateTime<Utc>>,
+    pub max_uses_per_user: Option<u32>,
+}
+
+/// The rule of a coupon document, stored as an object named after the variant.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PromotionRuleDocument {
+    Percentage {
+        #[serde(with = "crate::decimal128")]
+        percent: Decimal,
+    },
+    FixedAmount {
+        #[serde(with = "crate::decimal128")]
+        amount: Decimal,
+        #[serde(default = "default_currency")]
+        currency: String,
+    },
+    BuyXGetY {
+        product_id: bson::Uuid,
+        buy: i32,
+        get: i32,
+    },
+    MinimumSpend {
+        #[serde(with = "crate::decimal128")]
+        minimum: Decimal,
+        #[serde(with = "crate::decimal128")]
+        amount: Decimal,
+        #[serde(default = "default_currency")]
+        currency: String,
+    },
+}
+
+/// Currency of the amounts of coupons stored before they had one.
+fn default_currency() -> String {
+    "USD".to_string()
+}
+
+/// The taxes of an order document.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TaxBreakdownDocument {
+    pub region: Option<String>,
+    pub inclusive: bool,
+    pub lines: Vec<LineTaxDocument>,
+    #[serde(with = "crate::decimal128")]
+    pub total: Decimal,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LineTaxDocument {
+    pub product_id: bson::Uuid,
+    pub category: String,
+    #[serde(with = "crate::decimal128")]
+    pub rate: Decimal,
+    #[serde(with = "crate::decimal128")]
+    pub amount: Decimal,
+}
+
+/// A shipping or billing address of an order document.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AddressDocument {
+    pub name: String,
+    pub line1: String,
+    pub line2: Option<String>,
+    pub city: String,
+    pub region: Option<String>,
+    pub postal_code: String,
+    pub country: String,
+}
+
+/// The shipping method of an order document, stored as its name.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ShippingMethodDocument {
+    Standard,
+    Express,
+    Overnight,
+}
+
+/// A payment attempt of an order document.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PaymentAttemptDocument {
+    pub id: bson::Uuid,
+    #[serde(with = "crate::decimal128")]
+    pub amount: Decimal,
+    pub currency: String,
+    pub attempted_at: DateTime<Utc>,
+    pub outcome: PaymentOutcomeDocument,
+}
+
+/// How a payment attempt ended, stored as its name or, with its fields, as an object named after
+/// it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PaymentOutcomeDocument {
+    Approved { reference: String },
+    Declined { reason: String },
+    Failed,
+    Pending,
+}
+
+/// A return request of an order document.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReturnRequestDocument {
+    pub id: bson::Uuid,
+    pub lines: Vec<ReturnLineDocument>,
+    pub reason: String,
+    pub status: ReturnStatusDocument,
+    pub requested_at: DateTime<Utc>,
+    pub decided_at: Option<DateTime<Utc>>,
+    #[serde(with = "crate::decimal128")]
+    pub refund_amount: Decimal,
+    pub refund_reference: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReturnLineDocument {
+    pub item_index: usize,
+    pub product_id: bson::Uuid,
+    pub quantity: i32,
+    #[serde(with = "crate::decimal128")]
+    pub refund_amount: Decimal,
+}
+
+/// The stage of a return request, stored as its name.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ReturnStatusDocument {
+    Requested,
+    Approved,
+    Rejected,
+}
+
+/// An entry of the `order_audit` collection.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntryDocument {
+    pub id: bson::Uuid,
+    pub order_id: bson::Uuid,
+    pub actor: String,
+    pub timestamp: DateTime<Utc>,
+    pub operation: String,
+    /// The fields of the order that changed, already stored as BSON values.
+    pub changes: Vec<FieldChange>,
+}
+
+impl From<&Order> for OrderDocument {
+    fn from(order: &Order) -> Self {
+        OrderDocument {
+            id: to_document_uuid(order.id),
+            user_id: to_document_uuid(order.user_id),
+            created_at: order.created_at,
+            items: order.items.iter().map(ItemDocument::from).collect(),
+            status: order.status.into(),
+            currency: order.currency.clone(),
+            coupons: order.coupons.iter().map(CouponDocument::from).collect(),
+            subtotal: order.subtotal,
+            discount: order.discount,
+            tax: (&order.tax).into(),
+            shipping_address: order.shipping_address.as_ref().map(AddressDocument::from),
+            shipping_method: order.shipping_method.map(ShippingMethodDocument::from),
+            shipping_cost: order.shipping_cost,
+            billing_address: order.billing_address.as_ref().map(AddressDocument::from),
+            payments: order
+                .payments
+                .iter()
+                .map(PaymentAttemptDocument::from)
+                .collect(),
+            returns: order
+                .returns
+                .iter()
+                .map(ReturnRequestDocument::from)
+                .collect(),
+            refunded: order.refunded,
+            total: order.total,
+        }
+    }
+}
+
+impl From<OrderDocument> for Order {
+    fn from(document: OrderDocument) -> Self {
+        Order {
+            id: from_document_uuid(document.id),
+            user_id: from_document_uuid(document.user_id),
+            created_at: document.created_at,
+            items: document.items.into_iter().map(Item::from).collect(),
+            status: document.status.into(),
+            currency: document.currency,
+            coupons: document.coupons.into_iter().map(Coupon::from).collect(),
+            subtotal: document.subtotal,
+            discount: document.discount,
+            tax: document.tax.into(),
+            shipping_address: document.shipping_address.map(Address::from),
+            shipping_method: document.shipping_method.map(ShippingMethod::from),
+            shipping_cost: document.shipping_cost,
+            billing_address: document.billing_address.map(Address::from),
+            payments: document
+                .payments
+                .into_iter()
+                .map(PaymentAttempt::from)
+                .collect(),
+            returns: document
+                .returns
+                .into_iter()
+                .map(ReturnRequest::from)
+                .collect(),
+            refunded: document.refunded,
+            total: document.total,
+        }
+    }
+}
+
+impl From<&Item> for ItemDocument {
+    fn from(item: &Item) -> Self {
+        ItemDocument {
+            product_id: to_document_uuid(item.product_id),
+            quantity: item.quantity,
+            unit_price: item.unit_price,
+            currency: item.currency.clone(),
+            tax_category: item.tax_category.clone(),
+            line_total: item.line_total,
+        }
+    }
+}
+
+impl From<ItemDocument> for Item {
+    fn from(document: ItemDocument) -> Self {
+        Item {
+            product_id: from_document_uuid(document.product_id),
+            quantity: document.quantity,
+            unit_price: document.unit_price,
+            currency: document.currency,
+            tax_category: document.tax_category,
+            line_total: document.line_total,
+        }
+    }
+}
+
+impl From<OrderStatus> for OrderStatusDocument {
+    fn from(status: OrderStatus) -> Self {
+        match status {
+            OrderStatus::Draft => OrderStatusDocument::Draft,
+            OrderStatus::Placed => OrderStatusDocument::Placed,
+            OrderStatus::Paid => OrderStatusDocument::Paid,
+            OrderStatus::Shipped => OrderStatusDocument::Shipped,
+            OrderStatus::Delivered => OrderStatusDocument::Delivered,
+            OrderStatus::Cancelled => OrderStatusDocument::Cancelled,
+        }
+    }
+}
+
+impl From<OrderStatusDocument> for OrderStatus {
+    fn from(status: OrderStatusDocument) -> Self {
+        match status {
+            OrderStatusDocument::Draft => OrderStatus::Draft,
+            OrderStatusDocument::Placed => OrderStatus::Placed,
+            OrderStatusDocument::Paid => OrderStatus::Paid,
+            OrderStatusDocument::Shipped => OrderStatus::Shipped,
+            OrderStatusDocument::Delivered => OrderStatus::Delivered,
+            OrderStatusDocument::Cancelled => OrderStatus::Cancelled,
+        }
+    }
+}
+
+impl From<&Coupon> for CouponDocument {
+    fn from(coupon: &Coupon) -> Self {
+        let rule = match &coupon.rule {
+            PromotionRule::Percentage { percent } => {
+                PromotionRuleDocument::Percentage { percent: *percent }
+            }
+            PromotionRule::FixedAmount { amount, currency } => PromotionRuleDocument::FixedAmount {
+                amount: *amount,
+                currency: currency.clone(),
+            },
+            PromotionRule::BuyXGetY {
+                product_id,
+                buy,
+                get,
+            } => PromotionRuleDocument::BuyXGetY {
+                product_id: to_document_uuid(*product_id),
+                buy: *buy,
+                get: *get,
+            },
+            PromotionRule::MinimumSpend {
+                minimum,
+                amount,
+                currency,
+            } => PromotionRuleDocument::MinimumSpend {
+                minimum: *minimum,
+                amount: *amount,
+                currency: currency.clone(),
+            },
+        };
+        CouponDocument {
+            code: coupon.code.clone(),
+            rule,
+            valid_from: coupon.valid_from,
+            valid_until: coupon.valid_until,
+            max_uses_per_user: coupon.max_uses_per_user,
+        }
+    }
+}
+
+impl From<CouponDocument> for Coupon {
+    fn from(document: CouponDocument) -> Self {
+        let rule = match document.rule {
+            PromotionRuleDocument::Percentage { percent } => PromotionRule::Percentage { percent },
+            PromotionRuleDocument::FixedAmount { amount, currency } => {
+                PromotionRule::FixedAmount { amount, currency }
+            }
+            PromotionRuleDocument::BuyXGetY {
+                product_id,
+                buy,
+                get,
+            } => PromotionRule::BuyXGetY {
+                product_id: from_document_uuid(product_id),
+                buy,
+                get,
+            },
+            PromotionRuleDocument::MinimumSpend {
+                minimum,
+                amount,
+                currency,
+            } => PromotionRule::MinimumSpend {
+                minimum,
+                amount,
+                currency,
+            },
+        };
+        Coupon {
+            code: document.code,
+            rule,
+            valid_from: document.valid_from,
+            valid_until: document.valid_until,
+            max_uses_per_user: document.max_uses_per_user,
+        }
+    }
+}
+
+impl From<&TaxBreakdown> for TaxBreakdownDocument {
+    fn from(tax: &TaxBreakdown) -> Self {
+        TaxBreakdownDocument {
+            region: tax.region.clone(),
+            inclusive: tax.inclusive,
+            lines: tax
+                .lines
+                .iter()
+                .map(|line| LineTaxDocument {
+                    product_id: to_document_uuid(line.product_id),
+                    category: line.category.clone(),
+                    rate: line.rate,
+                    amount: line.amount,
+                })
+                .collect(),
+            total: tax.total,
+        }
+    }
+}
+
+impl From<TaxBreakdownDocument> for TaxBreakdown {
+    fn from(document: TaxBreakdownDocument) -> Self {
+        TaxBreakdown {
+            region: document.region,
+            inclusive: document.inclusive,
+            lines: document
+                .lines
+                .into_iter()
+                .map(|line| LineTax {
+                    product_id: from_document_uuid(line.product_id),
+                    category: line.category,
+                    rate: line.rate,
+                    amount: line.amount,
+                })
+                .collect(),
+            total: document.total,
+        }
+    }
+}
+
+impl From<&Address> for AddressDocument {
+    fn from(address: &Address) -> Self {
+        let address = address.clone();
+        AddressDocument {
+            name: address.name,
+            line1: address.line1,
+            line2: address.line2,
+            city: address.city,
+            region: address.region,
+            postal_code: address.postal_code,
+            country: address.country,
+        }
+    }
+}
+
+impl From<AddressDocument> for Address {
+    fn from(document: AddressDocument) -> Self {
+        Address {
+            name: document.name,
+            line1: document.line1,
+            line2: document.line2,
+            city: document.city,
+            region: document.region,
+            postal_code: document.postal_code,
+            country: document.country,
+        }
+    }
+}
+
+impl From<ShippingMethod> for ShippingMethodDocument {
+    fn from(method: ShippingMethod) -> Self {
+        match method {
+            ShippingMethod::Standard => ShippingMethodDocument::Standard,
+            ShippingMethod::Express => ShippingMethodDocument::Express,
+            ShippingMethod::Overnight => ShippingMethodDocument::Overnight,
+        }
+    }
+}
+
+impl From<ShippingMethodDocument> for ShippingMethod {
+    fn from(method: ShippingMethodDocument) -> Self {
+        match method {
+            ShippingMethodDocument::Standard => ShippingMethod::Standard,
+            ShippingMethodDocument::Express => ShippingMethod::Express,
+            ShippingMethodDocument::Overnight => ShippingMethod::Overnight,
+        }
+    }
+}
+
+impl From<&PaymentAttempt> for PaymentAttemptDocument {
+    fn from(attempt: &PaymentAttempt) -> Self {
+        let outcome = match &attempt.outcome {
+            PaymentOutcome::Approved { reference } => PaymentOutcomeDocument::Approved {
+                reference: reference.clone(),
+            },
+            PaymentOutcome::Declined { reason } => PaymentOutcomeDocument::Declined {
+                reason: reason.clone(),
+            },
+            PaymentOutcome::Failed => PaymentOutcomeDocument::Failed,
+            PaymentOutcome::Pending => PaymentOutcomeDocument::Pending,
+        };
+        PaymentAttemptDocument {
+            id: to_document_uuid(attempt.id),
+            amount: attempt.amount,
+            currency: attempt.currency.clone(),
+            attempted_at: attempt.attempted_at,
+            outcome,
+        }
+    }
+}
+
+impl From<PaymentAttemptDocument> for PaymentAttempt {
+    fn from(document: PaymentAttemptDocument) -> Self {
+        let outcome = match document.outcome {
+            PaymentOutcomeDocument::Approved { reference } => {
+                PaymentOutcome::Approved { reference }
+            }
+            PaymentOutcomeDocument::Declined { reason } => PaymentOutcome::Declined { reason },
+            PaymentOutcomeDocument::Failed => PaymentOutcome::Failed,
+            PaymentOutcomeDocument::Pending => PaymentOutcome::Pending,
+        };
+        PaymentAttempt {
+            id: from_document_uuid(document.id),
+            amount: document.amount,
+            currency: document.currency,
+            attempted_at: document.attempted_at,
+            outcome,
+        }
+    }
+}
+
+impl From<&ReturnRequest> for ReturnRequestDocument {
+    fn from(request: &ReturnRequest) -> Self {
+        let status = match request.status {
+            ReturnStatus::Requested => ReturnStatusDocument::Requested,
+            ReturnStatus::Approved => ReturnStatusDocument::Approved,
+            ReturnStatus::Rejected => ReturnStatusDocument::Rejected,
+        };
+        ReturnRequestDocument {
+            id: to_document_uuid(request.id),
+            lines: request
+                .lines
+                .iter()
+                .map(|line| ReturnLineDocument {
+                    item_index: line.item_index,
+                    product_id: to_document_uuid(line.product_id),
+                    quantity: line.quantity,
+                    refund_amount: line.refund_amount,
+                })
+                .collect(),
+            reason: request.reason.clone(),
+            status,
+            requested_at: request.requested_at,
+            decided_at: request.decided_at,
+            refund_amount: request.refund_amount,
+            refund_reference: request.refund_reference.clone(),
+        }
+    }
+}
+
+impl From<ReturnRequestDocument> for ReturnRequest {
+    fn from(document: ReturnRequestDocument) -> Self {
+        let status = match document.status {
+            ReturnStatusDocument::Requested => ReturnStatus::Requested,
+            ReturnStatusDocument::Approved => ReturnStatus::Approved,
+            ReturnStatusDocument::Rejected => ReturnStatus::Rejected,
+        };
+        ReturnRequest {
+            id: from_document_uuid(document.id),
+            lines: document
+                .lines
+                .into_iter()
+                .map(|line| ReturnLine {
+                    item_index: line.item_index,
+                    product_id: from_document_uuid(line.product_id),
+                    quantity: line.quantity,
+                    refund_amount: line.refund_amount,
+                })
+                .collect(),
+            reason: document.reason,
+            status,
+            requested_at: document.requested_at,
+            decided_at: document.decided_at,
+            refund_amount: document.refund_amount,
+            refund_reference: document.refund_reference,
+        }
+    }
+}
+
+impl From<&AuditEntry> for AuditEntryDocument {
+    fn from(entry: &AuditEntry) -> Self {
+        AuditEntryDocument {
+            id: to_document_uuid(entry.id),
+            order_id: to_document_uuid(entry.order_id),
+            actor: entry.actor.clone(),
+            timestamp: entry.timestamp,
+            operation: entry.operation.clone(),
+            changes: entry.changes.clone(),
+        }
+    }
+}
+
+impl From<AuditEntryDocument> for AuditEntry {
+    fn from(document: AuditEntryDocument) -> Self {
+        AuditEntry {
+            id: from_document_uuid(document.id),
+            order_id: from_document_uuid(document.order_id),
+            actor: document.actor,
+            timestamp: document.timestamp,
+            operation: document.operation,
+            changes: document.changes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::{spec::BinarySubtype, Document};
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    /// Serializes `order` the way the driver does when it writes it.
+    fn stored(order: &Order) -> Document {
+        bson::to_raw_document_buf(&OrderDocument::from(order))
+            .unwrap()
+            .to_document()
+            .unwrap()
+    }
+
+    fn uuid_subtype(document: &Document, path: &str) -> BinarySubtype {
+        let (field, rest) = path.split_once('.').unwrap_or((path, ""));
+        match (document.get(field), rest) {
+            (Some(Bson::Binary(binary)), "") => binary.subtype,
+            (Some(Bson::Document(nested)), rest) => uuid_subtype(nested, rest),
+            (Some(Bson::Array(values)), rest) => {
+                let (index, rest) = rest.split_once('.').unwrap_or((rest, ""));
+                match &values[index.parse::<usize>().unwrap()] {
+                    Bson::Document(nested) => uuid_subtype(nested, rest),
+                    Bson::Binary(binary) => binary.subtype,
+                    other => panic!("{path} is not binary: {other:?}"),
+                }
+            }
+            (other, _) => panic!("{path} is not binary: {other:?}"),
+        }
+    }
+
+    /// An order with an item, a coupon, a payment, a return and taxes, which all hold ids.
+    fn order_with_nested_ids() -> Order {
+        let product_id = Uuid::new_v4();
+        let mut order = Order::new(Uuid::new_v4());
+        order.status = OrderStatus::Delivered;
+        order.currency = Some("EUR".to_string());
+        order.items.push(Item {
+            product_id,
+            quantity: 2,
+            unit_price: dec!(6.25),
+            currency: "EUR".to_string(),
+            tax_category: "standard".to_string(),
+            line_total: dec!(12.50),
+        });
+        order.coupons.push(Coupon {
+            code: "TWOFORONE".to_string(),
+            rule: PromotionRule::BuyXGetY {
+                product_id,
+                buy: 1,
+                get: 1,
+            },
+            valid_from: None,
+            valid_until: Some(Utc::now()),
+            max_uses_per_user: Some(1),
+        });
+        order.tax.lines.push(LineTax {
+            product_id,
+            category: "standard".to_string(),
+            rate: dec!(0.19),
+            amount: dec!(2.38),
+        });
+        order.payments.push(PaymentAttempt {
+            id: Uuid::new_v4(),
+            amount: dec!(12.50),
+            currency: "EUR".to_string(),
+            attempted_at: Utc::now(),
+            outcome: PaymentOutcome::Approved {
+                reference: "ch_1".to_string(),
+            },
+        });
+        order.returns.push(ReturnRequest {
+            id: Uuid::new_v4(),
+            lines: vec![ReturnLine {
+                item_index: 0,
+                product_id,
+                quantity: 1,
+                refund_amount: dec!(6.25),
+            }],
+            reason: "too big".to_string(),
+            status: ReturnStatus::Requested,
+            requested_at: Utc::now(),
+            decided_at: None,
+            refund_amount: dec!(6.25),
+            refund_reference: None,
+        });
+        order.shipping_address = Some(Address {
+            name: "Ada".to_string(),
+            line1: "1 Main Street".to_string(),
+            line2: None,
+            city: "Lyon".to_string(),
+            region: None,
+            postal_code: "69001".to_string(),
+            country: "FR".to_string(),
+        });
+        order.shipping_method = Some(ShippingMethod::Express);
+        order
+    }
+
+    #[test]
+    fn ids_are_stored_as_bson_uuids() {
+        let order = Order::new(Uuid::new_v4());
+        let document = stored(&order);
+
+        assert_eq!(document.get("_id"), Some(&bson_uuid(order.id)));
+        assert_eq!(uuid_subtype(&document, "_id"), BinarySubtype::Uuid);
+        assert_eq!(uuid_subtype(&document, "user_id"), BinarySubtype::Uuid);
+        assert!(!document.contains_key("id"));
+    }
+
+    #[test]
+    fn nested_ids_are_stored_as_bson_uuids() {
+        let document = stored(&order_with_nested_ids());
+        for path in [
+            "items.0.product_id",
+            "coupons.0.rule.BuyXGetY.product_id",
+            "tax.lines.0.product_id",
+            "payments.0.id",
+            "returns.0.id",
+            "returns.0.lines.0.product_id",
+        ] {
+            assert_eq!(uuid_subtype(&document, path), BinarySubtype::Uuid, "{path}");
+        }
+    }
+
+    #[test]
+    fn orders_round_trip_through_their_documents() {
+        let mut order = Order::new(Uuid::new_v4());
+        order.status = OrderStatus::Placed;
+        order.currency = Some("EUR".to_string());
+        order.subtotal = dec!(12.50);
+        order.total = dec!(12.50);
+        order.shipping_method = Some(ShippingMethod::Express);
+
+        let document: OrderDocument = bson::from_document(stored(&order)).unwrap();
+        assert_eq!(Order::from(document), order);
+    }
+
+    #[test]
+    fn nested_ids_round_trip_through_their_documents() {
+        let order = order_with_nested_ids();
+        let document: OrderDocument = bson::from_document(stored(&order)).unwrap();
+        assert_eq!(Order::from(document), order);
+    }
+
+    #[test]
+    fn audit_entries_round_trip_through_their_documents() {
+        // A field that is null reads back as a missing one, so every field is set.
+        let mut order = order_with_nested_ids();
+        order.billing_address = order.shipping_address.clone();
+        let entry = AuditEntry::new("create_order", None, &order);
+        let document = bson::to_document(&AuditEntryDocument::from(&entry)).unwrap();
+        assert_eq!(uuid_subtype(&document, "order_id"), BinarySubtype::Uuid);
+
+        let document: AuditEntryDocument = bson::from_document(document).unwrap();
+        assert_eq!(AuditEntry::from(document), entry);
+    }
+}
@@ -1,64 +1,741 @@
-use mongodb::{options::ClientOptions, options::ResolverConfig, Client, Collection};
+use std::sync::Arc;
+
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, spec::BinarySubtype, Binary, Bson},
+    error::{ErrorKind, WriteFailure, TRANSIENT_TRANSACTION_ERROR},
+    options::{FindOneAndReplaceOptions, FindOptions, ReturnDocument},
+    Client, ClientSession, Collection,
+};
+use tracing::{error, warn};
 use uuid::Uuid;
 
-use crate::order_store::{Order, OrderStore, OrderStoreError};
+use crate::{
+    audit_log::AuditEntry,
+    fulfilment::{Address, ShippingMethod},
+    mongodb_inventory_store::{transfer, StockLevelDocument},
+    mongodb_order_document::{bson_uuid, AuditEntryDocument, OrderDocument},
+    mongodb_settings::MongodbNames,
+    order_events::OrderEvent,
+    order_store::{
+        find_applicable_coupon, find_orderable_product, pay, pay_refund, payment_result,
+        update_taxes, void_charge, Order, OrderStatus, OrderStore, OrderStoreError,
+    },
+    outbox::{DomainEvent, OutboxMessage},
+    payment_provider::{Charge, PaymentAttempt, PaymentOutcome, PaymentProvider},
+    product_store::ProductStoreNewType,
+    promotion_store::PromotionStoreNewType,
+    returns::{ReturnItem, ReturnRequest},
+    tax_calculator::TaxCalculator,
+};
+
+/// Converts `id` into the BSON value the driver writes for a serialized [`Uuid`], so it can be
+/// used in query filters.
+pub(crate) fn uuid_as_bson(id: Uuid) -> Bson {
+    Bson::Binary(Binary {
+        subtype: BinarySubtype::Generic,
+        bytes: id.as_bytes().to_vec(),
+    })
+}
+
+/// Server error codes classified on their own.
+const WRITE_CONFLICT: i32 = 112;
+const DUPLICATE_KEY: i32 = 11000;
+const MAX_TIME_EXPIRED: i32 = 50;
+const WRITE_CONCERN_FAILED: i32 = 64;
+
+/// Returns a function turning driver errors into the [`OrderStoreError`](OrderStoreError) for
+/// their cause, logging them with the `context` they happened in.
+pub(crate) fn store_error(
+    context: &'static str,
+) -> impl Fn(mongodb::error::Error) -> OrderStoreError {
+    move |err| {
+        let err = classify(err);
+        match err {
+            OrderStoreError::DuplicateKey(_) | OrderStoreError::WriteConflict(_) => {
+                warn!("{} failed: {}", context, err.detailed())
+            }
+            _ => error!("{} failed: {}", context, err.detailed()),
+        }
+        err
+    }
+}
+
+/// Turns a driver error into the [`OrderStoreError`](OrderStoreError) for its cause.
+pub(crate) fn classify(err: mongodb::error::Error) -> OrderStoreError {
+    let code = match err.kind.as_ref() {
+        ErrorKind::Command(err) => Some(err.code),
+        ErrorKind::Write(WriteFailure::WriteError(err)) => Some(err.code),
+        ErrorKind::Write(WriteFailure::WriteConcernError(err)) => Some(err.code),
+        _ => None,
+    };
+    match (err.kind.as_ref(), code) {
+        (ErrorKind::Io(io), _) if io.kind() == std::io::ErrorKind::TimedOut => {
+            OrderStoreError::StoreTimeout(Box::new(err))
+        }
+        (_, Some(MAX_TIME_EXPIRED | WRITE_CONCERN_FAILED)) => {
+            OrderStoreError::StoreTimeout(Box::new(err))
+        }
+        (
+            ErrorKind::Io(_)
+            | ErrorKind::ServerSelection { .. }
+            | ErrorKind::ConnectionPoolCleared { .. }
+            | ErrorKind::DnsResolve { .. },
+            _,
+        ) => OrderStoreError::StoreConnection(Box::new(err)),
+        (
+            ErrorKind::InvalidArgument { .. }
+            | ErrorKind::Authentication { .. }
+            | ErrorKind::InvalidTlsConfig { .. }
+            | ErrorKind::IncompatibleServer { .. }
+            | ErrorKind::SessionsNotSupported,
+            _,
+        ) => OrderStoreError::StoreMisconfigured(Box::new(err)),
+        (ErrorKind::BsonSerialization(_) | ErrorKind::BsonDeserialization(_), _) => {
+            OrderStoreError::StoreSerialization(Box::new(err))
+        }
+        (_, Some(DUPLICATE_KEY)) => OrderStoreError::DuplicateKey(Box::new(err)),
+        (_, Some(WRITE_CONFLICT)) => OrderStoreError::WriteConflict(Box::new(err)),
+        _ if err.contains_label(TRANSIENT_TRANSACTION_ERROR) => {
+            OrderStoreError::WriteConflict(Box::new(err))
+        }
+        _ => OrderStoreError::StoreFailure(Box::new(err)),
+    }
+}
 
 pub struct MongodbOrderStore {
     client: Client,
+    orders: Collection<OrderDocument>,
+    audit: Collection<AuditEntryDocument>,
+    outbox: Collection<OutboxMessage>,
+    stock: Collection<StockLevelDocument>,
+    products: Arc<ProductStoreNewType>,
+    promotions: Arc<PromotionStoreNewType>,
+    tax: Arc<dyn TaxCalculator>,
+    payments: Arc<dyn PaymentProvider>,
 }
 
 impl MongodbOrderStore {
-    pub async fn new(client_uri: &str) -> Result<MongodbOrderStore, OrderStoreError> {
-        if let Ok(options) =
-            ClientOptions::parse_with_resolver_config(&client_uri, ResolverConfig::cloudflare())
-                .await
-        {
-            if let Ok(client) = Client::with_options(options) {
-                Ok(MongodbOrderStore { client })
-            } else {
-                Err(OrderStoreError::StoreUnavailable)
-            }
+    pub fn new(
+        client: Client,
+        names: &MongodbNames,
+        products: Arc<ProductStoreNewType>,
+        promotions: Arc<PromotionStoreNewType>,
+        tax: Arc<dyn TaxCalculator>,
+        payments: Arc<dyn PaymentProvider>,
+    ) -> MongodbOrderStore {
+        MongodbOrderStore {
+            orders: names.collection(&client, &names.orders),
+            audit: names.collection(&client, &names.order_audit),
+            outbox: names.collection(&client, &names.outbox),
+            stock: names.collection(&client, &names.inventory),
+            client,
+            products,
+            promotions,
+            tax,
+            payments,
+        }
+    }
+
+    /// Starts a session with an open transaction. Dropping the session without committing aborts it.
+    async fn start_transaction(&self) -> Result<ClientSession, OrderStoreError> {
+        let mut session = self
+            .client
+            .start_session(None)
+            .await
+            .map_err(store_error("starting a session"))?;
+        session
+            .start_transaction(None)
+            .await
+            .map_err(store_error("starting a transaction"))?;
+        Ok(session)
+    }
+
+    async fn load_order(
+        &self,
+        order_id: Uuid,
+        session: &mut ClientSession,
+    ) -> Result<Order, OrderStoreError> {
+        self.orders
+            .find_one_with_session(doc! { "_id": bson_uuid(order_id) }, None, session)
+            .await
+            .map_err(store_error("loading order"))?
+            .map(Order::from)
+            .ok_or(OrderStoreError::OrderNotFound(order_id))
+    }
+
+    /// Replaces the stored version of `order`, records the change made by `operation` in the
+    /// audit log and writes the `event` it emitted to the outbox, all as part of the session's transaction.
+    async fn save_order(
+        &self,
+        operation: &str,
+        order: &Order,
+        event: OrderEvent,
+        session: &mut ClientSession,
+    ) -> Result<(), OrderStoreError> {
+        let options = FindOneAndReplaceOptions::builder()
+            .return_document(ReturnDocument::Before)
+            .build();
+        let before = self
+            .orders
+            .find_one_and_replace_with_session(
+                doc! { "_id": bson_uuid(order.id) },
+                OrderDocument::from(order),
+                options,
+                session,
+            )
+            .await
+            .map_err(store_error("replacing order"))?
+            .map(Order::from)
+            .ok_or(OrderStoreError::OrderNotFound(order.id))?;
+        self.record(AuditEntry::new(operation, Some(&before), order), session)
+            .await?;
+        self.publish(order, event, session).await
+    }
+
+    async fn publish(
+        &self,
+        order: &Order,
+        event: OrderEvent,
+        session: &mut ClientSession,
+    ) -> Result<(), OrderStoreError> {
+        let message = OutboxMessage::new(DomainEvent::new(order.id, order.user_id, event));
+        self.outbox
+            .insert_one_with_session(message, None, session)
+            .await
+            .map(|_| ())
+            .map_err(store_error("writing to the outbox"))
+    }
+
+    async fn record(
+        &self,
+        entry: AuditEntry,
+        session: &mut ClientSession,
+    ) -> Result<(), OrderStoreError> {
+        self.audit
+            .insert_one_with_session(AuditEntryDocument::from(&entry), None, session)
+            .await
+            .map(|_| ())
+            .map_err(store_error("writing to the audit log"))
+    }
+
+    /// Moves `quantity` units between two counters of the product's stock level, as part of the
+    /// session's transaction.
+    async fn move_stock(
+        &self,
+        product_id: Uuid,
+        quantity: i32,
+        from: &str,
+        to: &str,
+        session: &mut ClientSession,
+    ) -> Result<(), OrderStoreError> {
+        let (filter, update) = transfer(product_id, quantity, from, to);
+        let result = self
+            .stock
+            .update_one_with_session(filter, update, None, session)
+            .await
+            .map_err(store_error("moving stock"))?;
+        if result.matched_count == 0 {
+            Err(OrderStoreError::InsufficientStock(product_id))
         } else {
-            Err(OrderStoreError::StoreUnavailable)
+            Ok(())
+        }
+    }
+
+    /// Gives the usage of coupon `code` back to the user of `order` once the change dropping it is
+    /// committed, or once applying it failed. Coupons live in another collection, outside the
+    /// transaction, so a failure is logged instead of hiding the outcome of the change.
+    async fn release_coupon(&self, code: &str, order: &Order) {
+        if let Err(err) = self.promotions.release(code, order.user_id).await {
+            error!(
+                "cannot release coupon {} of order {}: {}",
+                code, order.id, err
+            );
+        }
+    }
+
+    /// Records the approved attempt of `charge` as [`Failed`](PaymentOutcome::Failed) once the
+    /// charge was given back because the stock of its order could not be committed.
+    async fn record_voided_charge(&self, charge: &Charge) -> Result<(), OrderStoreError> {
+        let mut session = self.start_transaction().await?;
+        let mut order = self.load_order(charge.order_id, &mut session).await?;
+        let (attempt, recorded) = order.record_payment(charge, PaymentOutcome::Failed);
+        if recorded {
+            let event = OrderEvent::PaymentAttempted { attempt };
+            self.save_order("checkout", &order, event, &mut session)
+                .await?;
+            self.commit(session).await?;
         }
+        Ok(())
+    }
+
+    async fn commit(&self, mut session: ClientSession) -> Result<(), OrderStoreError> {
+        session
+            .commit_transaction()
+            .await
+            .map_err(store_error("committing a transaction"))
     }
 }
 
 #[async_trait::async_trait]
 impl OrderStore for MongodbOrderStore {
     async fn create_order(&self, user_id: Uuid) -> Result<Order, OrderStoreError> {
-        let db = self.client.database("examplemongo-ms");
-        let orders: Collection<Order> = db.collection("orders"); // get collection "orders"
-        let order = Order {
-            id: Uuid::new_v4(),
+        let order = Order::new(user_id);
+        let mut session = self.start_transaction().await?;
+        self.orders
+            .insert_one_with_session(OrderDocument::from(&order), None, &mut session)
+            .await
+            .map_err(store_error("inserting order"))?;
+        self.record(AuditEntry::new("create_order", None, &order), &mut session)
+            .await?;
+        let event = OrderEvent::OrderCreated {
+            order_id: order.id,
             user_id,
-            items: vec![],
+            created_at: order.created_at,
         };
-        orders
-            .insert_one(order.clone(), None)
-            .await
-            .map(|_| order)
-            .map_err(|_| OrderStoreError::StoreUnavailable)
+        self.publish(&order, event, &mut session).await?;
+        self.commit(session).await?;
+        Ok(order)
     }
 
-    async fn get_order(&self, _user_id: Uuid) -> Result<Order, OrderStoreError> {
-        unimplemented!()
+    async fn get_order(&self, order_id: Uuid) -> Result<Order, OrderStoreError> {
+        self.orders
+            .find_one(doc! { "_id": bson_uuid(order_id) }, None)
+            .await
+            .map_err(store_error("reading order"))?
+            .map(Order::from)
+            .ok_or(OrderStoreError::OrderNotFound(order_id))
     }
 
-    async fn list_orders(&self, _user_id: Uuid) -> Result<Vec<Order>, OrderStoreError> {
-        unimplemented!()
+    async fn list_orders(&self, user_id: Uuid) -> Result<Vec<Order>, OrderStoreError> {
+        self.orders
+            .find(
+                doc! { "user_id": bson_uuid(user_id) },
+                FindOptions::builder()
+                    .sort(doc! { "created_at": 1 })
+                    .build(),
+            )
+            .await
+            .map_err(store_error("listing orders"))?
+            .map_ok(Order::from)
+            .try_collect()
+            .await
+            .map_err(store_error("listing orders"))
     }
 
     async fn add_item(
         &self,
-        _order_id: Uuid,
-        _product_id: Uuid,
-        _quantity: i32,
+        order_id: Uuid,
+        product_id: Uuid,
+        quantity: i32,
+    ) -> Result<(), OrderStoreError> {
+        let product = find_orderable_product(&self.products, product_id).await?;
+        let mut session = self.start_transaction().await?;
+        let mut order = self.load_order(order_id, &mut session).await?;
+        let item = order.add_item(&product, quantity)?;
+        update_taxes(&mut order, self.tax.as_ref()).await?;
+        self.move_stock(
+            item.product_id,
+            item.quantity,
+            "available",
+            "reserved",
+            &mut session,
+        )
+        .await?;
+        let event = OrderEvent::ItemAdded {
+            item,
+            tax: order.tax.clone(),
+        };
+        self.save_order("add_item", &order, event, &mut session)
+            .await?;
+        self.commit(session).await
+    }
+
+    async fn delete_item(
+        &self,
+        order_id: Uuid,
+        index: usize,
+        expected: Option<Uuid>,
     ) -> Result<(), OrderStoreError> {
-        unimplemented!()
+        let mut session = self.start_transaction().await?;
+        let mut order = self.load_order(order_id, &mut session).await?;
+        let item = order.delete_item(index, expected)?;
+        update_taxes(&mut order, self.tax.as_ref()).await?;
+        self.move_stock(
+            item.product_id,
+            item.quantity,
+            "reserved",
+            "available",
+            &mut session,
+        )
+        .await?;
+        let event = OrderEvent::ItemRemoved {
+            index,
+            tax: order.tax.clone(),
+        };
+        self.save_order("delete_item", &order, event, &mut session)
+            .await?;
+        self.commit(session).await
+    }
+
+    async fn update_item_quantity(
+        &self,
+        order_id: Uuid,
+        index: usize,
+        quantity: i32,
+        expected: Option<Uuid>,
+    ) -> Result<(), OrderStoreError> {
+        let mut session = self.start_transaction().await?;
+        let mut order = self.load_order(order_id, &mut session).await?;
+        let previous = order.set_item_quantity(index, quantity, expected)?;
+        update_taxes(&mut order, self.tax.as_ref()).await?;
+        let product_id = order.items[index].product_id;
+        if quantity > previous {
+            self.move_stock(
+                product_id,
+                quantity - previous,
+                "available",
+                "reserved",
+                &mut session,
+            )
+            .await?;
+        } else {
+            self.move_stock(
+                product_id,
+                previous - quantity,
+                "reserved",
+                "available",
+                &mut session,
+            )
+            .await?;
+        }
+        let event = OrderEvent::ItemQuantityChanged {
+            index,
+            quantity,
+            tax: order.tax.clone(),
+        };
+        self.save_order("update_item_quantity", &order, event, &mut session)
+            .await?;
+        self.commit(session).await
+    }
+
+    async fn update_status(
+        &self,
+        order_id: Uuid,
+        status: OrderStatus,
+    ) -> Result<(), OrderStoreError> {
+        let mut session = self.start_transaction().await?;
+        let mut order = self.load_order(order_id, &mut session).await?;
+        order.set_status(status)?;
+        for item in &order.items {
+            match status {
+                OrderStatus::Placed => {
+                    self.move_stock(
+                        item.product_id,
+                        item.quantity,
+                        "reserved",
+                        "committed",
+                        &mut session,
+                    )
+                    .await?
+                }
+                OrderStatus::Cancelled => {
+                    self.move_stock(
+                        item.product_id,
+                        item.quantity,
+                        "reserved",
+                        "available",
+                        &mut session,
+                    )
+                    .await?
+                }
+                OrderStatus::Draft
+                | OrderStatus::Paid
+                | OrderStatus::Shipped
+                | OrderStatus::Delivered => {}
+            }
+        }
+        let event = OrderEvent::StatusChanged { status };
+        self.save_order("update_status", &order, event, &mut session)
+            .await?;
+        self.commit(session).await?;
+        if status == OrderStatus::Cancelled {
+            for coupon in &order.coupons {
+                self.release_coupon(&coupon.code, &order).await;
+            }
+        }
+        Ok(())
+    }
+
+    async fn apply_coupon(&self, order_id: Uuid, code: &str) -> Result<(), OrderStoreError> {
+        let coupon = find_applicable_coupon(&self.promotions, code).await?;
+        let mut session = self.start_transaction().await?;
+        let mut order = self.load_order(order_id, &mut session).await?;
+        order.apply_coupon(coupon.clone())?;
+        update_taxes(&mut order, self.tax.as_ref()).await?;
+        self.promotions.redeem(code, order.user_id).await?;
+        let event = OrderEvent::CouponApplied {
+            coupon,
+            tax: Some(order.tax.clone()),
+        };
+        let saved = match self
+            .save_order("apply_coupon", &order, event, &mut session)
+            .await
+        {
+            Ok(()) => self.commit(session).await,
+            Err(err) => Err(err),
+        };
+        if saved.is_err() {
+            // coupons live in another collection, give the usage back by hand
+            self.release_coupon(code, &order).await;
+        }
+        saved
+    }
+
+    async fn remove_coupon(&self, order_id: Uuid, code: &str) -> Result<(), OrderStoreError> {
+        let mut session = self.start_transaction().await?;
+        let mut order = self.load_order(order_id, &mut session).await?;
+        order.remove_coupon(code)?;
+        update_taxes(&mut order, self.tax.as_ref()).await?;
+        let event = OrderEvent::CouponRemoved {
+            code: code.to_string(),
+            tax: Some(order.tax.clone()),
+        };
+        self.save_order("remove_coupon", &order, event, &mut session)
+            .await?;
+        self.commit(session).await?;
+        self.release_coupon(code, &order).await;
+        Ok(())
+    }
+
+    async fn set_shipping(
+        &self,
+        order_id: Uuid,
+        address: Address,
+        method: ShippingMethod,
+    ) -> Result<(), OrderStoreError> {
+        let mut session = self.start_transaction().await?;
+        let mut order = self.load_order(order_id, &mut session).await?;
+        order.set_shipping(address.clone(), method)?;
+        update_taxes(&mut order, self.tax.as_ref()).await?;
+        let event = OrderEvent::ShippingSet {
+            address,
+            method,
+            cost: order.shipping_cost,
+            tax: order.tax.clone(),
+        };
+        self.save_order("set_shipping", &order, event, &mut session)
+            .await?;
+        self.commit(session).await
+    }
+
+    async fn set_billing_address(
+        &self,
+        order_id: Uuid,
+        address: Address,
+    ) -> Result<(), OrderStoreError> {
+        let mut session = self.start_transaction().await?;
+        let mut order = self.load_order(order_id, &mut session).await?;
+        order.set_billing_address(address.clone())?;
+        let event = OrderEvent::BillingAddressSet { address };
+        self.save_order("set_billing_address", &order, event, &mut session)
+            .await?;
+        self.commit(session).await
+    }
+
+    async fn checkout(
+        &self,
+        order_id: Uuid,
+        payment_token: &str,
+    ) -> Result<PaymentAttempt, OrderStoreError> {
+        // the attempt is saved as pending before the provider is called, outside any transaction
+        let mut session = self.start_transaction().await?;
+        let mut order = self.load_order(order_id, &mut session).await?;
+        let charge = order.checkout_charge(payment_token)?;
+        if let Some(attempt) = order.start_payment(&charge) {
+            let event = OrderEvent::PaymentAttempted { attempt };
+            self.save_order("checkout", &order, event, &mut session)
+                .await?;
+            self.commit(session).await?;
+        }
+        let outcome = pay(self.payments.as_ref(), &charge).await;
+
+        // its outcome is saved together with the stock it commits, or not at all
+        let mut session = self.start_transaction().await?;
+        let mut order = self.load_order(order_id, &mut session).await?;
+        let (attempt, recorded) = order.record_payment(&charge, outcome);
+        if !recorded {
+            return payment_result(attempt);
+        }
+        if order.status == OrderStatus::Paid {
+            let committed = async {
+                for item in &order.items {
+                    self.move_stock(
+                        item.product_id,
+                        item.quantity,
+                        "reserved",
+                        "committed",
+                        &mut session,
+                    )
+                    .await?;
+                }
+                Ok::<_, OrderStoreError>(())
+            };
+            if let Err(err) = committed.await {
+                // the order cannot be placed, the charge is given back outside the transaction
+                drop(session);
+                if void_charge(self.payments.as_ref(), &charge, &attempt).await {
+                    if let Err(voided_err) = self.record_voided_charge(&charge).await {
+                        error!(
+                            "cannot record the charge of order {} as given back: {}",
+                            order_id,
+                            voided_err.detailed()
+                        );
+                    }
+                }
+                return Err(err);
+            }
+        }
+        let event = OrderEvent::PaymentAttempted {
+            attempt: attempt.clone(),
+        };
+        self.save_order("checkout", &order, event, &mut session)
+            .await?;
+        self.commit(session).await?;
+        payment_result(attempt)
+    }
+
+    async fn request_return(
+        &self,
+        order_id: Uuid,
+        items: Vec<ReturnItem>,
+        reason: String,
+    ) -> Result<ReturnRequest, OrderStoreError> {
+        let mut session = self.start_transaction().await?;
+        let mut order = self.load_order(order_id, &mut session).await?;
+        let request = order.request_return(&items, reason)?;
+        let event = OrderEvent::ReturnRequested {
+            request: request.clone(),
+        };
+        self.save_order("request_return", &order, event, &mut session)
+            .await?;
+        self.commit(session).await?;
+        Ok(request)
+    }
+
+    async fn approve_return(
+        &self,
+        order_id: Uuid,
+        return_id: Uuid,
+    ) -> Result<ReturnRequest, OrderStoreError> {
+        // the refund is paid before the transaction, so the provider is not waited for while it
+        // holds the order, and only its result is recorded in it
+        let order = self.get_order(order_id).await?;
+        let reference = pay_refund(&order, self.payments.as_ref(), return_id).await?;
+        let mut session = self.start_transaction().await?;
+        let mut order = self.load_order(order_id, &mut session).await?;
+        let request = order.approve_return(return_id, reference)?;
+        for line in &request.lines {
+            self.move_stock(
+                line.product_id,
+                line.quantity,
+                "committed",
+                "available",
+                &mut session,
+            )
+            .await?;
+        }
+        let event = OrderEvent::ReturnDecided {
+            request: request.clone(),
+        };
+        self.save_order("approve_return", &order, event, &mut session)
+            .await?;
+        self.commit(session).await?;
+        Ok(request)
+    }
+
+    async fn reject_return(
+        &self,
+        order_id: Uuid,
+        return_id: Uuid,
+    ) -> Result<ReturnRequest, OrderStoreError> {
+        let mut session = self.start_transaction().await?;
+        let mut order = self.load_order(order_id, &mut session).await?;
+        let request = order.reject_return(return_id)?;
+        let event = OrderEvent::ReturnDecided {
+            request: request.clone(),
+        };
+        self.save_order("reject_return", &order, event, &mut session)
+            .await?;
+        self.commit(session).await?;
+        Ok(request)
+    }
+
+    async fn history(&self, order_id: Uuid) -> Result<Vec<AuditEntry>, OrderStoreError> {
+        self.get_order(order_id).await?;
+        let options = FindOptions::builder().sort(doc! { "timestamp": 1 }).build();
+        self.audit
+            .find(doc! { "order_id": bson_uuid(order_id) }, options)
+            .await
+            .map_err(store_error("reading history"))?
+            .map_ok(AuditEntry::from)
+            .try_collect()
+            .await
+            .map_err(store_error("reading history"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use mongodb::bson::{self, Document};
+
+    use super::*;
+
+    fn write_error(code: i32) -> mongodb::error::Error {
+        let write_error = bson::from_document(doc! { "code": code, "errmsg": "rejected" }).unwrap();
+        ErrorKind::Write(WriteFailure::WriteError(write_error)).into()
+    }
+
+    #[test]
+    fn driver_errors_are_classified_by_cause() {
+        let timeout = std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out");
+        assert!(matches!(
+            classify(timeout.into()),
+            OrderStoreError::StoreTimeout(_)
+        ));
+        let refused = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused");
+        assert!(matches!(
+            classify(refused.into()),
+            OrderStoreError::StoreConnection(_)
+        ));
+        assert!(matches!(
+            classify(write_error(DUPLICATE_KEY)),
+            OrderStoreError::DuplicateKey(_)
+        ));
+        assert!(matches!(
+            classify(write_error(WRITE_CONFLICT)),
+            OrderStoreError::WriteConflict(_)
+        ));
+        let malformed = bson::from_document::<Order>(Document::new()).unwrap_err();
+        assert!(matches!(
+            classify(ErrorKind::BsonDeserialization(malformed).into()),
+            OrderStoreError::StoreSerialization(_)
+        ));
+        assert!(matches!(
+            classify(write_error(2)),
+            OrderStoreError::StoreFailure(_)
+        ));
     }
 
-    async fn delete_item(&self, _order_id: Uuid, _index: usize) -> Result<(), OrderStoreError> {
-        unimplemented!()
+    #[test]
+    fn classified_errors_keep_the_driver_error_as_source() {
+        let err = classify(write_error(DUPLICATE_KEY));
+        let source = err.source().unwrap();
+        assert!(source.downcast_ref::<mongodb::error::Error>().is_some());
+        assert!(!err.to_string().contains("rejected"));
+        assert!(err.detailed().contains("rejected"));
+        assert!(!err.is_transient());
     }
 }
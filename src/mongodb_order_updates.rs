@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+use futures::{stream::BoxStream, StreamExt};
+use mongodb::{
+    bson::{doc, Document},
+    change_stream::{event::ChangeStreamEvent, ChangeStream},
+    options::{ChangeStreamOptions, FullDocumentType},
+    Client, Collection,
+};
+use tracing::warn;
+
+use crate::{
+    mongodb_order_document::{bson_uuid, OrderDocument},
+    mongodb_order_store::store_error,
+    mongodb_settings::MongodbNames,
+    order_store::{Order, OrderStoreError},
+    order_updates::{OrderFilter, OrderUpdates},
+};
+
+/// Wait before following the changes again after the change stream failed.
+const RESUME_DELAY: Duration = Duration::from_secs(1);
+
+/// Follows the changes of the `orders` collection through a MongoDB change stream, so changes
+/// made by every instance of the service are seen. Change streams need a replica set.
+pub struct MongodbOrderUpdates {
+    orders: Collection<OrderDocument>,
+}
+
+impl MongodbOrderUpdates {
+    pub fn new(client: Client, names: &MongodbNames) -> MongodbOrderUpdates {
+        MongodbOrderUpdates {
+            orders: names.collection(&client, &names.orders),
+        }
+    }
+}
+
+/// Stages selecting the changes of the orders matching `filter`, so the server only sends those.
+fn pipeline(filter: OrderFilter) -> Vec<Document> {
+    let followed = match filter {
+        OrderFilter::Order(id) => doc! { "documentKey._id": bson_uuid(id) },
+        OrderFilter::User(user_id) => doc! { "fullDocument.user_id": bson_uuid(user_id) },
+    };
+    vec![
+        doc! { "$match": { "operationType": { "$in": ["insert", "update", "replace"] } } },
+        doc! { "$match": followed },
+    ]
+}
+
+/// Follows the changes selected by `pipeline`, after the one of `resume_token` if any.
+async fn watch(
+    orders: &Collection<OrderDocument>,
+    pipeline: &[Document],
+    resume_token: Option<mongodb::change_stream::event::ResumeToken>,
+) -> Result<ChangeStream<ChangeStreamEvent<OrderDocument>>, OrderStoreError> {
+    let options = ChangeStreamOptions::builder()
+        .full_document(Some(FullDocumentType::UpdateLookup))
+        .resume_after(resume_token)
+        .build();
+    orders
+        .watch(pipeline.to_vec(), options)
+        .await
+        .map_err(store_error("watching orders"))
+}
+
+#[async_trait::async_trait]
+impl OrderUpdates for MongodbOrderUpdates {
+    /// A change stream that fails is opened again from the last change it returned; the stream
+    /// of orders only ends when that fails too.
+    async fn subscribe(
+        &self,
+        filter: OrderFilter,
+    ) -> Result<BoxStream<'static, Order>, OrderStoreError> {
+        let pipeline = pipeline(filter);
+        let changes = watch(&self.orders, &pipeline, None).await?;
+        let orders = self.orders.clone();
+        let stream = futures::stream::unfold(changes, move |mut changes| {
+            let (orders, pipeline) = (orders.clone(), pipeline.clone());
+            async move {
+                loop {
+                    match changes.next().await {
+                        Some(Ok(change)) => {
+                            if let Some(document) = change.full_document {
+                                return Some((Order::from(document), changes));
+                            }
+                        }
+                        Some(Err(err)) => {
+                            warn!("following the changes of orders failed, resuming: {}", err);
+                            tokio::time::sleep(RESUME_DELAY).await;
+                            changes = watch(&orders, &pipeline, changes.resume_token())
+                                .await
+                                .ok()?;
+                        }
+                        None => return None,
+                    }
+                }
+            }
+        });
+        Ok(stream.boxed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn only_the_changes_of_the_followed_orders_are_sent() {
+        let id = Uuid::new_v4();
+        assert_eq!(
+            pipeline(OrderFilter::Order(id))[1],
+            doc! { "$match": { "documentKey._id": bson_uuid(id) } }
+        );
+        assert_eq!(
+            pipeline(OrderFilter::User(id))[1],
+            doc! { "$match": { "fullDocument.user_id": bson_uuid(id) } }
+        );
+    }
+}
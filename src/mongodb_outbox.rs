@@ -0,0 +1,89 @@
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{self, doc},
+    options::FindOptions,
+    Client, Collection,
+};
+use uuid::Uuid;
+
+use crate::{
+    mongodb_order_store::{store_error, uuid_as_bson},
+    mongodb_settings::MongodbNames,
+    order_store::OrderStoreError,
+    outbox::{Outbox, OutboxMessage},
+};
+
+/// Reads the messages written to `order_outbox` by the MongoDB order stores. Delivered messages
+/// are stamped with a `delivered_at` date, which the TTL index created by
+/// [`bootstrap`](crate::mongodb_schema::bootstrap) deletes them after.
+pub struct MongodbOutbox {
+    messages: Collection<OutboxMessage>,
+}
+
+impl MongodbOutbox {
+    pub fn new(client: Client, names: &MongodbNames) -> MongodbOutbox {
+        MongodbOutbox {
+            messages: names.collection(&client, &names.outbox),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Outbox for MongodbOutbox {
+    async fn pending(
+        &self,
+        now: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<OutboxMessage>, OrderStoreError> {
+        let options = FindOptions::builder()
+            .sort(doc! { "recorded_at": 1 })
+            .limit(limit as i64)
+            .build();
+        self.messages
+            .find(
+                doc! { "delivered": false, "next_attempt_at": { "$lte": now.timestamp_millis() } },
+                options,
+            )
+            .await
+            .map_err(store_error("reading pending messages"))?
+            .try_collect()
+            .await
+            .map_err(store_error("reading pending messages"))
+    }
+
+    async fn mark_delivered(&self, id: Uuid) -> Result<(), OrderStoreError> {
+        self.messages
+            .update_one(
+                doc! { "id": uuid_as_bson(id) },
+                doc! { "$set": { "delivered": true, "delivered_at": bson::DateTime::now() } },
+                None,
+            )
+            .await
+            .map(|_| ())
+            .map_err(store_error("marking a message delivered"))
+    }
+
+    async fn mark_failed(
+        &self,
+        id: Uuid,
+        error: String,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<(), OrderStoreError> {
+        self.messages
+            .update_one(
+                doc! { "id": uuid_as_bson(id) },
+                doc! {
+                    "$set": {
+                        "last_error": error,
+                        "next_attempt_at": next_attempt_at.timestamp_millis(),
+                    },
+                    "$inc": { "attempts": 1 },
+                },
+                None,
+            )
+            .await
+            .map(|_| ())
+            .map_err(store_error("marking a message failed"))
+    }
+}
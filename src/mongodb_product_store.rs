@@ -0,0 +1,190 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{self, doc},
+    error::{ErrorKind, WriteFailure},
+    Client, Collection,
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    mongodb_order_document::{bson_uuid, from_document_uuid, to_document_uuid},
+    mongodb_settings::MongodbNames,
+    product_store::{Product, ProductDetails, ProductStore, ProductStoreError},
+};
+
+/// A product as stored in the `products` collection, its id a BSON UUID.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct ProductDocument {
+    id: bson::Uuid,
+    name: String,
+    sku: String,
+    #[serde(with = "crate::decimal128")]
+    price: Decimal,
+    currency: String,
+    tax_category: String,
+    active: bool,
+}
+
+impl From<&Product> for ProductDocument {
+    fn from(product: &Product) -> Self {
+        let product = product.clone();
+        ProductDocument {
+            id: to_document_uuid(product.id),
+            name: product.name,
+            sku: product.sku,
+            price: product.price,
+            currency: product.currency,
+            tax_category: product.tax_category,
+            active: product.active,
+        }
+    }
+}
+
+impl From<ProductDocument> for Product {
+    fn from(document: ProductDocument) -> Self {
+        Product {
+            id: from_document_uuid(document.id),
+            name: document.name,
+            sku: document.sku,
+            price: document.price,
+            currency: document.currency,
+            tax_category: document.tax_category,
+            active: document.active,
+        }
+    }
+}
+
+const DUPLICATE_KEY: i32 = 11000;
+
+/// Turns a failed write into [`DuplicateSku`](ProductStoreError::DuplicateSku) when the unique
+/// index of `sku` rejected it, as two products with the same SKU can be saved concurrently.
+fn write_error(sku: &str) -> impl Fn(mongodb::error::Error) -> ProductStoreError + '_ {
+    move |err| match err.kind.as_ref() {
+        ErrorKind::Write(WriteFailure::WriteError(write_error))
+            if write_error.code == DUPLICATE_KEY =>
+        {
+            ProductStoreError::DuplicateSku(sku.to_string())
+        }
+        _ => ProductStoreError::StoreUnavailable,
+    }
+}
+
+/// Products are unique by SKU through a unique index of `sku`, created by
+/// [`bootstrap`](crate::mongodb_schema::bootstrap).
+pub struct MongodbProductStore {
+    products: Collection<ProductDocument>,
+}
+
+impl MongodbProductStore {
+    pub fn new(client: Client, names: &MongodbNames) -> MongodbProductStore {
+        MongodbProductStore {
+            products: names.collection(&client, &names.products),
+        }
+    }
+
+    /// Fails with [`DuplicateSku`](ProductStoreError::DuplicateSku) if a product other than
+    /// `product_id` already uses `sku`.
+    async fn ensure_sku_is_free(
+        &self,
+        sku: &str,
+        product_id: Option<Uuid>,
+    ) -> Result<(), ProductStoreError> {
+        let existing = self
+            .products
+            .find_one(doc! { "sku": sku }, None)
+            .await
+            .map_err(|_| ProductStoreError::StoreUnavailable)?;
+        match existing {
+            Some(product) if Some(from_document_uuid(product.id)) != product_id => {
+                Err(ProductStoreError::DuplicateSku(sku.to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ProductStore for MongodbProductStore {
+    async fn create_product(&self, details: ProductDetails) -> Result<Product, ProductStoreError> {
+        details.validate()?;
+        self.ensure_sku_is_free(&details.sku, None).await?;
+        let product = Product::new(details);
+        self.products
+            .insert_one(ProductDocument::from(&product), None)
+            .await
+            .map_err(write_error(&product.sku))?;
+        Ok(product)
+    }
+
+    async fn get_product(&self, product_id: Uuid) -> Result<Product, ProductStoreError> {
+        self.products
+            .find_one(doc! { "id": bson_uuid(product_id) }, None)
+            .await
+            .map_err(|_| ProductStoreError::StoreUnavailable)?
+            .map(Product::from)
+            .ok_or(ProductStoreError::ProductNotFound(product_id))
+    }
+
+    async fn get_products(&self, product_ids: &[Uuid]) -> Result<Vec<Product>, ProductStoreError> {
+        let ids: Vec<_> = product_ids.iter().map(|id| bson_uuid(*id)).collect();
+        self.products
+            .find(doc! { "id": { "$in": ids } }, None)
+            .await
+            .map_err(|_| ProductStoreError::StoreUnavailable)?
+            .map_ok(Product::from)
+            .try_collect()
+            .await
+            .map_err(|_| ProductStoreError::StoreUnavailable)
+    }
+
+    async fn list_products(&self) -> Result<Vec<Product>, ProductStoreError> {
+        self.products
+            .find(None, None)
+            .await
+            .map_err(|_| ProductStoreError::StoreUnavailable)?
+            .map_ok(Product::from)
+            .try_collect()
+            .await
+            .map_err(|_| ProductStoreError::StoreUnavailable)
+    }
+
+    async fn update_product(
+        &self,
+        product_id: Uuid,
+        details: ProductDetails,
+    ) -> Result<Product, ProductStoreError> {
+        details.validate()?;
+        self.ensure_sku_is_free(&details.sku, Some(product_id))
+            .await?;
+        let product = Product::with_id(product_id, details);
+        let result = self
+            .products
+            .replace_one(
+                doc! { "id": bson_uuid(product_id) },
+                ProductDocument::from(&product),
+                None,
+            )
+            .await
+            .map_err(write_error(&product.sku))?;
+        if result.matched_count == 0 {
+            Err(ProductStoreError::ProductNotFound(product_id))
+        } else {
+            Ok(product)
+        }
+    }
+
+    async fn delete_product(&self, product_id: Uuid) -> Result<(), ProductStoreError> {
+        let result = self
+            .products
+            .delete_one(doc! { "id": bson_uuid(product_id) }, None)
+            .await
+            .map_err(|_| ProductStoreError::StoreUnavailable)?;
+        if result.deleted_count == 0 {
+            Err(ProductStoreError::ProductNotFound(product_id))
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::doc,
+    error::{ErrorKind, WriteFailure},
+    Client, Collection,
+};
+use uuid::Uuid;
+
+use crate::{
+    mongodb_order_document::CouponDocument,
+    mongodb_settings::MongodbNames,
+    promotion_store::{validate, PromotionStore, PromotionStoreError},
+    promotions::Coupon,
+};
+
+const DUPLICATE_KEY: i32 = 11000;
+
+/// Coupons are stored with a `usages` sub-document that counts, per user id, how many times the
+/// coupon was redeemed, so usage limits can be enforced with a single conditional update. Codes
+/// are unique through a unique index of `code`, created by
+/// [`bootstrap`](crate::mongodb_schema::bootstrap).
+pub struct MongodbPromotionStore {
+    coupons: Collection<CouponDocument>,
+}
+
+impl MongodbPromotionStore {
+    pub fn new(client: Client, names: &MongodbNames) -> MongodbPromotionStore {
+        MongodbPromotionStore {
+            coupons: names.collection(&client, &names.coupons),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl PromotionStore for MongodbPromotionStore {
+    async fn create_coupon(&self, coupon: Coupon) -> Result<Coupon, PromotionStoreError> {
+        validate(&coupon)?;
+        self.coupons
+            .insert_one(CouponDocument::from(&coupon), None)
+            .await
+            .map_err(|err| match err.kind.as_ref() {
+                ErrorKind::Write(WriteFailure::WriteError(write_error))
+                    if write_error.code == DUPLICATE_KEY =>
+                {
+                    PromotionStoreError::DuplicateCoupon(coupon.code.clone())
+                }
+                _ => PromotionStoreError::StoreUnavailable,
+            })?;
+        Ok(coupon)
+    }
+
+    async fn get_coupon(&self, code: &str) -> Result<Coupon, PromotionStoreError> {
+        self.coupons
+            .find_one(doc! { "code": code }, None)
+            .await
+            .map_err(|_| PromotionStoreError::StoreUnavailable)?
+            .map(Coupon::from)
+            .ok_or_else(|| PromotionStoreError::CouponNotFound(code.to_string()))
+    }
+
+    async fn list_coupons(&self) -> Result<Vec<Coupon>, PromotionStoreError> {
+        self.coupons
+            .find(None, None)
+            .await
+            .map_err(|_| PromotionStoreError::StoreUnavailable)?
+            .map_ok(Coupon::from)
+            .try_collect()
+            .await
+            .map_err(|_| PromotionStoreError::StoreUnavailable)
+    }
+
+    async fn redeem(&self, code: &str, user_id: Uuid) -> Result<(), PromotionStoreError> {
+        let coupon = self.get_coupon(code).await?;
+        let usage = format!("usages.{user_id}");
+        let filter = match coupon.max_uses_per_user {
+            Some(max_uses) => doc! { "code": code, &usage: { "$not": { "$gte": max_uses } } },
+            None => doc! { "code": code },
+        };
+        let result = self
+            .coupons
+            .update_one(filter, doc! { "$inc": { &usage: 1 } }, None)
+            .await
+            .map_err(|_| PromotionStoreError::StoreUnavailable)?;
+        if result.matched_count == 0 {
+            Err(PromotionStoreError::UsageLimitReached(code.to_string()))
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn release(&self, code: &str, user_id: Uuid) -> Result<(), PromotionStoreError> {
+        let usage = format!("usages.{user_id}");
+        self.coupons
+            .update_one(
+                doc! { "code": code, &usage: { "$gt": 0 } },
+                doc! { "$inc": { &usage: -1 } },
+                None,
+            )
+            .await
+            .map(|_| ())
+            .map_err(|_| PromotionStoreError::StoreUnavailable)
+    }
+}
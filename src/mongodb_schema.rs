@@ -0,0 +1,594 @@
+use std::time::Duration;
+
+use mongodb::{
+    bson::{doc, Document},
+    error::ErrorKind,
+    options::{CreateCollectionOptions, IndexOptions, ValidationAction, ValidationLevel},
+    Client, Database, IndexModel,
+};
+use tracing::info;
+
+use crate::{
+    mongodb_order_store::store_error, mongodb_settings::MongodbNames, order_store::OrderStoreError,
+};
+
+/// Schema of arrays whose elements all match `items`.
+fn array_of(items: Document) -> Document {
+    doc! { "bsonType": "array", "items": items }
+}
+
+/// JSON schema the documents of the `orders` collection are validated against, matching
+/// [`OrderDocument`](crate::mongodb_order_document::OrderDocument) down to its items, coupons,
+/// payments and taxes.
+pub fn order_schema() -> Document {
+    let decimal = doc! { "bsonType": "decimal" };
+    let string = doc! { "bsonType": "string" };
+    let optional_string = doc! { "bsonType": ["string", "null"] };
+    let optional_object = doc! { "bsonType": ["object", "null"] };
+    let uuid = doc! { "bsonType": "binData" };
+    let item = doc! {
+        "bsonType": "object",
+        "required": ["product_id", "quantity", "unit_price", "currency", "tax_category", "line_total"],
+        "properties": {
+            "product_id": uuid.clone(),
+            "quantity": { "bsonType": "int", "minimum": 1 },
+            "unit_price": decimal.clone(),
+            "currency": string.clone(),
+            "tax_category": string.clone(),
+            "line_total": decimal.clone(),
+        },
+    };
+    let coupon = doc! {
+        "bsonType": "object",
+        "required": ["code", "rule"],
+        "properties": {
+            "code": string.clone(),
+            // one of the variants of `PromotionRule`, named by its only field
+            "rule": { "bsonType": "object" },
+            "valid_from": optional_string.clone(),
+            "valid_until": optional_string.clone(),
+            "max_uses_per_user": { "bsonType": ["long", "null"] },
+        },
+    };
+    let payment = doc! {
+        "bsonType": "object",
+        "required": ["id", "amount", "currency", "attempted_at", "outcome"],
+        "properties": {
+            "id": uuid.clone(),
+            "amount": decimal.clone(),
+            "currency": string.clone(),
+            "attempted_at": string.clone(),
+            // the outcomes without fields are stored as their name
+            "outcome": { "bsonType": ["string", "object"] },
+        },
+    };
+    let line_tax = doc! {
+        "bsonType": "object",
+        "required": ["product_id", "category", "rate", "amount"],
+        "properties": {
+            "product_id": uuid.clone(),
+            "category": string,
+            "rate": decimal.clone(),
+            "amount": decimal.clone(),
+        },
+    };
+    let tax = doc! {
+        "bsonType": "object",
+        "required": ["inclusive", "lines", "total"],
+        "properties": {
+            "region": optional_string.clone(),
+            "inclusive": { "bsonType": "bool" },
+            "lines": array_of(line_tax),
+            "total": decimal.clone(),
+        },
+    };
+    let array = doc! { "bsonType": "array" };
+    doc! {
+        "$jsonSchema": {
+            "bsonType": "object",
+            "required": [
+                "_id", "user_id", "created_at", "items", "status", "coupons", "subtotal",
+                "discount", "tax", "shipping_cost", "payments", "returns", "refunded", "total",
+            ],
+            "properties": {
+                "_id": uuid.clone(),
+                "user_id": uuid,
+                "created_at": { "bsonType": "long" },
+                "items": array_of(item),
+                "status": {
+                    "enum": ["Draft", "Placed", "Paid", "Shipped", "Delivered", "Cancelled"],
+                },
+                "currency": optional_string,
+                "coupons": array_of(coupon),
+                "subtotal": decimal.clone(),
+                "discount": decimal.clone(),
+                "tax": tax,
+                "shipping_address": optional_object.clone(),
+                "shipping_method": {
+                    "enum": ["Standard", "Express", "Overnight", null],
+                },
+                "shipping_cost": decimal.clone(),
+                "billing_address": optional_object,
+                "payments": array_of(payment),
+                "returns": array,
+                "refunded": decimal.clone(),
+                "total": decimal,
+            },
+        }
+    }
+}
+
+fn index(name: &str, keys: Document, unique: bool) -> IndexModel {
+    IndexModel::builder()
+        .keys(keys)
+        .options(
+            IndexOptions::builder()
+                .name(name.to_string())
+                .unique(unique)
+                .build(),
+        )
+        .build()
+}
+
+/// Indexes of the `orders` collection: orders are listed by user, oldest first, or by status.
+/// Looking them up by id uses the unique index of `_id`.
+fn order_indexes() -> Vec<IndexModel> {
+    vec![
+        index(
+            "user_id_created_at",
+            doc! { "user_id": 1, "created_at": 1 },
+            false,
+        ),
+        index("status", doc! { "status": 1 }, false),
+    ]
+}
+
+/// Index rejecting a second product with the same SKU, which checking before inserting alone
+/// lets through when both are created at the same time.
+fn sku_index() -> IndexModel {
+    index("sku_unique", doc! { "sku": 1 }, true)
+}
+
+/// Index keeping one stock level per product, which two concurrent upserts of a product without
+/// stock would otherwise both insert.
+fn inventory_index() -> IndexModel {
+    index("product_id_unique", doc! { "product_id": 1 }, true)
+}
+
+/// Index rejecting a second coupon with the same code.
+fn coupon_index() -> IndexModel {
+    index("code_unique", doc! { "code": 1 }, true)
+}
+
+/// How long delivered messages stay in the outbox, for troubleshooting, before MongoDB deletes them.
+const DELIVERED_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Indexes of the outbox: the relay looks up the undelivered messages that are due, and
+/// delivered messages expire [`DELIVERED_RETENTION`] after their `delivered_at` date.
+fn outbox_indexes() -> Vec<IndexModel> {
+    vec![
+        index(
+            "delivered_next_attempt_at",
+            doc! { "delivered": 1, "next_attempt_at": 1 },
+            false,
+        ),
+        IndexModel::builder()
+            .keys(doc! { "delivered_at": 1 })
+            .options(
+                IndexOptions::builder()
+                    .name("delivered_at_ttl".to_string())
+                    .expire_after(DELIVERED_RETENTION)
+                    .build(),
+            )
+            .build(),
+    ]
+}
+
+/// Index keeping a single delivery of an event to a subscription, however many times the event
+/// is published.
+fn delivery_index() -> IndexModel {
+    index(
+        "subscription_id_event_id_unique",
+        doc! { "subscription_id": 1, "event_id": 1 },
+        true,
+    )
+}
+
+/// Index of the `id` field orders had before it became their `_id`; once they are migrated no
+/// document has it, and a unique index would reject every order but the first.
+const OBSOLETE_ID_INDEX: &str = "id_unique";
+
+/// Creates the collections of the service that are missing, with the validator of `orders`, and
+/// the indexes of `orders`, `products`, `coupons`, the outbox and the webhook deliveries. Running it again leaves everything as it is, but for the validator
+/// that is brought up to date.
+///
+/// Collections must exist before the order store writes to them inside transactions.
+///
+/// # Errors
+///
+/// Returns the error of the first command MongoDB rejects, e.g.
+/// [`StoreMisconfigured`](OrderStoreError::StoreMisconfigured) when the user is not allowed to
+/// create collections or indexes.
+pub async fn bootstrap(client: &Client, names: &MongodbNames) -> Result<(), OrderStoreError> {
+    let database = client.database(&names.database);
+    let existing = database
+        .list_collection_names(None)
+        .await
+        .map_err(store_error("listing collections"))?;
+
+    let validated = CreateCollectionOptions::builder()
+        .validator(order_schema())
+        // documents stored before the validator can still be updated
+        .validation_level(ValidationLevel::Moderate)
+        .validation_action(ValidationAction::Error)
+        .build();
+    if existing.contains(&names.orders) {
+        database
+            .run_command(
+                doc! {
+                    "collMod": &names.orders,
+                    "validator": order_schema(),
+                    "validationLevel": "moderate",
+                    "validationAction": "error",
+                },
+                None,
+            )
+            .await
+            .map_err(store_error("updating the validator of orders"))?;
+    } else {
+        create_collection(&database, &names.orders, Some(validated)).await?;
+    }
+
+    let others = [
+        &names.order_audit,
+        &names.order_events,
+        &names.order_snapshots,
+        &names.outbox,
+        &names.inventory,
+        &names.products,
+        &names.coupons,
+        &names.webhooks,
+        &names.webhook_deliveries,
+    ];
+    for name in others {
+        if !existing.contains(name) {
+            create_collection(&database, name, None).await?;
+        }
+    }
+
+    let orders = database.collection::<Document>(&names.orders);
+    let indexes = orders
+        .list_index_names()
+        .await
+        .map_err(store_error("listing the indexes of orders"))?;
+    if indexes.iter().any(|name| name == OBSOLETE_ID_INDEX) {
+        orders
+            .drop_index(OBSOLETE_ID_INDEX, None)
+            .await
+            .map_err(store_error("dropping the id index of orders"))?;
+    }
+    orders
+        .create_indexes(order_indexes(), None)
+        .await
+        .map_err(store_error("creating the indexes of orders"))?;
+    database
+        .collection::<Document>(&names.products)
+        .create_index(sku_index(), None)
+        .await
+        .map_err(store_error("creating the index of products"))?;
+    database
+        .collection::<Document>(&names.inventory)
+        .create_index(inventory_index(), None)
+        .await
+        .map_err(store_error("creating the index of inventory"))?;
+    database
+        .collection::<Document>(&names.coupons)
+        .create_index(coupon_index(), None)
+        .await
+        .map_err(store_error("creating the index of coupons"))?;
+    database
+        .collection::<Document>(&names.outbox)
+        .create_indexes(outbox_indexes(), None)
+        .await
+        .map_err(store_error("creating the indexes of the outbox"))?;
+    database
+        .collection::<Document>(&names.webhook_deliveries)
+        .create_index(delivery_index(), None)
+        .await
+        .map_err(store_error("creating the index of webhook deliveries"))?;
+    info!("MongoDB database {} is ready", names.database);
+    Ok(())
+}
+
+/// Code of the error creating a collection that already exists.
+const NAMESPACE_EXISTS: i32 = 48;
+
+/// Creates collection `name`, which is no error when another instance bootstrapping at the same
+/// time created it since the collections were listed.
+async fn create_collection(
+    database: &Database,
+    name: &str,
+    options: Option<CreateCollectionOptions>,
+) -> Result<(), OrderStoreError> {
+    match database.create_collection(name, options).await {
+        Ok(()) => info!("created collection {}", name),
+        Err(err) if already_exists(&err) => info!("collection {} was just created", name),
+        Err(err) => return Err(store_error("creating a collection")(err)),
+    }
+    Ok(())
+}
+
+fn already_exists(err: &mongodb::error::Error) -> bool {
+    matches!(err.kind.as_ref(), ErrorKind::Command(err) if err.code == NAMESPACE_EXISTS)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use chrono::Utc;
+    use mongodb::{
+        bson::{self, Bson},
+        error::CommandError,
+    };
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        fulfilment::{Address, ShippingMethod},
+        mongodb_order_document::OrderDocument,
+        order_store::{Item, Order, OrderStatus},
+        payment_provider::{PaymentAttempt, PaymentOutcome},
+        promotions::{Coupon, PromotionRule},
+        tax_calculator::{LineTax, TaxBreakdown},
+    };
+
+    /// Name of the BSON type of `value`, as used by `$jsonSchema`.
+    fn bson_type(value: &Bson) -> &'static str {
+        match value {
+            Bson::Binary(_) => "binData",
+            Bson::Int32(_) => "int",
+            Bson::Int64(_) => "long",
+            Bson::Boolean(_) => "bool",
+            Bson::Array(_) => "array",
+            Bson::String(_) => "string",
+            Bson::Decimal128(_) => "decimal",
+            Bson::Document(_) => "object",
+            Bson::Null => "null",
+            other => panic!("unexpected BSON value {other:?}"),
+        }
+    }
+
+    /// Serializes `order` the way the driver does when it writes it.
+    fn stored(order: &Order) -> Document {
+        bson::to_raw_document_buf(&OrderDocument::from(order))
+            .unwrap()
+            .to_document()
+            .unwrap()
+    }
+
+    /// Checks `value`, found at `path`, against `schema` the way the server would, and that every
+    /// field of the documents with properties is in the schema, returning the first mismatch.
+    fn check(schema: &Document, value: &Bson, path: &str) -> Result<(), String> {
+        if let Ok(values) = schema.get_array("enum") {
+            return match values.contains(value) {
+                true => Ok(()),
+                false => Err(format!("{path} cannot be {value}")),
+            };
+        }
+        let allowed = match schema.get("bsonType").unwrap() {
+            Bson::String(name) => vec![name.as_str()],
+            Bson::Array(names) => names.iter().map(|name| name.as_str().unwrap()).collect(),
+            other => panic!("unexpected bsonType {other}"),
+        };
+        if !allowed.contains(&bson_type(value)) {
+            return Err(format!(
+                "{path} is a {} instead of {allowed:?}",
+                bson_type(value)
+            ));
+        }
+        match value {
+            Bson::Document(document) => {
+                for required in schema.get_array("required").into_iter().flatten() {
+                    let field = required.as_str().unwrap();
+                    if !document.contains_key(field) {
+                        return Err(format!("{path}.{field} is missing"));
+                    }
+                }
+                if let Ok(properties) = schema.get_document("properties") {
+                    for (field, value) in document {
+                        let property = properties
+                            .get_document(field)
+                            .map_err(|_| format!("{path}.{field} is not in the schema"))?;
+                        check(property, value, &format!("{path}.{field}"))?;
+                    }
+                }
+            }
+            Bson::Array(values) => {
+                if let Ok(items) = schema.get_document("items") {
+                    for (index, value) in values.iter().enumerate() {
+                        check(items, value, &format!("{path}.{index}"))?;
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Checks `document` against the schema of the `orders` collection.
+    pub(crate) fn check_order(document: Document) -> Result<(), String> {
+        let schema = order_schema();
+        let schema = schema.get_document("$jsonSchema").unwrap();
+        check(schema, &Bson::Document(document), "order")
+    }
+
+    #[test]
+    fn serialized_orders_match_the_schema() {
+        let mut order = Order::new(Uuid::new_v4());
+        check_order(stored(&order)).unwrap();
+
+        order.status = OrderStatus::Placed;
+        order.currency = Some("EUR".to_string());
+        order.shipping_method = Some(ShippingMethod::Express);
+        order.shipping_address = Some(Address {
+            name: "Ada".to_string(),
+            line1: "1 Main Street".to_string(),
+            line2: None,
+            city: "Lyon".to_string(),
+            postal_code: "69001".to_string(),
+            country: "FR".to_string(),
+            region: None,
+        });
+        let product_id = Uuid::new_v4();
+        order.items.push(Item {
+            product_id,
+            quantity: 2,
+            unit_price: dec!(4.50),
+            currency: "EUR".to_string(),
+            tax_category: "standard".to_string(),
+            line_total: dec!(9.00),
+        });
+        order.coupons = vec![
+            Coupon {
+                code: "TENOFF".to_string(),
+                rule: PromotionRule::Percentage { percent: dec!(10) },
+                valid_from: Some(Utc::now()),
+                valid_until: None,
+                max_uses_per_user: Some(1),
+            },
+            Coupon {
+                code: "FREECOFFEE".to_string(),
+                rule: PromotionRule::BuyXGetY {
+                    product_id,
+                    buy: 2,
+                    get: 1,
+                },
+                valid_from: None,
+                valid_until: None,
+                max_uses_per_user: None,
+            },
+        ];
+        order.tax = TaxBreakdown {
+            region: Some("FR".to_string()),
+            inclusive: true,
+            lines: vec![LineTax {
+                product_id,
+                category: "standard".to_string(),
+                rate: dec!(0.20),
+                amount: dec!(1.50),
+            }],
+            total: dec!(1.50),
+        };
+        order.payments = [
+            PaymentOutcome::Approved {
+                reference: "ch_1".to_string(),
+            },
+            PaymentOutcome::Failed,
+        ]
+        .into_iter()
+        .map(|outcome| PaymentAttempt {
+            id: Uuid::new_v4(),
+            amount: dec!(8.10),
+            currency: "EUR".to_string(),
+            attempted_at: Utc::now(),
+            outcome,
+        })
+        .collect();
+        order.recalculate();
+        check_order(stored(&order)).unwrap();
+    }
+
+    #[test]
+    fn items_must_have_the_shape_of_an_item() {
+        let mut order = Order::new(Uuid::new_v4());
+        order.items.push(Item {
+            product_id: Uuid::new_v4(),
+            quantity: 1,
+            unit_price: dec!(4.50),
+            currency: "EUR".to_string(),
+            tax_category: "standard".to_string(),
+            line_total: dec!(4.50),
+        });
+        let mut document = stored(&order);
+        let items = document.get_array_mut("items").unwrap();
+        items[0]
+            .as_document_mut()
+            .unwrap()
+            .insert("unit_price", "4.50");
+        assert_eq!(
+            check_order(document),
+            Err("order.items.0.unit_price is a string instead of [\"decimal\"]".to_string())
+        );
+    }
+
+    #[test]
+    fn creating_an_existing_collection_is_recognized() {
+        let exists: CommandError = bson::from_document(doc! {
+            "code": NAMESPACE_EXISTS,
+            "codeName": "NamespaceExists",
+            "errmsg": "Collection already exists.",
+        })
+        .unwrap();
+        assert!(already_exists(&ErrorKind::Command(exists).into()));
+        let unauthorized: CommandError =
+            bson::from_document(doc! { "code": 13, "codeName": "Unauthorized" }).unwrap();
+        assert!(!already_exists(&ErrorKind::Command(unauthorized).into()));
+    }
+
+    #[test]
+    fn orders_are_indexed_by_user_and_status() {
+        let keys: Vec<_> = order_indexes()
+            .into_iter()
+            .map(|index| index.keys)
+            .collect();
+        assert_eq!(
+            keys,
+            [doc! { "user_id": 1, "created_at": 1 }, doc! { "status": 1 }]
+        );
+    }
+
+    #[test]
+    fn skus_are_unique() {
+        let index = sku_index();
+        assert_eq!(index.keys, doc! { "sku": 1 });
+        assert_eq!(index.options.and_then(|options| options.unique), Some(true));
+    }
+
+    #[test]
+    fn stock_levels_are_unique_per_product() {
+        let index = inventory_index();
+        assert_eq!(index.keys, doc! { "product_id": 1 });
+        assert_eq!(index.options.and_then(|options| options.unique), Some(true));
+    }
+
+    #[test]
+    fn coupon_codes_are_unique() {
+        let index = coupon_index();
+        assert_eq!(index.keys, doc! { "code": 1 });
+        assert_eq!(index.options.and_then(|options| options.unique), Some(true));
+    }
+
+    #[test]
+    fn events_are_delivered_once_per_subscription() {
+        let index = delivery_index();
+        assert_eq!(index.keys, doc! { "subscription_id": 1, "event_id": 1 });
+        assert_eq!(index.options.and_then(|options| options.unique), Some(true));
+    }
+
+    #[test]
+    fn delivered_messages_expire() {
+        let indexes = outbox_indexes();
+        assert_eq!(
+            indexes[0].keys,
+            doc! { "delivered": 1, "next_attempt_at": 1 }
+        );
+        assert_eq!(indexes[1].keys, doc! { "delivered_at": 1 });
+        assert_eq!(
+            indexes[1]
+                .options
+                .as_ref()
+                .and_then(|options| options.expire_after),
+            Some(DELIVERED_RETENTION)
+        );
+    }
+}
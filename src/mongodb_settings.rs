@@ -0,0 +1,305 @@
+use std::{error::Error, fmt::Display, path::PathBuf, str::FromStr, time::Duration};
+
+use mongodb::{
+    bson::doc,
+    options::{
+        Acknowledgment, ClientOptions, ReadConcern, ReadConcernLevel, ResolverConfig, Tls,
+        TlsOptions, WriteConcern,
+    },
+    Client, Collection,
+};
+use tracing::info;
+
+use crate::{mongodb_order_store::store_error, order_store::OrderStoreError};
+
+/// DNS resolver used to look up the SRV and TXT records of `mongodb+srv://` connection strings.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Resolver {
+    /// The resolver configured on the host, the only one reachable in air-gapped networks.
+    #[default]
+    System,
+    Cloudflare,
+    Google,
+    Quad9,
+}
+
+/// Error of a setting that cannot be parsed, with the name of the setting.
+#[derive(Debug)]
+pub struct InvalidSetting(pub String);
+
+impl Display for InvalidSetting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid MongoDB setting: {}", self.0)
+    }
+}
+
+impl Error for InvalidSetting {}
+
+impl FromStr for Resolver {
+    type Err = InvalidSetting;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "system" => Ok(Resolver::System),
+            "cloudflare" => Ok(Resolver::Cloudflare),
+            "google" => Ok(Resolver::Google),
+            "quad9" => Ok(Resolver::Quad9),
+            _ => Err(InvalidSetting(format!("resolver {name}"))),
+        }
+    }
+}
+
+/// Parses a read concern level, e.g. `majority`.
+pub fn read_concern(level: &str) -> Result<ReadConcern, InvalidSetting> {
+    let level = match level {
+        "local" => ReadConcernLevel::Local,
+        "majority" => ReadConcernLevel::Majority,
+        "linearizable" => ReadConcernLevel::Linearizable,
+        "available" => ReadConcernLevel::Available,
+        "snapshot" => ReadConcernLevel::Snapshot,
+        _ => return Err(InvalidSetting(format!("read concern {level}"))),
+    };
+    Ok(level.into())
+}
+
+/// Parses the acknowledgment of a write concern: `majority`, a number of nodes or a tag set name.
+pub fn acknowledgment(w: &str) -> Acknowledgment {
+    match w.parse::<u32>() {
+        Ok(nodes) => nodes.into(),
+        Err(_) => w.to_string().into(),
+    }
+}
+
+/// TLS settings, on top of the ones of the connection string.
+#[derive(Clone, Debug, Default)]
+pub struct TlsSettings {
+    pub ca_file: Option<PathBuf>,
+    pub cert_key_file: Option<PathBuf>,
+    /// Accepts any server certificate; for development only.
+    pub allow_invalid_certificates: bool,
+}
+
+/// How to connect to MongoDB. Settings left unset keep the value of the connection string, or
+/// the default of the driver.
+#[derive(Clone, Debug, Default)]
+pub struct MongodbSettings {
+    pub uri: String,
+    pub resolver: Resolver,
+    pub app_name: Option<String>,
+    pub min_pool_size: Option<u32>,
+    pub max_pool_size: Option<u32>,
+    pub connect_timeout: Option<Duration>,
+    pub server_selection_timeout: Option<Duration>,
+    pub read_concern: Option<ReadConcern>,
+    pub write_concern: Option<WriteConcern>,
+    pub tls: Option<TlsSettings>,
+}
+
+/// Names of the database and collections of the service, so that several environments, or
+/// tests running in parallel, can share one cluster.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MongodbNames {
+    pub database: String,
+    pub orders: String,
+    pub order_audit: String,
+    pub order_events: String,
+    pub order_snapshots: String,
+    pub outbox: String,
+    pub inventory: String,
+    pub products: String,
+    pub coupons: String,
+    pub webhooks: String,
+    pub webhook_deliveries: String,
+    /// Migrations applied to the other collections.
+    pub migrations: String,
+}
+
+impl MongodbNames {
+    /// Names of the collections in `database`, each one starting with `prefix`, e.g. `staging_`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let names = MongodbNames::new("shop", "staging_");
+    /// assert_eq!(names.orders, "staging_orders");
+    /// ```
+    pub fn new(database: &str, prefix: &str) -> MongodbNames {
+        let name = |collection| format!("{prefix}{collection}");
+        MongodbNames {
+            database: database.to_string(),
+            orders: name("orders"),
+            order_audit: name("order_audit"),
+            order_events: name("order_events"),
+            order_snapshots: name("order_snapshots"),
+            outbox: name("order_outbox"),
+            inventory: name("inventory"),
+            products: name("products"),
+            coupons: name("coupons"),
+            webhooks: name("webhooks"),
+            webhook_deliveries: name("webhook_deliveries"),
+            migrations: name("_migrations"),
+        }
+    }
+
+    /// Handle on the collection `name` of the database.
+    pub fn collection<T>(&self, client: &Client, name: &str) -> Collection<T> {
+        client.database(&self.database).collection(name)
+    }
+}
+
+impl Default for MongodbNames {
+    fn default() -> Self {
+        MongodbNames::new("examplemongo-ms", "")
+    }
+}
+
+impl MongodbSettings {
+    /// Parses the connection string and applies the settings on top of it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreMisconfigured`](OrderStoreError::StoreMisconfigured) if the connection
+    /// string is malformed, or [`StoreConnection`](OrderStoreError::StoreConnection) if its DNS
+    /// records cannot be looked up.
+    pub async fn client_options(&self) -> Result<ClientOptions, OrderStoreError> {
+        let parse = store_error("parsing the MongoDB connection string");
+        let mut options = match self.resolver {
+            Resolver::System => ClientOptions::parse(&self.uri).await,
+            Resolver::Cloudflare => {
+                ClientOptions::parse_with_resolver_config(&self.uri, ResolverConfig::cloudflare())
+                    .await
+            }
+            Resolver::Google => {
+                ClientOptions::parse_with_resolver_config(&self.uri, ResolverConfig::google()).await
+            }
+            Resolver::Quad9 => {
+                ClientOptions::parse_with_resolver_config(&self.uri, ResolverConfig::quad9()).await
+            }
+        }
+        .map_err(parse)?;
+        if self.app_name.is_some() {
+            options.app_name = self.app_name.clone();
+        }
+        if self.min_pool_size.is_some() {
+            options.min_pool_size = self.min_pool_size;
+        }
+        if self.max_pool_size.is_some() {
+            options.max_pool_size = self.max_pool_size;
+        }
+        if self.connect_timeout.is_some() {
+            options.connect_timeout = self.connect_timeout;
+        }
+        if self.server_selection_timeout.is_some() {
+            options.server_selection_timeout = self.server_selection_timeout;
+        }
+        if self.read_concern.is_some() {
+            options.read_concern = self.read_concern.clone();
+        }
+        if self.write_concern.is_some() {
+            options.write_concern = self.write_concern.clone();
+        }
+        if let Some(tls) = &self.tls {
+            let tls = TlsOptions::builder()
+                .ca_file_path(tls.ca_file.clone())
+                .cert_key_file_path(tls.cert_key_file.clone())
+                .allow_invalid_certificates(tls.allow_invalid_certificates)
+                .build();
+            options.tls = Some(Tls::Enabled(tls));
+        }
+        Ok(options)
+    }
+
+    /// Creates a client shared by every MongoDB store and checks that the server answers,
+    /// so that a wrong address or credentials stop the service right away.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of [`client_options`](MongodbSettings::client_options), or the one of
+    /// the ping, e.g. [`StoreConnection`](OrderStoreError::StoreConnection) when no server can be
+    /// selected within the server selection timeout.
+    pub async fn connect(&self) -> Result<Client, OrderStoreError> {
+        let options = self.client_options().await?;
+        let hosts: Vec<String> = options.hosts.iter().map(ToString::to_string).collect();
+        let client =
+            Client::with_options(options).map_err(store_error("creating the MongoDB client"))?;
+        client
+            .database("admin")
+            .run_command(doc! { "ping": 1 }, None)
+            .await
+            .map_err(store_error("pinging MongoDB"))?;
+        info!("connected to MongoDB at {}", hosts.join(","));
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn settings_override_the_connection_string() {
+        let settings = MongodbSettings {
+            uri: "mongodb://db1:27017,db2:27017/?appName=from-uri&maxPoolSize=5&w=1".to_string(),
+            app_name: Some("orders".to_string()),
+            max_pool_size: Some(20),
+            server_selection_timeout: Some(Duration::from_secs(2)),
+            read_concern: Some(read_concern("majority").unwrap()),
+            tls: Some(TlsSettings::default()),
+            ..MongodbSettings::default()
+        };
+
+        let options = settings.client_options().await.unwrap();
+        assert_eq!(options.hosts.len(), 2);
+        assert_eq!(options.app_name.as_deref(), Some("orders"));
+        assert_eq!(options.max_pool_size, Some(20));
+        assert_eq!(
+            options.server_selection_timeout,
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(options.read_concern, Some(ReadConcern::majority()));
+        // not overridden
+        assert_eq!(
+            options.write_concern.and_then(|concern| concern.w),
+            Some(Acknowledgment::Nodes(1))
+        );
+        assert!(matches!(options.tls, Some(Tls::Enabled(_))));
+    }
+
+    #[tokio::test]
+    async fn malformed_settings_are_rejected() {
+        let settings = MongodbSettings {
+            uri: "postgres://db:5432".to_string(),
+            ..MongodbSettings::default()
+        };
+        assert!(matches!(
+            settings.client_options().await,
+            Err(OrderStoreError::StoreMisconfigured(_))
+        ));
+        assert!("opendns".parse::<Resolver>().is_err());
+        assert!(read_concern("strong").is_err());
+        assert_eq!(acknowledgment("majority"), Acknowledgment::Majority);
+        assert_eq!(acknowledgment("2"), Acknowledgment::Nodes(2));
+    }
+
+    #[test]
+    fn collection_names_are_prefixed() {
+        let names = MongodbNames::new("shop-test-1", "ci_");
+        assert_eq!(names.database, "shop-test-1");
+        assert_eq!(names.outbox, "ci_order_outbox");
+        assert_eq!(names.webhook_deliveries, "ci_webhook_deliveries");
+        assert_eq!(MongodbNames::default().orders, "orders");
+    }
+
+    #[tokio::test]
+    async fn unreachable_servers_fail_the_ping() {
+        let settings = MongodbSettings {
+            uri: "mongodb://127.0.0.1:1/".to_string(),
+            server_selection_timeout: Some(Duration::from_millis(200)),
+            ..MongodbSettings::default()
+        };
+        assert!(matches!(
+            settings.connect().await,
+            Err(OrderStoreError::StoreConnection(_))
+        ));
+    }
+}
@@ -0,0 +1,160 @@
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{self, doc},
+    error::{ErrorKind, WriteFailure},
+    options::{FindOptions, ReplaceOptions, UpdateOptions},
+    Client, Collection,
+};
+use uuid::Uuid;
+
+use crate::{
+    mongodb_order_store::uuid_as_bson,
+    mongodb_settings::MongodbNames,
+    webhook_store::{Delivery, Subscription, WebhookStore, WebhookStoreError},
+};
+
+/// Keeps subscriptions in `webhooks` and their deliveries in `webhook_deliveries`.
+pub struct MongodbWebhookStore {
+    subscriptions: Collection<Subscription>,
+    deliveries: Collection<Delivery>,
+}
+
+impl MongodbWebhookStore {
+    pub fn new(client: Client, names: &MongodbNames) -> MongodbWebhookStore {
+        MongodbWebhookStore {
+            subscriptions: names.collection(&client, &names.webhooks),
+            deliveries: names.collection(&client, &names.webhook_deliveries),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl WebhookStore for MongodbWebhookStore {
+    async fn create_subscription(
+        &self,
+        subscription: Subscription,
+    ) -> Result<Subscription, WebhookStoreError> {
+        self.subscriptions
+            .insert_one(&subscription, None)
+            .await
+            .map_err(|_| WebhookStoreError::StoreUnavailable)?;
+        Ok(subscription)
+    }
+
+    async fn get_subscription(&self, id: Uuid) -> Result<Subscription, WebhookStoreError> {
+        self.subscriptions
+            .find_one(doc! { "id": uuid_as_bson(id) }, None)
+            .await
+            .map_err(|_| WebhookStoreError::StoreUnavailable)?
+            .ok_or(WebhookStoreError::SubscriptionNotFound(id))
+    }
+
+    async fn list_subscriptions(&self) -> Result<Vec<Subscription>, WebhookStoreError> {
+        self.subscriptions
+            .find(None, None)
+            .await
+            .map_err(|_| WebhookStoreError::StoreUnavailable)?
+            .try_collect()
+            .await
+            .map_err(|_| WebhookStoreError::StoreUnavailable)
+    }
+
+    async fn delete_subscription(&self, id: Uuid) -> Result<(), WebhookStoreError> {
+        let result = self
+            .subscriptions
+            .delete_one(doc! { "id": uuid_as_bson(id) }, None)
+            .await
+            .map_err(|_| WebhookStoreError::StoreUnavailable)?;
+        if result.deleted_count == 0 {
+            return Err(WebhookStoreError::SubscriptionNotFound(id));
+        }
+        Ok(())
+    }
+
+    async fn queue_delivery(&self, delivery: &Delivery) -> Result<(), WebhookStoreError> {
+        let document =
+            bson::to_document(delivery).map_err(|_| WebhookStoreError::StoreUnavailable)?;
+        let options = UpdateOptions::builder().upsert(true).build();
+        let result = self
+            .deliveries
+            .update_one(
+                doc! {
+                    "subscription_id": uuid_as_bson(delivery.subscription_id),
+                    "event_id": uuid_as_bson(delivery.event_id),
+                },
+                doc! { "$setOnInsert": document },
+                options,
+            )
+            .await;
+        match result {
+            Ok(_) => Ok(()),
+            // queued at the same time by another relay, the unique index kept one
+            Err(err)
+                if matches!(
+                    err.kind.as_ref(),
+                    ErrorKind::Write(WriteFailure::WriteError(e)) if e.code == 11000
+                ) =>
+            {
+                Ok(())
+            }
+            Err(_) => Err(WebhookStoreError::StoreUnavailable),
+        }
+    }
+
+    async fn save_delivery(&self, delivery: &Delivery) -> Result<(), WebhookStoreError> {
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.deliveries
+            .replace_one(doc! { "id": uuid_as_bson(delivery.id) }, delivery, options)
+            .await
+            .map(|_| ())
+            .map_err(|_| WebhookStoreError::StoreUnavailable)
+    }
+
+    async fn due_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<Delivery>, WebhookStoreError> {
+        let subscription_ids: Vec<_> = self
+            .list_subscriptions()
+            .await?
+            .into_iter()
+            .map(|subscription| uuid_as_bson(subscription.id))
+            .collect();
+        let options = FindOptions::builder()
+            .sort(doc! { "next_attempt_at": 1 })
+            .limit(limit as i64)
+            .build();
+        self.deliveries
+            .find(
+                doc! {
+                    "status": "Pending",
+                    "next_attempt_at": { "$lte": now.timestamp_millis() },
+                    "subscription_id": { "$in": subscription_ids },
+                },
+                options,
+            )
+            .await
+            .map_err(|_| WebhookStoreError::StoreUnavailable)?
+            .try_collect()
+            .await
+            .map_err(|_| WebhookStoreError::StoreUnavailable)
+    }
+
+    async fn deliveries(&self, subscription_id: Uuid) -> Result<Vec<Delivery>, WebhookStoreError> {
+        self.get_subscription(subscription_id).await?;
+        // ObjectIds grow with insertion time
+        let options = FindOptions::builder().sort(doc! { "_id": -1 }).build();
+        self.deliveries
+            .find(
+                doc! { "subscription_id": uuid_as_bson(subscription_id) },
+                options,
+            )
+            .await
+            .map_err(|_| WebhookStoreError::StoreUnavailable)?
+            .try_collect()
+            .await
+            .map_err(|_| WebhookStoreError::StoreUnavailable)
+    }
+}
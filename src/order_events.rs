@@ -0,0 +1,350 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{
+    fulfilment::{Address, ShippingMethod},
+    order_store::{Item, Order, OrderStatus, OrderStoreError},
+    payment_provider::{PaymentAttempt, PaymentOutcome},
+    promotions::Coupon,
+    returns::{ReturnRequest, ReturnStatus},
+    tax_calculator::TaxBreakdown,
+};
+
+/// Names of every [`OrderEvent`](OrderEvent), as returned by [`OrderEvent::name`].
+pub const EVENT_TYPES: [&str; 12] = [
+    "OrderCreated",
+    "ItemAdded",
+    "ItemRemoved",
+    "ItemQuantityChanged",
+    "StatusChanged",
+    "CouponApplied",
+    "CouponRemoved",
+    "ShippingSet",
+    "BillingAddressSet",
+    "PaymentAttempted",
+    "ReturnRequested",
+    "ReturnDecided",
+];
+
+/// Something that happened to an order. Events carry the outcome of a change, prices and taxes
+/// included, so replaying them never needs the catalog or the tax calculator.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum OrderEvent {
+    OrderCreated {
+        order_id: Uuid,
+        user_id: Uuid,
+        /// Missing from the events recorded before orders had a creation time.
+        #[serde(default, with = "chrono::serde::ts_milliseconds")]
+        created_at: DateTime<Utc>,
+    },
+    ItemAdded {
+        item: Item,
+        tax: TaxBreakdown,
+    },
+    ItemRemoved {
+        index: usize,
+        tax: TaxBreakdown,
+    },
+    ItemQuantityChanged {
+        index: usize,
+        quantity: i32,
+        tax: TaxBreakdown,
+    },
+    StatusChanged {
+        status: OrderStatus,
+    },
+    CouponApplied {
+        coupon: Coupon,
+        /// Missing from the events recorded before coupons were taken into account by taxes.
+        #[serde(default)]
+        tax: Option<TaxBreakdown>,
+    },
+    CouponRemoved {
+        code: String,
+        /// Missing from the events recorded before coupons were taken into account by taxes.
+        #[serde(default)]
+        tax: Option<TaxBreakdown>,
+    },
+    ShippingSet {
+        address: Address,
+        method: ShippingMethod,
+        #[serde(with = "crate::decimal128")]
+        cost: Decimal,
+        tax: TaxBreakdown,
+    },
+    BillingAddressSet {
+        address: Address,
+    },
+    PaymentAttempted {
+        attempt: PaymentAttempt,
+    },
+    ReturnRequested {
+        request: ReturnRequest,
+    },
+    ReturnDecided {
+        request: ReturnRequest,
+    },
+}
+
+impl OrderEvent {
+    /// Name of the event, e.g. `ItemAdded`, as published to other services.
+    pub fn name(&self) -> &'static str {
+        match self {
+            OrderEvent::OrderCreated { .. } => "OrderCreated",
+            OrderEvent::ItemAdded { .. } => "ItemAdded",
+            OrderEvent::ItemRemoved { .. } => "ItemRemoved",
+            OrderEvent::ItemQuantityChanged { .. } => "ItemQuantityChanged",
+            OrderEvent::StatusChanged { .. } => "StatusChanged",
+            OrderEvent::CouponApplied { .. } => "CouponApplied",
+            OrderEvent::CouponRemoved { .. } => "CouponRemoved",
+            OrderEvent::ShippingSet { .. } => "ShippingSet",
+            OrderEvent::BillingAddressSet { .. } => "BillingAddressSet",
+            OrderEvent::PaymentAttempted { .. } => "PaymentAttempted",
+            OrderEvent::ReturnRequested { .. } => "ReturnRequested",
+            OrderEvent::ReturnDecided { .. } => "ReturnDecided",
+        }
+    }
+
+    /// Applies the event to `order`, which is `None` before the order is created.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreSerialization`](OrderStoreError::StoreSerialization) if the event doesn't
+    /// fit the order, which means the stream is corrupted.
+    pub fn apply(self, order: Option<Order>) -> Result<Order, OrderStoreError> {
+        match (self, order) {
+            (
+                OrderEvent::OrderCreated {
+                    order_id,
+                    user_id,
+                    created_at,
+                },
+                None,
+            ) => Ok(Order {
+                id: order_id,
+                created_at,
+                ..Order::new(user_id)
+            }),
+            (event, Some(mut order)) => {
+                event.apply_to(&mut order)?;
+                Ok(order)
+            }
+            (event, None) => Err(corrupted(format!("{} before OrderCreated", event.name()))),
+        }
+    }
+
+    fn apply_to(self, order: &mut Order) -> Result<(), OrderStoreError> {
+        match self {
+            OrderEvent::OrderCreated { order_id, .. } => {
+                return Err(corrupted(format!("order {} created twice", order_id)));
+            }
+            OrderEvent::ItemAdded { item, tax } => {
+                order.items.push(item);
+                order.tax = tax;
+            }
+            OrderEvent::ItemRemoved { index, tax } => {
+                if index >= order.items.len() {
+                    return Err(corrupted(format!("no item {} to remove", index)));
+                }
+                order.items.remove(index);
+                order.tax = tax;
+            }
+            OrderEvent::ItemQuantityChanged {
+                index,
+                quantity,
+                tax,
+            } => {
+                order
+                    .items
+                    .get_mut(index)
+                    .ok_or_else(|| corrupted(format!("no item {} to change", index)))?
+                    .set_quantity(quantity)
+                    .map_err(|_| corrupted(format!("quantity {} out of range", quantity)))?;
+                order.tax = tax;
+            }
+            OrderEvent::StatusChanged { status } => order.status = status,
+            OrderEvent::CouponApplied { coupon, tax } => {
+                order.coupons.push(coupon);
+                if let Some(tax) = tax {
+                    order.tax = tax;
+                }
+            }
+            OrderEvent::CouponRemoved { code, tax } => {
+                order.coupons.retain(|c| c.code != code);
+                if let Some(tax) = tax {
+                    order.tax = tax;
+                }
+            }
+            OrderEvent::ShippingSet {
+                address,
+                method,
+                cost,
+                tax,
+            } => {
+                order.shipping_address = Some(address);
+                order.shipping_method = Some(method);
+                order.shipping_cost = cost;
+                order.tax = tax;
+            }
+            OrderEvent::BillingAddressSet { address } => order.billing_address = Some(address),
+            OrderEvent::PaymentAttempted { attempt } => {
+                if matches!(attempt.outcome, PaymentOutcome::Approved { .. }) {
+                    order.status = OrderStatus::Paid;
+                }
+                // a pending attempt is recorded again once its outcome is known
+                match order.payments.iter_mut().find(|a| a.id == attempt.id) {
+                    Some(pending) => *pending = attempt,
+                    None => order.payments.push(attempt),
+                }
+            }
+            OrderEvent::ReturnRequested { request } => order.returns.push(request),
+            OrderEvent::ReturnDecided { request } => {
+                let stored = order
+                    .returns
+                    .iter_mut()
+                    .find(|stored| stored.id == request.id)
+                    .ok_or_else(|| corrupted(format!("return {} not requested", request.id)))?;
+                if request.status == ReturnStatus::Approved {
+                    order.refunded = order
+                        .refunded
+                        .checked_add(request.refund_amount)
+                        .ok_or_else(|| corrupted(format!("refund of return {}", request.id)))?;
+                }
+                *stored = request;
+            }
+        }
+        order.recalculate();
+        Ok(())
+    }
+}
+
+/// Error for an event that doesn't fit the order it is applied to, logging the provided reason.
+fn corrupted(reason: String) -> OrderStoreError {
+    error!("corrupted order stream: {}", reason);
+    OrderStoreError::StoreSerialization(format!("corrupted order stream: {}", reason).into())
+}
+
+/// An event as stored in the `order_events` collection.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StoredEvent {
+    pub order_id: Uuid,
+    /// Owner of the order, so the orders of a user can be found without replaying them.
+    pub user_id: Uuid,
+    /// Position of the event in the stream of the order, starting at 1.
+    pub version: i64,
+    /// Name of the [`OrderStore`](crate::order_store::OrderStore) method that emitted the event.
+    pub operation: String,
+    pub actor: String,
+    pub recorded_at: DateTime<Utc>,
+    pub event: OrderEvent,
+}
+
+/// State of an order after the event with version `version`, so replays can start from it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub order: Order,
+    pub version: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::product_store::Product;
+
+    fn product() -> Product {
+        Product {
+            id: Uuid::new_v4(),
+            name: "Coffee".to_string(),
+            sku: "COF-001".to_string(),
+            price: dec!(4.50),
+            currency: "USD".to_string(),
+            tax_category: "standard".to_string(),
+            active: true,
+        }
+    }
+
+    #[test]
+    fn replay_rebuilds_order_with_its_totals() {
+        let order_id = Uuid::new_v4();
+        let events = vec![
+            OrderEvent::OrderCreated {
+                order_id,
+                user_id: Uuid::new_v4(),
+                created_at: Utc::now(),
+            },
+            OrderEvent::ItemAdded {
+                item: Item::new(&product(), 2).unwrap(),
+                tax: TaxBreakdown::default(),
+            },
+            OrderEvent::ItemAdded {
+                item: Item::new(&product(), 1).unwrap(),
+                tax: TaxBreakdown::default(),
+            },
+            OrderEvent::ItemRemoved {
+                index: 0,
+                tax: TaxBreakdown::default(),
+            },
+            OrderEvent::ItemQuantityChanged {
+                index: 0,
+                quantity: 3,
+                tax: TaxBreakdown::default(),
+            },
+            OrderEvent::StatusChanged {
+                status: OrderStatus::Placed,
+            },
+        ];
+        let order = events
+            .into_iter()
+            .try_fold(None, |order, event| event.apply(order).map(Some))
+            .unwrap()
+            .unwrap();
+        assert_eq!(order.id, order_id);
+        assert_eq!(order.items.len(), 1);
+        assert_eq!(order.total, dec!(13.50));
+        assert_eq!(order.status, OrderStatus::Placed);
+    }
+
+    #[test]
+    fn events_that_do_not_fit_the_order_are_rejected() {
+        let created = OrderEvent::OrderCreated {
+            order_id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            created_at: Utc::now(),
+        };
+        let order = created.clone().apply(None).unwrap();
+        let removed = OrderEvent::ItemRemoved {
+            index: 0,
+            tax: TaxBreakdown::default(),
+        };
+        assert!(removed.clone().apply(None).is_err());
+        assert!(removed.apply(Some(order.clone())).is_err());
+        assert!(created.apply(Some(order.clone())).is_err());
+        let changed = OrderEvent::ItemQuantityChanged {
+            index: 1,
+            quantity: 2,
+            tax: TaxBreakdown::default(),
+        };
+        assert!(matches!(
+            changed.apply(Some(order)),
+            Err(OrderStoreError::StoreSerialization(_))
+        ));
+    }
+
+    #[test]
+    fn event_round_trips_through_bson() {
+        let event = OrderEvent::ItemAdded {
+            item: Item::new(&product(), 3).unwrap(),
+            tax: TaxBreakdown::default(),
+        };
+        let bytes = mongodb::bson::to_vec(&event).unwrap();
+        assert_eq!(
+            mongodb::bson::from_slice::<OrderEvent>(&bytes).unwrap(),
+            event
+        );
+    }
+}
@@ -1,8 +1,24 @@
 use std::{error::Error, fmt::Display, ops::Deref};
 
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use chrono::{DateTime, SubsecRound, Utc};
+use tracing::error;
+
+use crate::{
+    audit_log::AuditEntry,
+    fulfilment::{Address, ShippingMethod},
+    inventory_store::{InventoryStoreError, InventoryStoreNewType},
+    payment_provider::{Charge, PaymentAttempt, PaymentOutcome, PaymentProvider, Refund},
+    product_store::{Product, ProductStoreError, ProductStoreNewType},
+    promotion_store::{PromotionStoreError, PromotionStoreNewType},
+    promotions::{self, Coupon},
+    returns::{self, ReturnItem, ReturnLine, ReturnRequest, ReturnStatus},
+    tax_calculator::{TaxBreakdown, TaxCalculator, TaxError},
+};
+
 pub struct OrderStoreNewType(pub Box<dyn OrderStore>); // //dyn: dynamic implementation of orderStore -> so not pegged to a specific implementation but
                                                        // to a generalization
                                                        //struct OrderStoreNewType(...) -> struct type tuple which means
@@ -31,6 +47,98 @@ pub struct Item {
     pub product_id: Uuid,
     /// Number of items of this product.
     pub quantity: i32,
+    /// Price of one unit of the product at the time it was added to the order.
+    #[serde(with = "crate::decimal128")]
+    pub unit_price: Decimal,
+    /// ISO 4217 code of the currency `unit_price` is expressed in.
+    pub currency: String,
+    /// Tax category of the product at the time it was added to the order.
+    pub tax_category: String,
+    /// `unit_price` times `quantity`.
+    #[serde(with = "crate::decimal128")]
+    pub line_total: Decimal,
+}
+
+impl Item {
+    /// Creates an item for `quantity` units of `product`, snapshotting its current price.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`InvalidQuantity`](OrderStoreError::InvalidQuantity) if the line total is too
+    /// large to be represented.
+    pub fn new(product: &Product, quantity: i32) -> Result<Item, OrderStoreError> {
+        Ok(Item {
+            product_id: product.id,
+            quantity,
+            unit_price: product.price,
+            currency: product.currency.clone(),
+            tax_category: product.tax_category.clone(),
+            line_total: line_total(product.price, quantity)?,
+        })
+    }
+
+    /// Changes the number of units of the item, keeping its price.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`InvalidQuantity`](OrderStoreError::InvalidQuantity) if the line total is too
+    /// large to be represented; the item is left unchanged.
+    pub fn set_quantity(&mut self, quantity: i32) -> Result<(), OrderStoreError> {
+        self.line_total = line_total(self.unit_price, quantity)?;
+        self.quantity = quantity;
+        Ok(())
+    }
+}
+
+fn line_total(unit_price: Decimal, quantity: i32) -> Result<Decimal, OrderStoreError> {
+    unit_price
+        .checked_mul(Decimal::from(quantity))
+        .ok_or(OrderStoreError::InvalidQuantity(quantity))
+}
+
+/// Sums the line totals of `items`, `None` when the sum is too large to be represented.
+fn checked_subtotal(items: &[Item]) -> Option<Decimal> {
+    items.iter().try_fold(Decimal::ZERO, |subtotal, item| {
+        subtotal.checked_add(item.line_total)
+    })
+}
+
+/// Lifecycle stage of an order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderStatus {
+    /// The order is being filled; its items hold reserved stock.
+    Draft,
+    /// The order was confirmed; its stock is committed.
+    Placed,
+    /// The order was paid at checkout; its stock is committed.
+    Paid,
+    /// The order left the warehouse.
+    Shipped,
+    /// The order reached the customer, who can now return its items.
+    Delivered,
+    /// The order was abandoned; its stock went back to the inventory.
+    Cancelled,
+}
+
+impl OrderStatus {
+    /// Tells whether an order in this status can be moved to `next`. Orders only become
+    /// [`Paid`](OrderStatus::Paid) through a successful checkout, never by a plain transition.
+    pub fn can_transition_to(self, next: OrderStatus) -> bool {
+        matches!(
+            (self, next),
+            (OrderStatus::Draft, OrderStatus::Placed)
+                | (OrderStatus::Draft, OrderStatus::Cancelled)
+                | (OrderStatus::Placed, OrderStatus::Shipped)
+                | (OrderStatus::Paid, OrderStatus::Shipped)
+                | (OrderStatus::Shipped, OrderStatus::Delivered)
+        )
+    }
+}
+
+impl Display for OrderStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
 }
 
 /// Representation of an order in the system.
@@ -40,8 +148,45 @@ pub struct Order {
     pub id: Uuid,
     /// Each order belongs to a user.
     pub user_id: Uuid,
+    /// When the order was created, kept as milliseconds so the orders of a user can be sorted and
+    /// indexed by it. Orders stored before it was recorded read as created at the epoch.
+    #[serde(default, with = "chrono::serde::ts_milliseconds")]
+    pub created_at: DateTime<Utc>,
     /// This holds the list of items included in the order.
     pub items: Vec<Item>,
+    /// Current lifecycle stage of the order.
+    pub status: OrderStatus,
+    /// Currency shared by every item of the order, `None` while the order is empty.
+    pub currency: Option<String>,
+    /// Coupons applied to the order, as they were when they were applied.
+    pub coupons: Vec<Coupon>,
+    /// Sum of the line totals of every item.
+    #[serde(with = "crate::decimal128")]
+    pub subtotal: Decimal,
+    /// Amount taken off the subtotal by the applied coupons.
+    #[serde(with = "crate::decimal128")]
+    pub discount: Decimal,
+    /// Taxes of the items, as calculated the last time the items or the shipping address changed.
+    pub tax: TaxBreakdown,
+    /// Where the order is delivered, also deciding which tax rates apply.
+    pub shipping_address: Option<Address>,
+    /// How the order is delivered.
+    pub shipping_method: Option<ShippingMethod>,
+    /// Cost of the shipping method, zero until one is selected.
+    #[serde(with = "crate::decimal128")]
+    pub shipping_cost: Decimal,
+    /// Where the invoice of the order is sent.
+    pub billing_address: Option<Address>,
+    /// Every payment attempt made at checkout, oldest first.
+    pub payments: Vec<PaymentAttempt>,
+    /// Every return requested for the order, oldest first.
+    pub returns: Vec<ReturnRequest>,
+    /// Amount given back to the customer by approved returns.
+    #[serde(with = "crate::decimal128")]
+    pub refunded: Decimal,
+    /// Amount to be paid for the order.
+    #[serde(with = "crate::decimal128")]
+    pub total: Decimal,
 }
 
 impl Order {
@@ -50,40 +195,1096 @@ impl Order {
         Order {
             id: Uuid::new_v4(),
             user_id,
+            created_at: Utc::now().trunc_subsecs(3),
             items: vec![],
+            status: OrderStatus::Draft,
+            currency: None,
+            coupons: vec![],
+            subtotal: Decimal::ZERO,
+            discount: Decimal::ZERO,
+            tax: TaxBreakdown::default(),
+            shipping_address: None,
+            shipping_method: None,
+            shipping_cost: Decimal::ZERO,
+            billing_address: None,
+            payments: vec![],
+            returns: vec![],
+            refunded: Decimal::ZERO,
+            total: Decimal::ZERO,
+        }
+    }
+
+    /// Appends `quantity` units of `product` to the order, updates its totals and returns the new item.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`OrderNotEditable`](OrderStoreError::OrderNotEditable) if the order is no longer a draft.
+    ///
+    /// Returns [`InvalidQuantity`](OrderStoreError::InvalidQuantity) if `quantity` is not positive
+    /// or makes the order total too large to be represented.
+    ///
+    /// Returns [`CurrencyMismatch`](OrderStoreError::CurrencyMismatch) if the product is priced in a
+    /// different currency than the items already in the order.
+    pub fn add_item(&mut self, product: &Product, quantity: i32) -> Result<Item, OrderStoreError> {
+        self.ensure_editable()?;
+        if quantity <= 0 {
+            return Err(OrderStoreError::InvalidQuantity(quantity));
+        }
+        match &self.currency {
+            Some(currency) if *currency != product.currency => {
+                return Err(OrderStoreError::CurrencyMismatch(product.currency.clone()))
+            }
+            _ => {}
+        }
+        let item = Item::new(product, quantity)?;
+        self.items.push(item.clone());
+        if checked_subtotal(&self.items).is_none() {
+            self.items.pop();
+            return Err(OrderStoreError::InvalidQuantity(quantity));
+        }
+        self.recalculate();
+        Ok(item)
+    }
+
+    /// Checks there is an item at position `index` and, when a product is `expected` there, that
+    /// it is that product.
+    fn expect_item(&self, index: usize, expected: Option<Uuid>) -> Result<(), OrderStoreError> {
+        let item = self
+            .items
+            .get(index)
+            .ok_or(OrderStoreError::ItemIndexOutOfBounds(index))?;
+        match expected {
+            Some(product_id) if product_id != item.product_id => {
+                Err(OrderStoreError::ItemChanged(index, product_id))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Removes the item at position `index` and updates the order totals.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`OrderNotEditable`](OrderStoreError::OrderNotEditable) if the order is no longer a draft.
+    ///
+    /// Returns [`ItemIndexOutOfBounds`](OrderStoreError::ItemIndexOutOfBounds) if the item index doesn't exist in the order.
+    ///
+    /// Returns [`ItemChanged`](OrderStoreError::ItemChanged) if the item is not the `expected` product.
+    pub fn delete_item(
+        &mut self,
+        index: usize,
+        expected: Option<Uuid>,
+    ) -> Result<Item, OrderStoreError> {
+        self.ensure_editable()?;
+        self.expect_item(index, expected)?;
+        let item = self.items.remove(index);
+        self.recalculate();
+        Ok(item)
+    }
+
+    /// Sets the quantity of the item at position `index` and updates the order totals. Returns the
+    /// quantity the item had before.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`OrderNotEditable`](OrderStoreError::OrderNotEditable) if the order is no longer a draft.
+    ///
+    /// Returns [`InvalidQuantity`](OrderStoreError::InvalidQuantity) if `quantity` is not positive
+    /// or makes the order total too large to be represented.
+    ///
+    /// Returns [`ItemIndexOutOfBounds`](OrderStoreError::ItemIndexOutOfBounds) if the item index doesn't exist in the order.
+    ///
+    /// Returns [`ItemChanged`](OrderStoreError::ItemChanged) if the item is not the `expected` product.
+    pub fn set_item_quantity(
+        &mut self,
+        index: usize,
+        quantity: i32,
+        expected: Option<Uuid>,
+    ) -> Result<i32, OrderStoreError> {
+        self.ensure_editable()?;
+        if quantity <= 0 {
+            return Err(OrderStoreError::InvalidQuantity(quantity));
+        }
+        self.expect_item(index, expected)?;
+        let item = &mut self.items[index];
+        let previous = item.quantity;
+        item.set_quantity(quantity)?;
+        if checked_subtotal(&self.items).is_none() {
+            self.items[index].set_quantity(previous)?;
+            return Err(OrderStoreError::InvalidQuantity(quantity));
+        }
+        self.recalculate();
+        Ok(previous)
+    }
+
+    /// Applies `coupon` to the order and updates its totals, but for its taxes.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`OrderNotEditable`](OrderStoreError::OrderNotEditable) if the order is no longer a draft.
+    ///
+    /// Returns [`CouponAlreadyApplied`](OrderStoreError::CouponAlreadyApplied) if the order already uses the coupon.
+    pub fn apply_coupon(&mut self, coupon: Coupon) -> Result<(), OrderStoreError> {
+        self.ensure_editable()?;
+        if self
+            .coupons
+            .iter()
+            .any(|applied| applied.code == coupon.code)
+        {
+            return Err(OrderStoreError::CouponAlreadyApplied(coupon.code));
+        }
+        self.coupons.push(coupon);
+        self.recalculate();
+        Ok(())
+    }
+
+    /// Removes the coupon with code `code` from the order and updates its totals, but for its taxes.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`OrderNotEditable`](OrderStoreError::OrderNotEditable) if the order is no longer a draft.
+    ///
+    /// Returns [`CouponNotFound`](OrderStoreError::CouponNotFound) if the coupon is not applied to the order.
+    pub fn remove_coupon(&mut self, code: &str) -> Result<Coupon, OrderStoreError> {
+        self.ensure_editable()?;
+        match self.coupons.iter().position(|applied| applied.code == code) {
+            Some(index) => {
+                let coupon = self.coupons.remove(index);
+                self.recalculate();
+                Ok(coupon)
+            }
+            None => Err(OrderStoreError::CouponNotFound(code.to_string())),
+        }
+    }
+
+    /// Moves the order to `status`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`InvalidStatusTransition`](OrderStoreError::InvalidStatusTransition) if the order
+    /// cannot go from its current status to `status`.
+    pub fn set_status(&mut self, status: OrderStatus) -> Result<(), OrderStoreError> {
+        if self.pending_payment().is_some() {
+            return Err(OrderStoreError::OrderNotEditable(self.id));
+        }
+        if self.status.can_transition_to(status) {
+            self.status = status;
+            Ok(())
+        } else {
+            Err(OrderStoreError::InvalidStatusTransition(
+                self.status,
+                status,
+            ))
+        }
+    }
+
+    /// Ships the order to `address` with `method` and updates its totals.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`OrderNotEditable`](OrderStoreError::OrderNotEditable) if the order is no longer a draft.
+    ///
+    /// Returns [`InvalidAddress`](OrderStoreError::InvalidAddress) if a required field of `address` is missing.
+    pub fn set_shipping(
+        &mut self,
+        address: Address,
+        method: ShippingMethod,
+    ) -> Result<(), OrderStoreError> {
+        self.ensure_editable()?;
+        validate(&address)?;
+        self.shipping_address = Some(address);
+        self.shipping_method = Some(method);
+        self.shipping_cost = method.cost();
+        self.recalculate();
+        Ok(())
+    }
+
+    /// Bills the order to `address`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`OrderNotEditable`](OrderStoreError::OrderNotEditable) if the order is no longer a draft.
+    ///
+    /// Returns [`InvalidAddress`](OrderStoreError::InvalidAddress) if a required field of `address` is missing.
+    pub fn set_billing_address(&mut self, address: Address) -> Result<(), OrderStoreError> {
+        self.ensure_editable()?;
+        validate(&address)?;
+        self.billing_address = Some(address);
+        Ok(())
+    }
+
+    /// Checks that the order can be paid and returns the charge paying for it with `payment_token`.
+    /// While an attempt is pending the charge reuses its id, so the provider recognizes it as a
+    /// retry of the same charge.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`OrderNotEditable`](OrderStoreError::OrderNotEditable) if the order is no longer a draft.
+    ///
+    /// Returns [`NotReadyForCheckout`](OrderStoreError::NotReadyForCheckout) if the order is empty,
+    /// has nothing to pay or has no shipping address.
+    pub fn checkout_charge(&self, payment_token: &str) -> Result<Charge, OrderStoreError> {
+        if self.status != OrderStatus::Draft {
+            return Err(OrderStoreError::OrderNotEditable(self.id));
+        }
+        let not_ready =
+            |reason: &str| Err(OrderStoreError::NotReadyForCheckout(reason.to_string()));
+        let Some(currency) = &self.currency else {
+            return not_ready("the order has no items");
+        };
+        if self.total <= Decimal::ZERO {
+            return not_ready("the order has nothing to pay");
+        }
+        if self.shipping_address.is_none() {
+            return not_ready("the order has no shipping address");
+        }
+        Ok(Charge {
+            attempt_id: self
+                .pending_payment()
+                .map_or_else(Uuid::new_v4, |attempt| attempt.id),
+            order_id: self.id,
+            amount: self.total,
+            currency: currency.clone(),
+            payment_token: payment_token.to_string(),
+        })
+    }
+
+    /// Records `charge` as a [`Pending`](PaymentOutcome::Pending) attempt before it is sent to
+    /// the provider and returns it, `None` when the attempt is already recorded.
+    pub fn start_payment(&mut self, charge: &Charge) -> Option<PaymentAttempt> {
+        if self
+            .payments
+            .iter()
+            .any(|attempt| attempt.id == charge.attempt_id)
+        {
+            return None;
+        }
+        let attempt = PaymentAttempt {
+            id: charge.attempt_id,
+            amount: charge.amount,
+            currency: charge.currency.clone(),
+            attempted_at: Utc::now(),
+            outcome: PaymentOutcome::Pending,
+        };
+        self.payments.push(attempt.clone());
+        Some(attempt)
+    }
+
+    /// Records the `outcome` of paying `charge`, moving the order to [`Paid`](OrderStatus::Paid)
+    /// when it was approved. Returns the attempt and whether its outcome was recorded now; it is
+    /// not when a concurrent checkout retrying the same attempt recorded it first.
+    pub fn record_payment(
+        &mut self,
+        charge: &Charge,
+        outcome: PaymentOutcome,
+    ) -> (PaymentAttempt, bool) {
+        let index = match self
+            .payments
+            .iter()
+            .position(|attempt| attempt.id == charge.attempt_id)
+        {
+            Some(index) if self.payments[index].outcome != PaymentOutcome::Pending => {
+                return (self.payments[index].clone(), false);
+            }
+            Some(index) => index,
+            None => {
+                self.start_payment(charge);
+                self.payments.len() - 1
+            }
+        };
+        if matches!(outcome, PaymentOutcome::Approved { .. }) {
+            self.status = OrderStatus::Paid;
+        }
+        let attempt = &mut self.payments[index];
+        attempt.attempted_at = Utc::now();
+        attempt.outcome = outcome;
+        (attempt.clone(), true)
+    }
+
+    /// Records a request to return `items` for `reason` and returns it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`OrderNotDelivered`](OrderStoreError::OrderNotDelivered) if the order was not delivered.
+    ///
+    /// Returns [`ItemIndexOutOfBounds`](OrderStoreError::ItemIndexOutOfBounds) if an item index doesn't exist in the order.
+    ///
+    /// Returns [`InvalidQuantity`](OrderStoreError::InvalidQuantity) if a quantity is not positive.
+    ///
+    /// Returns [`InvalidReturn`](OrderStoreError::InvalidReturn) if no items are returned, or more
+    /// units than were bought and not returned yet.
+    pub fn request_return(
+        &mut self,
+        items: &[ReturnItem],
+        reason: String,
+    ) -> Result<ReturnRequest, OrderStoreError> {
+        if self.status != OrderStatus::Delivered {
+            return Err(OrderStoreError::OrderNotDelivered(self.id));
+        }
+        if items.is_empty() {
+            return Err(OrderStoreError::InvalidReturn(
+                "no items to return".to_string(),
+            ));
+        }
+        let mut lines: Vec<ReturnLine> = vec![];
+        for item in items {
+            let Some(bought) = self.items.get(item.index) else {
+                return Err(OrderStoreError::ItemIndexOutOfBounds(item.index));
+            };
+            if item.quantity <= 0 {
+                return Err(OrderStoreError::InvalidQuantity(item.quantity));
+            }
+            let returning: i32 = self
+                .returns
+                .iter()
+                .filter(|request| request.status != ReturnStatus::Rejected)
+                .flat_map(|request| &request.lines)
+                .chain(&lines)
+                .filter(|line| line.item_index == item.index)
+                .map(|line| line.quantity)
+                .sum();
+            if returning + item.quantity > bought.quantity {
+                return Err(OrderStoreError::InvalidReturn(format!(
+                    "only {} units of item {} can be returned",
+                    bought.quantity - returning,
+                    item.index
+                )));
+            }
+            lines.push(ReturnLine {
+                item_index: item.index,
+                product_id: bought.product_id,
+                quantity: item.quantity,
+                refund_amount: returns::refund_amount(self, item.index, item.quantity),
+            });
+        }
+        let request = ReturnRequest {
+            id: Uuid::new_v4(),
+            refund_amount: lines.iter().map(|line| line.refund_amount).sum(),
+            lines,
+            reason,
+            status: ReturnStatus::Requested,
+            requested_at: Utc::now(),
+            decided_at: None,
+            refund_reference: None,
+        };
+        self.returns.push(request.clone());
+        Ok(request)
+    }
+
+    /// What is left to refund of the approved payments of the order.
+    pub fn refundable(&self) -> Decimal {
+        let paid: Decimal = self
+            .payments
+            .iter()
+            .filter(|attempt| matches!(attempt.outcome, PaymentOutcome::Approved { .. }))
+            .map(|attempt| attempt.amount)
+            .sum();
+        (paid - self.refunded).max(Decimal::ZERO)
+    }
+
+    /// Checks that the return with id `return_id` can be approved and returns the refund to issue
+    /// for it, `None` when the order was never paid or nothing is left to refund. The refund is
+    /// identified by the id of the return, so issuing it again is a retry of the same refund.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ReturnNotFound`](OrderStoreError::ReturnNotFound) if the order has no such return.
+    ///
+    /// Returns [`ReturnAlreadyDecided`](OrderStoreError::ReturnAlreadyDecided) if the return was already approved or rejected.
+    pub fn return_refund(&self, return_id: Uuid) -> Result<Option<Refund>, OrderStoreError> {
+        let request = self.pending_return(return_id)?;
+        let payment_reference = self
+            .payments
+            .iter()
+            .find_map(|attempt| match &attempt.outcome {
+                PaymentOutcome::Approved { reference } => Some(reference.clone()),
+                _ => None,
+            });
+        let amount = request.refund_amount.min(self.refundable());
+        Ok(payment_reference
+            .filter(|_| amount > Decimal::ZERO)
+            .map(|payment_reference| Refund {
+                refund_id: request.id,
+                payment_reference,
+                amount,
+                currency: self.currency.clone().unwrap_or_default(),
+            }))
+    }
+
+    /// Approves the return with id `return_id`, refunded with `refund_reference`, and returns it.
+    /// Its refund amount is capped at what is left to refund of the order.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ReturnNotFound`](OrderStoreError::ReturnNotFound) if the order has no such return.
+    ///
+    /// Returns [`ReturnAlreadyDecided`](OrderStoreError::ReturnAlreadyDecided) if the return was already approved or rejected.
+    pub fn approve_return(
+        &mut self,
+        return_id: Uuid,
+        refund_reference: Option<String>,
+    ) -> Result<ReturnRequest, OrderStoreError> {
+        self.pending_return(return_id)?;
+        let refundable = self.refundable();
+        let request = self.decide_return(return_id, ReturnStatus::Approved);
+        request.refund_reference = refund_reference;
+        request.refund_amount = request.refund_amount.min(refundable);
+        let request = request.clone();
+        self.refunded += request.refund_amount;
+        Ok(request)
+    }
+
+    /// Rejects the return with id `return_id` and returns it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ReturnNotFound`](OrderStoreError::ReturnNotFound) if the order has no such return.
+    ///
+    /// Returns [`ReturnAlreadyDecided`](OrderStoreError::ReturnAlreadyDecided) if the return was already approved or rejected.
+    pub fn reject_return(&mut self, return_id: Uuid) -> Result<ReturnRequest, OrderStoreError> {
+        self.pending_return(return_id)?;
+        Ok(self
+            .decide_return(return_id, ReturnStatus::Rejected)
+            .clone())
+    }
+
+    fn pending_return(&self, return_id: Uuid) -> Result<&ReturnRequest, OrderStoreError> {
+        match self.returns.iter().find(|request| request.id == return_id) {
+            Some(request) if request.status == ReturnStatus::Requested => Ok(request),
+            Some(_) => Err(OrderStoreError::ReturnAlreadyDecided(return_id)),
+            None => Err(OrderStoreError::ReturnNotFound(return_id)),
+        }
+    }
+
+    fn decide_return(&mut self, return_id: Uuid, status: ReturnStatus) -> &mut ReturnRequest {
+        let request = self
+            .returns
+            .iter_mut()
+            .find(|request| request.id == return_id)
+            .expect("return checked by pending_return");
+        request.status = status;
+        request.decided_at = Some(Utc::now());
+        request
+    }
+
+    /// Region whose tax rates apply to the order, `None` to use the calculator's default.
+    pub fn tax_region(&self) -> Option<String> {
+        self.shipping_address.as_ref().map(Address::tax_region)
+    }
+
+    /// Replaces the tax breakdown of the order and updates its total.
+    pub fn set_tax(&mut self, tax: TaxBreakdown) {
+        self.tax = tax;
+        self.recalculate();
+    }
+
+    /// The payment attempt of the order that was sent to the provider and whose outcome is not
+    /// recorded yet, if any.
+    pub fn pending_payment(&self) -> Option<&PaymentAttempt> {
+        self.payments
+            .iter()
+            .find(|attempt| attempt.outcome == PaymentOutcome::Pending)
+    }
+
+    /// A draft can be changed unless a payment is pending: what was charged must stay what is ordered.
+    fn ensure_editable(&self) -> Result<(), OrderStoreError> {
+        if self.status == OrderStatus::Draft && self.pending_payment().is_none() {
+            Ok(())
+        } else {
+            Err(OrderStoreError::OrderNotEditable(self.id))
+        }
+    }
+
+    /// Returns the total of each item once the discount of the order is spread across them in
+    /// proportion to their totals, rounded to two decimal places. The last item takes the
+    /// rounding difference, so the discounted totals add up to the subtotal minus the discount.
+    pub fn discounted_line_totals(&self) -> Vec<Decimal> {
+        let mut remaining = self.discount;
+        let last = self.items.len().saturating_sub(1);
+        self.items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| {
+                let share = if index == last || self.subtotal.is_zero() {
+                    remaining
+                } else {
+                    (self.discount * (item.line_total / self.subtotal))
+                        .round_dp(2)
+                        .min(remaining)
+                };
+                remaining -= share;
+                item.line_total - share
+            })
+            .collect()
+    }
+
+    /// Recomputes the currency, subtotal, discount and total of the order from its items, coupons,
+    /// tax breakdown and shipping cost. Tax is only added to the total when it is not already part
+    /// of the prices. Amounts too large to be represented saturate instead of panicking.
+    pub fn recalculate(&mut self) {
+        self.currency = self.items.first().map(|item| item.currency.clone());
+        self.subtotal = checked_subtotal(&self.items).unwrap_or(Decimal::MAX);
+        self.discount = promotions::total_discount(&self.coupons, &self.items, self.subtotal);
+        self.total = (self.subtotal - self.discount).saturating_add(self.shipping_cost);
+        if !self.tax.inclusive {
+            self.total = self.total.saturating_add(self.tax.total);
         }
     }
 }
 
+fn validate(address: &Address) -> Result<(), OrderStoreError> {
+    match address.invalid_field() {
+        Some(field) => Err(OrderStoreError::InvalidAddress(field.to_string())),
+        None => Ok(()),
+    }
+}
+
+/// Error of the storage behind a store, kept as the [`source`](Error::source) of an [`OrderStoreError`](OrderStoreError).
+pub type StoreErrorSource = Box<dyn Error + Send + Sync>;
+
 /// Type fos describing errors that result from trying to interact with an [`OrderStore`](OrderStore).
 #[derive(Debug)]
 pub enum OrderStoreError {
     /// The store is unavailable.
-    #[allow(dead_code)]
     StoreUnavailable,
+    /// The storage cannot be reached.
+    StoreConnection(StoreErrorSource),
+    /// The storage did not answer in time.
+    StoreTimeout(StoreErrorSource),
+    /// The storage is not configured properly, e.g. its connection string or credentials are wrong.
+    StoreMisconfigured(StoreErrorSource),
+    /// A stored document already has the same value of a unique field.
+    DuplicateKey(StoreErrorSource),
+    /// A document cannot be converted to or from the shape it is stored in.
+    StoreSerialization(StoreErrorSource),
+    /// Another transaction changed the same documents at the same time; the change can be retried.
+    WriteConflict(StoreErrorSource),
+    /// Any other failure of the storage.
+    StoreFailure(StoreErrorSource),
     /// Provided order id was not found in the store.
     OrderNotFound(Uuid),
     /// Provided item index is out of bounds for the provided order.
     ItemIndexOutOfBounds(usize),
+    /// The item at the provided index is no longer the provided product, e.g. because another
+    /// client removed an item before it.
+    ItemChanged(usize, Uuid),
+    /// Provided product id was not found in the catalog.
+    ProductNotFound(Uuid),
+    /// Provided product exists but is not active, so it cannot be ordered.
+    ProductInactive(Uuid),
+    /// Provided product is priced in a currency different from the order's one.
+    CurrencyMismatch(String),
+    /// Provided quantity is not a positive number.
+    InvalidQuantity(i32),
+    /// Provided price is zero or negative.
+    InvalidPrice(Decimal),
+    /// There is not enough stock of the provided product.
+    InsufficientStock(Uuid),
+    /// The order is no longer a draft, or a payment of it is pending, so its items cannot change.
+    OrderNotEditable(Uuid),
+    /// The order cannot move from the first status to the second one.
+    InvalidStatusTransition(OrderStatus, OrderStatus),
+    /// Provided coupon code does not exist, or is not applied to the order.
+    CouponNotFound(String),
+    /// Provided coupon is outside its validity window.
+    CouponExpired(String),
+    /// The user already used the provided coupon as many times as allowed.
+    CouponUsageLimitReached(String),
+    /// Provided coupon is already applied to the order.
+    CouponAlreadyApplied(String),
+    /// Taxes cannot be calculated right now.
+    TaxUnavailable,
+    /// There is no tax rate for the provided region and tax category.
+    NoTaxRate(String, String),
+    /// The provided field of an address is missing or malformed.
+    InvalidAddress(String),
+    /// The order cannot be paid yet, for the provided reason.
+    NotReadyForCheckout(String),
+    /// The payment provider cannot be reached; failed checkout attempts are recorded on the order.
+    PaymentUnavailable,
+    /// The order was not delivered, so its items cannot be returned.
+    OrderNotDelivered(Uuid),
+    /// Provided return id was not found in the order.
+    ReturnNotFound(Uuid),
+    /// Provided return was already approved or rejected.
+    ReturnAlreadyDecided(Uuid),
+    /// The requested return is not acceptable, for the provided reason.
+    InvalidReturn(String),
+    /// The order was changed by someone else while being changed; the change can be retried.
+    ConcurrentModification(Uuid),
 }
 
+/// The message reaches clients, so errors of the storage leave out their [`source`](Error::source),
+/// which may name servers, collections or the stored values; [`detailed`](OrderStoreError::detailed)
+/// adds it for logs.
 impl Display for OrderStoreError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             OrderStoreError::StoreUnavailable => {
                 write!(f, "Store unavailable")
             }
+            OrderStoreError::StoreConnection(_) => {
+                write!(f, "Store unreachable")
+            }
+            OrderStoreError::StoreTimeout(_) => {
+                write!(f, "Store timed out")
+            }
+            OrderStoreError::StoreMisconfigured(_) => {
+                write!(f, "Store misconfigured")
+            }
+            OrderStoreError::DuplicateKey(_) => {
+                write!(f, "Duplicate key")
+            }
+            OrderStoreError::StoreSerialization(_) => {
+                write!(f, "Stored document malformed")
+            }
+            OrderStoreError::WriteConflict(_) => {
+                write!(f, "Write conflict")
+            }
+            OrderStoreError::StoreFailure(_) => {
+                write!(f, "Store failed")
+            }
             OrderStoreError::OrderNotFound(id) => {
                 write!(f, "Order not found {}", id)
             }
             OrderStoreError::ItemIndexOutOfBounds(index) => {
                 write!(f, "Item index out of bounds: {}", index)
             }
+            OrderStoreError::ItemChanged(index, product_id) => {
+                write!(f, "Item {} is no longer product {}", index, product_id)
+            }
+            OrderStoreError::ProductNotFound(id) => {
+                write!(f, "Product not found {}", id)
+            }
+            OrderStoreError::ProductInactive(id) => {
+                write!(f, "Product inactive {}", id)
+            }
+            OrderStoreError::CurrencyMismatch(currency) => {
+                write!(f, "Currency mismatch: {}", currency)
+            }
+            OrderStoreError::InvalidQuantity(quantity) => {
+                write!(f, "Invalid quantity: {}", quantity)
+            }
+            OrderStoreError::InvalidPrice(price) => {
+                write!(f, "Invalid price: {}", price)
+            }
+            OrderStoreError::InsufficientStock(id) => {
+                write!(f, "Insufficient stock for product {}", id)
+            }
+            OrderStoreError::OrderNotEditable(id) => {
+                write!(f, "Order not editable {}", id)
+            }
+            OrderStoreError::InvalidStatusTransition(from, to) => {
+                write!(f, "Invalid status transition from {} to {}", from, to)
+            }
+            OrderStoreError::CouponNotFound(code) => {
+                write!(f, "Coupon not found {}", code)
+            }
+            OrderStoreError::CouponExpired(code) => {
+                write!(f, "Coupon expired {}", code)
+            }
+            OrderStoreError::CouponUsageLimitReached(code) => {
+                write!(f, "Usage limit reached for coupon {}", code)
+            }
+            OrderStoreError::CouponAlreadyApplied(code) => {
+                write!(f, "Coupon already applied {}", code)
+            }
+            OrderStoreError::TaxUnavailable => {
+                write!(f, "Tax calculation unavailable")
+            }
+            OrderStoreError::NoTaxRate(region, category) => {
+                write!(
+                    f,
+                    "No tax rate for region {} and category {}",
+                    region, category
+                )
+            }
+            OrderStoreError::InvalidAddress(field) => {
+                write!(f, "Invalid address field: {}", field)
+            }
+            OrderStoreError::NotReadyForCheckout(reason) => {
+                write!(f, "Order not ready for checkout: {}", reason)
+            }
+            OrderStoreError::PaymentUnavailable => {
+                write!(f, "Payment provider unavailable")
+            }
+            OrderStoreError::OrderNotDelivered(id) => {
+                write!(f, "Order not delivered {}", id)
+            }
+            OrderStoreError::ReturnNotFound(id) => {
+                write!(f, "Return not found {}", id)
+            }
+            OrderStoreError::ReturnAlreadyDecided(id) => {
+                write!(f, "Return already decided {}", id)
+            }
+            OrderStoreError::InvalidReturn(reason) => {
+                write!(f, "Invalid return: {}", reason)
+            }
+            OrderStoreError::ConcurrentModification(id) => {
+                write!(f, "Concurrent modification of order {}", id)
+            }
+        }
+    }
+}
+
+impl OrderStoreError {
+    /// The message followed by the error of the storage behind it, for logs only.
+    pub fn detailed(&self) -> String {
+        match self.source() {
+            Some(source) => format!("{}: {}", self, source),
+            None => self.to_string(),
+        }
+    }
+
+    /// Whether the storage may answer if the call is made again a bit later.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            OrderStoreError::StoreUnavailable
+                | OrderStoreError::StoreConnection(_)
+                | OrderStoreError::StoreTimeout(_)
+        )
+    }
+}
+
+impl Error for OrderStoreError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            OrderStoreError::StoreConnection(err)
+            | OrderStoreError::StoreTimeout(err)
+            | OrderStoreError::StoreMisconfigured(err)
+            | OrderStoreError::DuplicateKey(err)
+            | OrderStoreError::StoreSerialization(err)
+            | OrderStoreError::WriteConflict(err)
+            | OrderStoreError::StoreFailure(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<ProductStoreError> for OrderStoreError {
+    fn from(err: ProductStoreError) -> Self {
+        match err {
+            ProductStoreError::ProductNotFound(id) => OrderStoreError::ProductNotFound(id),
+            ProductStoreError::StoreUnavailable => OrderStoreError::StoreUnavailable,
+            ProductStoreError::DuplicateSku(sku) => {
+                OrderStoreError::DuplicateKey(format!("SKU {} is already used", sku).into())
+            }
+            ProductStoreError::InvalidPrice(price) => OrderStoreError::InvalidPrice(price),
+        }
+    }
+}
+
+impl From<InventoryStoreError> for OrderStoreError {
+    fn from(err: InventoryStoreError) -> Self {
+        match err {
+            InventoryStoreError::InsufficientStock(id) => OrderStoreError::InsufficientStock(id),
+            InventoryStoreError::StoreUnavailable => OrderStoreError::StoreUnavailable,
+        }
+    }
+}
+
+impl From<PromotionStoreError> for OrderStoreError {
+    fn from(err: PromotionStoreError) -> Self {
+        match err {
+            PromotionStoreError::CouponNotFound(code) => OrderStoreError::CouponNotFound(code),
+            PromotionStoreError::UsageLimitReached(code) => {
+                OrderStoreError::CouponUsageLimitReached(code)
+            }
+            PromotionStoreError::StoreUnavailable
+            | PromotionStoreError::DuplicateCoupon(_)
+            | PromotionStoreError::InvalidRule(_) => OrderStoreError::StoreUnavailable,
         }
     }
 }
 
-impl Error for OrderStoreError {}
+impl From<TaxError> for OrderStoreError {
+    fn from(err: TaxError) -> Self {
+        match err {
+            TaxError::ProviderUnavailable => OrderStoreError::TaxUnavailable,
+            TaxError::NoRate(region, category) => OrderStoreError::NoTaxRate(region, category),
+        }
+    }
+}
+
+/// Recalculates the taxes of `order` with `calculator`. Stores call it whenever the items or
+/// the tax region of an order change.
+///
+/// # Errors
+///
+/// Returns [`TaxUnavailable`](OrderStoreError::TaxUnavailable) if the calculator cannot be used.
+///
+/// Returns [`NoTaxRate`](OrderStoreError::NoTaxRate) if an item has no applicable rate.
+pub async fn update_taxes(
+    order: &mut Order,
+    calculator: &dyn TaxCalculator,
+) -> Result<(), OrderStoreError> {
+    let tax = calculator.calculate(order).await?;
+    order.set_tax(tax);
+    Ok(())
+}
+
+/// Commits the stock reserved for the items of `order` when it moves to `status`
+/// [`Placed`](OrderStatus::Placed), or releases it when it moves to
+/// [`Cancelled`](OrderStatus::Cancelled). Either the stock of every item is moved or, when one
+/// cannot be, the items already moved are put back and none is.
+///
+/// # Errors
+///
+/// Returns [`InsufficientStock`](OrderStoreError::InsufficientStock) if the stock reserved for an
+/// item is missing.
+pub async fn move_order_stock(
+    inventory: &InventoryStoreNewType,
+    order: &Order,
+    status: OrderStatus,
+) -> Result<(), OrderStoreError> {
+    for (moved, item) in order.items.iter().enumerate() {
+        let result = match status {
+            OrderStatus::Placed => inventory.commit(item.product_id, item.quantity).await,
+            OrderStatus::Cancelled => inventory.release(item.product_id, item.quantity).await,
+            OrderStatus::Draft
+            | OrderStatus::Paid
+            | OrderStatus::Shipped
+            | OrderStatus::Delivered => Ok(()),
+        };
+        if let Err(err) = result {
+            for item in &order.items[..moved] {
+                if let Err(undo_err) = unmove_stock(inventory, item, status).await {
+                    error!(
+                        "cannot put back the stock of product {} of order {}: {}",
+                        item.product_id, order.id, undo_err
+                    );
+                }
+            }
+            return Err(err.into());
+        }
+    }
+    Ok(())
+}
+
+/// Puts the units of `request` back into the available stock. Either every line is restocked or,
+/// when one cannot be, the lines already restocked are committed again and none is.
+///
+/// # Errors
+///
+/// Returns [`InsufficientStock`](OrderStoreError::InsufficientStock) if fewer units of a product
+/// are committed than returned.
+pub async fn restock_return(
+    inventory: &InventoryStoreNewType,
+    request: &ReturnRequest,
+) -> Result<(), OrderStoreError> {
+    for (restocked, line) in request.lines.iter().enumerate() {
+        if let Err(err) = inventory.restock(line.product_id, line.quantity).await {
+            for line in &request.lines[..restocked] {
+                if let Err(undo_err) = unrestock_line(inventory, line).await {
+                    error!(
+                        "cannot commit again the stock of product {} of return {}: {}",
+                        line.product_id, request.id, undo_err
+                    );
+                }
+            }
+            return Err(err.into());
+        }
+    }
+    Ok(())
+}
+
+/// Undoes [`restock_return`], committing again the units of every line of `request`.
+///
+/// # Errors
+///
+/// Returns [`InsufficientStock`](InventoryStoreError::InsufficientStock) if the units are no
+/// longer available.
+pub async fn unrestock_return(
+    inventory: &InventoryStoreNewType,
+    request: &ReturnRequest,
+) -> Result<(), InventoryStoreError> {
+    for line in &request.lines {
+        unrestock_line(inventory, line).await?;
+    }
+    Ok(())
+}
+
+/// Commits again the units of `line` restocked by [`restock_return`].
+async fn unrestock_line(
+    inventory: &InventoryStoreNewType,
+    line: &ReturnLine,
+) -> Result<(), InventoryStoreError> {
+    inventory.reserve(line.product_id, line.quantity).await?;
+    inventory.commit(line.product_id, line.quantity).await
+}
+
+/// Undoes [`move_order_stock`], reserving again the stock of every item of `order`.
+///
+/// # Errors
+///
+/// Returns [`InsufficientStock`](InventoryStoreError::InsufficientStock) if the stock of an item
+/// is no longer available.
+pub async fn unmove_order_stock(
+    inventory: &InventoryStoreNewType,
+    order: &Order,
+    status: OrderStatus,
+) -> Result<(), InventoryStoreError> {
+    if matches!(status, OrderStatus::Placed | OrderStatus::Cancelled) {
+        for item in &order.items {
+            unmove_stock(inventory, item, status).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Reserves again the stock of `item` moved by [`move_order_stock`].
+async fn unmove_stock(
+    inventory: &InventoryStoreNewType,
+    item: &Item,
+    status: OrderStatus,
+) -> Result<(), InventoryStoreError> {
+    if status == OrderStatus::Placed {
+        inventory.restock(item.product_id, item.quantity).await?;
+    }
+    inventory.reserve(item.product_id, item.quantity).await
+}
+
+/// Sends `charge` to `provider`. An unreachable provider is a [`Failed`](PaymentOutcome::Failed)
+/// outcome.
+///
+/// Stores record the charge as a pending attempt of the order before calling this, and its
+/// outcome after: when the outcome cannot be recorded, the next checkout sends the same attempt
+/// again, which the provider doesn't charge twice.
+pub async fn pay(provider: &dyn PaymentProvider, charge: &Charge) -> PaymentOutcome {
+    provider
+        .charge(charge)
+        .await
+        .unwrap_or(PaymentOutcome::Failed)
+}
+
+/// Gives back `charge`, approved as `attempt`, when the stock of its order cannot be committed,
+/// so the customer doesn't pay for an order that is not placed. The refund is identified by the
+/// id of the attempt. Returns whether the charge was given back and the attempt can be recorded
+/// as [`Failed`](PaymentOutcome::Failed); when it was not, the attempt stays pending and the next
+/// checkout sends it again, which the provider doesn't charge twice.
+pub async fn void_charge(
+    provider: &dyn PaymentProvider,
+    charge: &Charge,
+    attempt: &PaymentAttempt,
+) -> bool {
+    let PaymentOutcome::Approved { reference } = &attempt.outcome else {
+        return false;
+    };
+    let refund = Refund {
+        refund_id: attempt.id,
+        payment_reference: reference.clone(),
+        amount: attempt.amount,
+        currency: attempt.currency.clone(),
+    };
+    match provider.refund(&refund).await {
+        Ok(_) => true,
+        Err(err) => {
+            error!(
+                "cannot give back charge {} of order {}, its attempt stays pending: {}",
+                reference, charge.order_id, err
+            );
+            false
+        }
+    }
+}
+
+/// Returns `attempt` as the result of a checkout.
+///
+/// # Errors
+///
+/// Returns [`PaymentUnavailable`](OrderStoreError::PaymentUnavailable) if the provider could not
+/// be reached.
+pub fn payment_result(attempt: PaymentAttempt) -> Result<PaymentAttempt, OrderStoreError> {
+    match attempt.outcome {
+        PaymentOutcome::Failed => Err(OrderStoreError::PaymentUnavailable),
+        _ => Ok(attempt),
+    }
+}
+
+/// Refunds the return with id `return_id` of `order` with `provider`, when the order was paid,
+/// and approves it. The refund is identified by `return_id`: when the approval cannot be saved,
+/// approving the return again retries the same refund, which the provider doesn't pay twice.
+///
+/// # Errors
+///
+/// Returns [`ReturnNotFound`](OrderStoreError::ReturnNotFound) or
+/// [`ReturnAlreadyDecided`](OrderStoreError::ReturnAlreadyDecided) if the return cannot be approved.
+///
+/// Returns [`PaymentUnavailable`](OrderStoreError::PaymentUnavailable) if the refund cannot be
+/// issued, in which case the order is left untouched.
+pub async fn refund(
+    order: &mut Order,
+    provider: &dyn PaymentProvider,
+    return_id: Uuid,
+) -> Result<ReturnRequest, OrderStoreError> {
+    let reference = pay_refund(order, provider, return_id).await?;
+    order.approve_return(return_id, reference)
+}
+
+/// Pays the refund of the return with id `return_id` of `order` with `provider`, when the order
+/// was paid, and returns its provider reference. The refund is identified by `return_id`, so
+/// paying it again after the approval could not be saved doesn't refund it twice.
+///
+/// # Errors
+///
+/// Returns [`ReturnNotFound`](OrderStoreError::ReturnNotFound) or
+/// [`ReturnAlreadyDecided`](OrderStoreError::ReturnAlreadyDecided) if the return cannot be approved.
+///
+/// Returns [`PaymentUnavailable`](OrderStoreError::PaymentUnavailable) if the refund cannot be
+/// issued.
+pub async fn pay_refund(
+    order: &Order,
+    provider: &dyn PaymentProvider,
+    return_id: Uuid,
+) -> Result<Option<String>, OrderStoreError> {
+    match order.return_refund(return_id)? {
+        Some(refund) => provider
+            .refund(&refund)
+            .await
+            .map(Some)
+            .map_err(|_| OrderStoreError::PaymentUnavailable),
+        None => Ok(None),
+    }
+}
+
+/// Looks up `code` in the promotions and checks that the coupon can be applied right now.
+///
+/// # Errors
+///
+/// Returns [`CouponNotFound`](OrderStoreError::CouponNotFound) if there is no coupon with that code.
+///
+/// Returns [`CouponExpired`](OrderStoreError::CouponExpired) if the coupon is outside its validity window.
+pub async fn find_applicable_coupon(
+    promotions: &PromotionStoreNewType,
+    code: &str,
+) -> Result<Coupon, OrderStoreError> {
+    let coupon = promotions.get_coupon(code).await?;
+    if coupon.is_valid_at(Utc::now()) {
+        Ok(coupon)
+    } else {
+        Err(OrderStoreError::CouponExpired(code.to_string()))
+    }
+}
+
+/// Looks up `product_id` in the catalog and checks that it can be added to an order.
+///
+/// # Errors
+///
+/// Returns [`ProductNotFound`](OrderStoreError::ProductNotFound) if the product is not in the catalog.
+///
+/// Returns [`ProductInactive`](OrderStoreError::ProductInactive) if the product is not active.
+pub async fn find_orderable_product(
+    products: &ProductStoreNewType,
+    product_id: Uuid,
+) -> Result<Product, OrderStoreError> {
+    let product = products.get_product(product_id).await?;
+    if product.active {
+        Ok(product)
+    } else {
+        Err(OrderStoreError::ProductInactive(product_id))
+    }
+}
 
 /// A trait that defines the behavior of a type used to store orders.
 #[async_trait::async_trait]
@@ -117,7 +1318,7 @@ pub trait OrderStore: Send + Sync + 'static {
     /// Returns [`StoreUnavailable`](OrderStoreError::StoreUnavailable) if the Store cannot be used to create an order.
     async fn list_orders(&self, user_id: Uuid) -> Result<Vec<Order>, OrderStoreError>;
 
-    /// Adds an item to the order with id `order_id`.
+    /// Adds an item to the order with id `order_id`, reserving `quantity` units of the product.
     ///
     /// Returns an empty Ok on success, otherwise it returns an error.
     ///
@@ -126,6 +1327,20 @@ pub trait OrderStore: Send + Sync + 'static {
     /// Returns [`StoreUnavailable`](OrderStoreError::StoreUnavailable) if the Store cannot be used to create an order.
     ///
     /// Returns [`OrderNotFound`](OrderStoreError::OrderNotFound) if there is no order with the provided id in the Store.
+    ///
+    /// Returns [`OrderNotEditable`](OrderStoreError::OrderNotEditable) if the order is no longer a draft.
+    ///
+    /// Returns [`InvalidQuantity`](OrderStoreError::InvalidQuantity) if `quantity` is not positive.
+    ///
+    /// Returns [`InsufficientStock`](OrderStoreError::InsufficientStock) if there are not enough units available.
+    ///
+    /// Returns [`ProductNotFound`](OrderStoreError::ProductNotFound) if the product is not in the catalog.
+    ///
+    /// Returns [`ProductInactive`](OrderStoreError::ProductInactive) if the product is not active.
+    ///
+    /// Returns [`CurrencyMismatch`](OrderStoreError::CurrencyMismatch) if the product is priced in another currency than the order.
+    ///
+    /// Returns [`TaxUnavailable`](OrderStoreError::TaxUnavailable) or [`NoTaxRate`](OrderStoreError::NoTaxRate) if the taxes of the order cannot be recalculated.
     async fn add_item(
         &self,
         order_id: Uuid,
@@ -133,7 +1348,7 @@ pub trait OrderStore: Send + Sync + 'static {
         quantity: i32,
     ) -> Result<(), OrderStoreError>;
 
-    /// Adds an item to the order with id `order_id`.
+    /// Deletes the item at position `index` from the order with id `order_id`, releasing its reserved stock.
     ///
     /// Returns an empty Ok on success, otherwise it returns an error.
     ///
@@ -143,6 +1358,252 @@ pub trait OrderStore: Send + Sync + 'static {
     ///
     /// Returns [`OrderNotFound`](OrderStoreError::OrderNotFound) if there is no order with the provided id in the Store.
     ///
+    /// Returns [`OrderNotEditable`](OrderStoreError::OrderNotEditable) if the order is no longer a draft.
+    ///
     /// Returns [`ItemIndexOutOfBounds`](OrderStoreError::ItemIndexOutOfBounds) if the item index doesn't exist in the order.
-    async fn delete_item(&self, order_id: Uuid, index: usize) -> Result<(), OrderStoreError>;
+    ///
+    /// Returns [`ItemChanged`](OrderStoreError::ItemChanged) if the item is not the `expected`
+    /// product, which clients send when they remove an item they saw at that position.
+    ///
+    /// Returns [`TaxUnavailable`](OrderStoreError::TaxUnavailable) or [`NoTaxRate`](OrderStoreError::NoTaxRate) if the taxes of the order cannot be recalculated.
+    async fn delete_item(
+        &self,
+        order_id: Uuid,
+        index: usize,
+        expected: Option<Uuid>,
+    ) -> Result<(), OrderStoreError>;
+
+    /// Sets the quantity of the item at position `index` of the order with id `order_id`,
+    /// reserving or releasing the difference in stock.
+    ///
+    /// Returns an empty Ok on success, otherwise it returns an error.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](OrderStoreError::StoreUnavailable) if the Store cannot be used to update an order.
+    ///
+    /// Returns [`OrderNotFound`](OrderStoreError::OrderNotFound) if there is no order with the provided id in the Store.
+    ///
+    /// Returns [`OrderNotEditable`](OrderStoreError::OrderNotEditable) if the order is no longer a draft.
+    ///
+    /// Returns [`InvalidQuantity`](OrderStoreError::InvalidQuantity) if `quantity` is not positive.
+    ///
+    /// Returns [`ItemIndexOutOfBounds`](OrderStoreError::ItemIndexOutOfBounds) if the item index doesn't exist in the order.
+    ///
+    /// Returns [`ItemChanged`](OrderStoreError::ItemChanged) if the item is not the `expected`
+    /// product.
+    ///
+    /// Returns [`InsufficientStock`](OrderStoreError::InsufficientStock) if there are not enough units available for the increase.
+    ///
+    /// Returns [`TaxUnavailable`](OrderStoreError::TaxUnavailable) or [`NoTaxRate`](OrderStoreError::NoTaxRate) if the taxes of the order cannot be recalculated.
+    async fn update_item_quantity(
+        &self,
+        order_id: Uuid,
+        index: usize,
+        quantity: i32,
+        expected: Option<Uuid>,
+    ) -> Result<(), OrderStoreError>;
+
+    /// Moves the order with id `order_id` to `status`.
+    ///
+    /// Placing an order commits the stock reserved by its items, cancelling it releases that stock
+    /// and the usages of its coupons.
+    ///
+    /// Returns an empty Ok on success, otherwise it returns an error.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](OrderStoreError::StoreUnavailable) if the Store cannot be used to update an order.
+    ///
+    /// Returns [`OrderNotFound`](OrderStoreError::OrderNotFound) if there is no order with the provided id in the Store.
+    ///
+    /// Returns [`InvalidStatusTransition`](OrderStoreError::InvalidStatusTransition) if the order cannot move to `status`.
+    async fn update_status(
+        &self,
+        order_id: Uuid,
+        status: OrderStatus,
+    ) -> Result<(), OrderStoreError>;
+
+    /// Applies the coupon with code `code` to the order with id `order_id`, counting it as one
+    /// usage by the order's user.
+    ///
+    /// Returns an empty Ok on success, otherwise it returns an error.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](OrderStoreError::StoreUnavailable) if the Store cannot be used to update an order.
+    ///
+    /// Returns [`OrderNotFound`](OrderStoreError::OrderNotFound) if there is no order with the provided id in the Store.
+    ///
+    /// Returns [`OrderNotEditable`](OrderStoreError::OrderNotEditable) if the order is no longer a draft.
+    ///
+    /// Returns [`CouponNotFound`](OrderStoreError::CouponNotFound) if there is no coupon with that code.
+    ///
+    /// Returns [`CouponExpired`](OrderStoreError::CouponExpired) if the coupon is outside its validity window.
+    ///
+    /// Returns [`CouponUsageLimitReached`](OrderStoreError::CouponUsageLimitReached) if the user cannot use the coupon anymore.
+    ///
+    /// Returns [`CouponAlreadyApplied`](OrderStoreError::CouponAlreadyApplied) if the order already uses the coupon.
+    ///
+    /// Returns [`TaxUnavailable`](OrderStoreError::TaxUnavailable) or [`NoTaxRate`](OrderStoreError::NoTaxRate) if the taxes of the order cannot be recalculated.
+    async fn apply_coupon(&self, order_id: Uuid, code: &str) -> Result<(), OrderStoreError>;
+
+    /// Removes the coupon with code `code` from the order with id `order_id`, giving the usage back to the user.
+    ///
+    /// Returns an empty Ok on success, otherwise it returns an error.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](OrderStoreError::StoreUnavailable) if the Store cannot be used to update an order.
+    ///
+    /// Returns [`OrderNotFound`](OrderStoreError::OrderNotFound) if there is no order with the provided id in the Store.
+    ///
+    /// Returns [`OrderNotEditable`](OrderStoreError::OrderNotEditable) if the order is no longer a draft.
+    ///
+    /// Returns [`CouponNotFound`](OrderStoreError::CouponNotFound) if the coupon is not applied to the order.
+    ///
+    /// Returns [`TaxUnavailable`](OrderStoreError::TaxUnavailable) or [`NoTaxRate`](OrderStoreError::NoTaxRate) if the taxes of the order cannot be recalculated.
+    async fn remove_coupon(&self, order_id: Uuid, code: &str) -> Result<(), OrderStoreError>;
+
+    /// Ships the order with id `order_id` to `address` with `method`, recalculating its taxes for
+    /// the region of the address.
+    ///
+    /// Returns an empty Ok on success, otherwise it returns an error.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](OrderStoreError::StoreUnavailable) if the Store cannot be used to update an order.
+    ///
+    /// Returns [`OrderNotFound`](OrderStoreError::OrderNotFound) if there is no order with the provided id in the Store.
+    ///
+    /// Returns [`OrderNotEditable`](OrderStoreError::OrderNotEditable) if the order is no longer a draft.
+    ///
+    /// Returns [`InvalidAddress`](OrderStoreError::InvalidAddress) if a required field of `address` is missing.
+    ///
+    /// Returns [`TaxUnavailable`](OrderStoreError::TaxUnavailable) or [`NoTaxRate`](OrderStoreError::NoTaxRate) if the taxes of the order cannot be recalculated.
+    async fn set_shipping(
+        &self,
+        order_id: Uuid,
+        address: Address,
+        method: ShippingMethod,
+    ) -> Result<(), OrderStoreError>;
+
+    /// Bills the order with id `order_id` to `address`.
+    ///
+    /// Returns an empty Ok on success, otherwise it returns an error.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](OrderStoreError::StoreUnavailable) if the Store cannot be used to update an order.
+    ///
+    /// Returns [`OrderNotFound`](OrderStoreError::OrderNotFound) if there is no order with the provided id in the Store.
+    ///
+    /// Returns [`OrderNotEditable`](OrderStoreError::OrderNotEditable) if the order is no longer a draft.
+    ///
+    /// Returns [`InvalidAddress`](OrderStoreError::InvalidAddress) if a required field of `address` is missing.
+    async fn set_billing_address(
+        &self,
+        order_id: Uuid,
+        address: Address,
+    ) -> Result<(), OrderStoreError>;
+
+    /// Pays the order with id `order_id` with `payment_token`. An approved payment commits the
+    /// stock of the order and moves it to [`Paid`](OrderStatus::Paid), a declined one leaves it
+    /// as a draft. Either way the attempt is recorded on the order and returned.
+    ///
+    /// The attempt is recorded as pending before the provider is called. If its outcome cannot be
+    /// recorded, the order cannot change until a new checkout retries the same attempt, which
+    /// the provider doesn't charge twice.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](OrderStoreError::StoreUnavailable) if the Store cannot be used to update an order.
+    ///
+    /// Returns [`OrderNotFound`](OrderStoreError::OrderNotFound) if there is no order with the provided id in the Store.
+    ///
+    /// Returns [`OrderNotEditable`](OrderStoreError::OrderNotEditable) if the order is no longer a draft.
+    ///
+    /// Returns [`NotReadyForCheckout`](OrderStoreError::NotReadyForCheckout) if the order is empty, has nothing to pay or has no shipping address.
+    ///
+    /// Returns [`PaymentUnavailable`](OrderStoreError::PaymentUnavailable) if the payment provider cannot be reached.
+    async fn checkout(
+        &self,
+        order_id: Uuid,
+        payment_token: &str,
+    ) -> Result<PaymentAttempt, OrderStoreError>;
+
+    /// Requests the return of `items` of the order with id `order_id` for `reason`.
+    ///
+    /// Returns the recorded return on success, otherwise it returns an error.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](OrderStoreError::StoreUnavailable) if the Store cannot be used to update an order.
+    ///
+    /// Returns [`OrderNotFound`](OrderStoreError::OrderNotFound) if there is no order with the provided id in the Store.
+    ///
+    /// Returns [`OrderNotDelivered`](OrderStoreError::OrderNotDelivered) if the order was not delivered.
+    ///
+    /// Returns [`ItemIndexOutOfBounds`](OrderStoreError::ItemIndexOutOfBounds) if an item index doesn't exist in the order.
+    ///
+    /// Returns [`InvalidQuantity`](OrderStoreError::InvalidQuantity) if a quantity is not positive.
+    ///
+    /// Returns [`InvalidReturn`](OrderStoreError::InvalidReturn) if no items are returned, or more units than can be.
+    async fn request_return(
+        &self,
+        order_id: Uuid,
+        items: Vec<ReturnItem>,
+        reason: String,
+    ) -> Result<ReturnRequest, OrderStoreError>;
+
+    /// Approves the return with id `return_id` of the order with id `order_id`: its amount is
+    /// refunded through the payment provider and its units go back to the inventory.
+    ///
+    /// Returns the approved return on success, otherwise it returns an error.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](OrderStoreError::StoreUnavailable) if the Store cannot be used to update an order.
+    ///
+    /// Returns [`OrderNotFound`](OrderStoreError::OrderNotFound) if there is no order with the provided id in the Store.
+    ///
+    /// Returns [`ReturnNotFound`](OrderStoreError::ReturnNotFound) if the order has no such return.
+    ///
+    /// Returns [`ReturnAlreadyDecided`](OrderStoreError::ReturnAlreadyDecided) if the return was already approved or rejected.
+    ///
+    /// Returns [`PaymentUnavailable`](OrderStoreError::PaymentUnavailable) if the refund cannot be issued; the return stays requested.
+    async fn approve_return(
+        &self,
+        order_id: Uuid,
+        return_id: Uuid,
+    ) -> Result<ReturnRequest, OrderStoreError>;
+
+    /// Rejects the return with id `return_id` of the order with id `order_id`.
+    ///
+    /// Returns the rejected return on success, otherwise it returns an error.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](OrderStoreError::StoreUnavailable) if the Store cannot be used to update an order.
+    ///
+    /// Returns [`OrderNotFound`](OrderStoreError::OrderNotFound) if there is no order with the provided id in the Store.
+    ///
+    /// Returns [`ReturnNotFound`](OrderStoreError::ReturnNotFound) if the order has no such return.
+    ///
+    /// Returns [`ReturnAlreadyDecided`](OrderStoreError::ReturnAlreadyDecided) if the return was already approved or rejected.
+    async fn reject_return(
+        &self,
+        order_id: Uuid,
+        return_id: Uuid,
+    ) -> Result<ReturnRequest, OrderStoreError>;
+
+    /// Returns every change made to the order with id `order_id`, oldest first. Each mutation of
+    /// the Store records one [`AuditEntry`](AuditEntry) with the fields it changed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](OrderStoreError::StoreUnavailable) if the Store cannot be used to read the history.
+    ///
+    /// Returns [`OrderNotFound`](OrderStoreError::OrderNotFound) if there is no order with the provided id in the Store.
+    async fn history(&self, order_id: Uuid) -> Result<Vec<AuditEntry>, OrderStoreError>;
 }
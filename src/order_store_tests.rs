@@ -0,0 +1,1311 @@
+//! Tests every [`OrderStore`](crate::order_store::OrderStore) implementation has to pass, shared
+//! by the implementations that keep their state outside MongoDB.
+
+/// Expands to the shared order store tests, building the store under test with `$new_store`,
+/// which takes the same arguments as [`InMemOrderStore::new`](crate::in_mem_order_store::InMemOrderStore::new).
+macro_rules! order_store_tests {
+    ($new_store:path) => {
+        use rust_decimal::Decimal;
+        use std::sync::Arc;
+        use uuid::Uuid;
+        use $crate::{
+            fulfilment::{Address, ShippingMethod},
+            inventory_store::{InventoryStoreError, InventoryStoreNewType},
+            order_store::{Order, OrderStatus, OrderStoreError, OrderStoreNewType},
+            payment_provider::{Charge, PaymentError, PaymentOutcome, Refund},
+            product_store::ProductStoreNewType,
+            promotion_store::PromotionStoreNewType,
+            returns::ReturnItem,
+        };
+        use $crate::{
+            audit_log::{as_actor, SYSTEM_ACTOR},
+            fake_payment_provider::{FakePaymentProvider, DECLINED_TOKEN, UNAVAILABLE_TOKEN},
+            in_mem_inventory_store::InMemInventoryStore,
+            in_mem_product_store::InMemProductStore,
+            in_mem_promotion_store::InMemPromotionStore,
+            product_store::{Product, ProductDetails},
+            promotions::{Coupon, PromotionRule},
+            returns::ReturnStatus,
+            rules_table_tax_calculator::{RulesTableTaxCalculator, TaxRules},
+        };
+        use mongodb::bson::Bson;
+        use rust_decimal_macros::dec;
+        use test_context::{test_context, AsyncTestContext};
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn create_order_adds_order_to_store(ctx: &mut Context) {
+            assert_eq!(
+                ctx.store
+                    .list_orders(ctx.user_id_1)
+                    .await
+                    .unwrap()
+                    .len(),
+                2
+            );
+            assert_eq!(
+                ctx.store
+                    .list_orders(ctx.user_id_2)
+                    .await
+                    .unwrap()
+                    .len(),
+                1
+            );
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn get_order_retrieves_existing_order(ctx: &mut Context) {
+            if let Ok(stored_order) = ctx.store.get_order(ctx.order_1_user_1.id).await {
+                assert_eq!(stored_order, ctx.order_1_user_1);
+            } else {
+                panic!("Order not found after being created");
+            }
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn get_order_returns_error_for_non_existing_order(ctx: &mut Context) {
+            let order_id = Uuid::new_v4();
+            if let Err(OrderStoreError::OrderNotFound(not_found_id)) =
+                ctx.store.get_order(order_id).await
+            {
+                assert_eq!(order_id, not_found_id);
+            } else {
+                panic!("Unexpected order found");
+            }
+        }
+
+        #[tokio::test]
+        async fn item_cannot_be_added_to_non_existing_order() {
+            let store = OrderStoreNewType::new($new_store(
+                catalog(),
+                inventory(),
+                promotions(),
+                Arc::new(RulesTableTaxCalculator::tax_free()),
+                Arc::new(FakePaymentProvider::new()),
+            ));
+            assert!(store
+                .add_item(Uuid::new_v4(), Uuid::new_v4(), 1)
+                .await
+                .is_err());
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn order_contains_added_item(ctx: &mut Context) {
+            let product_id = ctx.product_id_0;
+            let quantity = 42;
+            if let Ok(()) = ctx
+                .store
+                .add_item(ctx.order_1_user_1.id, product_id, quantity)
+                .await
+            {
+                if let Ok(stored_order) = ctx.store.get_order(ctx.order_1_user_1.id).await {
+                    assert_eq!(stored_order.items.len(), 1);
+                    assert_eq!(stored_order.items[0].product_id, product_id);
+                    assert_eq!(stored_order.items[0].quantity, quantity);
+                } else {
+                    panic!("Order not found after being created");
+                }
+            } else {
+                panic!("Failed to add item to order");
+            }
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn order_contains_added_items(ctx: &mut Context) {
+            let product_id_0 = ctx.product_id_0;
+            let quantity_0 = 42;
+            let product_id_1 = ctx.product_id_1;
+            let quantity_1 = 7;
+            if let (Ok(()), Ok(())) = (
+                ctx.store
+                    .add_item(ctx.order_1_user_1.id, product_id_0, quantity_0)
+                    .await,
+                ctx.store
+                    .add_item(ctx.order_1_user_1.id, product_id_1, quantity_1)
+                    .await,
+            ) {
+                if let Ok(stored_order) = ctx.store.get_order(ctx.order_1_user_1.id).await {
+                    assert_eq!(stored_order.items.len(), 2);
+                    assert_eq!(stored_order.items[0].product_id, product_id_0);
+                    assert_eq!(stored_order.items[0].quantity, quantity_0);
+                    assert_eq!(stored_order.items[1].product_id, product_id_1);
+                    assert_eq!(stored_order.items[1].quantity, quantity_1);
+                } else {
+                    panic!("Order not found after being created");
+                }
+            } else {
+                panic!("Failed to add items to order");
+            }
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn unknown_product_cannot_be_added_to_order(ctx: &mut Context) {
+            let product_id = Uuid::new_v4();
+            if let Err(OrderStoreError::ProductNotFound(not_found_id)) = ctx
+                .store
+                .add_item(ctx.order_1_user_1.id, product_id, 1)
+                .await
+            {
+                assert_eq!(not_found_id, product_id);
+            } else {
+                panic!("Adding an unknown product must produce error");
+            }
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn inactive_product_cannot_be_added_to_order(ctx: &mut Context) {
+            let inactive = add_product(
+                &ctx.products,
+                ProductDetails {
+                    active: false,
+                    ..product_details("SKU-INACTIVE")
+                },
+            )
+            .await;
+            if let Err(OrderStoreError::ProductInactive(inactive_id)) = ctx
+                .store
+                .add_item(ctx.order_1_user_1.id, inactive.id, 1)
+                .await
+            {
+                assert_eq!(inactive_id, inactive.id);
+            } else {
+                panic!("Adding an inactive product must produce error");
+            }
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn added_items_snapshot_price_and_update_totals(ctx: &mut Context) {
+            let order_id = ctx.order_1_user_1.id;
+            ctx.store
+                .add_item(order_id, ctx.product_id_0, 3)
+                .await
+                .unwrap();
+            ctx.store
+                .add_item(order_id, ctx.product_id_1, 7)
+                .await
+                .unwrap();
+            ctx.products
+                .update_product(
+                    ctx.product_id_0,
+                    ProductDetails {
+                        price: dec!(99.00),
+                        ..product_details("SKU-0")
+                    },
+                )
+                .await
+                .unwrap();
+
+            let stored_order = ctx.store.get_order(order_id).await.unwrap();
+            assert_eq!(stored_order.items[0].unit_price, dec!(10.00));
+            assert_eq!(stored_order.items[0].line_total, dec!(30.00));
+            assert_eq!(stored_order.items[1].line_total, dec!(16.45));
+            assert_eq!(stored_order.currency.as_deref(), Some("USD"));
+            assert_eq!(stored_order.subtotal, dec!(46.45));
+            assert_eq!(stored_order.total, dec!(46.45));
+
+            ctx.store.delete_item(order_id, 0, None).await.unwrap();
+            let stored_order = ctx.store.get_order(order_id).await.unwrap();
+            assert_eq!(stored_order.total, dec!(16.45));
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn item_with_an_unrepresentable_total_is_rejected(ctx: &mut Context) {
+            let order_id = ctx.order_1_user_1.id;
+            let expensive = add_product(
+                &ctx.products,
+                ProductDetails {
+                    price: Decimal::MAX,
+                    ..product_details("SKU-MAX")
+                },
+            )
+            .await;
+            ctx.inventory.set_available(expensive.id, 10).await.unwrap();
+            assert!(matches!(
+                ctx.store.add_item(order_id, expensive.id, 2).await,
+                Err(OrderStoreError::InvalidQuantity(2))
+            ));
+            ctx.store.add_item(order_id, expensive.id, 1).await.unwrap();
+            assert!(matches!(
+                ctx.store.add_item(order_id, ctx.product_id_0, 1).await,
+                Err(OrderStoreError::InvalidQuantity(1))
+            ));
+            assert!(matches!(
+                ctx.store.update_item_quantity(order_id, 0, 2, None).await,
+                Err(OrderStoreError::InvalidQuantity(2))
+            ));
+
+            let stored_order = ctx.store.get_order(order_id).await.unwrap();
+            assert_eq!(stored_order.items.len(), 1);
+            assert_eq!(stored_order.items[0].quantity, 1);
+            assert_eq!(stored_order.subtotal, Decimal::MAX);
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn product_in_another_currency_cannot_be_added_to_order(ctx: &mut Context) {
+            let euro_product = add_product(
+                &ctx.products,
+                ProductDetails {
+                    currency: "EUR".to_string(),
+                    ..product_details("SKU-EUR")
+                },
+            )
+            .await;
+            ctx.store
+                .add_item(ctx.order_1_user_1.id, ctx.product_id_0, 1)
+                .await
+                .unwrap();
+            if let Err(OrderStoreError::CurrencyMismatch(currency)) = ctx
+                .store
+                .add_item(ctx.order_1_user_1.id, euro_product.id, 1)
+                .await
+            {
+                assert_eq!(currency, "EUR");
+            } else {
+                panic!("Mixing currencies in an order must produce error");
+            }
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn adding_and_deleting_items_reserves_and_releases_stock(ctx: &mut Context) {
+            let order_id = ctx.order_1_user_1.id;
+            ctx.store
+                .add_item(order_id, ctx.product_id_0, 30)
+                .await
+                .unwrap();
+            assert_eq!(stock(ctx, ctx.product_id_0).await, (70, 30, 0));
+            ctx.store.delete_item(order_id, 0, None).await.unwrap();
+            assert_eq!(stock(ctx, ctx.product_id_0).await, (100, 0, 0));
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn changing_quantity_moves_the_difference_in_stock(ctx: &mut Context) {
+            let order_id = ctx.order_1_user_1.id;
+            ctx.store
+                .add_item(order_id, ctx.product_id_0, 3)
+                .await
+                .unwrap();
+            ctx.store
+                .update_item_quantity(order_id, 0, 5, None)
+                .await
+                .unwrap();
+            assert_eq!(stock(ctx, ctx.product_id_0).await, (95, 5, 0));
+            ctx.store
+                .update_item_quantity(order_id, 0, 2, None)
+                .await
+                .unwrap();
+            assert_eq!(stock(ctx, ctx.product_id_0).await, (98, 2, 0));
+
+            let stored_order = ctx.store.get_order(order_id).await.unwrap();
+            assert_eq!(stored_order.items[0].quantity, 2);
+            assert_eq!(
+                stored_order.items[0].line_total,
+                stored_order.items[0].unit_price * dec!(2)
+            );
+            assert_eq!(stored_order.subtotal, stored_order.items[0].line_total);
+            assert!(matches!(
+                ctx.store.update_item_quantity(order_id, 0, 0, None).await,
+                Err(OrderStoreError::InvalidQuantity(0))
+            ));
+            assert!(matches!(
+                ctx.store.update_item_quantity(order_id, 1, 1, None).await,
+                Err(OrderStoreError::ItemIndexOutOfBounds(1))
+            ));
+            assert!(matches!(
+                ctx.store.update_item_quantity(order_id, 0, 101, None).await,
+                Err(OrderStoreError::InsufficientStock(_))
+            ));
+            assert_eq!(stock(ctx, ctx.product_id_0).await, (98, 2, 0));
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn items_are_only_changed_when_they_are_the_expected_product(ctx: &mut Context) {
+            let order_id = ctx.order_1_user_1.id;
+            for product_id in [ctx.product_id_0, ctx.product_id_1] {
+                ctx.store.add_item(order_id, product_id, 1).await.unwrap();
+            }
+            // another client removed the first item meanwhile
+            ctx.store
+                .delete_item(order_id, 0, Some(ctx.product_id_0))
+                .await
+                .unwrap();
+            assert!(matches!(
+                ctx.store
+                    .delete_item(order_id, 0, Some(ctx.product_id_0))
+                    .await,
+                Err(OrderStoreError::ItemChanged(0, product_id)) if product_id == ctx.product_id_0
+            ));
+            assert!(matches!(
+                ctx.store
+                    .update_item_quantity(order_id, 0, 3, Some(ctx.product_id_0))
+                    .await,
+                Err(OrderStoreError::ItemChanged(0, _))
+            ));
+            let stored_order = ctx.store.get_order(order_id).await.unwrap();
+            assert_eq!(stored_order.items.len(), 1);
+            assert_eq!(stored_order.items[0].product_id, ctx.product_id_1);
+            assert_eq!(stored_order.items[0].quantity, 1);
+            ctx.store
+                .update_item_quantity(order_id, 0, 3, Some(ctx.product_id_1))
+                .await
+                .unwrap();
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn item_beyond_available_stock_is_rejected(ctx: &mut Context) {
+            let order_id = ctx.order_1_user_1.id;
+            if let Err(OrderStoreError::InsufficientStock(product_id)) = ctx
+                .store
+                .add_item(order_id, ctx.product_id_0, 101)
+                .await
+            {
+                assert_eq!(product_id, ctx.product_id_0);
+            } else {
+                panic!("Overselling must produce error");
+            }
+            let stored_order = ctx.store.get_order(order_id).await.unwrap();
+            assert!(stored_order.items.is_empty());
+            assert_eq!(stock(ctx, ctx.product_id_0).await, (100, 0, 0));
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn placing_order_commits_stock_and_freezes_items(ctx: &mut Context) {
+            let order_id = ctx.order_1_user_1.id;
+            ctx.store
+                .add_item(order_id, ctx.product_id_0, 5)
+                .await
+                .unwrap();
+            ctx.store
+                .update_status(order_id, OrderStatus::Placed)
+                .await
+                .unwrap();
+            assert_eq!(stock(ctx, ctx.product_id_0).await, (95, 0, 5));
+            assert!(matches!(
+                ctx.store.delete_item(order_id, 0, None).await,
+                Err(OrderStoreError::OrderNotEditable(_))
+            ));
+            assert!(matches!(
+                ctx.store
+                    .update_status(order_id, OrderStatus::Cancelled)
+                    .await,
+                Err(OrderStoreError::InvalidStatusTransition(
+                    OrderStatus::Placed,
+                    OrderStatus::Cancelled
+                ))
+            ));
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn placing_order_moves_no_stock_when_an_item_cannot_be_committed(
+            ctx: &mut Context,
+        ) {
+            let order_id = ctx.order_1_user_1.id;
+            ctx.store
+                .add_item(order_id, ctx.product_id_0, 5)
+                .await
+                .unwrap();
+            ctx.store
+                .add_item(order_id, ctx.product_id_1, 3)
+                .await
+                .unwrap();
+            ctx.inventory.release(ctx.product_id_1, 1).await.unwrap();
+
+            assert!(matches!(
+                ctx.store.update_status(order_id, OrderStatus::Placed).await,
+                Err(OrderStoreError::InsufficientStock(id)) if id == ctx.product_id_1
+            ));
+            assert_eq!(stock(ctx, ctx.product_id_0).await, (95, 5, 0));
+            assert_eq!(stock(ctx, ctx.product_id_1).await, (98, 2, 0));
+            let stored_order = ctx.store.get_order(order_id).await.unwrap();
+            assert_eq!(stored_order.status, OrderStatus::Draft);
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn cancelling_order_releases_stock(ctx: &mut Context) {
+            let order_id = ctx.order_1_user_1.id;
+            ctx.store
+                .add_item(order_id, ctx.product_id_0, 5)
+                .await
+                .unwrap();
+            ctx.store
+                .update_status(order_id, OrderStatus::Cancelled)
+                .await
+                .unwrap();
+            assert_eq!(stock(ctx, ctx.product_id_0).await, (100, 0, 0));
+            let stored_order = ctx.store.get_order(order_id).await.unwrap();
+            assert_eq!(stored_order.status, OrderStatus::Cancelled);
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn applied_coupons_are_reflected_in_totals(ctx: &mut Context) {
+            let order_id = ctx.order_1_user_1.id;
+            for rule in [
+                PromotionRule::Percentage { percent: dec!(10) },
+                PromotionRule::FixedAmount {
+                amount: dec!(1.50),
+                currency: "USD".to_string(),
+            },
+            ] {
+                let code = format!("{:?}", rule);
+                ctx.promotions
+                    .create_coupon(coupon(&code, rule))
+                    .await
+                    .unwrap();
+                ctx.store
+                    .apply_coupon(order_id, &code)
+                    .await
+                    .unwrap();
+            }
+            ctx.store
+                .add_item(order_id, ctx.product_id_0, 4)
+                .await
+                .unwrap();
+
+            let stored_order = ctx.store.get_order(order_id).await.unwrap();
+            assert_eq!(stored_order.subtotal, dec!(40.00));
+            assert_eq!(stored_order.discount, dec!(5.50));
+            assert_eq!(stored_order.total, dec!(34.50));
+
+            let code = stored_order.coupons[1].code.clone();
+            ctx.store
+                .remove_coupon(order_id, &code)
+                .await
+                .unwrap();
+            let stored_order = ctx.store.get_order(order_id).await.unwrap();
+            assert_eq!(stored_order.total, dec!(36.00));
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn coupon_cannot_be_used_beyond_its_limit_per_user(ctx: &mut Context) {
+            ctx.promotions
+                .create_coupon(coupon(
+                    "ONCE",
+                    PromotionRule::FixedAmount {
+                amount: dec!(1),
+                currency: "USD".to_string(),
+            },
+                ))
+                .await
+                .unwrap();
+            let second_order = ctx.store.create_order(ctx.user_id_1).await.unwrap();
+            ctx.store
+                .apply_coupon(ctx.order_1_user_1.id, "ONCE")
+                .await
+                .unwrap();
+            assert!(matches!(
+                ctx.store.apply_coupon(second_order.id, "ONCE").await,
+                Err(OrderStoreError::CouponUsageLimitReached(_))
+            ));
+            ctx.store
+                .update_status(ctx.order_1_user_1.id, OrderStatus::Cancelled)
+                .await
+                .unwrap();
+            ctx.store
+                .apply_coupon(second_order.id, "ONCE")
+                .await
+                .unwrap();
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn expired_coupon_is_rejected(ctx: &mut Context) {
+            ctx.promotions
+                .create_coupon(Coupon {
+                    valid_until: Some(chrono::Utc::now() - chrono::Duration::days(1)),
+                    ..coupon("OLD", PromotionRule::FixedAmount {
+                amount: dec!(1),
+                currency: "USD".to_string(),
+            })
+                })
+                .await
+                .unwrap();
+            assert!(matches!(
+                ctx.store
+                    .apply_coupon(ctx.order_1_user_1.id, "OLD")
+                    .await,
+                Err(OrderStoreError::CouponExpired(_))
+            ));
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn taxes_follow_the_items_of_the_order(ctx: &mut Context) {
+            let rules: TaxRules = serde_json::from_value(serde_json::json!({
+                "default_region": "US-CA",
+                "rates": [{ "region": "US-CA", "category": "standard", "rate": "0.0725" }]
+            }))
+            .unwrap();
+            let store = OrderStoreNewType::new($new_store(
+                ctx.products.clone(),
+                ctx.inventory.clone(),
+                ctx.promotions.clone(),
+                Arc::new(RulesTableTaxCalculator::new(rules)),
+                Arc::new(FakePaymentProvider::new()),
+            ));
+            let order = store.create_order(ctx.user_id_1).await.unwrap();
+            store
+                .add_item(order.id, ctx.product_id_0, 3)
+                .await
+                .unwrap();
+            store
+                .add_item(order.id, ctx.product_id_1, 1)
+                .await
+                .unwrap();
+
+            let stored_order = store.get_order(order.id).await.unwrap();
+            assert_eq!(stored_order.tax.region.as_deref(), Some("US-CA"));
+            assert_eq!(stored_order.tax.lines.len(), 2);
+            assert_eq!(stored_order.tax.lines[0].amount, dec!(2.18));
+            assert_eq!(stored_order.tax.lines[1].amount, dec!(0.17));
+            assert_eq!(stored_order.total, dec!(34.70));
+
+            store.delete_item(order.id, 0, None).await.unwrap();
+            let stored_order = store.get_order(order.id).await.unwrap();
+            assert_eq!(stored_order.tax.total, dec!(0.17));
+            assert_eq!(stored_order.total, dec!(2.52));
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn taxes_follow_the_coupons_of_the_order(ctx: &mut Context) {
+            let rules: TaxRules = serde_json::from_value(serde_json::json!({
+                "default_region": "US-CA",
+                "rates": [{ "region": "US-CA", "category": "standard", "rate": "0.0725" }]
+            }))
+            .unwrap();
+            let store = OrderStoreNewType::new($new_store(
+                ctx.products.clone(),
+                ctx.inventory.clone(),
+                ctx.promotions.clone(),
+                Arc::new(RulesTableTaxCalculator::new(rules)),
+                Arc::new(FakePaymentProvider::new()),
+            ));
+            ctx.promotions
+                .create_coupon(coupon(
+                    "TENOFF",
+                    PromotionRule::Percentage { percent: dec!(10) },
+                ))
+                .await
+                .unwrap();
+            let order = store.create_order(ctx.user_id_1).await.unwrap();
+            store
+                .add_item(order.id, ctx.product_id_0, 3)
+                .await
+                .unwrap();
+
+            store.apply_coupon(order.id, "TENOFF").await.unwrap();
+            let stored_order = store.get_order(order.id).await.unwrap();
+            assert_eq!(stored_order.tax.total, dec!(1.96));
+            assert_eq!(stored_order.total, dec!(28.96));
+
+            store.remove_coupon(order.id, "TENOFF").await.unwrap();
+            let stored_order = store.get_order(order.id).await.unwrap();
+            assert_eq!(stored_order.tax.total, dec!(2.18));
+            assert_eq!(stored_order.total, dec!(32.18));
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn shipping_adds_its_cost_and_taxes_of_the_destination(ctx: &mut Context) {
+            let rules: TaxRules = serde_json::from_value(serde_json::json!({
+                "default_region": "US-CA",
+                "rates": [
+                    { "region": "US-CA", "category": "standard", "rate": "0.0725" },
+                    { "region": "DE", "category": "standard", "rate": "0.19" }
+                ]
+            }))
+            .unwrap();
+            let store = OrderStoreNewType::new($new_store(
+                ctx.products.clone(),
+                ctx.inventory.clone(),
+                ctx.promotions.clone(),
+                Arc::new(RulesTableTaxCalculator::new(rules)),
+                Arc::new(FakePaymentProvider::new()),
+            ));
+            let order = store.create_order(ctx.user_id_1).await.unwrap();
+            store
+                .add_item(order.id, ctx.product_id_0, 1)
+                .await
+                .unwrap();
+            store
+                .set_shipping(order.id, address("DE"), ShippingMethod::Express)
+                .await
+                .unwrap();
+            store
+                .set_billing_address(order.id, address("US"))
+                .await
+                .unwrap();
+
+            let stored_order = store.get_order(order.id).await.unwrap();
+            assert_eq!(stored_order.tax.region.as_deref(), Some("DE"));
+            assert_eq!(stored_order.shipping_cost, dec!(14.99));
+            assert_eq!(stored_order.total, dec!(26.89));
+            assert_eq!(stored_order.billing_address, Some(address("US")));
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn incomplete_address_is_rejected(ctx: &mut Context) {
+            let address = Address {
+                postal_code: String::new(),
+                ..address("DE")
+            };
+            assert!(matches!(
+                ctx.store
+                    .set_billing_address(ctx.order_1_user_1.id, address)
+                    .await,
+                Err(OrderStoreError::InvalidAddress(field)) if field == "postal_code"
+            ));
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn approved_checkout_pays_order_and_commits_stock(ctx: &mut Context) {
+            let order_id = ctx.order_1_user_1.id;
+            ctx.store
+                .add_item(order_id, ctx.product_id_0, 2)
+                .await
+                .unwrap();
+            ctx.store
+                .set_shipping(order_id, address("US"), ShippingMethod::Standard)
+                .await
+                .unwrap();
+
+            let attempt = ctx
+                .store
+                .checkout(order_id, "tok_visa")
+                .await
+                .unwrap();
+            assert!(matches!(attempt.outcome, PaymentOutcome::Approved { .. }));
+            assert_eq!(attempt.amount, dec!(24.99));
+            let stored_order = ctx.store.get_order(order_id).await.unwrap();
+            assert_eq!(stored_order.status, OrderStatus::Paid);
+            assert_eq!(stored_order.payments, vec![attempt]);
+            assert_eq!(stock(ctx, ctx.product_id_0).await, (98, 0, 2));
+            assert!(matches!(
+                ctx.store.checkout(order_id, "tok_visa").await,
+                Err(OrderStoreError::OrderNotEditable(_))
+            ));
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn checkout_retries_the_attempt_whose_outcome_was_not_recorded(ctx: &mut Context) {
+            let payments = Arc::new(RecordingPaymentProvider {
+                unavailable_refunds: true,
+                ..Default::default()
+            });
+            let store = OrderStoreNewType::new($new_store(
+                ctx.products.clone(),
+                ctx.inventory.clone(),
+                ctx.promotions.clone(),
+                Arc::new(RulesTableTaxCalculator::tax_free()),
+                payments.clone(),
+            ));
+            let order = store.create_order(ctx.user_id_1).await.unwrap();
+            store
+                .add_item(order.id, ctx.product_id_0, 2)
+                .await
+                .unwrap();
+            store
+                .set_shipping(order.id, address("US"), ShippingMethod::Standard)
+                .await
+                .unwrap();
+            // the stock of the order goes missing, so the approved payment cannot be recorded, and
+            // the provider cannot be reached to give it back
+            ctx.inventory.release(ctx.product_id_0, 2).await.unwrap();
+            assert!(matches!(
+                store.checkout(order.id, "tok_visa").await,
+                Err(OrderStoreError::InsufficientStock(_))
+            ));
+            let stored_order = store.get_order(order.id).await.unwrap();
+            assert_eq!(stored_order.status, OrderStatus::Draft);
+            assert!(stored_order.pending_payment().is_some());
+            assert!(matches!(
+                store.delete_item(order.id, 0, None).await,
+                Err(OrderStoreError::OrderNotEditable(_))
+            ));
+
+            ctx.inventory.reserve(ctx.product_id_0, 2).await.unwrap();
+            let attempt = store.checkout(order.id, "tok_visa").await.unwrap();
+            assert!(matches!(attempt.outcome, PaymentOutcome::Approved { .. }));
+            assert_eq!(*payments.charged.lock().unwrap(), [attempt.id, attempt.id]);
+            let stored_order = store.get_order(order.id).await.unwrap();
+            assert_eq!(stored_order.status, OrderStatus::Paid);
+            assert_eq!(stored_order.payments, vec![attempt]);
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn checkout_gives_the_charge_back_when_the_stock_cannot_be_committed(
+            ctx: &mut Context,
+        ) {
+            let payments = Arc::new(RecordingPaymentProvider::default());
+            let store = OrderStoreNewType::new($new_store(
+                ctx.products.clone(),
+                Arc::new(InventoryStoreNewType::new(UncommittableInventory(
+                    ctx.inventory.clone(),
+                ))),
+                ctx.promotions.clone(),
+                Arc::new(RulesTableTaxCalculator::tax_free()),
+                payments.clone(),
+            ));
+            let order = store.create_order(ctx.user_id_1).await.unwrap();
+            store
+                .add_item(order.id, ctx.product_id_0, 2)
+                .await
+                .unwrap();
+            store
+                .set_shipping(order.id, address("US"), ShippingMethod::Standard)
+                .await
+                .unwrap();
+
+            assert!(matches!(
+                store.checkout(order.id, "tok_visa").await,
+                Err(OrderStoreError::StoreUnavailable)
+            ));
+            let stored_order = store.get_order(order.id).await.unwrap();
+            assert_eq!(stored_order.status, OrderStatus::Draft);
+            assert_eq!(stored_order.payments.len(), 1);
+            assert_eq!(stored_order.payments[0].outcome, PaymentOutcome::Failed);
+            assert_eq!(
+                *payments.refunded.lock().unwrap(),
+                [stored_order.payments[0].id]
+            );
+            assert_eq!(stock(ctx, ctx.product_id_0).await, (98, 2, 0));
+            store
+                .delete_item(order.id, 0, None)
+                .await
+                .unwrap();
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn declined_or_failed_checkout_keeps_order_as_draft(ctx: &mut Context) {
+            let order_id = ctx.order_1_user_1.id;
+            ctx.store
+                .add_item(order_id, ctx.product_id_0, 2)
+                .await
+                .unwrap();
+            ctx.store
+                .set_shipping(order_id, address("US"), ShippingMethod::Standard)
+                .await
+                .unwrap();
+
+            let attempt = ctx
+                .store
+                .checkout(order_id, DECLINED_TOKEN)
+                .await
+                .unwrap();
+            assert!(matches!(attempt.outcome, PaymentOutcome::Declined { .. }));
+            assert!(matches!(
+                ctx.store.checkout(order_id, UNAVAILABLE_TOKEN).await,
+                Err(OrderStoreError::PaymentUnavailable)
+            ));
+            let stored_order = ctx.store.get_order(order_id).await.unwrap();
+            assert_eq!(stored_order.status, OrderStatus::Draft);
+            assert_eq!(stored_order.payments.len(), 2);
+            assert_eq!(stored_order.payments[1].outcome, PaymentOutcome::Failed);
+            assert_eq!(stock(ctx, ctx.product_id_0).await, (98, 2, 0));
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn incomplete_order_cannot_be_checked_out(ctx: &mut Context) {
+            let order_id = ctx.order_1_user_1.id;
+            assert!(matches!(
+                ctx.store.checkout(order_id, "tok_visa").await,
+                Err(OrderStoreError::NotReadyForCheckout(_))
+            ));
+            ctx.store
+                .add_item(order_id, ctx.product_id_0, 1)
+                .await
+                .unwrap();
+            assert!(matches!(
+                ctx.store.checkout(order_id, "tok_visa").await,
+                Err(OrderStoreError::NotReadyForCheckout(_))
+            ));
+            let stored_order = ctx.store.get_order(order_id).await.unwrap();
+            assert!(stored_order.payments.is_empty());
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn approved_return_refunds_and_restocks_items(ctx: &mut Context) {
+            let order_id = delivered_order(ctx).await;
+            let request = ctx
+                .store
+                .request_return(
+                    order_id,
+                    vec![ReturnItem {
+                        index: 0,
+                        quantity: 2,
+                    }],
+                    "damaged".to_string(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(request.refund_amount, dec!(20.00));
+
+            let approved = ctx
+                .store
+                .approve_return(order_id, request.id)
+                .await
+                .unwrap();
+            assert_eq!(approved.status, ReturnStatus::Approved);
+            assert!(approved.refund_reference.is_some());
+            let stored_order = ctx.store.get_order(order_id).await.unwrap();
+            assert_eq!(stored_order.refunded, dec!(20.00));
+            assert_eq!(stored_order.returns, vec![approved]);
+            assert_eq!(stock(ctx, ctx.product_id_0).await, (99, 0, 1));
+            assert!(matches!(
+                ctx.store.reject_return(order_id, request.id).await,
+                Err(OrderStoreError::ReturnAlreadyDecided(_))
+            ));
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn approving_a_return_again_retries_the_same_refund(ctx: &mut Context) {
+            let payments = Arc::new(RecordingPaymentProvider::default());
+            let store = OrderStoreNewType::new($new_store(
+                ctx.products.clone(),
+                ctx.inventory.clone(),
+                ctx.promotions.clone(),
+                Arc::new(RulesTableTaxCalculator::tax_free()),
+                payments.clone(),
+            ));
+            let order = store.create_order(ctx.user_id_1).await.unwrap();
+            let order_id = deliver(&store, order.id, ctx.product_id_0).await;
+            let items = vec![ReturnItem {
+                index: 0,
+                quantity: 2,
+            }];
+            let request = store
+                .request_return(order_id, items, "damaged".to_string())
+                .await
+                .unwrap();
+            // the committed stock goes missing, so the refunded return cannot be restocked
+            ctx.inventory.restock(ctx.product_id_0, 3).await.unwrap();
+            assert!(matches!(
+                store.approve_return(order_id, request.id).await,
+                Err(OrderStoreError::InsufficientStock(_))
+            ));
+            let stored_order = store.get_order(order_id).await.unwrap();
+            assert_eq!(stored_order.returns[0].status, ReturnStatus::Requested);
+            assert_eq!(stored_order.refunded, dec!(0));
+
+            ctx.inventory.reserve(ctx.product_id_0, 3).await.unwrap();
+            ctx.inventory.commit(ctx.product_id_0, 3).await.unwrap();
+            store.approve_return(order_id, request.id).await.unwrap();
+            assert_eq!(
+                *payments.refunded.lock().unwrap(),
+                [request.id, request.id]
+            );
+            let stored_order = store.get_order(order_id).await.unwrap();
+            assert_eq!(stored_order.refunded, dec!(20.00));
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn units_cannot_be_returned_twice(ctx: &mut Context) {
+            let order_id = delivered_order(ctx).await;
+            let items = vec![ReturnItem {
+                index: 0,
+                quantity: 3,
+            }];
+            let request = ctx
+                .store
+                .request_return(order_id, items.clone(), "too many".to_string())
+                .await
+                .unwrap();
+            assert!(matches!(
+                ctx.store
+                    .request_return(order_id, items.clone(), "again".to_string())
+                    .await,
+                Err(OrderStoreError::InvalidReturn(_))
+            ));
+            // rejected returns give their units back
+            ctx.store
+                .reject_return(order_id, request.id)
+                .await
+                .unwrap();
+            ctx.store
+                .request_return(order_id, items, "again".to_string())
+                .await
+                .unwrap();
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn undelivered_order_cannot_be_returned(ctx: &mut Context) {
+            assert!(matches!(
+                ctx.store
+                    .request_return(
+                        ctx.order_1_user_1.id,
+                        vec![ReturnItem {
+                            index: 0,
+                            quantity: 1,
+                        }],
+                        "changed my mind".to_string(),
+                    )
+                    .await,
+                Err(OrderStoreError::OrderNotDelivered(_))
+            ));
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn every_change_is_recorded_in_the_history(ctx: &mut Context) {
+            let order_id = ctx.order_1_user_1.id;
+            as_actor("support".to_string(), async {
+                ctx.store
+                    .add_item(order_id, ctx.product_id_0, 2)
+                    .await
+                    .unwrap();
+                ctx.store.delete_item(order_id, 0, None).await.unwrap();
+            })
+            .await;
+            // rejected changes are not recorded
+            assert!(ctx.store.delete_item(order_id, 0, None).await.is_err());
+
+            let history = ctx.store.history(order_id).await.unwrap();
+            let operations: Vec<(&str, &str)> = history
+                .iter()
+                .map(|entry| (entry.operation.as_str(), entry.actor.as_str()))
+                .collect();
+            assert_eq!(
+                operations,
+                vec![
+                    ("create_order", SYSTEM_ACTOR),
+                    ("add_item", "support"),
+                    ("delete_item", "support")
+                ]
+            );
+            let removed_items = history[2]
+                .changes
+                .iter()
+                .find(|change| change.field == "items")
+                .unwrap();
+            assert_eq!(removed_items.after, Some(Bson::Array(vec![])));
+            assert!(matches!(
+                ctx.store.history(Uuid::new_v4()).await,
+                Err(OrderStoreError::OrderNotFound(_))
+            ));
+        }
+
+        #[tokio::test]
+        async fn item_cannot_be_deleted_from_non_existing_order() {
+            let store = OrderStoreNewType::new($new_store(
+                catalog(),
+                inventory(),
+                promotions(),
+                Arc::new(RulesTableTaxCalculator::tax_free()),
+                Arc::new(FakePaymentProvider::new()),
+            ));
+            assert!(store.delete_item(Uuid::new_v4(), 1, None).await.is_err());
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn attempt_to_delete_non_existent_item_from_order_returns_error(ctx: &mut Context) {
+            let product_id_0 = ctx.product_id_0;
+            let quantity_0 = 42;
+            let product_id_1 = ctx.product_id_1;
+            let quantity_1 = 7;
+            if let (Ok(()), Ok(())) = (
+                ctx.store
+                    .add_item(ctx.order_1_user_1.id, product_id_0, quantity_0)
+                    .await,
+                ctx.store
+                    .add_item(ctx.order_1_user_1.id, product_id_1, quantity_1)
+                    .await,
+            ) {
+                if let Err(OrderStoreError::ItemIndexOutOfBounds(index)) =
+                    ctx.store.delete_item(ctx.order_1_user_1.id, 2, None).await
+                {
+                    assert_eq!(index, 2);
+                } else {
+                    panic!("Deleting non-existent item must produce error");
+                }
+            } else {
+                panic!("Failed to add items to order");
+            }
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn last_item_can_be_deleted_from_order(ctx: &mut Context) {
+            let product_id_0 = ctx.product_id_0;
+            let quantity_0 = 42;
+            let product_id_1 = ctx.product_id_1;
+            let quantity_1 = 7;
+            if let (Ok(()), Ok(())) = (
+                ctx.store
+                    .add_item(ctx.order_1_user_1.id, product_id_0, quantity_0)
+                    .await,
+                ctx.store
+                    .add_item(ctx.order_1_user_1.id, product_id_1, quantity_1)
+                    .await,
+            ) {
+                if let Ok(()) = ctx.store.delete_item(ctx.order_1_user_1.id, 1, None).await {
+                    if let Ok(stored_order) = ctx.store.get_order(ctx.order_1_user_1.id).await {
+                        assert_eq!(stored_order.items.len(), 1);
+                        assert_eq!(stored_order.items[0].product_id, product_id_0);
+                        assert_eq!(stored_order.items[0].quantity, quantity_0);
+                    } else {
+                        panic!("Order not found after being created");
+                    }
+                } else {
+                    panic!("Failed to delete item from order");
+                }
+            } else {
+                panic!("Failed to add items to order");
+            }
+        }
+
+        #[test_context(Context)]
+        #[tokio::test]
+        async fn first_item_can_be_deleted_from_order(ctx: &mut Context) {
+            let product_id_0 = ctx.product_id_0;
+            let quantity_0 = 42;
+            let product_id_1 = ctx.product_id_1;
+            let quantity_1 = 7;
+            if let (Ok(()), Ok(())) = (
+                ctx.store
+                    .add_item(ctx.order_1_user_1.id, product_id_0, quantity_0)
+                    .await,
+                ctx.store
+                    .add_item(ctx.order_1_user_1.id, product_id_1, quantity_1)
+                    .await,
+            ) {
+                if let Ok(()) = ctx.store.delete_item(ctx.order_1_user_1.id, 0, None).await {
+                    if let Ok(stored_order) = ctx.store.get_order(ctx.order_1_user_1.id).await {
+                        assert_eq!(stored_order.items.len(), 1);
+                        assert_eq!(stored_order.items[0].product_id, product_id_1);
+                        assert_eq!(stored_order.items[0].quantity, quantity_1);
+                    } else {
+                        panic!("Order not found after being created");
+                    }
+                } else {
+                    panic!("Failed to delete item from order");
+                }
+            } else {
+                panic!("Failed to add items to order");
+            }
+        }
+
+        /// Pays, ships and delivers an order with 3 units of product 0, returning its id.
+        async fn delivered_order(ctx: &Context) -> Uuid {
+            deliver(&ctx.store, ctx.order_1_user_1.id, ctx.product_id_0).await
+        }
+
+        /// Pays for 3 units of `product_id` in the order with id `order_id` and delivers it.
+        async fn deliver(store: &OrderStoreNewType, order_id: Uuid, product_id: Uuid) -> Uuid {
+            store.add_item(order_id, product_id, 3).await.unwrap();
+            store
+                .set_shipping(order_id, address("US"), ShippingMethod::Standard)
+                .await
+                .unwrap();
+            store.checkout(order_id, "tok_visa").await.unwrap();
+            for status in [OrderStatus::Shipped, OrderStatus::Delivered] {
+                store.update_status(order_id, status).await.unwrap();
+            }
+            order_id
+        }
+
+        fn catalog() -> Arc<ProductStoreNewType> {
+            Arc::new(ProductStoreNewType::new(InMemProductStore::new()))
+        }
+
+        fn inventory() -> Arc<InventoryStoreNewType> {
+            Arc::new(InventoryStoreNewType::new(InMemInventoryStore::new()))
+        }
+
+        fn promotions() -> Arc<PromotionStoreNewType> {
+            Arc::new(PromotionStoreNewType::new(InMemPromotionStore::new()))
+        }
+
+        fn coupon(code: &str, rule: PromotionRule) -> Coupon {
+            Coupon {
+                code: code.to_string(),
+                rule,
+                valid_from: None,
+                valid_until: None,
+                max_uses_per_user: Some(1),
+            }
+        }
+
+        /// Fake provider remembering the attempts it was asked to charge and the refunds it was
+        /// asked to pay.
+        #[derive(Default)]
+        struct RecordingPaymentProvider {
+            charged: std::sync::Mutex<Vec<Uuid>>,
+            refunded: std::sync::Mutex<Vec<Uuid>>,
+            unavailable_refunds: bool,
+        }
+
+        #[async_trait::async_trait]
+        impl $crate::payment_provider::PaymentProvider for RecordingPaymentProvider {
+            async fn charge(&self, charge: &Charge) -> Result<PaymentOutcome, PaymentError> {
+                self.charged.lock().unwrap().push(charge.attempt_id);
+                FakePaymentProvider::new().charge(charge).await
+            }
+
+            async fn refund(&self, refund: &Refund) -> Result<String, PaymentError> {
+                self.refunded.lock().unwrap().push(refund.refund_id);
+                if self.unavailable_refunds {
+                    return Err(PaymentError::ProviderUnavailable);
+                }
+                FakePaymentProvider::new().refund(refund).await
+            }
+        }
+
+        /// Inventory whose stock can be reserved but never committed, as if it became unavailable
+        /// in between.
+        struct UncommittableInventory(Arc<InventoryStoreNewType>);
+
+        #[async_trait::async_trait]
+        impl $crate::inventory_store::InventoryStore for UncommittableInventory {
+            async fn get_stock(
+                &self,
+                product_id: Uuid,
+            ) -> Result<$crate::inventory_store::StockLevel, InventoryStoreError> {
+                self.0.get_stock(product_id).await
+            }
+
+            async fn set_available(
+                &self,
+                product_id: Uuid,
+                available: i32,
+            ) -> Result<$crate::inventory_store::StockLevel, InventoryStoreError> {
+                self.0.set_available(product_id, available).await
+            }
+
+            async fn reserve(&self, product_id: Uuid, quantity: i32) -> Result<(), InventoryStoreError> {
+                self.0.reserve(product_id, quantity).await
+            }
+
+            async fn release(&self, product_id: Uuid, quantity: i32) -> Result<(), InventoryStoreError> {
+                self.0.release(product_id, quantity).await
+            }
+
+            async fn commit(&self, _product_id: Uuid, _quantity: i32) -> Result<(), InventoryStoreError> {
+                Err(InventoryStoreError::StoreUnavailable)
+            }
+
+            async fn restock(&self, product_id: Uuid, quantity: i32) -> Result<(), InventoryStoreError> {
+                self.0.restock(product_id, quantity).await
+            }
+        }
+
+        fn address(country: &str) -> Address {
+            Address {
+                name: "Ada Lovelace".to_string(),
+                line1: "1 Main St".to_string(),
+                line2: None,
+                city: "Springfield".to_string(),
+                region: None,
+                postal_code: "12345".to_string(),
+                country: country.to_string(),
+            }
+        }
+
+        fn product_details(sku: &str) -> ProductDetails {
+            ProductDetails {
+                name: format!("Product {sku}"),
+                sku: sku.to_string(),
+                price: dec!(10.00),
+                currency: "USD".to_string(),
+                tax_category: "standard".to_string(),
+                active: true,
+            }
+        }
+
+        async fn add_product(products: &ProductStoreNewType, details: ProductDetails) -> Product {
+            products.create_product(details).await.unwrap()
+        }
+
+        async fn stock(ctx: &Context, product_id: Uuid) -> (i32, i32, i32) {
+            let level = ctx.inventory.get_stock(product_id).await.unwrap();
+            (level.available, level.reserved, level.committed)
+        }
+
+        struct Context {
+            user_id_1: Uuid,
+            user_id_2: Uuid,
+            products: Arc<ProductStoreNewType>,
+            inventory: Arc<InventoryStoreNewType>,
+            promotions: Arc<PromotionStoreNewType>,
+            product_id_0: Uuid,
+            product_id_1: Uuid,
+            store: OrderStoreNewType,
+            order_1_user_1: Order,
+        }
+
+        #[async_trait::async_trait]
+        impl AsyncTestContext for Context {
+            async fn setup() -> Context {
+                let user_id_1 = Uuid::new_v4();
+                let products = catalog();
+                let product_0 = add_product(&products, product_details("SKU-0")).await;
+                let product_1 = add_product(
+                    &products,
+                    ProductDetails {
+                        price: dec!(2.35),
+                        ..product_details("SKU-1")
+                    },
+                )
+                .await;
+                let inventory = inventory();
+                for product_id in [product_0.id, product_1.id] {
+                    inventory.set_available(product_id, 100).await.unwrap();
+                }
+                let promotions = promotions();
+                let store = OrderStoreNewType::new($new_store(
+                    products.clone(),
+                    inventory.clone(),
+                    promotions.clone(),
+                    Arc::new(RulesTableTaxCalculator::tax_free()),
+                    Arc::new(FakePaymentProvider::new()),
+                ));
+                let order = store.create_order(user_id_1).await;
+                let ctx = Context {
+                    user_id_1,
+                    user_id_2: Uuid::new_v4(),
+                    products,
+                    inventory,
+                    promotions,
+                    product_id_0: product_0.id,
+                    product_id_1: product_1.id,
+                    store,
+                    order_1_user_1: order.unwrap(),
+                };
+                _ = ctx.store.create_order(ctx.user_id_2).await;
+                _ = ctx.store.create_order(ctx.user_id_1).await;
+
+                ctx
+            }
+            async fn teardown(self) {}
+        }
+    };
+}
+
+pub(crate) use order_store_tests;
@@ -0,0 +1,38 @@
+use futures::stream::BoxStream;
+use uuid::Uuid;
+
+use crate::order_store::{Order, OrderStoreError};
+
+/// Orders a subscriber follows the changes of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OrderFilter {
+    /// The order with the provided id.
+    Order(Uuid),
+    /// Every order of the user with the provided id.
+    User(Uuid),
+}
+
+impl OrderFilter {
+    /// Tells whether the changes of `order` are followed.
+    pub fn matches(&self, order: &Order) -> bool {
+        match self {
+            OrderFilter::Order(id) => order.id == *id,
+            OrderFilter::User(user_id) => order.user_id == *user_id,
+        }
+    }
+}
+
+/// A trait that defines the behavior of a type used to follow the changes of orders as they happen.
+#[async_trait::async_trait]
+pub trait OrderUpdates: Send + Sync + 'static {
+    /// Returns a stream with the new state of every order matching `filter` changed from now on,
+    /// in the order the changes happened.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](OrderStoreError::StoreUnavailable) if the changes cannot be followed.
+    async fn subscribe(
+        &self,
+        filter: OrderFilter,
+    ) -> Result<BoxStream<'static, Order>, OrderStoreError>;
+}
@@ -0,0 +1,174 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{api::response::readable, order_events::OrderEvent, order_store::OrderStoreError};
+
+/// Delay before the first retry of a message the sink could not take.
+const FIRST_RETRY: Duration = Duration::from_secs(1);
+/// Longest delay between two deliveries of the same message.
+const LAST_RETRY: Duration = Duration::from_secs(300);
+
+/// Change of an order published to other services.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DomainEvent {
+    /// Identifies the event, so consumers can discard the ones delivered more than once.
+    pub id: Uuid,
+    /// Name of the event, e.g. `ItemAdded`.
+    pub event_type: String,
+    pub order_id: Uuid,
+    pub user_id: Uuid,
+    pub occurred_at: DateTime<Utc>,
+    pub event: OrderEvent,
+}
+
+impl DomainEvent {
+    pub fn new(order_id: Uuid, user_id: Uuid, event: OrderEvent) -> DomainEvent {
+        DomainEvent {
+            id: Uuid::new_v4(),
+            event_type: event.name().to_string(),
+            order_id,
+            user_id,
+            occurred_at: Utc::now(),
+            event,
+        }
+    }
+
+    /// Renders the event as JSON with UUIDs and amounts as strings, the way sinks publish it.
+    pub fn to_json(&self) -> Value {
+        mongodb::bson::to_bson(self)
+            .map(readable)
+            .expect("domain events are serializable")
+    }
+}
+
+/// A domain event waiting in the outbox to be delivered.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OutboxMessage {
+    pub id: Uuid,
+    pub event: DomainEvent,
+    /// Kept as milliseconds so pending messages can be sorted and filtered by it.
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    pub recorded_at: DateTime<Utc>,
+    /// Number of failed deliveries.
+    pub attempts: i32,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub delivered: bool,
+}
+
+impl OutboxMessage {
+    pub fn new(event: DomainEvent) -> OutboxMessage {
+        let now = Utc::now();
+        OutboxMessage {
+            id: event.id,
+            event,
+            recorded_at: now,
+            attempts: 0,
+            next_attempt_at: now,
+            last_error: None,
+            delivered: false,
+        }
+    }
+}
+
+/// Delay before delivering again a message that failed `attempts` times, doubling from
+/// [`FIRST_RETRY`] up to [`LAST_RETRY`].
+pub fn retry_delay(attempts: i32) -> Duration {
+    let exponent = attempts.clamp(1, 16) as u32 - 1;
+    (FIRST_RETRY * 2u32.pow(exponent)).min(LAST_RETRY)
+}
+
+/// A trait that defines the behavior of the outbox that order stores write their domain events to,
+/// as read by the [`OutboxRelay`](crate::outbox_relay::OutboxRelay).
+#[async_trait::async_trait]
+pub trait Outbox: Send + Sync + 'static {
+    /// Returns up to `limit` undelivered messages due by `now`, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](OrderStoreError::StoreUnavailable) if the outbox cannot be read.
+    async fn pending(
+        &self,
+        now: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<OutboxMessage>, OrderStoreError>;
+
+    /// Marks the message with id `id` as delivered, so it is not delivered again.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](OrderStoreError::StoreUnavailable) if the outbox cannot be written.
+    async fn mark_delivered(&self, id: Uuid) -> Result<(), OrderStoreError>;
+
+    /// Records a failed delivery of the message with id `id`, which is retried at `next_attempt_at`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](OrderStoreError::StoreUnavailable) if the outbox cannot be written.
+    async fn mark_failed(
+        &self,
+        id: Uuid,
+        error: String,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<(), OrderStoreError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::{
+        fulfilment::{Address, ShippingMethod},
+        order_store::OrderStatus,
+    };
+
+    #[test]
+    fn retries_back_off_exponentially_up_to_a_limit() {
+        assert_eq!(retry_delay(1), Duration::from_secs(1));
+        assert_eq!(retry_delay(2), Duration::from_secs(2));
+        assert_eq!(retry_delay(5), Duration::from_secs(16));
+        assert_eq!(retry_delay(30), LAST_RETRY);
+    }
+
+    #[test]
+    fn events_are_published_with_readable_ids_and_amounts() {
+        let order_id = Uuid::new_v4();
+        let event = DomainEvent::new(
+            order_id,
+            Uuid::new_v4(),
+            OrderEvent::StatusChanged {
+                status: OrderStatus::Placed,
+            },
+        );
+        let json = event.to_json();
+        assert_eq!(json["event_type"], "StatusChanged");
+        assert_eq!(json["order_id"], order_id.to_string());
+        assert_eq!(json["event"]["StatusChanged"]["status"], "Placed");
+
+        let cost = DomainEvent::new(
+            order_id,
+            Uuid::new_v4(),
+            OrderEvent::ShippingSet {
+                address: Address {
+                    name: "Ada".to_string(),
+                    line1: "1 Main St".to_string(),
+                    line2: None,
+                    city: "Springfield".to_string(),
+                    region: None,
+                    postal_code: "12345".to_string(),
+                    country: "DE".to_string(),
+                },
+                method: ShippingMethod::Standard,
+                cost: dec!(4.99),
+                tax: Default::default(),
+            },
+        );
+        assert_eq!(cost.to_json()["event"]["ShippingSet"]["cost"], "4.99");
+    }
+}
@@ -0,0 +1,191 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use tokio::task::JoinHandle;
+use tracing::{error, warn};
+
+use crate::{
+    event_sink::EventSink,
+    order_store::OrderStoreError,
+    outbox::{retry_delay, Outbox},
+};
+
+/// Number of messages delivered per poll of the outbox.
+const BATCH_SIZE: usize = 100;
+
+/// Background task delivering the messages of an [`Outbox`](Outbox) to an [`EventSink`](EventSink).
+///
+/// A message is marked as delivered only after the sink took it, so a crash in between delivers
+/// it again: delivery is at least once. Failed deliveries are retried with exponential backoff.
+pub struct OutboxRelay {
+    outbox: Arc<dyn Outbox>,
+    sink: Arc<dyn EventSink>,
+    poll_interval: Duration,
+}
+
+impl OutboxRelay {
+    /// Creates a new relay checking `outbox` for pending messages every `poll_interval`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let relay = OutboxRelay::new(outbox, Arc::new(StdoutEventSink::new()), Duration::from_secs(1));
+    /// ```
+    pub fn new(
+        outbox: Arc<dyn Outbox>,
+        sink: Arc<dyn EventSink>,
+        poll_interval: Duration,
+    ) -> OutboxRelay {
+        OutboxRelay {
+            outbox,
+            sink,
+            poll_interval,
+        }
+    }
+
+    /// Delivers the messages that are due and returns how many the sink took.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](OrderStoreError::StoreUnavailable) if the outbox cannot be
+    /// read or written; the messages not marked yet are delivered again on the next run.
+    pub async fn relay_pending(&self) -> Result<usize, OrderStoreError> {
+        let mut delivered = 0;
+        for message in self.outbox.pending(Utc::now(), BATCH_SIZE).await? {
+            match self.sink.publish(&message.event).await {
+                Ok(()) => {
+                    self.outbox.mark_delivered(message.id).await?;
+                    delivered += 1;
+                }
+                Err(err) => {
+                    let delay = retry_delay(message.attempts + 1);
+                    warn!(
+                        "delivery {} of event {} failed, retrying in {:?}: {}",
+                        message.attempts + 1,
+                        message.id,
+                        delay,
+                        err
+                    );
+                    let next_attempt_at = Utc::now()
+                        + chrono::Duration::from_std(delay).expect("retry delays are short");
+                    self.outbox
+                        .mark_failed(message.id, err.to_string(), next_attempt_at)
+                        .await?;
+                }
+            }
+        }
+        Ok(delivered)
+    }
+
+    /// Runs the relay in the background until the runtime shuts down.
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                if let Err(err) = self.relay_pending().await {
+                    error!("outbox relay failed: {}", err);
+                }
+                tokio::time::sleep(self.poll_interval).await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        event_sink::EventSinkError,
+        in_mem_outbox::InMemOutbox,
+        order_events::OrderEvent,
+        outbox::{DomainEvent, OutboxMessage},
+    };
+
+    /// Sink failing its first `failures` deliveries and keeping the ids of the events it took.
+    struct FlakySink {
+        failures: Mutex<usize>,
+        published: Mutex<Vec<Uuid>>,
+    }
+
+    impl FlakySink {
+        fn new(failures: usize) -> FlakySink {
+            FlakySink {
+                failures: Mutex::new(failures),
+                published: Mutex::new(vec![]),
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl EventSink for FlakySink {
+        async fn publish(&self, event: &DomainEvent) -> Result<(), EventSinkError> {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return Err(EventSinkError::Unavailable(
+                    "connection refused".to_string(),
+                ));
+            }
+            self.published.lock().unwrap().push(event.id);
+            Ok(())
+        }
+    }
+
+    fn message() -> OutboxMessage {
+        let order_id = Uuid::new_v4();
+        OutboxMessage::new(DomainEvent::new(
+            order_id,
+            Uuid::new_v4(),
+            OrderEvent::OrderCreated {
+                order_id,
+                user_id: Uuid::new_v4(),
+                created_at: Utc::now(),
+            },
+        ))
+    }
+
+    #[tokio::test]
+    async fn delivered_messages_are_not_delivered_again() {
+        let outbox = Arc::new(InMemOutbox::new());
+        let (first, second) = (message(), message());
+        outbox.add(first.clone());
+        outbox.add(second.clone());
+        let sink = Arc::new(FlakySink::new(0));
+        let relay = OutboxRelay::new(outbox.clone(), sink.clone(), Duration::from_secs(1));
+
+        assert_eq!(relay.relay_pending().await.unwrap(), 2);
+        assert_eq!(relay.relay_pending().await.unwrap(), 0);
+        assert_eq!(*sink.published.lock().unwrap(), vec![first.id, second.id]);
+    }
+
+    #[tokio::test]
+    async fn failed_deliveries_are_retried_later() {
+        let outbox = Arc::new(InMemOutbox::new());
+        let message = message();
+        outbox.add(message.clone());
+        let sink = Arc::new(FlakySink::new(1));
+        let relay = OutboxRelay::new(outbox.clone(), sink.clone(), Duration::from_secs(1));
+
+        assert_eq!(relay.relay_pending().await.unwrap(), 0);
+        let failed = outbox.messages().remove(0);
+        assert_eq!(failed.attempts, 1);
+        assert_eq!(
+            failed.last_error.as_deref(),
+            Some("Event sink unavailable: connection refused")
+        );
+        assert!(failed.next_attempt_at > Utc::now());
+        // not due yet
+        assert_eq!(relay.relay_pending().await.unwrap(), 0);
+
+        // skip the wait
+        outbox
+            .mark_failed(message.id, "skipped".to_string(), Utc::now())
+            .await
+            .unwrap();
+        assert_eq!(relay.relay_pending().await.unwrap(), 1);
+        assert_eq!(*sink.published.lock().unwrap(), vec![message.id]);
+    }
+}
@@ -0,0 +1,103 @@
+use std::{error::Error, fmt::Display};
+
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// What a [`PaymentProvider`](PaymentProvider) is asked to charge.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Charge {
+    /// Id of the attempt, so providers can recognize retries of the same charge.
+    pub attempt_id: Uuid,
+    /// Id of the order being paid.
+    pub order_id: Uuid,
+    pub amount: Decimal,
+    /// ISO 4217 code of the currency of `amount`.
+    pub currency: String,
+    /// Opaque reference to the payment method, as issued by the provider to the client.
+    pub payment_token: String,
+}
+
+/// What a [`PaymentProvider`](PaymentProvider) is asked to give back.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Refund {
+    /// Id of the refund, the id of the return it pays for or of the payment attempt it gives back,
+    /// so providers can recognize retries of the same refund.
+    pub refund_id: Uuid,
+    /// Provider reference of the approved payment being refunded.
+    pub payment_reference: String,
+    pub amount: Decimal,
+    /// ISO 4217 code of the currency of `amount`.
+    pub currency: String,
+}
+
+/// How a payment attempt ended.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PaymentOutcome {
+    /// The money was charged; `reference` identifies the charge at the provider.
+    Approved { reference: String },
+    /// The provider refused to charge the payment method.
+    Declined { reason: String },
+    /// The provider could not be reached, nothing was charged; or the charge was given back
+    /// because the stock of the order could not be committed.
+    Failed,
+    /// The charge is being sent to the provider; an attempt stays pending when its outcome could
+    /// not be recorded, and the next checkout retries it.
+    Pending,
+}
+
+/// A payment attempt of an order, as recorded on the order.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PaymentAttempt {
+    pub id: Uuid,
+    #[serde(with = "crate::decimal128")]
+    pub amount: Decimal,
+    pub currency: String,
+    pub attempted_at: DateTime<Utc>,
+    pub outcome: PaymentOutcome,
+}
+
+/// Type for describing errors that result from trying to charge with a [`PaymentProvider`](PaymentProvider).
+#[derive(Debug)]
+pub enum PaymentError {
+    /// The payment provider cannot be reached.
+    ProviderUnavailable,
+}
+
+impl Display for PaymentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PaymentError::ProviderUnavailable => {
+                write!(f, "Payment provider unavailable")
+            }
+        }
+    }
+}
+
+impl Error for PaymentError {}
+
+/// A trait that defines the behavior of a type used to charge customers for their orders.
+#[async_trait::async_trait]
+pub trait PaymentProvider: Send + Sync + 'static {
+    /// Charges `charge.amount` to the payment method behind `charge.payment_token`.
+    ///
+    /// Returns [`Approved`](PaymentOutcome::Approved) or [`Declined`](PaymentOutcome::Declined),
+    /// never [`Failed`](PaymentOutcome::Failed), which is reserved for errors, nor
+    /// [`Pending`](PaymentOutcome::Pending). Charges with the same `attempt_id` are retries of
+    /// the same charge and must be charged at most once.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ProviderUnavailable`](PaymentError::ProviderUnavailable) if the provider cannot be reached.
+    async fn charge(&self, charge: &Charge) -> Result<PaymentOutcome, PaymentError>;
+
+    /// Gives `refund.amount` of an approved payment back to the customer and returns the
+    /// provider reference of the refund. Refunds with the same `refund_id` are retries of the
+    /// same refund and must be paid at most once.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ProviderUnavailable`](PaymentError::ProviderUnavailable) if the provider cannot be reached.
+    async fn refund(&self, refund: &Refund) -> Result<String, PaymentError>;
+}
@@ -0,0 +1,197 @@
+use std::{error::Error, fmt::Display, ops::Deref};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub struct ProductStoreNewType(pub Box<dyn ProductStore>); // same idea as `OrderStoreNewType`: a tuple struct wrapping
+                                                           // whatever implements `ProductStore`
+
+impl ProductStoreNewType {
+    pub fn new(repo: impl ProductStore) -> ProductStoreNewType {
+        ProductStoreNewType(Box::new(repo))
+    }
+}
+
+impl Deref for ProductStoreNewType {
+    type Target = dyn ProductStore;
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+/// Editable attributes of a product.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProductDetails {
+    /// Human readable name of the product.
+    pub name: String,
+    /// Stock keeping unit, unique across the catalog.
+    pub sku: String,
+    /// Unit price of the product.
+    pub price: Decimal,
+    /// ISO 4217 code of the currency `price` is expressed in.
+    pub currency: String,
+    /// Category used to look up the tax rate of the product, like `standard` or `reduced`.
+    pub tax_category: String,
+    /// Only active products can be added to orders.
+    pub active: bool,
+}
+
+impl ProductDetails {
+    /// Checks the details can be saved.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`InvalidPrice`](ProductStoreError::InvalidPrice) if the price is not positive.
+    pub fn validate(&self) -> Result<(), ProductStoreError> {
+        if self.price <= Decimal::ZERO {
+            return Err(ProductStoreError::InvalidPrice(self.price));
+        }
+        Ok(())
+    }
+}
+
+/// Representation of a product in the catalog.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Product {
+    /// A product is identified by its id.
+    pub id: Uuid,
+    /// Human readable name of the product.
+    pub name: String,
+    /// Stock keeping unit, unique across the catalog.
+    pub sku: String,
+    /// Unit price of the product.
+    #[serde(with = "crate::decimal128")]
+    pub price: Decimal,
+    /// ISO 4217 code of the currency `price` is expressed in.
+    pub currency: String,
+    /// Category used to look up the tax rate of the product, like `standard` or `reduced`.
+    pub tax_category: String,
+    /// Only active products can be added to orders.
+    pub active: bool,
+}
+
+impl Product {
+    /// Creates a new product with a fresh id from `details`.
+    pub fn new(details: ProductDetails) -> Product {
+        Product::with_id(Uuid::new_v4(), details)
+    }
+
+    /// Creates a product with id `id` from `details`.
+    pub fn with_id(id: Uuid, details: ProductDetails) -> Product {
+        Product {
+            id,
+            name: details.name,
+            sku: details.sku,
+            price: details.price,
+            currency: details.currency,
+            tax_category: details.tax_category,
+            active: details.active,
+        }
+    }
+}
+
+/// Type for describing errors that result from trying to interact with a [`ProductStore`](ProductStore).
+#[derive(Debug)]
+pub enum ProductStoreError {
+    /// The store is unavailable.
+    StoreUnavailable,
+    /// Provided product id was not found in the store.
+    ProductNotFound(Uuid),
+    /// Another product already uses the provided SKU.
+    DuplicateSku(String),
+    /// The price of the product is zero or negative.
+    InvalidPrice(Decimal),
+}
+
+impl Display for ProductStoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProductStoreError::StoreUnavailable => {
+                write!(f, "Store unavailable")
+            }
+            ProductStoreError::ProductNotFound(id) => {
+                write!(f, "Product not found {}", id)
+            }
+            ProductStoreError::DuplicateSku(sku) => {
+                write!(f, "Duplicate SKU: {}", sku)
+            }
+            ProductStoreError::InvalidPrice(price) => {
+                write!(f, "Invalid price: {}", price)
+            }
+        }
+    }
+}
+
+impl Error for ProductStoreError {}
+
+/// A trait that defines the behavior of a type used to store the product catalog.
+#[async_trait::async_trait]
+pub trait ProductStore: Send + Sync + 'static {
+    /// Creates a new product from `details`.
+    ///
+    /// Returns a copy of the product on success, otherwise it returns an error.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](ProductStoreError::StoreUnavailable) if the Store cannot be used to create a product.
+    ///
+    /// Returns [`DuplicateSku`](ProductStoreError::DuplicateSku) if another product already uses the SKU.
+    ///
+    /// Returns [`InvalidPrice`](ProductStoreError::InvalidPrice) if the price is not positive.
+    async fn create_product(&self, details: ProductDetails) -> Result<Product, ProductStoreError>;
+
+    /// Gets a product from its id.
+    ///
+    /// Returns a copy of the product on success, otherwise it returns an error.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](ProductStoreError::StoreUnavailable) if the Store cannot be used to get a product.
+    ///
+    /// Returns [`ProductNotFound`](ProductStoreError::ProductNotFound) if there is no product with the provided id in the Store.
+    async fn get_product(&self, product_id: Uuid) -> Result<Product, ProductStoreError>;
+
+    /// Gets the products with the ids in `product_ids` in a single lookup. Ids that are not in
+    /// the Store are left out of the result.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](ProductStoreError::StoreUnavailable) if the Store cannot be used to get products.
+    async fn get_products(&self, product_ids: &[Uuid]) -> Result<Vec<Product>, ProductStoreError>;
+
+    /// Returns every product in the catalog.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](ProductStoreError::StoreUnavailable) if the Store cannot be used to list products.
+    async fn list_products(&self) -> Result<Vec<Product>, ProductStoreError>;
+
+    /// Replaces the details of the product with id `product_id`.
+    ///
+    /// Returns a copy of the updated product on success, otherwise it returns an error.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](ProductStoreError::StoreUnavailable) if the Store cannot be used to update a product.
+    ///
+    /// Returns [`ProductNotFound`](ProductStoreError::ProductNotFound) if there is no product with the provided id in the Store.
+    ///
+    /// Returns [`DuplicateSku`](ProductStoreError::DuplicateSku) if another product already uses the SKU.
+    ///
+    /// Returns [`InvalidPrice`](ProductStoreError::InvalidPrice) if the price is not positive.
+    async fn update_product(
+        &self,
+        product_id: Uuid,
+        details: ProductDetails,
+    ) -> Result<Product, ProductStoreError>;
+
+    /// Removes the product with id `product_id` from the catalog.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](ProductStoreError::StoreUnavailable) if the Store cannot be used to delete a product.
+    ///
+    /// Returns [`ProductNotFound`](ProductStoreError::ProductNotFound) if there is no product with the provided id in the Store.
+    async fn delete_product(&self, product_id: Uuid) -> Result<(), ProductStoreError>;
+}
@@ -0,0 +1,121 @@
+use std::{error::Error, fmt::Display, ops::Deref};
+
+use uuid::Uuid;
+
+use crate::promotions::Coupon;
+
+pub struct PromotionStoreNewType(pub Box<dyn PromotionStore>); // same idea as `OrderStoreNewType`
+
+impl PromotionStoreNewType {
+    pub fn new(repo: impl PromotionStore) -> PromotionStoreNewType {
+        PromotionStoreNewType(Box::new(repo))
+    }
+}
+
+impl Deref for PromotionStoreNewType {
+    type Target = dyn PromotionStore;
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+/// Type for describing errors that result from trying to interact with a [`PromotionStore`](PromotionStore).
+#[derive(Debug)]
+pub enum PromotionStoreError {
+    /// The store is unavailable.
+    StoreUnavailable,
+    /// Provided coupon code was not found in the store.
+    CouponNotFound(String),
+    /// Another coupon already uses the provided code.
+    DuplicateCoupon(String),
+    /// The user already used the coupon as many times as allowed.
+    UsageLimitReached(String),
+    /// A field of the coupon rule is out of range.
+    InvalidRule(String),
+}
+
+impl Display for PromotionStoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PromotionStoreError::StoreUnavailable => {
+                write!(f, "Store unavailable")
+            }
+            PromotionStoreError::CouponNotFound(code) => {
+                write!(f, "Coupon not found {}", code)
+            }
+            PromotionStoreError::DuplicateCoupon(code) => {
+                write!(f, "Duplicate coupon: {}", code)
+            }
+            PromotionStoreError::UsageLimitReached(code) => {
+                write!(f, "Usage limit reached for coupon {}", code)
+            }
+            PromotionStoreError::InvalidRule(field) => {
+                write!(f, "Invalid promotion rule: {}", field)
+            }
+        }
+    }
+}
+
+impl Error for PromotionStoreError {}
+
+/// Checks `coupon` can be saved.
+///
+/// # Errors
+///
+/// Returns [`InvalidRule`](PromotionStoreError::InvalidRule) with the name of the field of the
+/// rule that is out of range.
+pub fn validate(coupon: &Coupon) -> Result<(), PromotionStoreError> {
+    match coupon.rule.invalid_field() {
+        Some(field) => Err(PromotionStoreError::InvalidRule(field.to_string())),
+        None => Ok(()),
+    }
+}
+
+/// A trait that defines the behavior of a type used to store coupons and how often they were used.
+#[async_trait::async_trait]
+pub trait PromotionStore: Send + Sync + 'static {
+    /// Stores a new coupon.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](PromotionStoreError::StoreUnavailable) if the Store cannot be used to create a coupon.
+    ///
+    /// Returns [`DuplicateCoupon`](PromotionStoreError::DuplicateCoupon) if another coupon already uses the code.
+    ///
+    /// Returns [`InvalidRule`](PromotionStoreError::InvalidRule) if a field of the rule is out of range.
+    async fn create_coupon(&self, coupon: Coupon) -> Result<Coupon, PromotionStoreError>;
+
+    /// Gets a coupon from its code.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](PromotionStoreError::StoreUnavailable) if the Store cannot be used to get a coupon.
+    ///
+    /// Returns [`CouponNotFound`](PromotionStoreError::CouponNotFound) if there is no coupon with the provided code.
+    async fn get_coupon(&self, code: &str) -> Result<Coupon, PromotionStoreError>;
+
+    /// Returns every coupon in the store.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](PromotionStoreError::StoreUnavailable) if the Store cannot be used to list coupons.
+    async fn list_coupons(&self) -> Result<Vec<Coupon>, PromotionStoreError>;
+
+    /// Records that user `user_id` used the coupon with code `code`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](PromotionStoreError::StoreUnavailable) if the Store cannot be used to record the usage.
+    ///
+    /// Returns [`CouponNotFound`](PromotionStoreError::CouponNotFound) if there is no coupon with the provided code.
+    ///
+    /// Returns [`UsageLimitReached`](PromotionStoreError::UsageLimitReached) if the user cannot use the coupon anymore.
+    async fn redeem(&self, code: &str, user_id: Uuid) -> Result<(), PromotionStoreError>;
+
+    /// Forgets one usage of the coupon with code `code` by user `user_id`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](PromotionStoreError::StoreUnavailable) if the Store cannot be used to update the usage.
+    async fn release(&self, code: &str, user_id: Uuid) -> Result<(), PromotionStoreError>;
+}
@@ -0,0 +1,327 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::order_store::Item;
+
+/// How a coupon reduces the price of an order.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PromotionRule {
+    /// Takes `percent` percent off the subtotal.
+    Percentage {
+        #[serde(with = "crate::decimal128")]
+        percent: Decimal,
+    },
+    /// Takes a fixed `amount` off the subtotal of orders in `currency`.
+    FixedAmount {
+        #[serde(with = "crate::decimal128")]
+        amount: Decimal,
+        #[serde(default = "default_currency")]
+        currency: String,
+    },
+    /// For every `buy` units of `product_id`, the next `get` units are free.
+    BuyXGetY {
+        product_id: Uuid,
+        buy: i32,
+        get: i32,
+    },
+    /// Takes a fixed `amount` off the subtotal of orders in `currency` once it reaches `minimum`.
+    MinimumSpend {
+        #[serde(with = "crate::decimal128")]
+        minimum: Decimal,
+        #[serde(with = "crate::decimal128")]
+        amount: Decimal,
+        #[serde(default = "default_currency")]
+        currency: String,
+    },
+}
+
+/// Currency of the amounts of coupons stored before they had one, the default currency of products.
+fn default_currency() -> String {
+    "USD".to_string()
+}
+
+impl PromotionRule {
+    /// Returns the name of the first field out of range, `None` if the rule is valid: percentages
+    /// go from 0 to 100, amounts are not negative and come with an ISO 4217 currency code, `buy`
+    /// and `get` are positive.
+    pub fn invalid_field(&self) -> Option<&'static str> {
+        match self {
+            PromotionRule::Percentage { percent }
+                if *percent < Decimal::ZERO || *percent > Decimal::ONE_HUNDRED =>
+            {
+                Some("percent")
+            }
+            PromotionRule::FixedAmount { amount, .. }
+            | PromotionRule::MinimumSpend { amount, .. }
+                if *amount < Decimal::ZERO =>
+            {
+                Some("amount")
+            }
+            PromotionRule::MinimumSpend { minimum, .. } if *minimum < Decimal::ZERO => {
+                Some("minimum")
+            }
+            PromotionRule::FixedAmount { currency, .. }
+            | PromotionRule::MinimumSpend { currency, .. }
+                if currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_uppercase()) =>
+            {
+                Some("currency")
+            }
+            PromotionRule::BuyXGetY { buy, .. } if *buy <= 0 => Some("buy"),
+            PromotionRule::BuyXGetY { get, .. } if *get <= 0 => Some("get"),
+            _ => None,
+        }
+    }
+}
+
+/// A discount code that can be applied to orders.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Coupon {
+    /// Code customers type in to get the discount.
+    pub code: String,
+    /// Discount granted by the coupon.
+    pub rule: PromotionRule,
+    /// The coupon cannot be applied before this instant.
+    pub valid_from: Option<DateTime<Utc>>,
+    /// The coupon cannot be applied after this instant.
+    pub valid_until: Option<DateTime<Utc>>,
+    /// How many orders of the same user can use the coupon, unlimited when `None`.
+    pub max_uses_per_user: Option<u32>,
+}
+
+impl Coupon {
+    /// Tells whether `now` falls inside the validity window of the coupon.
+    pub fn is_valid_at(&self, now: DateTime<Utc>) -> bool {
+        self.valid_from.is_none_or(|from| from <= now)
+            && self.valid_until.is_none_or(|until| now <= until)
+    }
+
+    /// Returns the discount this coupon grants to an order with `items` and `subtotal`.
+    ///
+    /// Amounts derived from percentages are rounded to two decimal places. Fixed amounts are only
+    /// taken off orders in the currency of the coupon.
+    pub fn discount(&self, items: &[Item], subtotal: Decimal) -> Decimal {
+        let in_currency =
+            |currency: &str| items.first().is_some_and(|item| item.currency == currency);
+        match &self.rule {
+            PromotionRule::Percentage { percent } => subtotal
+                .saturating_mul(*percent / Decimal::ONE_HUNDRED)
+                .round_dp(2),
+            PromotionRule::FixedAmount { amount, currency } if in_currency(currency) => *amount,
+            PromotionRule::FixedAmount { .. } => Decimal::ZERO,
+            PromotionRule::BuyXGetY {
+                product_id,
+                buy,
+                get,
+            } => {
+                let matching = items.iter().filter(|item| item.product_id == *product_id);
+                let quantity: i64 = matching.clone().map(|item| i64::from(item.quantity)).sum();
+                let cheapest = matching.map(|item| item.unit_price).min();
+                let group = i64::from(*buy) + i64::from(*get);
+                match cheapest {
+                    Some(unit_price) if *buy > 0 && *get > 0 => {
+                        unit_price.saturating_mul(Decimal::from(quantity / group * i64::from(*get)))
+                    }
+                    _ => Decimal::ZERO,
+                }
+            }
+            PromotionRule::MinimumSpend {
+                minimum,
+                amount,
+                currency,
+            } => {
+                if subtotal >= *minimum && in_currency(currency) {
+                    *amount
+                } else {
+                    Decimal::ZERO
+                }
+            }
+        }
+    }
+}
+
+/// Returns the discount `coupons` grant together, never more than `subtotal`.
+pub fn total_discount(coupons: &[Coupon], items: &[Item], subtotal: Decimal) -> Decimal {
+    coupons
+        .iter()
+        .map(|coupon| coupon.discount(items, subtotal))
+        .fold(Decimal::ZERO, Decimal::saturating_add)
+        .clamp(Decimal::ZERO, subtotal)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    fn coupon(rule: PromotionRule) -> Coupon {
+        Coupon {
+            code: "SAVE".to_string(),
+            rule,
+            valid_from: None,
+            valid_until: None,
+            max_uses_per_user: None,
+        }
+    }
+
+    fn item(product_id: Uuid, quantity: i32, unit_price: Decimal) -> Item {
+        Item {
+            product_id,
+            quantity,
+            unit_price,
+            currency: "USD".to_string(),
+            tax_category: "standard".to_string(),
+            line_total: unit_price * Decimal::from(quantity),
+        }
+    }
+
+    #[test]
+    fn percentage_is_taken_off_the_subtotal() {
+        let coupon = coupon(PromotionRule::Percentage { percent: dec!(15) });
+        assert_eq!(coupon.discount(&[], dec!(33.33)), dec!(5.00));
+    }
+
+    #[test]
+    fn buy_x_get_y_makes_every_group_of_units_partially_free() {
+        let product_id = Uuid::new_v4();
+        let coupon = coupon(PromotionRule::BuyXGetY {
+            product_id,
+            buy: 2,
+            get: 1,
+        });
+        let items = [
+            item(product_id, 5, dec!(3.00)),
+            item(Uuid::new_v4(), 9, dec!(1.00)),
+            item(product_id, 2, dec!(2.50)),
+        ];
+        assert_eq!(coupon.discount(&items, dec!(29.00)), dec!(5.00));
+    }
+
+    #[test]
+    fn buy_x_get_y_does_not_overflow_with_large_groups() {
+        let product_id = Uuid::new_v4();
+        let coupon = coupon(PromotionRule::BuyXGetY {
+            product_id,
+            buy: i32::MAX,
+            get: i32::MAX,
+        });
+        let items = [item(product_id, i32::MAX, dec!(1.00))];
+        assert_eq!(coupon.discount(&items, dec!(1.00)), Decimal::ZERO);
+    }
+
+    #[test]
+    fn minimum_spend_only_applies_from_the_minimum() {
+        let coupon = coupon(PromotionRule::MinimumSpend {
+            minimum: dec!(50),
+            amount: dec!(10),
+            currency: "USD".to_string(),
+        });
+        let items = [item(Uuid::new_v4(), 1, dec!(50))];
+        assert_eq!(coupon.discount(&items, dec!(49.99)), Decimal::ZERO);
+        assert_eq!(coupon.discount(&items, dec!(50.00)), dec!(10));
+    }
+
+    #[test]
+    fn fixed_amounts_only_apply_to_orders_in_their_currency() {
+        let coupon = coupon(PromotionRule::FixedAmount {
+            amount: dec!(5),
+            currency: "EUR".to_string(),
+        });
+        let items = [item(Uuid::new_v4(), 1, dec!(20))];
+        assert_eq!(coupon.discount(&items, dec!(20)), Decimal::ZERO);
+    }
+
+    #[test]
+    fn total_discount_never_exceeds_the_subtotal() {
+        let coupons = [
+            coupon(PromotionRule::FixedAmount {
+                amount: dec!(20),
+                currency: "USD".to_string(),
+            }),
+            coupon(PromotionRule::Percentage { percent: dec!(50) }),
+        ];
+        let items = [item(Uuid::new_v4(), 1, dec!(30))];
+        assert_eq!(total_discount(&coupons, &items, dec!(30)), dec!(30));
+    }
+
+    #[test]
+    fn rules_out_of_range_are_invalid() {
+        let usd = || "USD".to_string();
+        let invalid = [
+            (PromotionRule::Percentage { percent: dec!(-1) }, "percent"),
+            (
+                PromotionRule::Percentage {
+                    percent: dec!(100.5),
+                },
+                "percent",
+            ),
+            (
+                PromotionRule::FixedAmount {
+                    amount: dec!(-5),
+                    currency: usd(),
+                },
+                "amount",
+            ),
+            (
+                PromotionRule::FixedAmount {
+                    amount: dec!(5),
+                    currency: "usd".to_string(),
+                },
+                "currency",
+            ),
+            (
+                PromotionRule::MinimumSpend {
+                    minimum: dec!(-1),
+                    amount: dec!(5),
+                    currency: usd(),
+                },
+                "minimum",
+            ),
+            (
+                PromotionRule::BuyXGetY {
+                    product_id: Uuid::new_v4(),
+                    buy: 2,
+                    get: -1,
+                },
+                "get",
+            ),
+        ];
+        for (rule, field) in invalid {
+            assert_eq!(rule.invalid_field(), Some(field), "{rule:?}");
+        }
+        assert_eq!(
+            PromotionRule::Percentage { percent: dec!(100) }.invalid_field(),
+            None
+        );
+    }
+
+    #[test]
+    fn coupon_round_trips_through_bson() {
+        let coupon = coupon(PromotionRule::MinimumSpend {
+            minimum: dec!(50.00),
+            amount: dec!(7.5),
+            currency: "EUR".to_string(),
+        });
+        let bytes = mongodb::bson::to_vec(&coupon).unwrap();
+        assert_eq!(mongodb::bson::from_slice::<Coupon>(&bytes).unwrap(), coupon);
+    }
+
+    #[test]
+    fn coupon_is_only_valid_inside_its_window() {
+        let now = Utc::now();
+        let coupon = Coupon {
+            valid_from: Some(now - Duration::days(1)),
+            valid_until: Some(now + Duration::days(1)),
+            ..coupon(PromotionRule::FixedAmount {
+                amount: dec!(1),
+                currency: "USD".to_string(),
+            })
+        };
+        assert!(coupon.is_valid_at(now));
+        assert!(!coupon.is_valid_at(now + Duration::days(2)));
+        assert!(!coupon.is_valid_at(now - Duration::days(2)));
+    }
+}
@@ -0,0 +1,601 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+use serde::Serialize;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    audit_log::AuditEntry,
+    fulfilment::{Address, ShippingMethod},
+    order_store::{Order, OrderStatus, OrderStore, OrderStoreError},
+    payment_provider::PaymentAttempt,
+    returns::{ReturnItem, ReturnRequest},
+};
+
+/// How reads failing with a [transient](OrderStoreError::is_transient) error are retried.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Attempts of a read, the first one included.
+    pub attempts: u32,
+    /// Longest wait before the first retry, doubled for every retry after it.
+    pub base_delay: Duration,
+    /// Longest wait before any retry.
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Random wait before retry number `retry`, starting at 0, so that callers failing together
+    /// don't retry together.
+    fn delay(&self, retry: u32) -> Duration {
+        let ceiling = (self.base_delay * 2u32.pow(retry.min(16))).min(self.max_delay);
+        ceiling.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BreakerState {
+    Closed { failures: u32 },
+    Open { since: Instant },
+    HalfOpen { since: Instant }, // a single trial call is let through
+}
+
+/// State of a [`CircuitBreaker`](CircuitBreaker) as reported on the readiness endpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum BreakerStatus {
+    Closed { consecutive_failures: u32 },
+    Open { retry_after_secs: u64 },
+    HalfOpen,
+}
+
+/// Stops calling the store after `failure_threshold` consecutive failures, failing fast for
+/// `open_for` before letting a trial call through to check whether it recovered.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    open_for: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    /// Creates a new closed breaker.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let breaker = CircuitBreaker::new(5, Duration::from_secs(30));
+    /// ```
+    pub fn new(failure_threshold: u32, open_for: Duration) -> CircuitBreaker {
+        CircuitBreaker {
+            failure_threshold,
+            open_for,
+            state: Mutex::new(BreakerState::Closed { failures: 0 }),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, BreakerState> {
+        // every update writes a whole state, a panic cannot leave it half updated
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// How long callers should wait before trying again, `None` when calls go through.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match *self.state() {
+            BreakerState::Open { since } => Some(self.open_for.saturating_sub(since.elapsed())),
+            BreakerState::HalfOpen { .. } | BreakerState::Closed { .. } => None,
+        }
+    }
+
+    /// [`retry_after`](Self::retry_after) in whole seconds, rounded up since callers waiting a bit
+    /// less would fail again.
+    pub fn retry_after_secs(&self) -> Option<u64> {
+        self.retry_after()
+            .map(|retry_after| retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0))
+    }
+
+    pub fn status(&self) -> BreakerStatus {
+        match *self.state() {
+            BreakerState::Closed { failures } => BreakerStatus::Closed {
+                consecutive_failures: failures,
+            },
+            BreakerState::Open { since } => BreakerStatus::Open {
+                retry_after_secs: self.open_for.saturating_sub(since.elapsed()).as_secs() + 1,
+            },
+            BreakerState::HalfOpen { .. } => BreakerStatus::HalfOpen,
+        }
+    }
+
+    /// Whether a call can be made now, moving to half-open once the breaker was open long enough.
+    fn allow(&self) -> bool {
+        let mut state = self.state();
+        match *state {
+            BreakerState::Closed { .. } => true,
+            // a trial whose caller went away must not keep the breaker half-open forever
+            BreakerState::Open { since } | BreakerState::HalfOpen { since }
+                if since.elapsed() >= self.open_for =>
+            {
+                *state = BreakerState::HalfOpen {
+                    since: Instant::now(),
+                };
+                true
+            }
+            BreakerState::Open { .. } | BreakerState::HalfOpen { .. } => false,
+        }
+    }
+
+    fn record(&self, succeeded: bool) {
+        let mut state = self.state();
+        *state = match (*state, succeeded) {
+            (_, true) => BreakerState::Closed { failures: 0 },
+            (BreakerState::Closed { failures }, false) if failures + 1 < self.failure_threshold => {
+                BreakerState::Closed {
+                    failures: failures + 1,
+                }
+            }
+            (BreakerState::Open { since }, false) => BreakerState::Open { since },
+            (_, false) => {
+                warn!("order store failing, opening its circuit breaker");
+                BreakerState::Open {
+                    since: Instant::now(),
+                }
+            }
+        };
+    }
+}
+
+/// Retries the reads of another [`OrderStore`](OrderStore) while it is unavailable and stops
+/// calling it through a [`CircuitBreaker`](CircuitBreaker) when it keeps failing.
+///
+/// Changes are not retried, as they may have been made before the store failed to answer.
+pub struct ResilientOrderStore<S: OrderStore> {
+    store: S,
+    retry: RetryPolicy,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl<S: OrderStore> ResilientOrderStore<S> {
+    /// Creates a new wrapper of `store`, sharing `breaker` with whoever reports its state.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let store = ResilientOrderStore::new(store, retry, Arc::new(CircuitBreaker::new(5, Duration::from_secs(30))));
+    /// ```
+    pub fn new(
+        store: S,
+        retry: RetryPolicy,
+        breaker: Arc<CircuitBreaker>,
+    ) -> ResilientOrderStore<S> {
+        ResilientOrderStore {
+            store,
+            retry,
+            breaker,
+        }
+    }
+
+    /// Makes `call` unless the breaker is open, recording whether the store answered.
+    async fn guarded<T>(
+        &self,
+        call: impl Future<Output = Result<T, OrderStoreError>>,
+    ) -> Result<T, OrderStoreError> {
+        if !self.breaker.allow() {
+            return Err(OrderStoreError::StoreUnavailable);
+        }
+        let result = call.await;
+        self.breaker
+            .record(!matches!(&result, Err(err) if err.is_transient()));
+        result
+    }
+
+    /// Makes the call built by `read` until the store answers, the attempts run out or the breaker opens.
+    async fn read<T, F, Fut>(&self, read: F) -> Result<T, OrderStoreError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, OrderStoreError>>,
+    {
+        let mut retry = 0;
+        loop {
+            match self.guarded(read()).await {
+                Err(err)
+                    if err.is_transient()
+                        && retry + 1 < self.retry.attempts
+                        && self.breaker.retry_after().is_none() =>
+                {
+                    tokio::time::sleep(self.retry.delay(retry)).await;
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: OrderStore> OrderStore for ResilientOrderStore<S> {
+    async fn create_order(&self, user_id: Uuid) -> Result<Order, OrderStoreError> {
+        self.guarded(self.store.create_order(user_id)).await
+    }
+
+    async fn get_order(&self, order_id: Uuid) -> Result<Order, OrderStoreError> {
+        self.read(|| self.store.get_order(order_id)).await
+    }
+
+    async fn list_orders(&self, user_id: Uuid) -> Result<Vec<Order>, OrderStoreError> {
+        self.read(|| self.store.list_orders(user_id)).await
+    }
+
+    async fn add_item(
+        &self,
+        order_id: Uuid,
+        product_id: Uuid,
+        quantity: i32,
+    ) -> Result<(), OrderStoreError> {
+        self.guarded(self.store.add_item(order_id, product_id, quantity))
+            .await
+    }
+
+    async fn delete_item(
+        &self,
+        order_id: Uuid,
+        index: usize,
+        expected: Option<Uuid>,
+    ) -> Result<(), OrderStoreError> {
+        self.guarded(self.store.delete_item(order_id, index, expected))
+            .await
+    }
+
+    async fn update_item_quantity(
+        &self,
+        order_id: Uuid,
+        index: usize,
+        quantity: i32,
+        expected: Option<Uuid>,
+    ) -> Result<(), OrderStoreError> {
+        self.guarded(
+            self.store
+                .update_item_quantity(order_id, index, quantity, expected),
+        )
+        .await
+    }
+
+    async fn update_status(
+        &self,
+        order_id: Uuid,
+        status: OrderStatus,
+    ) -> Result<(), OrderStoreError> {
+        self.guarded(self.store.update_status(order_id, status))
+            .await
+    }
+
+    async fn apply_coupon(&self, order_id: Uuid, code: &str) -> Result<(), OrderStoreError> {
+        self.guarded(self.store.apply_coupon(order_id, code)).await
+    }
+
+    async fn remove_coupon(&self, order_id: Uuid, code: &str) -> Result<(), OrderStoreError> {
+        self.guarded(self.store.remove_coupon(order_id, code)).await
+    }
+
+    async fn set_shipping(
+        &self,
+        order_id: Uuid,
+        address: Address,
+        method: ShippingMethod,
+    ) -> Result<(), OrderStoreError> {
+        self.guarded(self.store.set_shipping(order_id, address, method))
+            .await
+    }
+
+    async fn set_billing_address(
+        &self,
+        order_id: Uuid,
+        address: Address,
+    ) -> Result<(), OrderStoreError> {
+        self.guarded(self.store.set_billing_address(order_id, address))
+            .await
+    }
+
+    async fn checkout(
+        &self,
+        order_id: Uuid,
+        payment_token: &str,
+    ) -> Result<PaymentAttempt, OrderStoreError> {
+        self.guarded(self.store.checkout(order_id, payment_token))
+            .await
+    }
+
+    async fn request_return(
+        &self,
+        order_id: Uuid,
+        items: Vec<ReturnItem>,
+        reason: String,
+    ) -> Result<ReturnRequest, OrderStoreError> {
+        self.guarded(self.store.request_return(order_id, items, reason))
+            .await
+    }
+
+    async fn approve_return(
+        &self,
+        order_id: Uuid,
+        return_id: Uuid,
+    ) -> Result<ReturnRequest, OrderStoreError> {
+        self.guarded(self.store.approve_return(order_id, return_id))
+            .await
+    }
+
+    async fn reject_return(
+        &self,
+        order_id: Uuid,
+        return_id: Uuid,
+    ) -> Result<ReturnRequest, OrderStoreError> {
+        self.guarded(self.store.reject_return(order_id, return_id))
+            .await
+    }
+
+    async fn history(&self, order_id: Uuid) -> Result<Vec<AuditEntry>, OrderStoreError> {
+        self.read(|| self.store.history(order_id)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::{
+        in_mem_order_store::InMemOrderStore, payment_provider::PaymentProvider,
+        tax_calculator::TaxCalculator,
+    };
+
+    const RETRY: RetryPolicy = RetryPolicy {
+        attempts: 3,
+        base_delay: Duration::from_millis(1),
+        max_delay: Duration::from_millis(5),
+    };
+
+    fn new_store(
+        products: Arc<ProductStoreNewType>,
+        inventory: Arc<InventoryStoreNewType>,
+        promotions: Arc<PromotionStoreNewType>,
+        tax: Arc<dyn TaxCalculator>,
+        payments: Arc<dyn PaymentProvider>,
+    ) -> ResilientOrderStore<InMemOrderStore> {
+        ResilientOrderStore::new(
+            InMemOrderStore::new(products, inventory, promotions, tax, payments),
+            RETRY,
+            Arc::new(CircuitBreaker::new(5, Duration::from_secs(30))),
+        )
+    }
+
+    crate::order_store_tests::order_store_tests!(new_store);
+
+    /// Order store failing its next `outages` reads and creations of orders.
+    struct FlakyOrderStore {
+        store: InMemOrderStore,
+        outages: AtomicUsize,
+        calls: AtomicUsize,
+    }
+
+    impl FlakyOrderStore {
+        fn new(outages: usize) -> FlakyOrderStore {
+            FlakyOrderStore {
+                store: InMemOrderStore::new(
+                    Arc::new(ProductStoreNewType::new(InMemProductStore::new())),
+                    Arc::new(InventoryStoreNewType::new(InMemInventoryStore::new())),
+                    Arc::new(PromotionStoreNewType::new(InMemPromotionStore::new())),
+                    Arc::new(RulesTableTaxCalculator::tax_free()),
+                    Arc::new(FakePaymentProvider::new()),
+                ),
+                outages: AtomicUsize::new(outages),
+                calls: AtomicUsize::new(0),
+            }
+        }
+
+        fn outage(&self) -> Result<(), OrderStoreError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            match self
+                .outages
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            {
+                Ok(_) => Err(OrderStoreError::StoreUnavailable),
+                Err(_) => Ok(()),
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl OrderStore for FlakyOrderStore {
+        async fn create_order(&self, user_id: Uuid) -> Result<Order, OrderStoreError> {
+            self.outage()?;
+            self.store.create_order(user_id).await
+        }
+
+        async fn get_order(&self, order_id: Uuid) -> Result<Order, OrderStoreError> {
+            self.outage()?;
+            self.store.get_order(order_id).await
+        }
+
+        async fn list_orders(&self, user_id: Uuid) -> Result<Vec<Order>, OrderStoreError> {
+            self.outage()?;
+            self.store.list_orders(user_id).await
+        }
+
+        async fn add_item(
+            &self,
+            order_id: Uuid,
+            product_id: Uuid,
+            quantity: i32,
+        ) -> Result<(), OrderStoreError> {
+            self.store.add_item(order_id, product_id, quantity).await
+        }
+
+        async fn delete_item(
+            &self,
+            order_id: Uuid,
+            index: usize,
+            expected: Option<Uuid>,
+        ) -> Result<(), OrderStoreError> {
+            self.store.delete_item(order_id, index, expected).await
+        }
+
+        async fn update_item_quantity(
+            &self,
+            order_id: Uuid,
+            index: usize,
+            quantity: i32,
+            expected: Option<Uuid>,
+        ) -> Result<(), OrderStoreError> {
+            self.store
+                .update_item_quantity(order_id, index, quantity, expected)
+                .await
+        }
+
+        async fn update_status(
+            &self,
+            order_id: Uuid,
+            status: OrderStatus,
+        ) -> Result<(), OrderStoreError> {
+            self.store.update_status(order_id, status).await
+        }
+
+        async fn apply_coupon(&self, order_id: Uuid, code: &str) -> Result<(), OrderStoreError> {
+            self.store.apply_coupon(order_id, code).await
+        }
+
+        async fn remove_coupon(&self, order_id: Uuid, code: &str) -> Result<(), OrderStoreError> {
+            self.store.remove_coupon(order_id, code).await
+        }
+
+        async fn set_shipping(
+            &self,
+            order_id: Uuid,
+            address: Address,
+            method: ShippingMethod,
+        ) -> Result<(), OrderStoreError> {
+            self.store.set_shipping(order_id, address, method).await
+        }
+
+        async fn set_billing_address(
+            &self,
+            order_id: Uuid,
+            address: Address,
+        ) -> Result<(), OrderStoreError> {
+            self.store.set_billing_address(order_id, address).await
+        }
+
+        async fn checkout(
+            &self,
+            order_id: Uuid,
+            payment_token: &str,
+        ) -> Result<PaymentAttempt, OrderStoreError> {
+            self.store.checkout(order_id, payment_token).await
+        }
+
+        async fn request_return(
+            &self,
+            order_id: Uuid,
+            items: Vec<ReturnItem>,
+            reason: String,
+        ) -> Result<ReturnRequest, OrderStoreError> {
+            self.store.request_return(order_id, items, reason).await
+        }
+
+        async fn approve_return(
+            &self,
+            order_id: Uuid,
+            return_id: Uuid,
+        ) -> Result<ReturnRequest, OrderStoreError> {
+            self.store.approve_return(order_id, return_id).await
+        }
+
+        async fn reject_return(
+            &self,
+            order_id: Uuid,
+            return_id: Uuid,
+        ) -> Result<ReturnRequest, OrderStoreError> {
+            self.store.reject_return(order_id, return_id).await
+        }
+
+        async fn history(&self, order_id: Uuid) -> Result<Vec<AuditEntry>, OrderStoreError> {
+            self.store.history(order_id).await
+        }
+    }
+
+    fn flaky_store(
+        outages: usize,
+        breaker: CircuitBreaker,
+    ) -> (ResilientOrderStore<FlakyOrderStore>, Arc<CircuitBreaker>) {
+        let breaker = Arc::new(breaker);
+        let store = ResilientOrderStore::new(FlakyOrderStore::new(outages), RETRY, breaker.clone());
+        (store, breaker)
+    }
+
+    #[tokio::test]
+    async fn reads_are_retried_until_the_store_answers() {
+        let (store, breaker) = flaky_store(0, CircuitBreaker::new(5, Duration::from_secs(30)));
+        let order = store.create_order(Uuid::new_v4()).await.unwrap();
+        store.store.outages.store(2, Ordering::SeqCst);
+
+        assert_eq!(store.get_order(order.id).await.unwrap(), order);
+        assert_eq!(store.store.calls.load(Ordering::SeqCst), 4);
+        assert_eq!(
+            breaker.status(),
+            BreakerStatus::Closed {
+                consecutive_failures: 0
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn changes_are_not_retried() {
+        let (store, breaker) = flaky_store(1, CircuitBreaker::new(5, Duration::from_secs(30)));
+
+        assert!(matches!(
+            store.create_order(Uuid::new_v4()).await,
+            Err(OrderStoreError::StoreUnavailable)
+        ));
+        assert_eq!(store.store.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            breaker.status(),
+            BreakerStatus::Closed {
+                consecutive_failures: 1
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn breaker_fails_fast_until_a_trial_call_succeeds() {
+        let open_for = Duration::from_millis(50);
+        let (store, breaker) = flaky_store(usize::MAX, CircuitBreaker::new(2, open_for));
+        let order_id = Uuid::new_v4();
+
+        // the second failed attempt opens the breaker, which stops the retries
+        assert!(store.get_order(order_id).await.is_err());
+        assert_eq!(store.store.calls.load(Ordering::SeqCst), 2);
+        assert!(matches!(breaker.status(), BreakerStatus::Open { .. }));
+        assert!(breaker.retry_after().unwrap() <= open_for);
+        assert!(store.list_orders(order_id).await.is_err());
+        assert_eq!(store.store.calls.load(Ordering::SeqCst), 2);
+
+        store.store.outages.store(0, Ordering::SeqCst);
+        tokio::time::sleep(open_for).await;
+        assert!(matches!(
+            store.get_order(order_id).await,
+            Err(OrderStoreError::OrderNotFound(_))
+        ));
+        assert_eq!(store.store.calls.load(Ordering::SeqCst), 3);
+        assert_eq!(breaker.retry_after(), None);
+        assert_eq!(
+            breaker.status(),
+            BreakerStatus::Closed {
+                consecutive_failures: 0
+            }
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::order_store::Order;
+
+/// Units of one item of an order a customer wants to return.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReturnItem {
+    /// Position of the item in the order.
+    pub index: usize,
+    pub quantity: i32,
+}
+
+/// Units of one item of an order included in a return, with what they are worth.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReturnLine {
+    /// Position of the item in the order.
+    pub item_index: usize,
+    pub product_id: Uuid,
+    pub quantity: i32,
+    /// What the customer paid for these units, see [`refund_amount`](refund_amount).
+    #[serde(with = "crate::decimal128")]
+    pub refund_amount: Decimal,
+}
+
+/// Stage of a return.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReturnStatus {
+    /// Waiting for a decision.
+    Requested,
+    /// Accepted; the units went back to the inventory and their amount was refunded.
+    Approved,
+    /// Refused; nothing is refunded.
+    Rejected,
+}
+
+/// A request to return items of a delivered order, as recorded on the order.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReturnRequest {
+    pub id: Uuid,
+    pub lines: Vec<ReturnLine>,
+    /// Why the customer returns the items.
+    pub reason: String,
+    pub status: ReturnStatus,
+    pub requested_at: DateTime<Utc>,
+    /// When the return was approved or rejected.
+    pub decided_at: Option<DateTime<Utc>>,
+    /// Sum of the refund amounts of the lines; once approved, what was actually refunded, never
+    /// more than what was left of the payment of the order.
+    #[serde(with = "crate::decimal128")]
+    pub refund_amount: Decimal,
+    /// Provider reference of the refund, `None` until approved or when nothing was paid.
+    pub refund_reference: Option<String>,
+}
+
+/// Returns what the customer paid for `quantity` units of the item at `index` of `order`: their
+/// price, minus their share of the order discount, plus their tax when it was charged on top of
+/// the prices. Shipping is never refunded. The amount is rounded to two decimal places.
+pub fn refund_amount(order: &Order, index: usize, quantity: i32) -> Decimal {
+    let item = &order.items[index];
+    let goods = item.unit_price * Decimal::from(quantity);
+    let discount = if order.subtotal.is_zero() {
+        Decimal::ZERO
+    } else {
+        order.discount * goods / order.subtotal
+    };
+    // tax lines follow the items, but their position is only trusted for the same product
+    let line = order
+        .tax
+        .lines
+        .get(index)
+        .filter(|line| line.product_id == item.product_id)
+        .or_else(|| {
+            order
+                .tax
+                .lines
+                .iter()
+                .find(|line| line.product_id == item.product_id)
+        });
+    let tax = match line {
+        Some(line) if !order.tax.inclusive => {
+            line.amount * Decimal::from(quantity) / Decimal::from(item.quantity)
+        }
+        _ => Decimal::ZERO,
+    };
+    (goods - discount + tax).round_dp(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::{
+        order_store::Item,
+        payment_provider::{PaymentAttempt, PaymentOutcome},
+        tax_calculator::{LineTax, TaxBreakdown},
+    };
+
+    fn order(inclusive: bool) -> Order {
+        let mut order = Order::new(Uuid::new_v4());
+        order.items = vec![Item {
+            product_id: Uuid::new_v4(),
+            quantity: 4,
+            unit_price: dec!(10.00),
+            currency: "USD".to_string(),
+            tax_category: "standard".to_string(),
+            line_total: dec!(40.00),
+        }];
+        order.tax = TaxBreakdown {
+            region: Some("DE".to_string()),
+            inclusive,
+            lines: vec![LineTax {
+                product_id: order.items[0].product_id,
+                category: "standard".to_string(),
+                rate: dec!(0.19),
+                amount: dec!(7.60),
+            }],
+            total: dec!(7.60),
+        };
+        order.subtotal = dec!(40.00);
+        order.discount = dec!(10.00);
+        order
+    }
+
+    #[test]
+    fn refund_includes_discount_and_tax_shares() {
+        assert_eq!(refund_amount(&order(false), 0, 1), dec!(9.40));
+        assert_eq!(refund_amount(&order(false), 0, 4), dec!(37.60));
+    }
+
+    #[test]
+    fn inclusive_tax_is_already_part_of_the_price() {
+        assert_eq!(refund_amount(&order(true), 0, 2), dec!(15.00));
+    }
+
+    #[test]
+    fn tax_line_is_found_by_product_when_out_of_position() {
+        let mut order = order(false);
+        let other = LineTax {
+            product_id: Uuid::new_v4(),
+            category: "reduced".to_string(),
+            rate: dec!(0.07),
+            amount: dec!(0.70),
+        };
+        order.tax.lines.insert(0, other);
+        assert_eq!(refund_amount(&order, 0, 1), dec!(9.40));
+    }
+
+    #[test]
+    fn refunds_never_exceed_what_is_left_of_the_payment() {
+        let mut order = order(false);
+        order.payments = vec![PaymentAttempt {
+            id: Uuid::new_v4(),
+            amount: dec!(37.60),
+            currency: "USD".to_string(),
+            attempted_at: Utc::now(),
+            outcome: PaymentOutcome::Approved {
+                reference: "ch_1".to_string(),
+            },
+        }];
+        order.refunded = dec!(30.00);
+        let request = ReturnRequest {
+            id: Uuid::new_v4(),
+            lines: vec![],
+            reason: "damaged".to_string(),
+            status: ReturnStatus::Requested,
+            requested_at: Utc::now(),
+            decided_at: None,
+            refund_amount: dec!(9.40),
+            refund_reference: None,
+        };
+        order.returns.push(request.clone());
+
+        let refund = order.return_refund(request.id).unwrap().unwrap();
+        assert_eq!(refund.amount, dec!(7.60));
+        let approved = order
+            .approve_return(request.id, Some("re_1".to_string()))
+            .unwrap();
+        assert_eq!(approved.refund_amount, dec!(7.60));
+        assert_eq!(order.refunded, dec!(37.60));
+    }
+}
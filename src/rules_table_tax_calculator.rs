@@ -0,0 +1,199 @@
+use std::{fs, path::Path};
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::{
+    order_store::Order,
+    tax_calculator::{LineTax, TaxBreakdown, TaxCalculator, TaxError},
+};
+
+/// Rate of one tax category in one region.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TaxRate {
+    pub region: String,
+    pub category: String,
+    pub rate: Decimal,
+}
+
+/// Contents of the tax rules file.
+///
+/// ```json
+/// {
+///   "prices_include_tax": false,
+///   "default_region": "US-CA",
+///   "rates": [{ "region": "US-CA", "category": "standard", "rate": "0.0725" }]
+/// }
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+pub struct TaxRules {
+    /// Whether product prices already include tax.
+    #[serde(default)]
+    pub prices_include_tax: bool,
+    /// Region used for orders that don't have one.
+    pub default_region: String,
+    /// Rate used when no entry of `rates` matches, orders fail to be taxed when `None`.
+    pub default_rate: Option<Decimal>,
+    pub rates: Vec<TaxRate>,
+}
+
+/// Calculates taxes from a static table of rates by region and product tax category.
+///
+/// Tax is calculated per line on the line total less its share of the discount of the order, and
+/// rounded to two decimal places.
+pub struct RulesTableTaxCalculator {
+    rules: TaxRules,
+}
+
+impl RulesTableTaxCalculator {
+    pub fn new(rules: TaxRules) -> RulesTableTaxCalculator {
+        RulesTableTaxCalculator { rules }
+    }
+
+    /// Loads the rules from the JSON file at `path`.
+    pub fn from_file(
+        path: impl AsRef<Path>,
+    ) -> Result<RulesTableTaxCalculator, Box<dyn std::error::Error>> {
+        let contents = fs::read_to_string(path)?;
+        Ok(RulesTableTaxCalculator::new(serde_json::from_str(
+            &contents,
+        )?))
+    }
+
+    /// A calculator that charges no tax at all.
+    pub fn tax_free() -> RulesTableTaxCalculator {
+        RulesTableTaxCalculator::new(TaxRules {
+            prices_include_tax: false,
+            default_region: String::new(),
+            default_rate: Some(Decimal::ZERO),
+            rates: vec![],
+        })
+    }
+
+    fn rate(&self, region: &str, category: &str) -> Result<Decimal, TaxError> {
+        self.rules
+            .rates
+            .iter()
+            .find(|rate| rate.region == region && rate.category == category)
+            .map(|rate| rate.rate)
+            .or(self.rules.default_rate)
+            .ok_or_else(|| TaxError::NoRate(region.to_string(), category.to_string()))
+    }
+}
+
+#[async_trait::async_trait]
+impl TaxCalculator for RulesTableTaxCalculator {
+    async fn calculate(&self, order: &Order) -> Result<TaxBreakdown, TaxError> {
+        let region = order
+            .tax_region()
+            .unwrap_or_else(|| self.rules.default_region.clone());
+        let inclusive = self.rules.prices_include_tax;
+        let lines = order
+            .items
+            .iter()
+            .zip(order.discounted_line_totals())
+            .map(|(item, taxable)| {
+                let rate = self.rate(&region, &item.tax_category)?;
+                let amount = if inclusive {
+                    taxable - taxable / (Decimal::ONE + rate)
+                } else {
+                    taxable * rate
+                };
+                Ok(LineTax {
+                    product_id: item.product_id,
+                    category: item.tax_category.clone(),
+                    rate,
+                    amount: amount.round_dp(2),
+                })
+            })
+            .collect::<Result<Vec<LineTax>, TaxError>>()?;
+        Ok(TaxBreakdown {
+            region: Some(region),
+            inclusive,
+            total: lines.iter().map(|line| line.amount).sum(),
+            lines,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::order_store::Item;
+
+    fn rules(prices_include_tax: bool) -> TaxRules {
+        serde_json::from_value(serde_json::json!({
+            "prices_include_tax": prices_include_tax,
+            "default_region": "DE",
+            "rates": [
+                { "region": "DE", "category": "standard", "rate": "0.19" },
+                { "region": "DE", "category": "reduced", "rate": "0.07" },
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn order(items: &[(&str, Decimal)]) -> Order {
+        let mut order = Order::new(Uuid::new_v4());
+        order.items = items
+            .iter()
+            .map(|(category, line_total)| Item {
+                product_id: Uuid::new_v4(),
+                quantity: 1,
+                unit_price: *line_total,
+                currency: "EUR".to_string(),
+                tax_category: category.to_string(),
+                line_total: *line_total,
+            })
+            .collect();
+        order
+    }
+
+    #[tokio::test]
+    async fn exclusive_tax_is_charged_on_top_of_each_line() {
+        let calculator = RulesTableTaxCalculator::new(rules(false));
+        let tax = calculator
+            .calculate(&order(&[("standard", dec!(100)), ("reduced", dec!(10.99))]))
+            .await
+            .unwrap();
+        assert_eq!(tax.region.as_deref(), Some("DE"));
+        assert_eq!(tax.lines[0].amount, dec!(19.00));
+        assert_eq!(tax.lines[1].amount, dec!(0.77));
+        assert_eq!(tax.total, dec!(19.77));
+    }
+
+    #[tokio::test]
+    async fn inclusive_tax_is_extracted_from_each_line() {
+        let calculator = RulesTableTaxCalculator::new(rules(true));
+        let tax = calculator
+            .calculate(&order(&[("standard", dec!(119))]))
+            .await
+            .unwrap();
+        assert!(tax.inclusive);
+        assert_eq!(tax.total, dec!(19.00));
+    }
+
+    #[tokio::test]
+    async fn tax_is_charged_after_the_discount() {
+        let calculator = RulesTableTaxCalculator::new(rules(false));
+        let mut order = order(&[("standard", dec!(100)), ("reduced", dec!(50))]);
+        order.subtotal = dec!(150);
+        order.discount = dec!(30);
+        let tax = calculator.calculate(&order).await.unwrap();
+        assert_eq!(tax.lines[0].amount, dec!(15.20));
+        assert_eq!(tax.lines[1].amount, dec!(2.80));
+        assert_eq!(tax.total, dec!(18.00));
+    }
+
+    #[tokio::test]
+    async fn category_without_rate_is_rejected() {
+        let calculator = RulesTableTaxCalculator::new(rules(false));
+        assert!(matches!(
+            calculator.calculate(&order(&[("luxury", dec!(1))])).await,
+            Err(TaxError::NoRate(region, category)) if region == "DE" && category == "luxury"
+        ));
+    }
+}
@@ -0,0 +1,28 @@
+use crate::{
+    event_sink::{EventSink, EventSinkError},
+    outbox::DomainEvent,
+};
+
+/// Prints every event as a line of JSON, for local development.
+pub struct StdoutEventSink;
+
+impl StdoutEventSink {
+    /// Creates a new sink printing to the standard output.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let sink = StdoutEventSink::new();
+    /// ```
+    pub fn new() -> StdoutEventSink {
+        StdoutEventSink
+    }
+}
+
+#[async_trait::async_trait]
+impl EventSink for StdoutEventSink {
+    async fn publish(&self, event: &DomainEvent) -> Result<(), EventSinkError> {
+        println!("{}", event.to_json());
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+use std::{error::Error, fmt::Display};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::order_store::Order;
+
+/// Tax charged on one item of an order.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LineTax {
+    /// Id of the product of the item.
+    pub product_id: Uuid,
+    /// Tax category of the product.
+    pub category: String,
+    /// Rate applied to the line total, `0.19` meaning 19%.
+    #[serde(with = "crate::decimal128")]
+    pub rate: Decimal,
+    /// Tax amount of the line.
+    #[serde(with = "crate::decimal128")]
+    pub amount: Decimal,
+}
+
+/// Taxes of a whole order, with one entry per item in the same order as the items.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TaxBreakdown {
+    /// Region whose rates were applied.
+    pub region: Option<String>,
+    /// Whether the tax is already part of the item prices, or charged on top of them.
+    pub inclusive: bool,
+    /// Tax of every item.
+    pub lines: Vec<LineTax>,
+    /// Sum of the tax of every item.
+    #[serde(with = "crate::decimal128")]
+    pub total: Decimal,
+}
+
+/// Type for describing errors that result from trying to calculate taxes with a [`TaxCalculator`](TaxCalculator).
+#[derive(Debug)]
+pub enum TaxError {
+    /// The tax provider cannot be reached.
+    #[allow(dead_code)]
+    ProviderUnavailable,
+    /// There is no rate for the provided region and tax category.
+    NoRate(String, String),
+}
+
+impl Display for TaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaxError::ProviderUnavailable => {
+                write!(f, "Tax provider unavailable")
+            }
+            TaxError::NoRate(region, category) => {
+                write!(
+                    f,
+                    "No tax rate for region {} and category {}",
+                    region, category
+                )
+            }
+        }
+    }
+}
+
+impl Error for TaxError {}
+
+/// A trait that defines the behavior of a type used to calculate the taxes of an order.
+#[async_trait::async_trait]
+pub trait TaxCalculator: Send + Sync + 'static {
+    /// Calculates the taxes of `order` from its items and [`tax_region`](Order::tax_region).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ProviderUnavailable`](TaxError::ProviderUnavailable) if the taxes cannot be calculated right now.
+    ///
+    /// Returns [`NoRate`](TaxError::NoRate) if an item has no applicable rate.
+    async fn calculate(&self, order: &Order) -> Result<TaxBreakdown, TaxError>;
+}
@@ -0,0 +1,17 @@
+use crate::payment_provider::{Charge, PaymentError, PaymentOutcome, PaymentProvider, Refund};
+
+/// Payment provider of the deployments without a real one, where the fake provider is not
+/// allowed. Every charge and refund fails as if the provider could not be reached, so checkouts
+/// and refunds fail while the rest of the service keeps working.
+pub struct UnconfiguredPaymentProvider;
+
+#[async_trait::async_trait]
+impl PaymentProvider for UnconfiguredPaymentProvider {
+    async fn charge(&self, _charge: &Charge) -> Result<PaymentOutcome, PaymentError> {
+        Err(PaymentError::ProviderUnavailable)
+    }
+
+    async fn refund(&self, _refund: &Refund) -> Result<String, PaymentError> {
+        Err(PaymentError::ProviderUnavailable)
+    }
+}
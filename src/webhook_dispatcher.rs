@@ -0,0 +1,376 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::header::CONTENT_TYPE;
+use sha2::Sha256;
+use tokio::task::JoinHandle;
+use tracing::{error, warn};
+
+use crate::{
+    event_sink::{EventSink, EventSinkError},
+    outbox::{retry_delay, DomainEvent},
+    webhook_store::{
+        Delivery, DeliveryAttempt, DeliveryStatus, Subscription, WebhookStoreError,
+        WebhookStoreNewType,
+    },
+};
+
+/// Header with the HMAC-SHA256 of `<timestamp>.<body>` keyed with the subscription's secret, as
+/// `sha256=<hex>`.
+pub const SIGNATURE_HEADER: &str = "x-webhook-signature";
+/// Header with the Unix time the delivery was signed at, so partners can reject replays.
+pub const TIMESTAMP_HEADER: &str = "x-webhook-timestamp";
+/// Header with the name of the delivered event.
+pub const EVENT_TYPE_HEADER: &str = "x-webhook-event";
+/// Header with the id of the delivered event, the same in every delivery of the event.
+pub const EVENT_ID_HEADER: &str = "x-webhook-event-id";
+
+/// Attempts after which a delivery is dead-lettered.
+pub const MAX_ATTEMPTS: usize = 8;
+
+/// Number of deliveries attempted per poll.
+const BATCH_SIZE: usize = 50;
+
+/// Signs `payload`, delivered at Unix time `timestamp`, with `secret`.
+pub fn sign(secret: &str, timestamp: i64, payload: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(format!("{}.{}", timestamp, payload).as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Event sink queueing a delivery of every event for each subscription that wants it, once
+/// however many times the event is published. The deliveries are pushed by the
+/// [`WebhookDispatcher`](WebhookDispatcher).
+pub struct WebhookEventSink {
+    store: Arc<WebhookStoreNewType>,
+}
+
+impl WebhookEventSink {
+    pub fn new(store: Arc<WebhookStoreNewType>) -> WebhookEventSink {
+        WebhookEventSink { store }
+    }
+}
+
+#[async_trait::async_trait]
+impl EventSink for WebhookEventSink {
+    async fn publish(&self, event: &DomainEvent) -> Result<(), EventSinkError> {
+        let subscriptions = self
+            .store
+            .list_subscriptions()
+            .await
+            .map_err(|err| EventSinkError::Unavailable(err.to_string()))?;
+        let payload = event.to_json().to_string();
+        for subscription in subscriptions
+            .iter()
+            .filter(|subscription| subscription.wants(&event.event_type))
+        {
+            let delivery = Delivery::new(
+                subscription.id,
+                event.id,
+                &event.event_type,
+                payload.clone(),
+            );
+            self.store
+                .queue_delivery(&delivery)
+                .await
+                .map_err(|err| EventSinkError::Unavailable(err.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Background task pushing the pending deliveries to the partners' URLs, retrying failed ones
+/// with exponential backoff and dead-lettering them after [`MAX_ATTEMPTS`] attempts.
+pub struct WebhookDispatcher {
+    store: Arc<WebhookStoreNewType>,
+    client: reqwest::Client,
+    poll_interval: Duration,
+}
+
+impl WebhookDispatcher {
+    /// Creates a new dispatcher checking `store` for due deliveries every `poll_interval`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let dispatcher = WebhookDispatcher::new(webhooks, Duration::from_secs(1));
+    /// ```
+    pub fn new(store: Arc<WebhookStoreNewType>, poll_interval: Duration) -> WebhookDispatcher {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("the HTTP client is built from static settings");
+        WebhookDispatcher {
+            store,
+            client,
+            poll_interval,
+        }
+    }
+
+    /// Attempts the deliveries due by `now` and returns how many succeeded.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](WebhookStoreError::StoreUnavailable) if the deliveries cannot
+    /// be read or saved.
+    pub async fn dispatch_due(&self, now: DateTime<Utc>) -> Result<usize, WebhookStoreError> {
+        let mut delivered = 0;
+        for mut delivery in self.store.due_deliveries(now, BATCH_SIZE).await? {
+            let subscription = match self.store.get_subscription(delivery.subscription_id).await {
+                Ok(subscription) => subscription,
+                // deleted meanwhile
+                Err(WebhookStoreError::SubscriptionNotFound(_)) => continue,
+                Err(err) => return Err(err),
+            };
+            let attempt = self.attempt(&subscription, &delivery).await;
+            let succeeded = attempt.error.is_none();
+            delivery.attempts.push(attempt);
+            if succeeded {
+                delivery.status = DeliveryStatus::Delivered;
+                delivered += 1;
+            } else if delivery.attempts.len() >= MAX_ATTEMPTS {
+                warn!(
+                    "dead-lettering delivery {} to {} after {} attempts",
+                    delivery.id, subscription.url, MAX_ATTEMPTS
+                );
+                delivery.status = DeliveryStatus::DeadLettered;
+            } else {
+                let delay = retry_delay(delivery.attempts.len() as i32);
+                delivery.next_attempt_at =
+                    now + chrono::Duration::from_std(delay).expect("retry delays are short");
+            }
+            self.store.save_delivery(&delivery).await?;
+        }
+        Ok(delivered)
+    }
+
+    async fn attempt(&self, subscription: &Subscription, delivery: &Delivery) -> DeliveryAttempt {
+        let attempted_at = Utc::now();
+        let timestamp = attempted_at.timestamp();
+        let response = self
+            .client
+            .post(&subscription.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(EVENT_TYPE_HEADER, &delivery.event_type)
+            .header(EVENT_ID_HEADER, delivery.event_id.to_string())
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(
+                SIGNATURE_HEADER,
+                sign(&subscription.secret, timestamp, &delivery.payload),
+            )
+            .body(delivery.payload.clone())
+            .send()
+            .await;
+        let (status_code, error) = match response {
+            Ok(response) if response.status().is_success() => {
+                (Some(response.status().as_u16()), None)
+            }
+            Ok(response) => (
+                Some(response.status().as_u16()),
+                Some(format!("HTTP {}", response.status())),
+            ),
+            Err(err) => (None, Some(err.to_string())),
+        };
+        DeliveryAttempt {
+            attempted_at,
+            status_code,
+            error,
+        }
+    }
+
+    /// Runs the dispatcher in the background until the runtime shuts down.
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                if let Err(err) = self.dispatch_due(Utc::now()).await {
+                    error!("webhook dispatcher failed: {}", err);
+                }
+                tokio::time::sleep(self.poll_interval).await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::TcpListener,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{
+        http::{HeaderMap, StatusCode},
+        routing::post,
+        Extension, Router, Server,
+    };
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        in_mem_webhook_store::InMemWebhookStore, order_events::OrderEvent,
+        order_store::OrderStatus, webhook_store::ALL_EVENTS,
+    };
+
+    /// Requests received by a [`stand_in`] partner, with their headers and body.
+    type Received = Arc<Mutex<Vec<(HeaderMap, String)>>>;
+
+    /// Starts a local partner answering with `statuses` in turn, then with 200.
+    async fn stand_in(statuses: Vec<StatusCode>) -> (String, Received) {
+        let received: Received = Arc::new(Mutex::new(vec![]));
+        let statuses = Arc::new(Mutex::new(statuses));
+        let app = Router::new()
+            .route(
+                "/hooks",
+                post(
+                    |Extension((received, statuses)): Extension<(
+                        Received,
+                        Arc<Mutex<Vec<StatusCode>>>,
+                    )>,
+                     headers: HeaderMap,
+                     body: String| async move {
+                        received.lock().unwrap().push((headers, body));
+                        let mut statuses = statuses.lock().unwrap();
+                        if statuses.is_empty() {
+                            StatusCode::OK
+                        } else {
+                            statuses.remove(0)
+                        }
+                    },
+                ),
+            )
+            .layer(Extension((received.clone(), statuses)));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hooks", listener.local_addr().unwrap());
+        let server = Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+        (url, received)
+    }
+
+    /// Subscribes `url` to `event_type`, skipping the checks of the URL so stand-ins on the
+    /// loopback interface can be subscribed.
+    async fn subscribe(store: &WebhookStoreNewType, url: &str, event_type: &str) -> Subscription {
+        let subscription = Subscription {
+            url: url.to_string(),
+            ..Subscription::new(
+                "https://partner.example/hooks".to_string(),
+                vec![event_type.to_string()],
+                Some("s3cr3t".to_string()),
+            )
+            .unwrap()
+        };
+        store.create_subscription(subscription).await.unwrap()
+    }
+
+    async fn publish_status_change(store: &Arc<WebhookStoreNewType>) -> DomainEvent {
+        let event = DomainEvent::new(
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            OrderEvent::StatusChanged {
+                status: OrderStatus::Placed,
+            },
+        );
+        WebhookEventSink::new(store.clone())
+            .publish(&event)
+            .await
+            .unwrap();
+        event
+    }
+
+    #[tokio::test]
+    async fn events_are_queued_for_the_subscriptions_that_want_them() {
+        let store = Arc::new(WebhookStoreNewType::new(InMemWebhookStore::new()));
+        let status = subscribe(&store, "http://127.0.0.1:1/", "StatusChanged").await;
+        let all = subscribe(&store, "http://127.0.0.1:1/", ALL_EVENTS).await;
+        let items = subscribe(&store, "http://127.0.0.1:1/", "ItemAdded").await;
+
+        let event = publish_status_change(&store).await;
+
+        // published again, e.g. because another sink failed to take it
+        WebhookEventSink::new(store.clone())
+            .publish(&event)
+            .await
+            .unwrap();
+
+        for (subscription, expected) in [(status, 1), (all, 1), (items, 0)] {
+            let deliveries = store.deliveries(subscription.id).await.unwrap();
+            assert_eq!(deliveries.len(), expected);
+            assert!(deliveries
+                .iter()
+                .all(|delivery| delivery.event_id == event.id));
+        }
+    }
+
+    #[tokio::test]
+    async fn deliveries_are_signed_with_their_timestamp() {
+        let (url, received) = stand_in(vec![]).await;
+        let store = Arc::new(WebhookStoreNewType::new(InMemWebhookStore::new()));
+        let subscription = subscribe(&store, &url, "StatusChanged").await;
+        let event = publish_status_change(&store).await;
+        let dispatcher = WebhookDispatcher::new(store.clone(), Duration::from_secs(1));
+
+        assert_eq!(dispatcher.dispatch_due(Utc::now()).await.unwrap(), 1);
+
+        let (headers, body) = received.lock().unwrap().remove(0);
+        let header = |name: &str| headers.get(name).unwrap().to_str().unwrap().to_string();
+        let timestamp: i64 = header(TIMESTAMP_HEADER).parse().unwrap();
+        assert_eq!(header(SIGNATURE_HEADER), sign("s3cr3t", timestamp, &body));
+        assert_eq!(header(EVENT_TYPE_HEADER), "StatusChanged");
+        assert_eq!(header(EVENT_ID_HEADER), event.id.to_string());
+        assert_eq!(body, event.to_json().to_string());
+        let deliveries = store.deliveries(subscription.id).await.unwrap();
+        assert_eq!(deliveries[0].status, DeliveryStatus::Delivered);
+    }
+
+    #[tokio::test]
+    async fn failed_deliveries_back_off_before_being_retried() {
+        let (url, received) = stand_in(vec![StatusCode::INTERNAL_SERVER_ERROR]).await;
+        let store = Arc::new(WebhookStoreNewType::new(InMemWebhookStore::new()));
+        let subscription = subscribe(&store, &url, "StatusChanged").await;
+        publish_status_change(&store).await;
+        let dispatcher = WebhookDispatcher::new(store.clone(), Duration::from_secs(1));
+
+        let now = Utc::now();
+        assert_eq!(dispatcher.dispatch_due(now).await.unwrap(), 0);
+        assert_eq!(dispatcher.dispatch_due(now).await.unwrap(), 0);
+        assert_eq!(received.lock().unwrap().len(), 1);
+
+        let later = now + chrono::Duration::seconds(1);
+        assert_eq!(dispatcher.dispatch_due(later).await.unwrap(), 1);
+        let delivery = store.deliveries(subscription.id).await.unwrap().remove(0);
+        assert_eq!(delivery.status, DeliveryStatus::Delivered);
+        let status_codes: Vec<_> = delivery
+            .attempts
+            .iter()
+            .map(|attempt| attempt.status_code)
+            .collect();
+        assert_eq!(status_codes, [Some(500), Some(200)]);
+    }
+
+    #[tokio::test]
+    async fn deliveries_are_dead_lettered_after_repeated_failures() {
+        let (url, received) = stand_in(vec![StatusCode::BAD_GATEWAY; MAX_ATTEMPTS + 1]).await;
+        let store = Arc::new(WebhookStoreNewType::new(InMemWebhookStore::new()));
+        let subscription = subscribe(&store, &url, "StatusChanged").await;
+        publish_status_change(&store).await;
+        let dispatcher = WebhookDispatcher::new(store.clone(), Duration::from_secs(1));
+
+        let mut now = Utc::now();
+        for _ in 0..MAX_ATTEMPTS + 1 {
+            dispatcher.dispatch_due(now).await.unwrap();
+            now += chrono::Duration::days(1);
+        }
+
+        assert_eq!(received.lock().unwrap().len(), MAX_ATTEMPTS);
+        let delivery = store.deliveries(subscription.id).await.unwrap().remove(0);
+        assert_eq!(delivery.status, DeliveryStatus::DeadLettered);
+        assert_eq!(delivery.attempts.len(), MAX_ATTEMPTS);
+        assert_eq!(
+            delivery.attempts[0].error.as_deref(),
+            Some("HTTP 502 Bad Gateway")
+        );
+    }
+}
@@ -0,0 +1,363 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    ops::Deref,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::order_events::EVENT_TYPES;
+
+/// Event type subscribing to every event.
+pub const ALL_EVENTS: &str = "*";
+
+pub struct WebhookStoreNewType(pub Box<dyn WebhookStore>); // same idea as `OrderStoreNewType`
+
+impl WebhookStoreNewType {
+    pub fn new(repo: impl WebhookStore) -> WebhookStoreNewType {
+        WebhookStoreNewType(Box::new(repo))
+    }
+}
+
+impl Deref for WebhookStoreNewType {
+    type Target = dyn WebhookStore;
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+/// A partner URL that order events are pushed to.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Subscription {
+    pub id: Uuid,
+    pub url: String,
+    /// Names of the events pushed to the URL, e.g. `ItemAdded`, or [`ALL_EVENTS`].
+    pub event_types: Vec<String>,
+    /// Key the deliveries are signed with, so the partner can tell they come from us.
+    pub secret: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Subscription {
+    /// Creates a subscription of `url` to `event_types`, signed with `secret` or, without it,
+    /// with a new random secret.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`InvalidSubscription`](WebhookStoreError::InvalidSubscription) if the URL is not an
+    /// HTTPS URL of a public host, there are no event types or one of them is unknown.
+    pub fn new(
+        url: String,
+        event_types: Vec<String>,
+        secret: Option<String>,
+    ) -> Result<Subscription, WebhookStoreError> {
+        if !is_public_https(&url) {
+            return Err(WebhookStoreError::InvalidSubscription("url".to_string()));
+        }
+        if event_types.is_empty() {
+            return Err(WebhookStoreError::InvalidSubscription(
+                "event_types".to_string(),
+            ));
+        }
+        if let Some(unknown) = event_types.iter().find(|event_type| {
+            *event_type != ALL_EVENTS && !EVENT_TYPES.contains(&event_type.as_str())
+        }) {
+            return Err(WebhookStoreError::InvalidSubscription(unknown.clone()));
+        }
+        let secret = match secret {
+            Some(secret) if secret.is_empty() => {
+                return Err(WebhookStoreError::InvalidSubscription("secret".to_string()))
+            }
+            Some(secret) => secret,
+            None => format!(
+                "whsec_{}{}",
+                Uuid::new_v4().simple(),
+                Uuid::new_v4().simple()
+            ),
+        };
+        Ok(Subscription {
+            id: Uuid::new_v4(),
+            url,
+            event_types,
+            secret,
+            created_at: Utc::now(),
+        })
+    }
+
+    /// Tells whether events named `event_type` are pushed to the subscription.
+    pub fn wants(&self, event_type: &str) -> bool {
+        self.event_types
+            .iter()
+            .any(|wanted| wanted == ALL_EVENTS || wanted == event_type)
+    }
+}
+
+/// Tells whether `url` is an HTTPS URL whose host is neither `localhost` nor a private, loopback
+/// or link-local address, so subscriptions cannot make the service call its own network.
+fn is_public_https(url: &str) -> bool {
+    let Ok(parsed) = reqwest::Url::parse(url) else {
+        return false;
+    };
+    let Some(host) = parsed.host_str() else {
+        return false;
+    };
+    if parsed.scheme() != "https" {
+        return false;
+    }
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => is_public_ipv4(ip),
+        Ok(IpAddr::V6(ip)) => is_public_ipv6(ip),
+        Err(_) => {
+            let host = host.trim_end_matches('.').to_ascii_lowercase();
+            host != "localhost" && !host.ends_with(".localhost")
+        }
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [first, second, ..] = ip.octets();
+    let shared = first == 100 && (second & 0xc0) == 64; // 100.64.0.0/10, carrier-grade NAT
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || shared)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    if let Some(ip) = ip.to_ipv4_mapped() {
+        return is_public_ipv4(ip);
+    }
+    let first = ip.segments()[0];
+    let unique_local = (first & 0xfe00) == 0xfc00; // fc00::/7
+    let link_local = (first & 0xffc0) == 0xfe80; // fe80::/10
+    !(ip.is_loopback() || ip.is_unspecified() || unique_local || link_local)
+}
+
+/// Where a delivery stands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeliveryStatus {
+    /// Waiting for its first attempt or for a retry.
+    Pending,
+    /// The partner answered with a 2xx.
+    Delivered,
+    /// Every attempt failed; the delivery is kept as a record but not retried anymore.
+    DeadLettered,
+}
+
+/// One try at pushing a delivery.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DeliveryAttempt {
+    pub attempted_at: DateTime<Utc>,
+    /// Status of the partner's response, `None` when there was no response.
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+}
+
+/// An event to push to one subscription.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Delivery {
+    pub id: Uuid,
+    pub subscription_id: Uuid,
+    /// Id of the [`DomainEvent`](crate::outbox::DomainEvent), the same in every delivery of the event.
+    pub event_id: Uuid,
+    pub event_type: String,
+    /// JSON body sent on every attempt.
+    pub payload: String,
+    pub status: DeliveryStatus,
+    pub attempts: Vec<DeliveryAttempt>,
+    /// Kept as milliseconds so due deliveries can be filtered by it.
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    pub next_attempt_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Delivery {
+    pub fn new(
+        subscription_id: Uuid,
+        event_id: Uuid,
+        event_type: &str,
+        payload: String,
+    ) -> Delivery {
+        let now = Utc::now();
+        Delivery {
+            id: Uuid::new_v4(),
+            subscription_id,
+            event_id,
+            event_type: event_type.to_string(),
+            payload,
+            status: DeliveryStatus::Pending,
+            attempts: vec![],
+            next_attempt_at: now,
+            created_at: now,
+        }
+    }
+}
+
+/// Type for describing errors that result from trying to interact with a [`WebhookStore`](WebhookStore).
+#[derive(Debug)]
+pub enum WebhookStoreError {
+    /// The store is unavailable.
+    StoreUnavailable,
+    /// Provided subscription id was not found in the store.
+    SubscriptionNotFound(Uuid),
+    /// The provided field of a subscription is missing or malformed.
+    InvalidSubscription(String),
+}
+
+impl Display for WebhookStoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebhookStoreError::StoreUnavailable => {
+                write!(f, "Store unavailable")
+            }
+            WebhookStoreError::SubscriptionNotFound(id) => {
+                write!(f, "Subscription not found {}", id)
+            }
+            WebhookStoreError::InvalidSubscription(field) => {
+                write!(f, "Invalid subscription field: {}", field)
+            }
+        }
+    }
+}
+
+impl Error for WebhookStoreError {}
+
+/// A trait that defines the behavior of a type used to store webhook subscriptions and their deliveries.
+#[async_trait::async_trait]
+pub trait WebhookStore: Send + Sync + 'static {
+    /// Stores a new subscription.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](WebhookStoreError::StoreUnavailable) if the Store cannot be used to create a subscription.
+    async fn create_subscription(
+        &self,
+        subscription: Subscription,
+    ) -> Result<Subscription, WebhookStoreError>;
+
+    /// Gets a subscription from its id.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](WebhookStoreError::StoreUnavailable) if the Store cannot be used to get a subscription.
+    ///
+    /// Returns [`SubscriptionNotFound`](WebhookStoreError::SubscriptionNotFound) if there is no subscription with the provided id.
+    async fn get_subscription(&self, id: Uuid) -> Result<Subscription, WebhookStoreError>;
+
+    /// Returns every subscription in the store.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](WebhookStoreError::StoreUnavailable) if the Store cannot be used to list subscriptions.
+    async fn list_subscriptions(&self) -> Result<Vec<Subscription>, WebhookStoreError>;
+
+    /// Deletes a subscription; its pending deliveries are not attempted anymore.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](WebhookStoreError::StoreUnavailable) if the Store cannot be used to delete a subscription.
+    ///
+    /// Returns [`SubscriptionNotFound`](WebhookStoreError::SubscriptionNotFound) if there is no subscription with the provided id.
+    async fn delete_subscription(&self, id: Uuid) -> Result<(), WebhookStoreError>;
+
+    /// Stores a new delivery, unless the subscription already has a delivery of the same event,
+    /// which is left as it is: events published again are not delivered twice.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](WebhookStoreError::StoreUnavailable) if the Store cannot be used to save a delivery.
+    async fn queue_delivery(&self, delivery: &Delivery) -> Result<(), WebhookStoreError>;
+
+    /// Stores a delivery, replacing the one with the same id if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](WebhookStoreError::StoreUnavailable) if the Store cannot be used to save a delivery.
+    async fn save_delivery(&self, delivery: &Delivery) -> Result<(), WebhookStoreError>;
+
+    /// Returns up to `limit` pending deliveries of existing subscriptions due by `now`, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](WebhookStoreError::StoreUnavailable) if the Store cannot be used to list deliveries.
+    async fn due_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<Delivery>, WebhookStoreError>;
+
+    /// Returns the deliveries of the subscription with id `subscription_id`, newest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreUnavailable`](WebhookStoreError::StoreUnavailable) if the Store cannot be used to list deliveries.
+    ///
+    /// Returns [`SubscriptionNotFound`](WebhookStoreError::SubscriptionNotFound) if there is no subscription with the provided id.
+    async fn deliveries(&self, subscription_id: Uuid) -> Result<Vec<Delivery>, WebhookStoreError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subscriptions_need_a_public_https_url_and_known_event_types() {
+        let subscribe = |url: &str, event_types: &[&str]| {
+            Subscription::new(
+                url.to_string(),
+                event_types.iter().map(|t| t.to_string()).collect(),
+                None,
+            )
+        };
+        assert!(subscribe("https://partner.example/hooks", &["ItemAdded"]).is_ok());
+        assert!(subscribe("https://203.0.113.7:9000/", &[ALL_EVENTS]).is_ok());
+        for url in [
+            "ftp://partner.example/",
+            "http://partner.example/hooks",
+            "https://127.0.0.1:9000/",
+            "https://localhost/hooks",
+            "https://10.1.2.3/",
+            "https://192.168.0.10/",
+            "https://169.254.169.254/latest/meta-data",
+            "https://[::1]/",
+            "https://[fd00::1]/",
+            "https://[::ffff:10.0.0.1]/",
+            "https://0.0.0.0/",
+        ] {
+            assert!(
+                matches!(
+                    subscribe(url, &["ItemAdded"]),
+                    Err(WebhookStoreError::InvalidSubscription(field)) if field == "url"
+                ),
+                "{url} was accepted"
+            );
+        }
+        assert!(matches!(
+            subscribe("https://partner.example/hooks", &[]),
+            Err(WebhookStoreError::InvalidSubscription(field)) if field == "event_types"
+        ));
+        assert!(matches!(
+            subscribe("https://partner.example/hooks", &["OrderExploded"]),
+            Err(WebhookStoreError::InvalidSubscription(field)) if field == "OrderExploded"
+        ));
+    }
+
+    #[test]
+    fn subscriptions_get_the_events_they_asked_for() {
+        let subscription = Subscription::new(
+            "https://partner.example/hooks".to_string(),
+            vec!["StatusChanged".to_string()],
+            None,
+        )
+        .unwrap();
+        assert!(subscription.secret.starts_with("whsec_"));
+        assert!(subscription.wants("StatusChanged"));
+        assert!(!subscription.wants("ItemAdded"));
+    }
+}